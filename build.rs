@@ -25,6 +25,11 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let idl_mod_path = current_dir.join("crates/src/drift_idl.rs");
     generate_idl_types(&idl_source_path, idl_mod_path.as_path())?;
 
+    if std::env::var("CARGO_FEATURE_NATIVE_FFI").is_ok() {
+        println!("cargo:warning=native_ffi enabled. skipping {LIB} build");
+        return Ok(());
+    }
+
     // Only build FFI lib if static or no lib path provided
     if should_build_from_source() {
         build_ffi_lib(&current_dir)?;
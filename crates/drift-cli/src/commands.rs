@@ -0,0 +1,573 @@
+//! CLI command implementations
+
+use std::{fmt, time::Duration};
+
+use drift_rs::{
+    constants::{MarketExt, PROGRAM_ID},
+    dlob::builder::DLOBBuilder,
+    event_subscriber::EventSubscriber,
+    math::{
+        constants::{BASE_PRECISION, PRICE_PRECISION, QUOTE_PRECISION},
+        leverage::UserMargin,
+    },
+    memcmp,
+    types::{
+        MarketId, MarketType, ModifyOrderParams, NewOrder, PostOnlyParam, SdkError, SdkResult,
+    },
+    DriftClient, Pubkey,
+};
+use futures_util::StreamExt;
+use serde::Serialize;
+use solana_sdk::signature::Signature;
+
+use crate::{
+    decode,
+    output::{emit, emit_json, emit_json_line, native_amount, ui_amount},
+};
+
+const PRICE_DECIMALS: u32 = PRICE_PRECISION.ilog10();
+const QUOTE_DECIMALS: u32 = QUOTE_PRECISION.ilog10();
+const BASE_DECIMALS: u32 = BASE_PRECISION.ilog10();
+
+/// Shared command state
+pub struct Ctx<'a> {
+    pub drift: &'a DriftClient,
+    /// the selected sub-account
+    pub sub_account: Pubkey,
+    /// sign txs as delegate
+    pub delegated: bool,
+    /// print JSON output
+    pub json: bool,
+}
+
+impl Ctx<'_> {
+    fn lookup(&self, symbol: &str) -> SdkResult<MarketId> {
+        self.drift
+            .market_lookup(symbol)
+            .ok_or_else(|| SdkError::Generic(format!("unknown market: {symbol}")))
+    }
+    /// Decimals of the base asset of `market`
+    fn base_decimals(&self, market: MarketId) -> SdkResult<u32> {
+        if market.is_perp() {
+            Ok(BASE_DECIMALS)
+        } else {
+            self.drift
+                .program_data()
+                .spot_market_config_by_index(market.index())
+                .map(|m| m.decimals)
+                .ok_or(SdkError::NoMarketData(market))
+        }
+    }
+    fn symbol(&self, market: MarketId) -> String {
+        let program_data = self.drift.program_data();
+        match market.kind() {
+            MarketType::Perp => program_data
+                .perp_market_config_by_index(market.index())
+                .map(|m| m.symbol().to_string()),
+            MarketType::Spot => program_data
+                .spot_market_config_by_index(market.index())
+                .map(|m| m.symbol().to_string()),
+        }
+        .unwrap_or_else(|| format!("{}-{}", market.kind().as_str(), market.index()))
+    }
+    async fn send(&self, tx: drift_rs::types::VersionedMessage) -> SdkResult<()> {
+        let signature = self.drift.sign_and_send(tx).await?;
+        emit(self.json, &TxReport { signature });
+        Ok(())
+    }
+}
+
+#[derive(Serialize)]
+struct TxReport {
+    #[serde(serialize_with = "serialize_display")]
+    signature: Signature,
+}
+
+impl fmt::Display for TxReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "sent: {}", self.signature)
+    }
+}
+
+fn serialize_display<T: fmt::Display, S: serde::Serializer>(
+    value: &T,
+    s: S,
+) -> Result<S::Ok, S::Error> {
+    s.collect_str(value)
+}
+
+#[derive(Serialize)]
+struct UserReport {
+    sub_account: String,
+    authority: String,
+    perp_positions: Vec<PerpPositionRow>,
+    spot_positions: Vec<SpotPositionRow>,
+    orders: Vec<OrderRow>,
+    margin: Option<MarginRow>,
+}
+
+#[derive(Serialize)]
+struct PerpPositionRow {
+    market: String,
+    base_amount: f64,
+    quote_entry_amount: f64,
+    unrealized_pnl: Option<f64>,
+}
+
+#[derive(Serialize)]
+struct SpotPositionRow {
+    market: String,
+    token_amount: f64,
+}
+
+#[derive(Serialize)]
+struct OrderRow {
+    order_id: u32,
+    user_order_id: u8,
+    market: String,
+    order_type: String,
+    direction: String,
+    price: f64,
+    amount: f64,
+    filled: f64,
+    reduce_only: bool,
+    post_only: bool,
+}
+
+#[derive(Serialize)]
+struct MarginRow {
+    total_collateral: f64,
+    margin_requirement: f64,
+    free_collateral: f64,
+    total_perp_pnl: f64,
+}
+
+impl fmt::Display for UserReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "sub-account: {}", self.sub_account)?;
+        writeln!(f, "authority:   {}", self.authority)?;
+        if let Some(margin) = &self.margin {
+            writeln!(f, "\nmargin (USDC)")?;
+            writeln!(f, "  total collateral:   {:>14.2}", margin.total_collateral)?;
+            writeln!(
+                f,
+                "  margin requirement: {:>14.2}",
+                margin.margin_requirement
+            )?;
+            writeln!(f, "  free collateral:    {:>14.2}", margin.free_collateral)?;
+            writeln!(f, "  perp pnl:           {:>14.2}", margin.total_perp_pnl)?;
+        }
+        writeln!(f, "\nperp positions")?;
+        for p in &self.perp_positions {
+            writeln!(
+                f,
+                "  {:<12} {:>16.4} entry: {:>14.2} pnl: {:>12}",
+                p.market,
+                p.base_amount,
+                p.quote_entry_amount,
+                p.unrealized_pnl
+                    .map(|x| format!("{x:.2}"))
+                    .unwrap_or("-".into()),
+            )?;
+        }
+        writeln!(f, "\nspot positions")?;
+        for p in &self.spot_positions {
+            writeln!(f, "  {:<12} {:>16.4}", p.market, p.token_amount)?;
+        }
+        writeln!(f, "\nopen orders")?;
+        for o in &self.orders {
+            writeln!(
+                f,
+                "  #{:<6} {:<12} {:<6} {:<14} {:>14.4} @ {:>12.4} filled: {:.4}{}{}",
+                o.order_id,
+                o.market,
+                o.direction,
+                o.order_type,
+                o.amount,
+                o.price,
+                o.filled,
+                if o.reduce_only { " reduce-only" } else { "" },
+                if o.post_only { " post-only" } else { "" },
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Show the sub-account's positions, orders and margin
+pub async fn user(ctx: Ctx<'_>) -> SdkResult<()> {
+    let drift = ctx.drift;
+    let user = drift.get_user_account(&ctx.sub_account).await?;
+
+    // margin calculation needs live markets/oracles for every position
+    let mut markets = vec![MarketId::QUOTE_SPOT];
+    markets.extend(
+        user.perp_positions
+            .iter()
+            .filter(|p| !p.is_available())
+            .map(|p| MarketId::perp(p.market_index)),
+    );
+    markets.extend(
+        user.spot_positions
+            .iter()
+            .filter(|p| !p.is_available())
+            .map(|p| MarketId::spot(p.market_index)),
+    );
+    markets.sort_by_key(|m| (m.kind() as u8, m.index()));
+    markets.dedup();
+    drift.subscribe_markets(&markets).await?;
+    drift.subscribe_oracles(&markets).await?;
+    drift.subscribe_account(&ctx.sub_account).await?;
+
+    let margin = drift
+        .calculate_margin_info(&user)
+        .map(|m| MarginRow {
+            total_collateral: ui_amount(m.total_collateral, QUOTE_DECIMALS),
+            margin_requirement: ui_amount(m.margin_requirement as i128, QUOTE_DECIMALS),
+            free_collateral: ui_amount(m.get_free_collateral() as i128, QUOTE_DECIMALS),
+            total_perp_pnl: ui_amount(m.total_perp_pnl, QUOTE_DECIMALS),
+        })
+        .inspect_err(|err| log::warn!("margin calculation failed: {err:?}"))
+        .ok();
+
+    let mut perp_positions = vec![];
+    for p in user.perp_positions.iter().filter(|p| !p.is_available()) {
+        let market = MarketId::perp(p.market_index);
+        let unrealized_pnl = drift
+            .try_get_oracle_price_data_and_slot(market)
+            .and_then(|o| p.get_unrealized_pnl(o.data.price).ok())
+            .map(|pnl| ui_amount(pnl, QUOTE_DECIMALS));
+        perp_positions.push(PerpPositionRow {
+            market: ctx.symbol(market),
+            base_amount: ui_amount(p.base_asset_amount as i128, BASE_DECIMALS),
+            quote_entry_amount: ui_amount(p.quote_entry_amount as i128, QUOTE_DECIMALS),
+            unrealized_pnl,
+        });
+    }
+
+    let mut spot_positions = vec![];
+    for p in user.spot_positions.iter().filter(|p| !p.is_available()) {
+        let market = drift.get_spot_market_account(p.market_index).await?;
+        spot_positions.push(SpotPositionRow {
+            market: ctx.symbol(MarketId::spot(p.market_index)),
+            token_amount: ui_amount(p.get_signed_token_amount(&market)?, market.decimals),
+        });
+    }
+
+    let mut orders = vec![];
+    for o in drift.all_orders(&ctx.sub_account).await? {
+        let market = MarketId::new(o.market_index, o.market_type);
+        let decimals = ctx.base_decimals(market)?;
+        orders.push(OrderRow {
+            order_id: o.order_id,
+            user_order_id: o.user_order_id,
+            market: ctx.symbol(market),
+            order_type: o.order_type.as_str().to_string(),
+            direction: format!("{:?}", o.direction).to_ascii_lowercase(),
+            price: ui_amount(o.price as i128, PRICE_DECIMALS),
+            amount: ui_amount(o.base_asset_amount as i128, decimals),
+            filled: ui_amount(o.base_asset_amount_filled as i128, decimals),
+            reduce_only: o.reduce_only,
+            post_only: o.post_only,
+        });
+    }
+
+    emit(
+        ctx.json,
+        &UserReport {
+            sub_account: ctx.sub_account.to_string(),
+            authority: user.authority.to_string(),
+            perp_positions,
+            spot_positions,
+            orders,
+            margin,
+        },
+    );
+
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct MarketRow {
+    symbol: String,
+    market_type: &'static str,
+    market_index: u16,
+    oracle: String,
+}
+
+#[derive(Serialize)]
+#[serde(transparent)]
+struct MarketsReport(Vec<MarketRow>);
+
+impl fmt::Display for MarketsReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for m in &self.0 {
+            writeln!(
+                f,
+                "{:<5} {:>4}  {:<16} oracle: {}",
+                m.market_type, m.market_index, m.symbol, m.oracle
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// List all markets
+pub async fn markets(ctx: Ctx<'_>) -> SdkResult<()> {
+    let program_data = ctx.drift.program_data();
+    let perps = program_data
+        .perp_market_configs()
+        .iter()
+        .map(|m| MarketRow {
+            symbol: m.symbol().to_string(),
+            market_type: m.market_type(),
+            market_index: m.market_index,
+            oracle: m.amm.oracle.to_string(),
+        });
+    let spots = program_data
+        .spot_market_configs()
+        .iter()
+        .map(|m| MarketRow {
+            symbol: m.symbol().to_string(),
+            market_type: m.market_type(),
+            market_index: m.market_index,
+            oracle: m.oracle.to_string(),
+        });
+    emit(ctx.json, &MarketsReport(perps.chain(spots).collect()));
+
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct BookReport {
+    market: String,
+    slot: u64,
+    oracle_price: f64,
+    /// (price, size) best first
+    asks: Vec<(f64, f64)>,
+    /// (price, size) best first
+    bids: Vec<(f64, f64)>,
+}
+
+impl fmt::Display for BookReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} slot: {} oracle: {:.4}",
+            self.market, self.slot, self.oracle_price
+        )?;
+        for (price, size) in self.asks.iter().rev() {
+            writeln!(f, "  ASK {price:>14.4} | {size:>14.4}")?;
+        }
+        writeln!(f, "  ---")?;
+        for (price, size) in &self.bids {
+            writeln!(f, "  BID {price:>14.4} | {size:>14.4}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Print the L2 book of a perp market built from on-chain orders
+pub async fn book(ctx: Ctx<'_>, symbol: &str, depth: usize) -> SdkResult<()> {
+    let drift = ctx.drift;
+    let market = ctx.lookup(symbol)?;
+    if !market.is_perp() {
+        return Err(SdkError::Generic(
+            "book: only perp markets are supported".into(),
+        ));
+    }
+    drift.subscribe_markets(&[market]).await?;
+    drift.subscribe_oracles(&[market]).await?;
+    drift
+        .sync_user_accounts(vec![memcmp::get_user_with_order_filter()])
+        .await?;
+
+    let builder = DLOBBuilder::new(drift.backend().account_map());
+    let on_slot = builder.slot_update_handler(drift.clone(), vec![market]);
+    let slot = drift.rpc().get_slot().await?;
+    on_slot(slot);
+
+    // the DLOB processes updates in the background
+    let mut l2 = None;
+    for _ in 0..50 {
+        match builder
+            .dlob()
+            .get_l2_snapshot_safe(market.index(), market.kind())
+        {
+            Some(book) if book.slot >= slot => {
+                l2 = Some(book);
+                break;
+            }
+            _ => tokio::time::sleep(Duration::from_millis(100)).await,
+        }
+    }
+    let l2 = l2.ok_or_else(|| SdkError::Generic("timed out building book".into()))?;
+
+    let level = |(price, size): (&u64, &u64)| {
+        (
+            ui_amount(*price as i128, PRICE_DECIMALS),
+            ui_amount(*size as i128, BASE_DECIMALS),
+        )
+    };
+    emit(
+        ctx.json,
+        &BookReport {
+            market: ctx.symbol(market),
+            slot: l2.slot,
+            oracle_price: ui_amount(l2.oracle_price as i128, PRICE_DECIMALS),
+            asks: l2.asks.iter().take(depth).map(level).collect(),
+            bids: l2.bids.iter().rev().take(depth).map(level).collect(),
+        },
+    );
+
+    Ok(())
+}
+
+/// Place an order, `amount` < 0 is a short
+pub async fn place(
+    ctx: Ctx<'_>,
+    symbol: &str,
+    amount: f64,
+    price: Option<f64>,
+    post_only: bool,
+    reduce_only: bool,
+    user_order_id: u8,
+) -> SdkResult<()> {
+    let market = ctx.lookup(symbol)?;
+    let base_amount = native_amount(amount, ctx.base_decimals(market)?) as i64;
+    let order = match price {
+        Some(price) => NewOrder::limit(market).price(native_amount(price, PRICE_DECIMALS)),
+        None => NewOrder::market(market),
+    }
+    .amount(if amount < 0.0 {
+        -base_amount
+    } else {
+        base_amount
+    })
+    .reduce_only(reduce_only)
+    .post_only(if post_only {
+        PostOnlyParam::MustPostOnly
+    } else {
+        PostOnlyParam::None
+    })
+    .user_order_id(user_order_id)
+    .build();
+
+    let tx = ctx
+        .drift
+        .init_tx(&ctx.sub_account, ctx.delegated)
+        .await?
+        .place_orders(vec![order])
+        .build();
+    ctx.send(tx).await
+}
+
+/// Cancel orders by id, by market or all
+pub async fn cancel(ctx: Ctx<'_>, order_ids: Vec<u32>, market: Option<&str>) -> SdkResult<()> {
+    let builder = ctx.drift.init_tx(&ctx.sub_account, ctx.delegated).await?;
+    let tx = if !order_ids.is_empty() {
+        builder.cancel_orders_by_id(order_ids)
+    } else if let Some(symbol) = market {
+        builder.cancel_orders(ctx.lookup(symbol)?.to_parts(), None)
+    } else {
+        builder.cancel_all_orders()
+    }
+    .build();
+    ctx.send(tx).await
+}
+
+/// Modify an open order's price and/or size
+pub async fn modify(
+    ctx: Ctx<'_>,
+    order_id: u32,
+    price: Option<f64>,
+    amount: Option<f64>,
+) -> SdkResult<()> {
+    let order = ctx
+        .drift
+        .get_order_by_id(&ctx.sub_account, order_id)
+        .await?
+        .ok_or_else(|| SdkError::Generic(format!("no open order: {order_id}")))?;
+    let decimals = ctx.base_decimals(MarketId::new(order.market_index, order.market_type))?;
+    let params = ModifyOrderParams {
+        price: price.map(|p| native_amount(p, PRICE_DECIMALS)),
+        base_asset_amount: amount.map(|a| native_amount(a, decimals)),
+        ..Default::default()
+    };
+    let tx = ctx
+        .drift
+        .init_tx(&ctx.sub_account, ctx.delegated)
+        .await?
+        .modify_orders(&[(order_id, params)])
+        .build();
+    ctx.send(tx).await
+}
+
+/// Deposit `amount` tokens of spot market `symbol`
+pub async fn deposit(ctx: Ctx<'_>, symbol: &str, amount: f64) -> SdkResult<()> {
+    let market = spot_market(&ctx, symbol)?;
+    let amount = native_amount(amount, ctx.base_decimals(market)?);
+    let tx = ctx
+        .drift
+        .init_tx(&ctx.sub_account, ctx.delegated)
+        .await?
+        .deposit(amount, market.index(), None, None)
+        .build();
+    ctx.send(tx).await
+}
+
+/// Withdraw `amount` tokens of spot market `symbol`
+pub async fn withdraw(ctx: Ctx<'_>, symbol: &str, amount: f64, reduce_only: bool) -> SdkResult<()> {
+    let market = spot_market(&ctx, symbol)?;
+    let amount = native_amount(amount, ctx.base_decimals(market)?);
+    let tx = ctx
+        .drift
+        .init_tx(&ctx.sub_account, ctx.delegated)
+        .await?
+        .withdraw(amount, market.index(), Some(reduce_only), None)
+        .build();
+    ctx.send(tx).await
+}
+
+fn spot_market(ctx: &Ctx<'_>, symbol: &str) -> SdkResult<MarketId> {
+    let market = ctx.lookup(symbol)?;
+    if !market.is_spot() {
+        return Err(SdkError::Generic(format!("not a spot market: {symbol}")));
+    }
+    Ok(market)
+}
+
+/// Stream the sub-account's events until interrupted
+pub async fn events(ctx: Ctx<'_>) -> SdkResult<()> {
+    let mut events = EventSubscriber::subscribe(ctx.drift.ws(), ctx.sub_account).await?;
+    eprintln!("streaming events for: {}", ctx.sub_account);
+    while let Some(event) = events.next().await {
+        if ctx.json {
+            emit_json_line(&event);
+        } else {
+            println!("{event:?}");
+        }
+    }
+
+    Ok(())
+}
+
+/// Decode a drift account as its IDL type
+pub async fn decode(ctx: Ctx<'_>, account: &Pubkey) -> SdkResult<()> {
+    let data = ctx.drift.rpc().get_account(account).await?;
+    if data.owner != PROGRAM_ID {
+        return Err(SdkError::Generic(format!(
+            "not a drift account, owner: {}",
+            data.owner
+        )));
+    }
+    let (name, value) = decode::decode_account(&data.data)?;
+    if ctx.json {
+        emit_json(&value);
+    } else {
+        println!("{name} {account}");
+        emit_json(&value);
+    }
+
+    Ok(())
+}
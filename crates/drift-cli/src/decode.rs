@@ -0,0 +1,85 @@
+//! Decode raw drift accounts into their IDL types
+
+use anchor_lang::{AccountDeserialize, Discriminator};
+use drift_rs::types::{accounts, SdkError, SdkResult};
+use serde_json::Value;
+
+macro_rules! decode_as {
+    ($data:expr, $($ty:ident),+ $(,)?) => {
+        match &$data[..8] {
+            $(
+                d if d == accounts::$ty::DISCRIMINATOR => {
+                    let account = accounts::$ty::try_deserialize(&mut &$data[..])
+                        .map_err(|_| SdkError::Deserializing)?;
+                    Ok((
+                        stringify!($ty),
+                        serde_json::to_value(account).map_err(|_| SdkError::Deserializing)?,
+                    ))
+                }
+            )+
+            _ => Err(SdkError::InvalidAccount),
+        }
+    };
+}
+
+/// Decode drift account `data` by its discriminator
+///
+/// Returns the account type name and its fields as JSON
+pub fn decode_account(data: &[u8]) -> SdkResult<(&'static str, Value)> {
+    if data.len() < 8 {
+        return Err(SdkError::InvalidAccount);
+    }
+    decode_as!(
+        data,
+        AmmCache,
+        AmmConstituentMapping,
+        Constituent,
+        ConstituentCorrelations,
+        ConstituentTargetBase,
+        FuelOverflow,
+        HighLeverageModeConfig,
+        IfRebalanceConfig,
+        InsuranceFundStake,
+        LPPool,
+        OpenbookV2FulfillmentConfig,
+        PerpMarket,
+        PhoenixV1FulfillmentConfig,
+        PrelaunchOracle,
+        ProtectedMakerModeConfig,
+        ProtocolIfSharesTransferConfig,
+        PythLazerOracle,
+        ReferrerName,
+        RevenueShare,
+        RevenueShareEscrow,
+        SerumV3FulfillmentConfig,
+        SignedMsgUserOrders,
+        SignedMsgWsDelegates,
+        SpotMarket,
+        State,
+        User,
+        UserStats,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use drift_rs::{types::accounts::User, utils::zero_account_to_bytes, Pubkey};
+
+    use super::*;
+
+    #[test]
+    fn decode_user() {
+        let authority = Pubkey::new_unique();
+        let user = User {
+            authority,
+            sub_account_id: 3,
+            ..Default::default()
+        };
+        let (name, value) = decode_account(&zero_account_to_bytes(user)).unwrap();
+        assert_eq!(name, "User");
+        assert_eq!(value["sub_account_id"], 3);
+
+        assert!(decode_account(&[0_u8; 64]).is_err());
+        assert!(decode_account(&[1, 2]).is_err());
+    }
+}
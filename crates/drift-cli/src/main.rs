@@ -0,0 +1,244 @@
+//!
+//! drift CLI
+//!
+//! Inspect drift accounts, markets and order books, place and manage orders from the command line
+//!
+use clap::{parser::ValueSource, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use drift_rs::{
+    types::{Context, SdkError, SdkResult},
+    utils::load_keypair_multi_format,
+    DriftClient, Pubkey, RpcClient, Wallet,
+};
+
+mod commands;
+mod decode;
+mod output;
+
+#[derive(Parser, Debug)]
+#[command(name = "drift", version, about = "Drift V2 command line")]
+struct Cli {
+    /// RPC endpoint
+    #[arg(
+        long,
+        env = "RPC_URL",
+        default_value = "https://api.mainnet-beta.solana.com"
+    )]
+    rpc_url: String,
+    /// Target devnet
+    #[arg(long)]
+    devnet: bool,
+    /// Signing keypair, base58 private key or path to keypair.json
+    #[arg(long, env = "DRIFT_KEYPAIR", hide_env_values = true)]
+    keypair: Option<String>,
+    /// Drift account authority (default: keypair pubkey), read-only unless `--delegated`
+    #[arg(long)]
+    authority: Option<Pubkey>,
+    /// Sign as delegate of `authority`
+    #[arg(long, requires = "authority")]
+    delegated: bool,
+    /// Sub-account id
+    #[arg(long, short = 's', default_value_t = 0)]
+    sub_account: u16,
+    /// Print JSON output
+    #[arg(long, global = true)]
+    json: bool,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Show the sub-account's positions, orders and margin
+    User,
+    /// List markets
+    Markets,
+    /// Print the L2 book of a perp market e.g. SOL-PERP
+    Book {
+        market: String,
+        /// number of levels per side
+        #[arg(long, default_value_t = 10)]
+        depth: usize,
+    },
+    /// Place an order, market order unless `--price` is set
+    Place {
+        /// market symbol e.g. SOL-PERP, SOL
+        market: String,
+        side: Side,
+        /// order size in base units e.g 1.5 (SOL)
+        amount: f64,
+        /// limit price
+        #[arg(long)]
+        price: Option<f64>,
+        #[arg(long)]
+        post_only: bool,
+        #[arg(long)]
+        reduce_only: bool,
+        #[arg(long, default_value_t = 0)]
+        user_order_id: u8,
+    },
+    /// Cancel orders by id, by market or all orders (default)
+    Cancel {
+        #[arg(long)]
+        order_id: Vec<u32>,
+        /// cancel all orders in market
+        #[arg(long, conflicts_with = "order_id")]
+        market: Option<String>,
+    },
+    /// Modify an open order
+    Modify {
+        order_id: u32,
+        /// new limit price
+        #[arg(long)]
+        price: Option<f64>,
+        /// new order size in base units
+        #[arg(long)]
+        amount: Option<f64>,
+    },
+    /// Deposit collateral e.g. `deposit USDC 100`
+    Deposit { market: String, amount: f64 },
+    /// Withdraw collateral e.g. `withdraw USDC 100`
+    Withdraw {
+        market: String,
+        amount: f64,
+        /// only reduce an existing deposit, do not borrow
+        #[arg(long)]
+        reduce_only: bool,
+    },
+    /// Stream the sub-account's events
+    Events,
+    /// Decode a drift account as its IDL type
+    Decode { account: Pubkey },
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum Side {
+    Long,
+    Short,
+}
+
+#[tokio::main]
+async fn main() {
+    let _ = env_logger::try_init();
+    let matches = Cli::command().get_matches();
+    let keypair_from_env = matches.value_source("keypair") == Some(ValueSource::EnvVariable);
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+    if let Err(err) = run(cli, keypair_from_env).await {
+        eprintln!("error: {err}");
+        std::process::exit(1);
+    }
+}
+
+/// Build the wallet from `cli` args
+///
+/// A keypair from `DRIFT_KEYPAIR` is ignored when inspecting another `--authority` (read-only),
+/// an explicit `--keypair` with `--authority` must be `--delegated`
+fn wallet(cli: &Cli, keypair_from_env: bool) -> SdkResult<Wallet> {
+    match (cli.keypair.as_deref(), cli.authority) {
+        (Some(key), Some(authority)) if cli.delegated => Ok(Wallet::delegated(
+            load_keypair_multi_format(key)?,
+            authority,
+        )),
+        (Some(_), Some(_)) if !keypair_from_env => Err(SdkError::Generic(
+            "--keypair with --authority requires --delegated, omit --keypair for read-only access"
+                .into(),
+        )),
+        (Some(key), None) => Wallet::try_from_str(key),
+        (_, Some(authority)) => Ok(Wallet::read_only(authority)),
+        (None, None) => Err(SdkError::Generic(
+            "either --keypair or --authority is required".into(),
+        )),
+    }
+}
+
+async fn run(cli: Cli, keypair_from_env: bool) -> SdkResult<()> {
+    let context = if cli.devnet {
+        Context::DevNet
+    } else {
+        Context::MainNet
+    };
+    let wallet = wallet(&cli, keypair_from_env)?;
+    let drift = DriftClient::new(context, RpcClient::new(cli.rpc_url), wallet).await?;
+    let sub_account = drift.wallet().sub_account(cli.sub_account);
+    let ctx = commands::Ctx {
+        drift: &drift,
+        sub_account,
+        delegated: cli.delegated,
+        json: cli.json,
+    };
+
+    match cli.command {
+        Command::User => commands::user(ctx).await,
+        Command::Markets => commands::markets(ctx).await,
+        Command::Book { market, depth } => commands::book(ctx, &market, depth).await,
+        Command::Place {
+            market,
+            side,
+            amount,
+            price,
+            post_only,
+            reduce_only,
+            user_order_id,
+        } => {
+            let amount = if side == Side::Long { amount } else { -amount };
+            commands::place(
+                ctx,
+                &market,
+                amount,
+                price,
+                post_only,
+                reduce_only,
+                user_order_id,
+            )
+            .await
+        }
+        Command::Cancel { order_id, market } => {
+            commands::cancel(ctx, order_id, market.as_deref()).await
+        }
+        Command::Modify {
+            order_id,
+            price,
+            amount,
+        } => commands::modify(ctx, order_id, price, amount).await,
+        Command::Deposit { market, amount } => commands::deposit(ctx, &market, amount).await,
+        Command::Withdraw {
+            market,
+            amount,
+            reduce_only,
+        } => commands::withdraw(ctx, &market, amount, reduce_only).await,
+        Command::Events => commands::events(ctx).await,
+        Command::Decode { account } => commands::decode(ctx, &account).await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::{signature::Keypair, signer::Signer};
+
+    use super::*;
+
+    #[test]
+    fn wallet_from_args() {
+        let keypair = Keypair::new();
+        let key = keypair.to_base58_string();
+        let authority = Pubkey::new_unique().to_string();
+        let parse = |args: &[&str]| {
+            Cli::try_parse_from(["drift"].iter().chain(args).chain(&["user"])).unwrap()
+        };
+
+        let cli = parse(&["--keypair", &key]);
+        assert_eq!(wallet(&cli, false).unwrap().signer(), keypair.pubkey());
+
+        // explicit keypair is not silently dropped
+        let cli = parse(&["--keypair", &key, "--authority", &authority]);
+        assert!(wallet(&cli, false).is_err());
+        assert!(wallet(&cli, true).unwrap().is_read_only());
+
+        let cli = parse(&["--keypair", &key, "--authority", &authority, "--delegated"]);
+        let delegated = wallet(&cli, false).unwrap();
+        assert!(delegated.is_delegated());
+        assert_eq!(delegated.signer(), keypair.pubkey());
+
+        let cli = parse(&["--authority", &authority]);
+        assert!(wallet(&cli, false).unwrap().is_read_only());
+    }
+}
@@ -0,0 +1,118 @@
+//! Human-readable and JSON output
+
+use std::fmt::Display;
+
+use serde::Serialize;
+use serde_json::Value;
+
+/// Print `value` as pretty JSON or with its `Display` impl
+pub fn emit<T: Serialize + Display>(json: bool, value: &T) {
+    if json {
+        emit_json(value);
+    } else {
+        println!("{value}");
+    }
+}
+
+/// Print `value` as pretty JSON
+pub fn emit_json<T: Serialize>(value: &T) {
+    let value = humanize(serde_json::to_value(value).expect("serializes"));
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&value).expect("serializes")
+    );
+}
+
+/// Print `value` as a single line of JSON (for streams)
+pub fn emit_json_line<T: Serialize>(value: &T) {
+    let value = humanize(serde_json::to_value(value).expect("serializes"));
+    println!("{value}");
+}
+
+/// Convert a native `amount` with `decimals` precision to UI units
+pub fn ui_amount(amount: i128, decimals: u32) -> f64 {
+    amount as f64 / 10_f64.powi(decimals as i32)
+}
+
+/// Convert a UI `amount` to native units with `decimals` precision
+pub fn native_amount(amount: f64, decimals: u32) -> u64 {
+    (amount.abs() * 10_f64.powi(decimals as i32)).round() as u64
+}
+
+/// Make IDL types readable, serde encodes `Pubkey`s and fixed-size names as byte arrays
+///
+/// 32 byte arrays are printed as base58 pubkeys, except `name` fields which are utf8
+pub fn humanize(value: Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .map(|(k, v)| {
+                    let v = match as_bytes32(&v) {
+                        Some(bytes) if k == "name" => {
+                            Value::String(String::from_utf8_lossy(&bytes).trim_end().to_string())
+                        }
+                        _ => humanize(v),
+                    };
+                    (k, v)
+                })
+                .collect(),
+        ),
+        Value::Array(_) => match as_bytes32(&value) {
+            Some(bytes) => Value::String(bs58::encode(bytes).into_string()),
+            None => match value {
+                Value::Array(values) => Value::Array(values.into_iter().map(humanize).collect()),
+                other => other,
+            },
+        },
+        other => other,
+    }
+}
+
+fn as_bytes32(value: &Value) -> Option<Vec<u8>> {
+    let values = value.as_array()?;
+    if values.len() != 32 {
+        return None;
+    }
+    values
+        .iter()
+        .map(|v| v.as_u64().and_then(|b| u8::try_from(b).ok()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use drift_rs::Pubkey;
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn humanize_pubkeys_and_names() {
+        let pubkey = Pubkey::new_unique();
+        let mut name = [b' '; 32];
+        name[..8].copy_from_slice(b"SOL-PERP");
+        let value = json!({
+            "authority": pubkey,
+            "name": name,
+            "orders": [{ "owner": pubkey, "price": 1 }],
+            "small": [1, 2, 3],
+        });
+
+        assert_eq!(
+            humanize(value),
+            json!({
+                "authority": pubkey.to_string(),
+                "name": "SOL-PERP",
+                "orders": [{ "owner": pubkey.to_string(), "price": 1 }],
+                "small": [1, 2, 3],
+            })
+        );
+    }
+
+    #[test]
+    fn amount_conversion() {
+        assert_eq!(native_amount(1.5, 9), 1_500_000_000);
+        assert_eq!(native_amount(-0.25, 6), 250_000);
+        assert_eq!(ui_amount(-1_500_000, 6), -1.5);
+    }
+}
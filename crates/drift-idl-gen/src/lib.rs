@@ -529,12 +529,31 @@ fn generate_idl_types(idl: &Idl) -> String {
         }
     });
 
+    // IDL error codes include anchor's `ERROR_CODE_OFFSET` (6000)
+    let error_code_arms = idl.errors.iter().map(|error| {
+        let variant_name = Ident::new(&error.name, proc_macro2::Span::call_site());
+        let code = proc_macro2::Literal::u32_unsuffixed(error.code);
+        quote! {
+            #code => Ok(Self::#variant_name),
+        }
+    });
+
     let error_enum = quote! {
         #[derive(PartialEq)]
         #[error_code]
         pub enum ErrorCode {
             #(#error_variants)*
         }
+        impl TryFrom<u32> for ErrorCode {
+            type Error = u32;
+            #[doc = r" inverse of anchor's `From<ErrorCode> for u32`, returns the code if unknown"]
+            fn try_from(code: u32) -> std::result::Result<Self, u32> {
+                match code {
+                    #(#error_code_arms)*
+                    _ => Err(code),
+                }
+            }
+        }
     };
 
     errors_tokens = quote! {
@@ -1,4 +1,8 @@
-use std::{collections::BTreeMap, time::Duration};
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
 
 use futures_util::{
     future::{ready, BoxFuture, FutureExt},
@@ -26,7 +30,7 @@ use thiserror::Error;
 use tokio::{
     sync::{
         mpsc::{self, UnboundedSender},
-        oneshot,
+        oneshot, watch,
     },
     task::JoinHandle,
 };
@@ -40,6 +44,10 @@ use tokio_tungstenite::{
 };
 use url::Url;
 
+use crate::retry_policy::TaskRetryPolicy;
+
+pub mod retry_policy;
+
 pub type PubsubClientResult<T = ()> = Result<T, PubsubClientError>;
 
 #[derive(Debug, Error)]
@@ -95,6 +103,19 @@ struct SubscriptionInfo {
     payload: String,
 }
 
+/// Ws connection status of a `PubsubClient`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ConnectionStatus {
+    /// initial connection in progress
+    Connecting,
+    /// connected, subscriptions are live
+    Connected,
+    /// connection lost, reconnecting. updates may be missed until `Connected`
+    Reconnecting,
+    /// reconnect attempts exhausted, subscriptions are closed
+    Failed,
+}
+
 /// A client for subscribing to messages from the RPC server.
 ///
 /// See the [module documentation][self].
@@ -104,20 +125,50 @@ pub struct PubsubClient {
     _request_sender: mpsc::UnboundedSender<RequestMsg>,
     shutdown_sender: oneshot::Sender<()>,
     ws: JoinHandle<Result<(), PubsubClientError>>,
-    url: Url,
+    /// URL of the current Ws connection
+    url: Arc<RwLock<Url>>,
+    status: watch::Receiver<ConnectionStatus>,
 }
 
 impl PubsubClient {
     pub async fn new(url: &str) -> PubsubClientResult<Self> {
-        let url = Url::parse(url)?;
+        Self::new_with_failover(&[url]).await
+    }
+
+    /// Create a new `PubsubClient` connecting to the first of `urls`
+    ///
+    /// On disconnect or stall the client reconnects to the next URL (round-robin) and restores all subscriptions
+    pub async fn new_with_failover(urls: &[&str]) -> PubsubClientResult<Self> {
+        Self::new_with_retry_policy(urls, retry_policy::exponential_backoff(3)).await
+    }
+
+    /// Create a new `PubsubClient` connecting to the first of `urls`
+    ///
+    /// * `retry_policy` - decides whether to retry after a failed connection attempt,
+    ///   once exhausted the client status is `Failed` and all subscriptions are closed
+    pub async fn new_with_retry_policy(
+        urls: &[&str],
+        retry_policy: impl TaskRetryPolicy,
+    ) -> PubsubClientResult<Self> {
+        let urls = urls
+            .iter()
+            .map(|url| Url::parse(url))
+            .collect::<Result<Vec<Url>, _>>()?;
+        let url = Arc::new(RwLock::new(urls.first().cloned().ok_or_else(|| {
+            PubsubClientError::RequestError("no Ws URL provided".into())
+        })?));
 
         let (subscribe_sender, subscribe_receiver) = mpsc::unbounded_channel();
         let (_request_sender, request_receiver) = mpsc::unbounded_channel();
         let (shutdown_sender, shutdown_receiver) = oneshot::channel();
+        let (status_sender, status) = watch::channel(ConnectionStatus::Connecting);
 
         // spawn Ws manager task
         let ws_handle = tokio::spawn(PubsubClient::run_ws(
-            url.clone(),
+            urls,
+            Arc::clone(&url),
+            Box::new(retry_policy),
+            status_sender,
             subscribe_receiver,
             request_receiver,
             shutdown_receiver,
@@ -130,12 +181,20 @@ impl PubsubClient {
             shutdown_sender,
             ws: ws_handle,
             url,
+            status,
         })
     }
 
+    /// Returns a channel of Ws connection status updates
+    ///
+    /// e.g. consumers may resync state via RPC after `Reconnecting` => `Connected`
+    pub fn status(&self) -> watch::Receiver<ConnectionStatus> {
+        self.status.clone()
+    }
+
     /// Returns the URL of the underlying Ws
     pub fn url(&self) -> Url {
-        self.url.clone()
+        self.url.read().expect("acquired").clone()
     }
 
     /// Returns true if the underlying Ws connection task is running
@@ -332,14 +391,16 @@ impl PubsubClient {
     }
 
     async fn run_ws(
-        url: Url,
+        urls: Vec<Url>,
+        current_url: Arc<RwLock<Url>>,
+        mut retry_policy: Box<dyn TaskRetryPolicy>,
+        status: watch::Sender<ConnectionStatus>,
         mut subscribe_receiver: mpsc::UnboundedReceiver<SubscribeRequestMsg>,
         mut request_receiver: mpsc::UnboundedReceiver<RequestMsg>,
         mut shutdown_receiver: oneshot::Receiver<()>,
     ) -> PubsubClientResult {
         // manage Ws requests and forward subscription messages to subscribers
-        // this loop will retry indefinitely unless the consumer invokes `shutdown` or successive failures exceed maximum
-        let max_retry_count = 3;
+        // this loop will retry until the consumer invokes `shutdown` or `retry_policy` gives up
         let mut retry_count = 0;
 
         // all existing subscriptions here
@@ -347,33 +408,38 @@ impl PubsubClient {
         let mut subscriptions = BTreeMap::<u64, SubscriptionInfo>::new();
         let mut request_id_to_sid = BTreeMap::<u64, u64>::new();
         let (unsubscribe_sender, mut unsubscribe_receiver) = mpsc::unbounded_channel();
+        let mut connect_attempts: usize = 0;
 
         'reconnect: loop {
+            // failover to the next URL on each (re)connect
+            let url = urls[connect_attempts % urls.len()].clone();
+            connect_attempts += 1;
+            *current_url.write().expect("acquired") = url.clone();
             log::debug!(target: "ws", "PubsubClient connecting: {:?}", url.as_str());
-            let mut ws = match connect_async(url.as_str()).await {
+            let connection = match connect_async(url.as_str()).await {
                 Ok((ws, response)) => {
                     if response.status().is_server_error() || response.status().is_client_error() {
-                        log::warn!(target: "ws", "couldn't reconnect: {response:?}");
-                        retry_count += 1;
-                        let delay = 2_u64.pow(2 + retry_count);
-                        info!(target: "ws", "PubsubClient trying reconnect after {delay}s, attempt: {retry_count}/{max_retry_count}");
-                        tokio::time::sleep(Duration::from_secs(delay)).await;
-                        continue 'reconnect;
+                        Err(format!("{response:?}"))
+                    } else {
+                        Ok(ws)
                     }
-
+                }
+                Err(err) => Err(format!("{err:?}")),
+            };
+            let mut ws = match connection {
+                Ok(ws) => {
                     retry_count = 0;
                     ws
                 }
                 Err(err) => {
-                    log::warn!(target: "ws", "couldn't reconnect: {err:?}");
-                    if retry_count >= max_retry_count {
-                        log::error!(target: "ws", "reached max reconnect attempts: {err:?}");
-                        panic!("PubsubCliwnt reached max reconnect attempts: {err:?}");
+                    log::warn!(target: "ws", "couldn't reconnect: {err}");
+                    if !retry_policy.check(retry_count).await {
+                        log::error!(target: "ws", "reached max reconnect attempts: {err}");
+                        let _ = status.send(ConnectionStatus::Failed);
+                        break 'reconnect Err(PubsubClientError::ConnectionClosed(err));
                     }
                     retry_count += 1;
-                    let delay = 2_u64.pow(2 + retry_count);
-                    info!(target: "ws", "PubsubClient trying reconnect after {delay}s, attempt: {retry_count}/{max_retry_count}");
-                    tokio::time::sleep(Duration::from_secs(delay)).await;
+                    info!(target: "ws", "PubsubClient trying reconnect, attempt: {retry_count}");
                     continue 'reconnect;
                 }
             };
@@ -396,10 +462,13 @@ impl PubsubClient {
                     .await
                 {
                     error!(target: "ws", "PubsubClient failed resubscribing: {err:?}");
+                    let _ = status.send(ConnectionStatus::Reconnecting);
                     continue 'reconnect;
                 }
             }
 
+            let _ = status.send(ConnectionStatus::Connected);
+
             let mut liveness_check = tokio::time::interval(Duration::from_secs(60));
             let _ = liveness_check.tick().await;
 
@@ -610,7 +679,10 @@ impl PubsubClient {
                         inflight_requests.insert(request_id, response_sender);
                     },
                     _ = heartbeat.tick() => {
-                        ws.send(Message::Ping(Default::default())).await?;
+                        if let Err(err) = ws.send(Message::Ping(Default::default())).await {
+                            log::warn!(target: "ws", "PubsubClient heartbeat failed: {err:?}");
+                            break 'manager;
+                        }
                     },
                     _ = liveness_check.tick() => {
                         warn!(target: "ws", "PubsubClient timed out");
@@ -619,6 +691,7 @@ impl PubsubClient {
                 }
             }
             log::debug!(target: "ws", "manager finished");
+            let _ = status.send(ConnectionStatus::Reconnecting);
         }
     }
 }
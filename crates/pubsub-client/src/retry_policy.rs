@@ -0,0 +1,92 @@
+//! retry policies for async tasks
+use std::{collections::hash_map::RandomState, hash::BuildHasher, time::Duration};
+
+use futures_util::future::{ready, BoxFuture, FutureExt};
+
+/// Defines whether an async task should be retried or not
+pub trait TaskRetryPolicy: Send + Sync + 'static {
+    /// called pre-retry, returns whether retry should proceed or not
+    fn check(&mut self, _attempts: u32) -> BoxFuture<'_, bool>;
+}
+/// Create a new fail fast policy
+pub fn never() -> FailFast {
+    FailFast {}
+}
+
+/// Create a new exponential backoff policy
+pub fn exponential_backoff(max_attempts: u32) -> ExponentialBackoff {
+    ExponentialBackoff { max_attempts }
+}
+
+/// Create a new never ending retry policy
+pub fn forever(delay_s: u32) -> InfiniteRetry {
+    InfiniteRetry { delay_s }
+}
+
+/// Add a random delay of up to `max_jitter` before each retry of `policy`
+pub fn with_jitter<P: TaskRetryPolicy>(policy: P, max_jitter: Duration) -> Jitter<P> {
+    Jitter { policy, max_jitter }
+}
+
+/// TaskFails on first retry
+pub struct FailFast;
+
+impl TaskRetryPolicy for FailFast {
+    fn check(&mut self, _attempts: u32) -> BoxFuture<'_, bool> {
+        ready(false).boxed()
+    }
+}
+
+/// Exponential back-off policy up to `max_attempts`
+pub struct ExponentialBackoff {
+    max_attempts: u32,
+}
+
+impl TaskRetryPolicy for ExponentialBackoff {
+    fn check(&mut self, attempts: u32) -> BoxFuture<'_, bool> {
+        async move {
+            if attempts > self.max_attempts {
+                false
+            } else {
+                tokio::time::sleep(Duration::from_secs(2_u64.pow(attempts))).await;
+                true
+            }
+        }
+        .boxed()
+    }
+}
+
+/// A policy that retries a task indefinitely, with constant delay between successive retries
+pub struct InfiniteRetry {
+    delay_s: u32,
+}
+
+impl TaskRetryPolicy for InfiniteRetry {
+    fn check(&mut self, _attempts: u32) -> BoxFuture<'_, bool> {
+        async move {
+            tokio::time::sleep(Duration::from_secs(self.delay_s as u64)).await;
+            true
+        }
+        .boxed()
+    }
+}
+
+/// Adds a random delay to another policy e.g. to avoid many clients retrying in lockstep
+pub struct Jitter<P> {
+    policy: P,
+    max_jitter: Duration,
+}
+
+impl<P: TaskRetryPolicy> TaskRetryPolicy for Jitter<P> {
+    fn check(&mut self, attempts: u32) -> BoxFuture<'_, bool> {
+        async move {
+            let max_jitter_ms = self.max_jitter.as_millis() as u64;
+            if max_jitter_ms > 0 {
+                let jitter_ms = RandomState::new().hash_one(attempts) % max_jitter_ms;
+                tokio::time::sleep(Duration::from_millis(jitter_ms)).await;
+            }
+            self.policy.check(attempts).await
+        }
+        .boxed()
+    }
+}
@@ -231,6 +231,39 @@ impl AccountMap {
         })
     }
 
+    /// Resync Ws subscribed accounts via RPC e.g. to fill updates missed during a Ws reconnect
+    ///
+    /// Accounts with newer data are not overwritten. NB: account callbacks are not invoked
+    pub async fn resync(&self) -> SdkResult<()> {
+        let accounts: Vec<Pubkey> = self
+            .subscriptions
+            .iter()
+            .filter(|s| matches!(s.subscription, SubscriptionImpl::Ws(_)))
+            .map(|s| *s.key())
+            .collect();
+
+        for chunk in accounts.chunks(100) {
+            let response = self
+                .rpc
+                .get_multiple_accounts_with_commitment(chunk, self.commitment)
+                .await?;
+            let slot = response.context.slot;
+            for (pubkey, account) in chunk.iter().zip(response.value) {
+                let Some(account) = account else {
+                    continue;
+                };
+                if let Some(mut entry) = self.inner.get_mut(pubkey) {
+                    if entry.slot < slot {
+                        entry.slot = slot;
+                        entry.raw = Arc::from(account.data);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     pub async fn sync_stats_accounts(&self) -> SdkResult<()> {
         // TODO: rust sdk does not surface with_context slot on GPA
         let slot = self
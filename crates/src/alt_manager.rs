@@ -0,0 +1,415 @@
+//!
+//! Address lookup table management
+//!
+//! Create and maintain personal LUTs holding frequently used drift accounts (sub-accounts, markets, oracles, etc.)
+//! to supplement the protocol LUTs (`Context::luts`)
+//!
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use dashmap::DashMap;
+use solana_rpc_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{
+    account::Account,
+    address_lookup_table::{
+        instruction::{create_lookup_table, extend_lookup_table},
+        AddressLookupTableAccount,
+    },
+    clock::Slot,
+    commitment_config::CommitmentConfig,
+    instruction::Instruction,
+    message::{Message, VersionedMessage},
+    pubkey::Pubkey,
+};
+
+use crate::{
+    constants::ProgramData,
+    types::{MarketId, MarketType, SdkError, SdkResult},
+    utils::deserialize_alt,
+    Wallet,
+};
+
+/// Max. number of addresses in a LUT
+pub const MAX_LUT_ADDRESSES: usize = 256;
+/// Max. number of addresses per extend ix (limited by tx size)
+const EXTEND_CHUNK_SIZE: usize = 20;
+/// Interval between slot polls while waiting for LUT activation
+const ACTIVATION_POLL_INTERVAL: Duration = Duration::from_millis(400);
+
+/// Returns the slot the LUT was last extended in, addresses are usable from the following slot
+fn last_extended_slot(account: &Account) -> SdkResult<Slot> {
+    // bincode `ProgramState::LookupTable(LookupTableMeta)`
+    // u32 tag | u64 deactivation_slot | u64 last_extended_slot | ..
+    let data = account.data.as_slice();
+    match data.get(..4) {
+        Some([1, 0, 0, 0]) => (),
+        _ => return Err(SdkError::InvalidAccount),
+    }
+    data.get(12..20)
+        .map(|b| Slot::from_le_bytes(b.try_into().unwrap()))
+        .ok_or(SdkError::InvalidAccount)
+}
+
+/// Creates, extends, and caches address lookup tables owned by `wallet`
+pub struct AltManager {
+    rpc_client: Arc<RpcClient>,
+    wallet: Wallet,
+    /// cached LUTs by address
+    tables: DashMap<Pubkey, AddressLookupTableAccount, ahash::RandomState>,
+    /// observed account usage counts
+    usage: DashMap<Pubkey, u64, ahash::RandomState>,
+}
+
+impl AltManager {
+    /// Create a new `AltManager`
+    ///
+    /// * `rpc_client` - RPC client for fetching LUTs and sending txs
+    /// * `wallet` - LUT authority and fee payer
+    pub fn new(rpc_client: Arc<RpcClient>, wallet: Wallet) -> Self {
+        Self {
+            rpc_client,
+            wallet,
+            tables: Default::default(),
+            usage: Default::default(),
+        }
+    }
+
+    /// Returns the drift accounts commonly used by `authority`
+    ///
+    /// i.e. sub-accounts, stats account, swift account, and the markets with their oracles
+    ///
+    /// * `sub_account_ids` - sub-account ids of `authority`
+    /// * `markets` - frequently used markets
+    pub fn drift_accounts(
+        program_data: &ProgramData,
+        authority: &Pubkey,
+        sub_account_ids: &[u16],
+        markets: &[MarketId],
+    ) -> Vec<Pubkey> {
+        let mut accounts: Vec<Pubkey> = sub_account_ids
+            .iter()
+            .map(|id| Wallet::derive_user_account(authority, *id))
+            .collect();
+        accounts.push(Wallet::derive_stats_account(authority));
+        accounts.push(Wallet::derive_swift_order_account(authority));
+
+        for market in markets {
+            let market_and_oracle = match market.kind() {
+                MarketType::Spot => program_data
+                    .spot_market_config_by_index(market.index())
+                    .map(|m| (m.pubkey, m.oracle)),
+                MarketType::Perp => program_data
+                    .perp_market_config_by_index(market.index())
+                    .map(|m| (m.pubkey, m.amm.oracle)),
+            };
+            if let Some((market, oracle)) = market_and_oracle {
+                accounts.push(market);
+                accounts.push(oracle);
+            }
+        }
+
+        new_addresses(&[], &accounts)
+    }
+
+    /// Create a new LUT holding `addresses`
+    ///
+    /// The LUT is usable after activation, see `wait_for_activation`
+    ///
+    /// Returns the LUT address
+    pub async fn create(&self, addresses: &[Pubkey]) -> SdkResult<Pubkey> {
+        let addresses = new_addresses(&[], addresses);
+        if addresses.len() > MAX_LUT_ADDRESSES {
+            return Err(SdkError::Generic(format!(
+                "too many LUT addresses: {}",
+                addresses.len()
+            )));
+        }
+        let authority = self.wallet.signer();
+        // the recent slot must be present in the slot hashes sysvar
+        let recent_slot = self
+            .rpc_client
+            .get_slot_with_commitment(CommitmentConfig::finalized())
+            .await?;
+        let (create_ix, lut) = create_lookup_table(authority, authority, recent_slot);
+
+        let mut chunks = addresses.chunks(EXTEND_CHUNK_SIZE);
+        let mut ixs = vec![create_ix];
+        if let Some(chunk) = chunks.next() {
+            ixs.push(extend_lookup_table(
+                lut,
+                authority,
+                Some(authority),
+                chunk.to_vec(),
+            ));
+        }
+        self.send_ixs(&ixs).await?;
+
+        for chunk in chunks {
+            self.send_ixs(&[extend_lookup_table(
+                lut,
+                authority,
+                Some(authority),
+                chunk.to_vec(),
+            )])
+            .await?;
+        }
+
+        Ok(lut)
+    }
+
+    /// Extend `lut` with `addresses`, addresses already in the LUT are skipped
+    ///
+    /// The new addresses are usable after activation, see `wait_for_activation`
+    pub async fn extend(&self, lut: &Pubkey, addresses: &[Pubkey]) -> SdkResult<()> {
+        let table = self.fetch(lut).await?;
+        let new_addresses = new_addresses(&table.addresses, addresses);
+        if table.addresses.len() + new_addresses.len() > MAX_LUT_ADDRESSES {
+            return Err(SdkError::Generic(format!("LUT is full: {lut}")));
+        }
+
+        let authority = self.wallet.signer();
+        for chunk in new_addresses.chunks(EXTEND_CHUNK_SIZE) {
+            self.send_ixs(&[extend_lookup_table(
+                *lut,
+                authority,
+                Some(authority),
+                chunk.to_vec(),
+            )])
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Wait until all addresses of `lut` are usable, then cache it
+    ///
+    /// * `timeout` - max. time to wait
+    pub async fn wait_for_activation(
+        &self,
+        lut: &Pubkey,
+        timeout: Duration,
+    ) -> SdkResult<AddressLookupTableAccount> {
+        let account = self.rpc_client.get_account(lut).await?;
+        let last_extended_slot = last_extended_slot(&account)?;
+        let started = Instant::now();
+        loop {
+            let slot = self.rpc_client.get_slot().await?;
+            if slot > last_extended_slot {
+                break;
+            }
+            if started.elapsed() >= timeout {
+                return Err(SdkError::Generic(format!(
+                    "LUT activation timed out: {lut}"
+                )));
+            }
+            tokio::time::sleep(ACTIVATION_POLL_INTERVAL).await;
+        }
+
+        let table = deserialize_alt(*lut, &account)?;
+        self.tables.insert(*lut, table.clone());
+
+        Ok(table)
+    }
+
+    /// Fetch `lut` from the network and cache it
+    pub async fn fetch(&self, lut: &Pubkey) -> SdkResult<AddressLookupTableAccount> {
+        let account = self.rpc_client.get_account(lut).await?;
+        let table = deserialize_alt(*lut, &account)?;
+        self.tables.insert(*lut, table.clone());
+
+        Ok(table)
+    }
+
+    /// Get a cached LUT
+    pub fn get(&self, lut: &Pubkey) -> Option<AddressLookupTableAccount> {
+        self.tables.get(lut).map(|t| t.clone())
+    }
+
+    /// Returns all cached LUTs
+    ///
+    /// suitable for `TransactionBuilder::lookup_tables`
+    pub fn lookup_tables(&self) -> Vec<AddressLookupTableAccount> {
+        self.tables.iter().map(|t| t.value().clone()).collect()
+    }
+
+    /// Record the accounts used by a tx message (e.g. from `TransactionBuilder::build`)
+    ///
+    /// Only accounts loadable from a LUT are counted i.e. signers and invoked programs are ignored
+    pub fn observe(&self, message: &VersionedMessage) {
+        let header = message.header();
+        let keys = message.static_account_keys();
+        for (idx, key) in keys.iter().enumerate() {
+            let is_signer = idx < header.num_required_signatures as usize;
+            let is_program = message
+                .instructions()
+                .iter()
+                .any(|ix| ix.program_id_index as usize == idx);
+            if !is_signer && !is_program {
+                *self.usage.entry(*key).or_default() += 1;
+            }
+        }
+    }
+
+    /// Suggest LUT addresses from observed account usage, most used first
+    ///
+    /// * `min_uses` - min. number of observations to include an account
+    /// * `exclude` - LUTs whose addresses should not be suggested e.g. the protocol LUTs
+    pub fn suggest(&self, min_uses: u64, exclude: &[AddressLookupTableAccount]) -> Vec<Pubkey> {
+        let mut candidates: Vec<(Pubkey, u64)> = self
+            .usage
+            .iter()
+            .filter(|entry| *entry.value() >= min_uses)
+            .filter(|entry| !exclude.iter().any(|t| t.addresses.contains(entry.key())))
+            .map(|entry| (*entry.key(), *entry.value()))
+            .collect();
+        candidates.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+        candidates
+            .into_iter()
+            .take(MAX_LUT_ADDRESSES)
+            .map(|(key, _)| key)
+            .collect()
+    }
+
+    /// Sign and send `ixs` with the manager wallet, waits for confirmation
+    async fn send_ixs(&self, ixs: &[Instruction]) -> SdkResult<()> {
+        let message = VersionedMessage::Legacy(Message::new(ixs, Some(&self.wallet.signer())));
+        let recent_blockhash = self.rpc_client.get_latest_blockhash().await?;
+        let tx = self.wallet.sign_tx(message, recent_blockhash)?;
+        self.rpc_client.send_and_confirm_transaction(&tx).await?;
+
+        Ok(())
+    }
+}
+
+/// Returns `addresses` without duplicates or any already in `existing`, preserving order
+fn new_addresses(existing: &[Pubkey], addresses: &[Pubkey]) -> Vec<Pubkey> {
+    let mut new_addresses = Vec::with_capacity(addresses.len());
+    for address in addresses {
+        if !existing.contains(address) && !new_addresses.contains(address) {
+            new_addresses.push(*address);
+        }
+    }
+
+    new_addresses
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::Engine;
+    use serde_json::json;
+    use solana_rpc_client::rpc_client::Mocks;
+    use solana_rpc_client_api::request::RpcRequest;
+    use solana_sdk::{address_lookup_table::program, hash::Hash, message::v0, signature::Keypair};
+
+    use super::*;
+
+    fn lut_account(last_extended_slot: Slot, addresses: &[Pubkey]) -> Account {
+        let mut data = vec![0_u8; 56];
+        data[..4].copy_from_slice(&1_u32.to_le_bytes());
+        data[4..12].copy_from_slice(&u64::MAX.to_le_bytes());
+        data[12..20].copy_from_slice(&last_extended_slot.to_le_bytes());
+        for address in addresses {
+            data.extend_from_slice(address.as_ref());
+        }
+        Account {
+            lamports: 1_000_000,
+            data,
+            owner: program::id(),
+            ..Default::default()
+        }
+    }
+
+    fn alt_manager(mocks: Mocks) -> AltManager {
+        let rpc = RpcClient::new_mock_with_mocks("succeeds".into(), mocks);
+        AltManager::new(Arc::new(rpc), Wallet::from(Keypair::new()))
+    }
+
+    #[test]
+    fn alt_manager_suggest() {
+        let manager = alt_manager(Mocks::default());
+        let payer = Pubkey::new_unique();
+        let hot = Pubkey::new_unique();
+        let cold = Pubkey::new_unique();
+        let in_lut = Pubkey::new_unique();
+        let program_id = Pubkey::new_unique();
+
+        let ix = |accounts: &[Pubkey]| {
+            Instruction::new_with_bytes(
+                program_id,
+                &[],
+                accounts
+                    .iter()
+                    .map(|a| solana_sdk::instruction::AccountMeta::new(*a, false))
+                    .collect(),
+            )
+        };
+        for _ in 0..3 {
+            let message =
+                v0::Message::try_compile(&payer, &[ix(&[hot, in_lut])], &[], Hash::default())
+                    .unwrap();
+            manager.observe(&VersionedMessage::V0(message));
+        }
+        manager.observe(&VersionedMessage::Legacy(Message::new(
+            &[ix(&[cold, hot])],
+            Some(&payer),
+        )));
+
+        let protocol_lut = AddressLookupTableAccount {
+            key: Pubkey::new_unique(),
+            addresses: vec![in_lut],
+        };
+        assert_eq!(manager.suggest(1, &[protocol_lut.clone()]), vec![hot, cold]);
+        assert_eq!(manager.suggest(2, &[protocol_lut]), vec![hot]);
+        assert_eq!(manager.suggest(4, &[]), vec![hot]);
+        assert!(manager.suggest(5, &[]).is_empty());
+    }
+
+    #[tokio::test]
+    async fn alt_manager_wait_for_activation() {
+        let lut = Pubkey::new_unique();
+        let addresses = [Pubkey::new_unique(), Pubkey::new_unique()];
+        let account = lut_account(100, &addresses);
+
+        let mut mocks = Mocks::default();
+        mocks.insert(
+            RpcRequest::GetAccountInfo,
+            json!({
+                "context": { "slot": 101 },
+                "value": {
+                    "data": [base64::prelude::BASE64_STANDARD.encode(&account.data), "base64"],
+                    "executable": false,
+                    "lamports": account.lamports,
+                    "owner": account.owner.to_string(),
+                    "rentEpoch": 0,
+                    "space": account.data.len(),
+                },
+            }),
+        );
+        mocks.insert(RpcRequest::GetSlot, json!(101));
+        let manager = alt_manager(mocks);
+
+        let table = manager
+            .wait_for_activation(&lut, Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert_eq!(table.addresses, addresses.to_vec());
+        assert_eq!(manager.get(&lut).unwrap().addresses, addresses.to_vec());
+        assert_eq!(manager.lookup_tables().len(), 1);
+    }
+
+    #[test]
+    fn lut_new_addresses_deduped() {
+        let [a, b, c] = [(); 3].map(|_| Pubkey::new_unique());
+        assert_eq!(new_addresses(&[], &[a, b, a, c, b]), vec![a, b, c]);
+        assert_eq!(new_addresses(&[b], &[a, b, a, c]), vec![a, c]);
+    }
+
+    #[test]
+    fn lut_last_extended_slot() {
+        assert_eq!(last_extended_slot(&lut_account(1234, &[])).unwrap(), 1234);
+        assert!(last_extended_slot(&Account::default()).is_err());
+    }
+}
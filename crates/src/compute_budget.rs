@@ -0,0 +1,237 @@
+//!
+//! Automatic compute budget estimation
+//!
+//! Txs built with `TransactionBuilder::auto_compute_budget` include placeholder compute budget ixs
+//! which are resolved at send time by simulation (CU limit) and recent priority fees (CU price)
+//!
+//! Auto mode is flagged by a marker ix rather than the placeholder values so explicitly set budgets
+//! are never rewritten. The marker is removed on resolution
+//!
+use std::sync::Arc;
+
+use solana_sdk::{
+    compute_budget::{self, ComputeBudgetInstruction},
+    instruction::Instruction,
+    message::VersionedMessage,
+};
+
+use crate::{
+    priority_fee_strategy::PriorityFeeStrategy, priority_fee_subscriber::PriorityFeeSubscriber,
+};
+
+/// Max. CU limit of a tx
+pub const MAX_COMPUTE_UNIT_LIMIT: u32 = 1_400_000;
+/// Max. loaded accounts data size of a tx (also the runtime default)
+const MAX_LOADED_ACCOUNTS_DATA_SIZE_BYTES: u32 = 64 * 1024 * 1024;
+
+/// Config for automatic compute budget estimation
+#[derive(Clone)]
+pub struct ComputeBudgetConfig {
+    /// CU limit margin over the simulated CUs consumed e.g. 0.1 = +10%
+    pub cu_margin: f32,
+    /// lower bound of the CU limit
+    pub min_cu_limit: u32,
+    /// source of recent priority fees, `default_microlamports_per_cu` is used if unset
+    pub priority_fee_subscriber: Option<Arc<PriorityFeeSubscriber>>,
+    /// percentile of recent priority fees to pay, given as decimal 0.0 < n <= 1.0
+    pub priority_fee_percentile: f32,
+    /// CU price strategy, takes precedence over `priority_fee_subscriber` if set
+    pub priority_fee_strategy: Option<Arc<dyn PriorityFeeStrategy>>,
+    /// CU price used when priority fees are unavailable
+    pub default_microlamports_per_cu: u64,
+    /// upper bound of the CU price
+    pub max_microlamports_per_cu: u64,
+}
+
+impl Default for ComputeBudgetConfig {
+    fn default() -> Self {
+        Self {
+            cu_margin: 0.1,
+            min_cu_limit: 5_000,
+            priority_fee_subscriber: None,
+            priority_fee_percentile: 0.5,
+            priority_fee_strategy: None,
+            default_microlamports_per_cu: 0,
+            max_microlamports_per_cu: u64::MAX,
+        }
+    }
+}
+
+impl ComputeBudgetConfig {
+    /// Returns the CU limit given simulated `units_consumed`
+    pub fn cu_limit(&self, units_consumed: u64) -> u32 {
+        let cu_limit = units_consumed + (units_consumed as f32 * self.cu_margin) as u64;
+        (cu_limit.min(MAX_COMPUTE_UNIT_LIMIT as u64) as u32).max(self.min_cu_limit)
+    }
+
+    /// Returns the CU price in µ-lamports
+    pub fn microlamports_per_cu(&self) -> u64 {
+        let fee = match self.priority_fee_strategy {
+            Some(ref strategy) => strategy.microlamports_per_cu(),
+            None => self
+                .priority_fee_subscriber
+                .as_ref()
+                .and_then(|s| s.priority_fee_nth_safe(self.priority_fee_percentile)),
+        };
+        fee.unwrap_or(self.default_microlamports_per_cu)
+            .min(self.max_microlamports_per_cu)
+    }
+}
+
+/// Marks a tx for compute budget resolution at send time
+///
+/// requests the default loaded accounts data size so it is a no-op if left unresolved
+fn auto_compute_budget_marker_ix() -> Instruction {
+    ComputeBudgetInstruction::set_loaded_accounts_data_size_limit(
+        MAX_LOADED_ACCOUNTS_DATA_SIZE_BYTES,
+    )
+}
+
+/// Placeholder compute budget ixs, marks a tx for compute budget resolution at send time
+///
+/// Unresolved, the tx requests the max. CU limit at zero priority fee
+pub fn auto_compute_budget_ixs() -> [Instruction; 3] {
+    [
+        ComputeBudgetInstruction::set_compute_unit_price(0),
+        ComputeBudgetInstruction::set_compute_unit_limit(MAX_COMPUTE_UNIT_LIMIT),
+        auto_compute_budget_marker_ix(),
+    ]
+}
+
+/// Returns true if `message` is marked for compute budget resolution (see `auto_compute_budget_ixs`)
+pub fn is_auto_compute_budget(message: &VersionedMessage) -> bool {
+    let keys = message.static_account_keys();
+    let marker = auto_compute_budget_marker_ix();
+    message.instructions().iter().any(|ix| {
+        keys.get(ix.program_id_index as usize) == Some(&compute_budget::id())
+            && ix.data == marker.data
+    })
+}
+
+/// Rewrite the compute budget ixs of `message` with the given CU limit and price,
+/// removing the auto compute budget marker
+///
+/// Returns false if `message` has no compute budget ixs
+pub fn set_compute_budget(
+    message: &mut VersionedMessage,
+    cu_limit: u32,
+    microlamports_per_cu: u64,
+) -> bool {
+    let cu_limit_ix = ComputeBudgetInstruction::set_compute_unit_limit(cu_limit);
+    let cu_price_ix = ComputeBudgetInstruction::set_compute_unit_price(microlamports_per_cu);
+    let program_id_index = match message
+        .static_account_keys()
+        .iter()
+        .position(|k| k == &compute_budget::id())
+    {
+        Some(idx) => idx as u8,
+        None => return false,
+    };
+
+    let ixs = match message {
+        VersionedMessage::Legacy(m) => &mut m.instructions,
+        VersionedMessage::V0(m) => &mut m.instructions,
+    };
+    let marker = auto_compute_budget_marker_ix();
+    ixs.retain(|ix| !(ix.program_id_index == program_id_index && ix.data == marker.data));
+    let mut updated = false;
+    for ix in ixs
+        .iter_mut()
+        .filter(|ix| ix.program_id_index == program_id_index)
+    {
+        // ix tags, see `ComputeBudgetInstruction`
+        match ix.data.first() {
+            Some(2) => ix.data.clone_from(&cu_limit_ix.data),
+            Some(3) => ix.data.clone_from(&cu_price_ix.data),
+            _ => continue,
+        }
+        updated = true;
+    }
+
+    updated
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::{
+        hash::Hash,
+        message::{v0, Message},
+        pubkey::Pubkey,
+        system_instruction,
+    };
+
+    use super::*;
+    use crate::priority_fee_strategy::Fixed;
+
+    fn messages(payer: &Pubkey, ixs: &[Instruction]) -> [VersionedMessage; 2] {
+        [
+            VersionedMessage::Legacy(Message::new(ixs, Some(payer))),
+            VersionedMessage::V0(
+                v0::Message::try_compile(payer, ixs, &[], Hash::default()).unwrap(),
+            ),
+        ]
+    }
+
+    #[test]
+    fn auto_compute_budget_resolves() {
+        let payer = Pubkey::new_unique();
+        let transfer = system_instruction::transfer(&payer, &Pubkey::new_unique(), 1);
+        let ixs: Vec<Instruction> = auto_compute_budget_ixs()
+            .into_iter()
+            .chain(std::iter::once(transfer.clone()))
+            .collect();
+
+        for mut message in messages(&payer, &ixs) {
+            assert!(is_auto_compute_budget(&message));
+            assert!(set_compute_budget(&mut message, 50_000, 1_234));
+            assert!(!is_auto_compute_budget(&message));
+
+            let expected = messages(
+                &payer,
+                &[
+                    ComputeBudgetInstruction::set_compute_unit_price(1_234),
+                    ComputeBudgetInstruction::set_compute_unit_limit(50_000),
+                    transfer.clone(),
+                ],
+            );
+            assert!(expected.contains(&message));
+        }
+
+        for mut message in messages(&payer, &[transfer.clone()]) {
+            assert!(!is_auto_compute_budget(&message));
+            assert!(!set_compute_budget(&mut message, 50_000, 1_234));
+        }
+
+        // explicit budget with the placeholder values is left as is
+        let explicit = [
+            ComputeBudgetInstruction::set_compute_unit_price(0),
+            ComputeBudgetInstruction::set_compute_unit_limit(MAX_COMPUTE_UNIT_LIMIT),
+            transfer,
+        ];
+        for message in messages(&payer, &explicit) {
+            assert!(!is_auto_compute_budget(&message));
+        }
+    }
+
+    #[test]
+    fn compute_budget_config_cu_limit() {
+        let config = ComputeBudgetConfig::default();
+        assert_eq!(config.cu_limit(100_000), 110_000);
+        assert_eq!(config.cu_limit(1_000), 5_000);
+        assert_eq!(config.cu_limit(1_390_000), MAX_COMPUTE_UNIT_LIMIT);
+        assert_eq!(config.microlamports_per_cu(), 0);
+
+        let config = ComputeBudgetConfig {
+            default_microlamports_per_cu: 10_000,
+            max_microlamports_per_cu: 5_000,
+            ..Default::default()
+        };
+        assert_eq!(config.microlamports_per_cu(), 5_000);
+
+        let config = ComputeBudgetConfig {
+            priority_fee_strategy: Some(Arc::new(Fixed(2_000))),
+            ..Default::default()
+        };
+        assert_eq!(config.microlamports_per_cu(), 2_000);
+    }
+}
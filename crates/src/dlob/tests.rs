@@ -2424,6 +2424,8 @@ fn l3book_bids_includes_all_order_types() {
     assert!(order_ids.contains(&3)); // Market (or MarketTriggered)
 }
 
+// relies on the libdrift vAMM fallback price
+#[cfg(not(feature = "native_ffi"))]
 #[test]
 fn l3book_vamm_orders_sorted_correctly() {
     let _ = env_logger::try_init();
@@ -2846,6 +2848,8 @@ fn dlob_l3_order_flags_correctness() {
     );
 }
 
+// relies on libdrift trigger auction prices
+#[cfg(not(feature = "native_ffi"))]
 #[test]
 fn dlob_l3_trigger_orders_by_price() {
     use crate::types::OrderTriggerCondition;
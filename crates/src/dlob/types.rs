@@ -396,8 +396,7 @@ impl TriggerOrder {
                         sequence_id: None,
                     },
                     Some(market),
-                )
-                .unwrap();
+                )?;
             order.auction_duration = auction_duration;
             order.auction_start_price = auction_start;
             order.auction_end_price = auction_end;
@@ -0,0 +1,195 @@
+//!
+//! Durable nonce utilities
+//!
+//! Txs signed with a durable nonce (instead of a recent blockhash) do not expire until the nonce is advanced,
+//! allowing pre-signed or offline approved txs.
+//!
+//! see `TransactionBuilder::with_durable_nonce`
+//!
+use solana_sdk::{
+    account::Account,
+    hash::Hash,
+    instruction::Instruction,
+    message::VersionedMessage,
+    nonce::{
+        state::{Data, Versions},
+        State,
+    },
+    pubkey::Pubkey,
+    system_instruction::{self, SystemInstruction},
+    system_program,
+};
+
+use crate::{SdkError, SdkResult};
+
+/// Size of a nonce account in bytes
+pub const NONCE_ACCOUNT_SIZE: usize = State::size();
+
+/// Derive the address of a nonce account created with `create_nonce_account_ixs`
+///
+/// * `base` - the account creator (payer)
+/// * `seed` - some seed, max 32 bytes
+pub fn derive_nonce_account(base: &Pubkey, seed: &str) -> SdkResult<Pubkey> {
+    Pubkey::create_with_seed(base, seed, &system_program::ID)
+        .map_err(|err| SdkError::Generic(err.to_string()))
+}
+
+/// Build ixs to create and initialize a nonce account derived from `payer` and `seed`
+///
+/// Only `payer` is required to sign the tx
+///
+/// * `payer` - funds the nonce account
+/// * `seed` - nonce account seed (see `derive_nonce_account`)
+/// * `authority` - authority allowed to advance the nonce
+/// * `lamports` - initial balance, must be rent exempt for `NONCE_ACCOUNT_SIZE`
+///
+/// Returns the nonce account address and ixs
+pub fn create_nonce_account_ixs(
+    payer: &Pubkey,
+    seed: &str,
+    authority: &Pubkey,
+    lamports: u64,
+) -> SdkResult<(Pubkey, Vec<Instruction>)> {
+    let nonce_account = derive_nonce_account(payer, seed)?;
+    let ixs = system_instruction::create_nonce_account_with_seed(
+        payer,
+        &nonce_account,
+        payer,
+        seed,
+        authority,
+        lamports,
+    );
+
+    Ok((nonce_account, ixs))
+}
+
+/// Build the ix to advance the nonce, it must be the first ix of a durable nonce tx
+pub fn advance_nonce_ix(nonce_account: &Pubkey, nonce_authority: &Pubkey) -> Instruction {
+    system_instruction::advance_nonce_account(nonce_account, nonce_authority)
+}
+
+/// Returns the nonce account of a durable nonce tx i.e. one whose first ix advances a nonce
+///
+/// Returns `None` for regular (recent blockhash) txs
+pub fn durable_nonce_account(message: &VersionedMessage) -> Option<Pubkey> {
+    let keys = message.static_account_keys();
+    let ix = message.instructions().first()?;
+    if keys.get(ix.program_id_index as usize) != Some(&system_program::ID) {
+        return None;
+    }
+    match bincode::deserialize(&ix.data) {
+        Ok(SystemInstruction::AdvanceNonceAccount) => ix
+            .accounts
+            .first()
+            .and_then(|idx| keys.get(*idx as usize))
+            .copied(),
+        _ => None,
+    }
+}
+
+/// Deserialize the nonce data from a nonce `account`
+///
+/// Returns error if the account is not an initialized nonce account
+pub fn nonce_data_from_account(account: &Account) -> SdkResult<Data> {
+    if account.owner != system_program::ID {
+        return Err(SdkError::InvalidAccount);
+    }
+    let versions: Versions =
+        bincode::deserialize(&account.data).map_err(|_| SdkError::Deserializing)?;
+    match versions.state() {
+        State::Initialized(data) => Ok(data.clone()),
+        State::Uninitialized => Err(SdkError::InvalidAccount),
+    }
+}
+
+/// Get the current nonce value (blockhash) from a nonce `account`
+pub fn nonce_from_account(account: &Account) -> SdkResult<Hash> {
+    nonce_data_from_account(account).map(|data| data.blockhash())
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::nonce::state::DurableNonce;
+
+    use super::*;
+
+    #[test]
+    fn nonce_account_roundtrip() {
+        let authority = Pubkey::new_unique();
+        let durable_nonce = DurableNonce::from_blockhash(&Hash::new_unique());
+        let data = Data::new(authority, durable_nonce, 5_000);
+        let account = Account {
+            lamports: 1_447_680,
+            data: bincode::serialize(&Versions::new(State::Initialized(data.clone()))).unwrap(),
+            owner: system_program::ID,
+            ..Default::default()
+        };
+
+        let decoded = nonce_data_from_account(&account).unwrap();
+        assert_eq!(decoded.authority, authority);
+        assert_eq!(decoded.get_lamports_per_signature(), 5_000);
+        assert_eq!(
+            nonce_from_account(&account).unwrap(),
+            *durable_nonce.as_hash()
+        );
+        assert_eq!(decoded, data);
+
+        let uninitialized = Account {
+            data: bincode::serialize(&Versions::new(State::Uninitialized)).unwrap(),
+            ..account.clone()
+        };
+        assert!(nonce_data_from_account(&uninitialized).is_err());
+
+        let wrong_owner = Account {
+            owner: Pubkey::new_unique(),
+            ..account
+        };
+        assert!(nonce_data_from_account(&wrong_owner).is_err());
+    }
+
+    #[test]
+    fn durable_nonce_account_detected() {
+        let payer = Pubkey::new_unique();
+        let nonce_account = Pubkey::new_unique();
+        let transfer = system_instruction::transfer(&payer, &Pubkey::new_unique(), 1);
+
+        let message = VersionedMessage::Legacy(solana_sdk::message::Message::new(
+            &[advance_nonce_ix(&nonce_account, &payer), transfer.clone()],
+            Some(&payer),
+        ));
+        assert_eq!(durable_nonce_account(&message), Some(nonce_account));
+
+        let message = VersionedMessage::Legacy(solana_sdk::message::Message::new(
+            &[transfer.clone(), advance_nonce_ix(&nonce_account, &payer)],
+            Some(&payer),
+        ));
+        assert_eq!(durable_nonce_account(&message), None);
+
+        let message =
+            VersionedMessage::Legacy(solana_sdk::message::Message::new(&[transfer], Some(&payer)));
+        assert_eq!(durable_nonce_account(&message), None);
+    }
+
+    #[test]
+    fn create_nonce_account() {
+        let payer = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let (nonce_account, ixs) =
+            create_nonce_account_ixs(&payer, "drift-nonce-0", &authority, 1_447_680).unwrap();
+
+        assert_eq!(
+            nonce_account,
+            derive_nonce_account(&payer, "drift-nonce-0").unwrap()
+        );
+        assert_eq!(ixs.len(), 2);
+        assert!(ixs
+            .iter()
+            .all(|ix| ix.accounts.iter().any(|a| a.pubkey == nonce_account)));
+        // only the payer signs
+        assert!(ixs
+            .iter()
+            .flat_map(|ix| ix.accounts.iter())
+            .filter(|a| a.is_signer)
+            .all(|a| a.pubkey == payer));
+    }
+}
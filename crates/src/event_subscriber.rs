@@ -13,6 +13,7 @@ pub use drift_pubsub_client::PubsubClient;
 use futures_util::{future::BoxFuture, stream::FuturesOrdered, FutureExt, Stream, StreamExt};
 use log::{debug, info, warn};
 use regex::Regex;
+use serde::Serialize;
 pub use solana_rpc_client::nonblocking::rpc_client::RpcClient;
 use solana_rpc_client::rpc_client::GetConfirmedSignaturesForAddress2Config;
 use solana_rpc_client_api::{
@@ -35,8 +36,14 @@ use tokio::{
 use crate::{
     constants::{self, PROGRAM_ID},
     drift_idl::{
-        events::{FundingPaymentRecord, OrderActionRecord, OrderRecord},
-        types::{MarketType, Order, OrderAction, OrderActionExplanation, PositionDirection},
+        events::{
+            FundingPaymentRecord, LiquidationRecord, OrderActionRecord, OrderRecord,
+            SettlePnlRecord, SignedMsgOrderRecord,
+        },
+        types::{
+            LiquidatePerpRecord, LiquidationType, MarketType, Order, OrderAction,
+            OrderActionExplanation, PerpBankruptcyRecord, PositionDirection, SettlePnlExplanation,
+        },
     },
     grpc::{
         grpc_subscriber::{DriftGrpcClient, GeyserSubscribeOpts, GrpcConnectionOpts},
@@ -602,7 +609,7 @@ pub fn try_parse_log(raw: &str, signature: &str, tx_idx: usize) -> Option<DriftE
 static ORDER_CANCEL_MISSING_RE: OnceLock<Regex> = OnceLock::new();
 
 /// Enum of all drift program events
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize)]
 pub enum DriftEvent {
     OrderFill {
         maker: Option<Pubkey>,
@@ -685,11 +692,109 @@ pub enum DriftEvent {
         /// base asset amount
         amount: u64,
     },
+    SettlePnl {
+        user: Pubkey,
+        market_index: u16,
+        /// pnl settled to the user's quote balance
+        pnl: i128,
+        /// position state after settlement
+        base_asset_amount: i64,
+        quote_asset_amount_after: i64,
+        quote_entry_amount: i64,
+        settle_price: i64,
+        explanation: SettlePnlExplanation,
+        ts: u64,
+        signature: String,
+        tx_idx: usize,
+    },
+    Liquidation {
+        /// liquidated user
+        user: Pubkey,
+        liquidator: Pubkey,
+        liquidation_type: LiquidationType,
+        bankrupt: bool,
+        /// set for `LiquidationType::LiquidatePerp`
+        liquidate_perp: LiquidatePerpRecord,
+        /// set for `LiquidationType::PerpBankruptcy`
+        perp_bankruptcy: PerpBankruptcyRecord,
+        ts: u64,
+        signature: String,
+        tx_idx: usize,
+    },
+    /// A swift (signed msg) order was placed onchain
+    SwiftOrder {
+        /// taker sub-account
+        user: Pubkey,
+        /// hash of the signed msg
+        hash: String,
+        /// the swift order's UUID
+        uuid: [u8; 8],
+        user_order_id: u32,
+        /// last slot the order could be placed at
+        max_slot: u64,
+        ts: u64,
+        signature: String,
+        tx_idx: usize,
+    },
+}
+
+/// A sub-account's side of an `OrderFill` event
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FillSide {
+    pub user: Pubkey,
+    pub order_id: u32,
+    pub direction: PositionDirection,
+    /// true if the sub-account was maker
+    pub is_maker: bool,
+    /// fee paid, negative for rebates (QUOTE_PRECISION)
+    pub fee: i64,
+    /// the order's total filled base amount after this fill, if known
+    pub cumulative_base_filled: Option<u64>,
 }
 
 impl DriftEvent {
+    /// Get the side of `sub_account` in an `OrderFill` event, if any
+    pub fn fill_side(&self, sub_account: &Pubkey) -> Option<FillSide> {
+        let Self::OrderFill {
+            maker,
+            maker_fee,
+            maker_order_id,
+            maker_side,
+            maker_order_cumulative_base_filled,
+            taker,
+            taker_fee,
+            taker_order_id,
+            taker_side,
+            taker_order_cumulative_base_filled,
+            ..
+        } = self
+        else {
+            return None;
+        };
+        if maker.as_ref() == Some(sub_account) {
+            Some(FillSide {
+                user: *sub_account,
+                order_id: *maker_order_id,
+                direction: (*maker_side)?,
+                is_maker: true,
+                fee: *maker_fee,
+                cumulative_base_filled: *maker_order_cumulative_base_filled,
+            })
+        } else if taker.as_ref() == Some(sub_account) {
+            Some(FillSide {
+                user: *sub_account,
+                order_id: *taker_order_id,
+                direction: (*taker_side)?,
+                is_maker: false,
+                fee: *taker_fee as i64,
+                cumulative_base_filled: *taker_order_cumulative_base_filled,
+            })
+        } else {
+            None
+        }
+    }
     /// Return true if the event is connected to sub-account
-    fn pertains_to(&self, sub_account: Pubkey) -> bool {
+    pub(crate) fn pertains_to(&self, sub_account: Pubkey) -> bool {
         if sub_account == PROGRAM_ID {
             return true;
         }
@@ -704,6 +809,11 @@ impl DriftEvent {
             Self::FundingPayment { user, .. } => *user == sub_account,
             Self::Swap { user, .. } => *user == sub_account,
             Self::OrderTrigger { user, .. } => *user == sub_account,
+            Self::SettlePnl { user, .. } => *user == sub_account,
+            Self::Liquidation {
+                user, liquidator, ..
+            } => *user == sub_account || *liquidator == sub_account,
+            Self::SwiftOrder { user, .. } => *user == sub_account,
         }
     }
     /// Deserialize drift event by discriminant
@@ -735,6 +845,21 @@ impl DriftEvent {
                 signature,
                 tx_idx,
             )),
+            SettlePnlRecord::DISCRIMINATOR => Some(Self::from_settle_pnl_record(
+                SettlePnlRecord::deserialize(data).expect("deserializes"),
+                signature,
+                tx_idx,
+            )),
+            LiquidationRecord::DISCRIMINATOR => Some(Self::from_liquidation_record(
+                LiquidationRecord::deserialize(data).expect("deserializes"),
+                signature,
+                tx_idx,
+            )),
+            SignedMsgOrderRecord::DISCRIMINATOR => Some(Self::from_signed_msg_order_record(
+                SignedMsgOrderRecord::deserialize(data).expect("deserializes"),
+                signature,
+                tx_idx,
+            )),
             _ => {
                 debug!(target: LOG_TARGET, "unhandled event: {disc:?}");
                 None
@@ -768,6 +893,50 @@ impl DriftEvent {
             tx_idx,
         }
     }
+    fn from_settle_pnl_record(value: SettlePnlRecord, signature: &str, tx_idx: usize) -> Self {
+        Self::SettlePnl {
+            user: value.user,
+            market_index: value.market_index,
+            pnl: value.pnl.as_i128(),
+            base_asset_amount: value.base_asset_amount,
+            quote_asset_amount_after: value.quote_asset_amount_after,
+            quote_entry_amount: value.quote_entry_amount,
+            settle_price: value.settle_price,
+            explanation: value.explanation,
+            ts: value.ts.unsigned_abs(),
+            signature: signature.to_string(),
+            tx_idx,
+        }
+    }
+    fn from_liquidation_record(value: LiquidationRecord, signature: &str, tx_idx: usize) -> Self {
+        Self::Liquidation {
+            user: value.user,
+            liquidator: value.liquidator,
+            liquidation_type: value.liquidation_type,
+            bankrupt: value.bankrupt,
+            liquidate_perp: value.liquidate_perp,
+            perp_bankruptcy: value.perp_bankruptcy,
+            ts: value.ts.unsigned_abs(),
+            signature: signature.to_string(),
+            tx_idx,
+        }
+    }
+    fn from_signed_msg_order_record(
+        value: SignedMsgOrderRecord,
+        signature: &str,
+        tx_idx: usize,
+    ) -> Self {
+        Self::SwiftOrder {
+            user: value.user,
+            hash: value.hash,
+            uuid: value.signed_msg_order_uuid,
+            user_order_id: value.user_order_id,
+            max_slot: value.signed_msg_order_max_slot,
+            ts: value.ts.unsigned_abs(),
+            signature: signature.to_string(),
+            tx_idx,
+        }
+    }
     fn from_order_record(value: OrderRecord, signature: &str, tx_idx: usize) -> Option<Self> {
         Some(DriftEvent::OrderCreate {
             order: value.order,
@@ -875,6 +1044,41 @@ impl TxSignatureCache {
     }
 }
 
+#[cfg(test)]
+impl DriftEvent {
+    /// `OrderFill` of `base_asset_amount` between `maker` and `taker` in perp market 0
+    pub(crate) fn test_fill(
+        maker: Option<FillSide>,
+        taker: Option<FillSide>,
+        base_asset_amount: u64,
+        quote_asset_amount: u64,
+    ) -> Self {
+        Self::OrderFill {
+            maker: maker.map(|m| m.user),
+            maker_fee: maker.map(|m| m.fee).unwrap_or_default(),
+            maker_order_id: maker.map(|m| m.order_id).unwrap_or_default(),
+            maker_side: maker.map(|m| m.direction),
+            maker_order_base_asset_amount: None,
+            maker_order_cumulative_base_filled: maker.and_then(|m| m.cumulative_base_filled),
+            taker: taker.map(|t| t.user),
+            taker_fee: taker.map(|t| t.fee as u64).unwrap_or_default(),
+            taker_order_id: taker.map(|t| t.order_id).unwrap_or_default(),
+            taker_side: taker.map(|t| t.direction),
+            taker_order_base_asset_amount: None,
+            taker_order_cumulative_base_filled: taker.and_then(|t| t.cumulative_base_filled),
+            base_asset_amount_filled: base_asset_amount,
+            quote_asset_amount_filled: quote_asset_amount,
+            market_index: 0,
+            market_type: MarketType::Perp,
+            oracle_price: 0,
+            signature: String::new(),
+            tx_idx: 0,
+            ts: 0,
+            bit_flags: 0,
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use ahash::HashMap;
@@ -1022,6 +1226,37 @@ mod test {
         assert!(event_rx.try_recv().is_err()); // no more events
     }
 
+    #[test]
+    fn fill_side_of_sub_account() {
+        let maker = FillSide {
+            user: Pubkey::new_unique(),
+            order_id: 1,
+            direction: PositionDirection::Long,
+            is_maker: true,
+            fee: -10,
+            cumulative_base_filled: Some(5),
+        };
+        let taker = FillSide {
+            user: Pubkey::new_unique(),
+            order_id: 2,
+            direction: PositionDirection::Short,
+            is_maker: false,
+            fee: 20,
+            cumulative_base_filled: None,
+        };
+        let fill = DriftEvent::test_fill(Some(maker), Some(taker), 5, 500);
+        assert_eq!(fill.fill_side(&maker.user), Some(maker));
+        assert_eq!(fill.fill_side(&taker.user), Some(taker));
+        assert_eq!(fill.fill_side(&Pubkey::new_unique()), None);
+
+        // side without direction
+        let mut fill = DriftEvent::test_fill(Some(maker), None, 5, 500);
+        if let DriftEvent::OrderFill { maker_side, .. } = &mut fill {
+            *maker_side = None;
+        }
+        assert_eq!(fill.fill_side(&maker.user), None);
+    }
+
     #[ignore = "base64 encoded logs need updating"]
     #[test]
     fn parses_order_trigger() {
@@ -1301,6 +1536,75 @@ mod test {
         });
     }
 
+    #[test]
+    fn parses_swift_order_record() {
+        let user = Pubkey::new_unique();
+        let log = format!(
+            "{PROGRAM_DATA}{}",
+            serialize_event(SignedMsgOrderRecord {
+                user,
+                hash: "hash".into(),
+                user_order_id: 7,
+                signed_msg_order_max_slot: 1_000,
+                signed_msg_order_uuid: *b"abcdefgh",
+                ts: 1_700_000_000,
+                ..Default::default()
+            })
+        );
+        let event = try_parse_log(&log, "sig", 1).expect("parsed");
+        assert!(event.pertains_to(user));
+        assert_eq!(
+            event,
+            DriftEvent::SwiftOrder {
+                user,
+                hash: "hash".into(),
+                uuid: *b"abcdefgh",
+                user_order_id: 7,
+                max_slot: 1_000,
+                ts: 1_700_000_000,
+                signature: "sig".into(),
+                tx_idx: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn parses_settle_pnl_record() {
+        let user = Pubkey::new_unique();
+        let log = format!(
+            "{PROGRAM_DATA}{}",
+            serialize_event(SettlePnlRecord {
+                ts: 1_700_000_000,
+                user,
+                market_index: 2,
+                pnl: (-5_000_000_i128).into(),
+                base_asset_amount: 1_000_000_000,
+                quote_asset_amount_after: -95_000_000,
+                quote_entry_amount: -100_000_000,
+                settle_price: 95_000_000,
+                explanation: SettlePnlExplanation::ExpiredPosition,
+            })
+        );
+        let event = try_parse_log(&log, "sig", 3).expect("parsed");
+        assert!(event.pertains_to(user));
+        assert_eq!(
+            event,
+            DriftEvent::SettlePnl {
+                user,
+                market_index: 2,
+                pnl: -5_000_000,
+                base_asset_amount: 1_000_000_000,
+                quote_asset_amount_after: -95_000_000,
+                quote_entry_amount: -100_000_000,
+                settle_price: 95_000_000,
+                explanation: SettlePnlExplanation::ExpiredPosition,
+                ts: 1_700_000_000,
+                signature: "sig".into(),
+                tx_idx: 3,
+            }
+        );
+    }
+
     /// Make transaction with dummy instruction for drift program
     fn make_transaction(
         account: Pubkey,
@@ -8,7 +8,13 @@ use abi_stable::std_types::ROption;
 use anchor_lang::{prelude::AccountInfo, Discriminator};
 use solana_sdk::{account::Account, clock::Slot, pubkey::Pubkey};
 
+// compiled for tests without the feature for the parity tests against libdrift
+#[cfg(any(feature = "native_ffi", test))]
+pub(crate) mod native;
+
 pub use self::abi_types::*;
+#[cfg(feature = "native_ffi")]
+pub use self::native::*;
 use crate::{
     constants::{high_leverage_mode_account, PROGRAM_ID},
     drift_idl::{
@@ -34,6 +40,9 @@ use crate::{
 // DEV: the types here are deliberately received as those defined in `::abi_types`-
 // which are equivalent to the drift-ffi exported types directly from drift program crate
 // the result is that this code can use its own solana-program/* crates without restriction from the version used by drift program
+//
+// with `native_ffi` enabled these are replaced by the pure-Rust equivalents in `native`
+#[cfg(not(feature = "native_ffi"))]
 extern "C" {
     #[allow(improper_ctypes)]
     pub fn ffi_version() -> String;
@@ -236,14 +245,49 @@ pub fn calculate_auction_price(
 }
 
 impl OrderParams {
+    /// Update the order's auction params as the program would on placement
+    ///
+    /// With `native_ffi` this is not supported and the params are left unchanged,
+    /// use `try_update_perp_auction_params` to observe the error
     pub fn update_perp_auction_params(
         &mut self,
         perp_market: &accounts::PerpMarket,
         oracle_price: i64,
         is_signed_msg: bool,
     ) {
-        unsafe {
-            order_params_update_perp_auction_params(self, perp_market, oracle_price, is_signed_msg)
+        if let Err(err) =
+            self.try_update_perp_auction_params(perp_market, oracle_price, is_signed_msg)
+        {
+            log::warn!("update_perp_auction_params failed: {err:?}");
+        }
+    }
+    /// Fallible `update_perp_auction_params`
+    ///
+    /// Not supported by `native_ffi`, always returns an error
+    pub fn try_update_perp_auction_params(
+        &mut self,
+        perp_market: &accounts::PerpMarket,
+        oracle_price: i64,
+        is_signed_msg: bool,
+    ) -> SdkResult<()> {
+        #[cfg(not(feature = "native_ffi"))]
+        {
+            unsafe {
+                order_params_update_perp_auction_params(
+                    self,
+                    perp_market,
+                    oracle_price,
+                    is_signed_msg,
+                )
+            };
+            Ok(())
+        }
+        #[cfg(feature = "native_ffi")]
+        {
+            let _ = (perp_market, oracle_price, is_signed_msg);
+            Err(SdkError::Generic(
+                "update_perp_auction_params is not supported by native_ffi".into(),
+            ))
         }
     }
 }
@@ -273,6 +317,8 @@ impl MarketState {
     /// Calculate margin requirement for user
     ///
     /// incremental version allows partial updates e.g. when specific positions or oracle prices change
+    ///
+    /// With `native_ffi` this panics if a position's margin can't be calculated, see `IncrementalMarginCalculation::try_from_user`
     pub fn calculate_incremental_margin_requirement(
         &self,
         user: &accounts::User,
@@ -814,6 +860,8 @@ impl IncrementalMarginCalculation {
         self.total_collateral - self.margin_requirement as i128
     }
     /// Create a new cached margin calculation from a user account
+    ///
+    /// With `native_ffi` this panics if a position's margin can't be calculated, see `try_from_user`
     pub fn from_user(
         user: &accounts::User,
         market_state: &MarketState,
@@ -833,6 +881,39 @@ impl IncrementalMarginCalculation {
         }
     }
 
+    /// Fallible `from_user`
+    ///
+    /// With `native_ffi` errors if a position's margin can't be calculated e.g. missing market or oracle price
+    pub fn try_from_user(
+        user: &accounts::User,
+        market_state: &MarketState,
+        margin_type: MarginRequirementType,
+        timestamp: u64,
+        margin_buffer: Option<u32>,
+    ) -> SdkResult<Self> {
+        #[cfg(not(feature = "native_ffi"))]
+        {
+            Ok(Self::from_user(
+                user,
+                market_state,
+                margin_type,
+                timestamp,
+                margin_buffer,
+            ))
+        }
+        #[cfg(feature = "native_ffi")]
+        {
+            let m = market_state.load();
+            to_sdk_result(native::try_incremental_margin_calculation_from_user(
+                user,
+                &m,
+                margin_type,
+                timestamp,
+                margin_buffer.unwrap_or(0),
+            ))
+        }
+    }
+
     /// Create a new cached margin calculation from a user account with current timestamp
     pub fn from_user_now(
         user: &accounts::User,
@@ -848,6 +929,8 @@ impl IncrementalMarginCalculation {
     }
 
     /// Update the cached calculation with a spot position change
+    ///
+    /// With `native_ffi` this panics if the position's margin can't be calculated, see `try_update_spot_position`
     pub fn update_spot_position(
         &mut self,
         spot_position: &types::SpotPosition,
@@ -860,7 +943,35 @@ impl IncrementalMarginCalculation {
         }
     }
 
+    /// Fallible `update_spot_position`, the cached calculation is unchanged on error
+    pub fn try_update_spot_position(
+        &mut self,
+        spot_position: &types::SpotPosition,
+        market_state: &MarketState,
+        timestamp: u64,
+    ) -> SdkResult<()> {
+        #[cfg(not(feature = "native_ffi"))]
+        {
+            self.update_spot_position(spot_position, market_state, timestamp);
+            Ok(())
+        }
+        #[cfg(feature = "native_ffi")]
+        {
+            let m = market_state.load();
+            to_sdk_result(
+                native::try_incremental_margin_calculation_update_spot_position(
+                    self,
+                    spot_position,
+                    &m,
+                    timestamp,
+                ),
+            )
+        }
+    }
+
     /// Update the cached calculation with a perp position change
+    ///
+    /// With `native_ffi` this panics if the position's margin can't be calculated, see `try_update_perp_position`
     pub fn update_perp_position(
         &mut self,
         perp_position: &types::PerpPosition,
@@ -872,6 +983,32 @@ impl IncrementalMarginCalculation {
             incremental_margin_calculation_update_perp_position(self, perp_position, &m, timestamp);
         }
     }
+
+    /// Fallible `update_perp_position`, the cached calculation is unchanged on error
+    pub fn try_update_perp_position(
+        &mut self,
+        perp_position: &types::PerpPosition,
+        market_state: &MarketState,
+        timestamp: u64,
+    ) -> SdkResult<()> {
+        #[cfg(not(feature = "native_ffi"))]
+        {
+            self.update_perp_position(perp_position, market_state, timestamp);
+            Ok(())
+        }
+        #[cfg(feature = "native_ffi")]
+        {
+            let m = market_state.load();
+            to_sdk_result(
+                native::try_incremental_margin_calculation_update_perp_position(
+                    self,
+                    perp_position,
+                    &m,
+                    timestamp,
+                ),
+            )
+        }
+    }
 }
 
 pub mod abi_types {
@@ -1163,6 +1300,7 @@ mod tests {
         }
     }
 
+    #[cfg(not(feature = "native_ffi"))]
     #[test]
     fn ffi_check_version() {
         let drift_ffi_sys = include_str!("../drift-ffi-sys/Cargo.toml");
@@ -1171,6 +1309,13 @@ mod tests {
         assert_eq!(&check_ffi_version(), expected_version.unwrap());
     }
 
+    #[cfg(feature = "native_ffi")]
+    #[test]
+    fn ffi_check_version_native() {
+        assert_eq!(check_ffi_version(), crate::drift_idl::IDL_VERSION);
+        assert!(crate::DriftClient::check_libs().is_ok());
+    }
+
     #[test]
     fn ffi_deser_1_76_0_spot_market() {
         // smoke test for deserializing program data (where u128/i128 alignment is 8)
@@ -1370,6 +1515,7 @@ mod tests {
         }
     }
 
+    #[cfg(not(feature = "native_ffi"))]
     #[test]
     fn ffi_perp_market_get_mm_oracle_data_basic() {
         let perp_market = PerpMarket {
@@ -1402,6 +1548,7 @@ mod tests {
         assert!(mm_oracle_data.safe_oracle_price_data.price > 0);
     }
 
+    #[cfg(not(feature = "native_ffi"))]
     #[test]
     fn ffi_perp_market_fallback_price() {
         use crate::math::constants::{AMM_RESERVE_PRECISION, PEG_PRECISION};
@@ -1497,6 +1644,7 @@ mod tests {
         assert_eq!(maintenance_margin_ratio, 1_234); // 5%
     }
 
+    #[cfg(not(feature = "native_ffi"))]
     #[test]
     fn ffi_order_params_update_perp_auction_params_populates_fields() {
         let market_index = 3u16;
@@ -1581,6 +1729,7 @@ mod tests {
         assert!(end != 0);
     }
 
+    #[cfg(not(feature = "native_ffi"))]
     #[test]
     fn ffi_order_params_update_perp_auction_params_reads_amm_fields() {
         let market_index = 4u16;
@@ -1805,6 +1954,7 @@ mod tests {
         }
     }
 
+    #[cfg(not(feature = "native_ffi"))]
     #[test]
     fn ffi_simulate_place_perp_order() {
         // smoke test for ffi compatibility, logic tested in `math::` module
@@ -1967,6 +2117,7 @@ mod tests {
         assert!(res.is_ok_and(|truthy| truthy));
     }
 
+    #[cfg(not(feature = "native_ffi"))]
     #[test]
     fn ffi_simulate_place_perp_order_with_max_margin_ratio() {
         // smoke test for ffi compatibility, logic tested in `math::` module
@@ -2250,6 +2401,7 @@ mod tests {
         assert_eq!(params.tick_size, 1_000);
     }
 
+    #[cfg(not(feature = "native_ffi"))]
     #[test]
     fn ffi_calculate_auction_params_for_trigger_order() {
         use crate::{
@@ -2551,9 +2703,235 @@ mod tests {
             );
         }
     }
+
+    fn oracle_price_data(price: i64) -> OraclePriceData {
+        OraclePriceData {
+            price,
+            confidence: 1,
+            delay: 0,
+            has_sufficient_number_of_data_points: true,
+            sequence_id: None,
+        }
+    }
+
+    /// $1,000 USDC deposit and 1 long perp at $100 entered at $95
+    fn fixed_margin_user_and_state() -> (User, crate::market_state::MarketState) {
+        let market_state = crate::market_state::MarketState::default();
+        market_state.set_spot_market(usdc_spot_market());
+        market_state.set_perp_market(PerpMarket {
+            market_index: 1,
+            margin_ratio_initial: MARGIN_PRECISION / 10,
+            margin_ratio_maintenance: MARGIN_PRECISION / 20,
+            unrealized_pnl_initial_asset_weight: SPOT_WEIGHT_PRECISION,
+            unrealized_pnl_maintenance_asset_weight: SPOT_WEIGHT_PRECISION,
+            ..Default::default()
+        });
+        market_state.set_spot_oracle_price(0, oracle_price_data(QUOTE_PRECISION_I64));
+        market_state.set_perp_oracle_price(1, oracle_price_data(100 * PRICE_PRECISION_I64));
+
+        let mut user = User::default();
+        user.spot_positions[0] = SpotPosition {
+            market_index: 0,
+            scaled_balance: 1_000 * SPOT_BALANCE_PRECISION_U64,
+            balance_type: SpotBalanceType::Deposit,
+            ..Default::default()
+        };
+        user.perp_positions[0] = PerpPosition {
+            market_index: 1,
+            base_asset_amount: BASE_PRECISION_I64,
+            quote_asset_amount: -95 * QUOTE_PRECISION_I64,
+            ..Default::default()
+        };
+
+        (user, market_state)
+    }
+
+    #[test]
+    fn ffi_simplified_margin_requirement_fixed_values() {
+        let (user, market_state) = fixed_margin_user_and_state();
+
+        // $1,000 + $5 pnl collateral, 10% of $100 notional
+        let initial = market_state
+            .calculate_simplified_margin_requirement(&user, MarginRequirementType::Initial, None)
+            .unwrap();
+        assert_eq!(initial.total_collateral, 1_005_000_000);
+        assert_eq!(initial.total_collateral_buffer, 0);
+        assert_eq!(initial.margin_requirement, 10_000_000);
+        assert_eq!(initial.margin_requirement_plus_buffer, 10_000_000);
+
+        // 5% of notional, 1% buffer on the $100 liability
+        let maintenance = market_state
+            .calculate_simplified_margin_requirement(
+                &user,
+                MarginRequirementType::Maintenance,
+                Some(100),
+            )
+            .unwrap();
+        assert_eq!(maintenance.total_collateral, 1_005_000_000);
+        assert_eq!(maintenance.total_collateral_buffer, 0);
+        assert_eq!(maintenance.margin_requirement, 5_000_000);
+        assert_eq!(maintenance.margin_requirement_plus_buffer, 6_000_000);
+    }
+
+    #[test]
+    fn ffi_incremental_margin_calculation_fixed_values() {
+        let (user, market_state) = fixed_margin_user_and_state();
+
+        let mut calculation = IncrementalMarginCalculation::from_user(
+            &user,
+            &market_state,
+            MarginRequirementType::Maintenance,
+            1_000,
+            None,
+        );
+        assert_eq!(calculation.total_collateral, 1_005_000_000);
+        assert_eq!(calculation.margin_requirement, 5_000_000);
+
+        // 2 long perp: $105 pnl, 5% of $200 notional
+        let perp_position = PerpPosition {
+            base_asset_amount: 2 * BASE_PRECISION_I64,
+            ..user.perp_positions[0]
+        };
+        calculation.update_perp_position(&perp_position, &market_state, 1_001);
+        assert_eq!(calculation.total_collateral, 1_105_000_000);
+        assert_eq!(calculation.margin_requirement, 10_000_000);
+
+        // $2,000 USDC deposit
+        let spot_position = SpotPosition {
+            scaled_balance: 2_000 * SPOT_BALANCE_PRECISION_U64,
+            ..user.spot_positions[0]
+        };
+        calculation.update_spot_position(&spot_position, &market_state, 1_002);
+        assert_eq!(calculation.total_collateral, 2_105_000_000);
+        assert_eq!(calculation.margin_requirement, 10_000_000);
+        assert_eq!(calculation.free_collateral(), 2_095_000_000);
+        assert_eq!(calculation.last_updated, 1_002);
+    }
+
+    #[test]
+    fn ffi_perp_market_margin_ratio_size_premium() {
+        let perp_market = PerpMarket {
+            margin_ratio_initial: MARGIN_PRECISION / 10,
+            margin_ratio_maintenance: MARGIN_PRECISION / 20,
+            high_leverage_margin_ratio_initial: 200,
+            high_leverage_margin_ratio_maintenance: 100,
+            imf_factor: 500,
+            ..Default::default()
+        };
+
+        for (size, high_leverage_mode, expected) in [
+            (0, false, [1_000, 750, 500]),
+            (0, true, [200, 150, 100]),
+            (BASE_PRECISION, false, [1_000, 750, 500]),
+            (1_000_000 * BASE_PRECISION, false, [5_800, 5_600, 5_400]),
+            (1_000_000 * BASE_PRECISION, true, [5_160, 5_120, 5_080]),
+        ] {
+            let actual = [
+                MarginRequirementType::Initial,
+                MarginRequirementType::Fill,
+                MarginRequirementType::Maintenance,
+            ]
+            .map(|margin_type| {
+                perp_market
+                    .get_margin_ratio(size, margin_type, high_leverage_mode)
+                    .unwrap()
+            });
+            assert_eq!(actual, expected, "size: {size}, hlm: {high_leverage_mode}");
+        }
+    }
+
+    #[cfg(feature = "native_ffi")]
+    #[test]
+    fn ffi_native_mm_oracle_and_trigger_price() {
+        let perp_market = PerpMarket {
+            amm: AMM {
+                mm_oracle_price: 101 * PRICE_PRECISION_I64,
+                mm_oracle_slot: 90,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let oracle = oracle_price_data(100 * PRICE_PRECISION_I64);
+
+        let mm_oracle = perp_market
+            .get_mm_oracle_price_data(oracle, 100, &ValidityGuardRails::default())
+            .unwrap();
+        assert_eq!(mm_oracle.safe_oracle_price_data.price, oracle.price);
+        assert_eq!(mm_oracle.exchange_oracle_price_data.price, oracle.price);
+        assert_eq!(mm_oracle.mm_oracle_price, 101 * PRICE_PRECISION_I64);
+        assert_eq!(mm_oracle.mm_oracle_delay, 10);
+        assert_eq!(mm_oracle.mm_exchange_diff_bps, 100);
+
+        assert_eq!(
+            perp_market
+                .get_trigger_price(oracle.price, 0, false)
+                .unwrap(),
+            100 * PRICE_PRECISION_U64
+        );
+        assert!(perp_market
+            .get_trigger_price(oracle.price, 0, true)
+            .is_err());
+    }
+
+    #[cfg(feature = "native_ffi")]
+    #[test]
+    fn ffi_incremental_margin_calculation_errors() {
+        let market_state = crate::market_state::MarketState::default();
+        market_state.set_spot_market(usdc_spot_market());
+        market_state.set_spot_oracle_price(
+            0,
+            OraclePriceData {
+                price: QUOTE_PRECISION as i64,
+                confidence: 1,
+                delay: 0,
+                has_sufficient_number_of_data_points: true,
+                sequence_id: None,
+            },
+        );
+        let mut user = User::default();
+        user.spot_positions[0] = SpotPosition {
+            market_index: 0,
+            scaled_balance: 1_000 * SPOT_BALANCE_PRECISION_U64,
+            balance_type: SpotBalanceType::Deposit,
+            ..Default::default()
+        };
+        let perp_position = PerpPosition {
+            market_index: 1,
+            base_asset_amount: BASE_PRECISION_I64,
+            ..Default::default()
+        };
+
+        // no perp market 1 in the market state
+        let mut with_perp = user;
+        with_perp.perp_positions[0] = perp_position;
+        assert!(IncrementalMarginCalculation::try_from_user(
+            &with_perp,
+            &market_state,
+            MarginRequirementType::Maintenance,
+            1,
+            None,
+        )
+        .is_err());
+
+        let mut calculation = IncrementalMarginCalculation::try_from_user(
+            &user,
+            &market_state,
+            MarginRequirementType::Maintenance,
+            1,
+            None,
+        )
+        .unwrap();
+        let before = calculation.total_collateral;
+        assert!(calculation
+            .try_update_perp_position(&perp_position, &market_state, 2)
+            .is_err());
+        assert_eq!(calculation.total_collateral, before);
+        assert_eq!(calculation.last_updated, 1);
+    }
 }
 
 // Simplified Margin Calculation FFI declarations
+#[cfg(not(feature = "native_ffi"))]
 extern "C" {
     #[allow(improper_ctypes)]
     pub fn margin_calculate_simplified_margin_requirement(
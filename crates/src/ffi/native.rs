@@ -0,0 +1,2603 @@
+//!
+//! Pure-Rust implementations of the `libdrift_ffi_sys` exports
+//!
+//! Enabled with the `native_ffi` feature, removing the need for a prebuilt libdrift for the
+//! `1.76.0` toolchain. Functions mirror the `extern "C"` declarations in `ffi.rs` exactly so the
+//! shims are unchanged. They are `unsafe` only to keep the shims' `unsafe {}` call sites warning free.
+//!
+//! Supported: auction/limit prices, order predicates, perp margin ratios, spot weights,
+//! position helpers, oracle price decoding (Pyth, Pyth Pull, Pyth Lazer, Switchboard, Prelaunch,
+//! QuoteAsset), oracle trigger prices and the standard/simplified/incremental margin calculations.
+//!
+//! Degraded: `perp_market_get_mm_oracle_price_data` always selects the exchange oracle,
+//! `order_params_update_perp_auction_params` leaves the params unchanged.
+//!
+//! Not supported (returns `ErrorCode::DefaultError`):
+//! `orders_place_perp_order`, `order_params_will_auction_params_sanitize`,
+//! `order_calculate_auction_params_for_trigger_order`, median `perp_market_get_trigger_price`
+//! and `perp_market_get_fallback_price`. SDK callers skip trigger and VAMM prices on error.
+//!
+#![allow(clippy::missing_safety_doc)]
+#![cfg_attr(not(feature = "native_ffi"), allow(dead_code))]
+
+use abi_stable::std_types::{ROption, RResult};
+use anchor_lang::{prelude::AccountInfo, Discriminator};
+use solana_sdk::{account::Account, clock::Slot, pubkey::Pubkey};
+
+use super::{
+    AccountsList, FfiResult, IncrementalMarginCalculation, MMOraclePriceData, MarginCalculation,
+    MarginContextMode, OraclePriceData, PositionCollateral, SimplifiedMarginCalculation,
+};
+use crate::{
+    drift_idl::{
+        accounts,
+        errors::ErrorCode,
+        types::{
+            self, AssetTier, ContractTier, ContractType, MarginRequirementType, MarketStatus,
+            OracleSource, OrderTriggerCondition, OrderType, PositionDirection, SpotBalanceType,
+        },
+    },
+    market_state::MarketStateData,
+    math::{
+        constants::{
+            AMM_RESERVE_PRECISION, AMM_TO_QUOTE_PRECISION_RATIO, BASE_PRECISION_I128,
+            MARGIN_PRECISION_U128, MAX_PREDICTION_MARKET_PRICE, OPEN_ORDER_MARGIN_REQUIREMENT,
+            PRICE_PRECISION, PRICE_PRECISION_I128, PRICE_PRECISION_I64,
+            PRICE_TIMES_AMM_TO_QUOTE_PRECISION_RATIO_I128, QUOTE_SPOT_MARKET_INDEX,
+            SPOT_IMF_PRECISION, SPOT_WEIGHT_PRECISION, SPOT_WEIGHT_PRECISION_I128,
+            SPOT_WEIGHT_PRECISION_U128,
+        },
+        interest_rate::get_token_amount,
+    },
+    types::{ProtectedMakerParams, RevenueShareOrder, ValidityGuardRails},
+};
+
+type NativeResult<T> = Result<T, ErrorCode>;
+
+fn to_ffi_result<T>(value: NativeResult<T>) -> FfiResult<T> {
+    match value {
+        Ok(t) => RResult::ROk(t),
+        Err(code) => RResult::RErr(code.into()),
+    }
+}
+
+//
+// Exports
+//
+
+/// The native implementation tracks the program version of the IDL
+pub unsafe fn ffi_version() -> String {
+    crate::drift_idl::IDL_VERSION.to_string()
+}
+
+pub unsafe fn math_calculate_auction_price(
+    order: &types::Order,
+    slot: Slot,
+    tick_size: u64,
+    oracle_price: ROption<i64>,
+    is_prediction_market: bool,
+) -> FfiResult<u64> {
+    to_ffi_result(calculate_auction_price(
+        order,
+        slot,
+        tick_size,
+        oracle_price.into_option(),
+        is_prediction_market,
+    ))
+}
+
+pub unsafe fn math_calculate_margin_requirement_and_total_collateral_and_liability_info(
+    user: &accounts::User,
+    accounts: &mut AccountsList,
+    mode: MarginContextMode,
+) -> FfiResult<MarginCalculation> {
+    let margin_type = match mode {
+        MarginContextMode::StandardMaintenance => MarginRequirementType::Maintenance,
+        MarginContextMode::StandardInitial => MarginRequirementType::Initial,
+        MarginContextMode::StandardCustom(margin_type) => margin_type,
+    };
+    to_ffi_result(calculate_margin(user, accounts, margin_type))
+}
+
+pub unsafe fn oracle_get_oracle_price(
+    oracle_source: OracleSource,
+    oracle_account: &mut (Pubkey, Account),
+    slot: Slot,
+) -> FfiResult<OraclePriceData> {
+    to_ffi_result(get_oracle_price(
+        oracle_source,
+        &oracle_account.1.data,
+        slot,
+    ))
+}
+
+pub unsafe fn order_is_limit_order(order: &types::Order) -> bool {
+    matches!(order.order_type, OrderType::Limit | OrderType::TriggerLimit)
+}
+
+pub unsafe fn order_is_resting_limit_order(order: &types::Order, slot: Slot) -> FfiResult<bool> {
+    to_ffi_result(is_resting_limit_order(order, slot))
+}
+
+pub unsafe fn order_triggered(order: &types::Order) -> bool {
+    matches!(
+        order.trigger_condition,
+        OrderTriggerCondition::TriggeredAbove | OrderTriggerCondition::TriggeredBelow
+    )
+}
+
+pub unsafe fn order_get_limit_price(
+    order: &types::Order,
+    valid_oracle_price: Option<i64>,
+    fallback_price: Option<u64>,
+    slot: u64,
+    tick_size: u64,
+    is_prediction_market: bool,
+    pmm_params: Option<ProtectedMakerParams>,
+) -> FfiResult<Option<u64>> {
+    to_ffi_result(get_limit_price(
+        order,
+        valid_oracle_price,
+        fallback_price,
+        slot,
+        tick_size,
+        is_prediction_market,
+        pmm_params,
+    ))
+}
+
+pub unsafe fn perp_market_get_margin_ratio(
+    market: &accounts::PerpMarket,
+    size: u128,
+    margin_type: MarginRequirementType,
+    high_leverage_mode: bool,
+) -> FfiResult<u32> {
+    to_ffi_result(perp_margin_ratio(
+        market,
+        size,
+        margin_type,
+        high_leverage_mode,
+    ))
+}
+
+pub unsafe fn perp_market_get_open_interest(market: &accounts::PerpMarket) -> u128 {
+    market
+        .amm
+        .base_asset_amount_long
+        .as_i128()
+        .unsigned_abs()
+        .max(market.amm.base_asset_amount_short.as_i128().unsigned_abs())
+}
+
+pub unsafe fn perp_market_get_protected_maker_params(
+    market: &accounts::PerpMarket,
+) -> ProtectedMakerParams {
+    let dynamic_offset = if market.protected_maker_dynamic_divisor > 0 {
+        market.amm.oracle_std.max(market.amm.mark_std)
+            / market.protected_maker_dynamic_divisor as u64
+    } else {
+        0
+    };
+
+    ProtectedMakerParams {
+        limit_price_divisor: market.protected_maker_limit_price_divisor,
+        dynamic_offset,
+        tick_size: market.amm.order_tick_size,
+    }
+}
+
+/// Only the oracle trigger price is supported, median trigger prices return an error
+pub unsafe fn perp_market_get_trigger_price(
+    _market: &accounts::PerpMarket,
+    oracle_price: i64,
+    _now: i64,
+    use_median_trigger_price: bool,
+) -> FfiResult<u64> {
+    if use_median_trigger_price {
+        return to_ffi_result(Err(ErrorCode::DefaultError));
+    }
+    to_ffi_result(Ok(oracle_price.unsigned_abs()))
+}
+
+/// MM oracle validity is not evaluated, the exchange oracle is always the safe oracle
+pub unsafe fn perp_market_get_mm_oracle_price_data(
+    market: &accounts::PerpMarket,
+    oracle_price_data: OraclePriceData,
+    clock_slot: Slot,
+    _oracle_guard_rails: &ValidityGuardRails,
+) -> FfiResult<MMOraclePriceData> {
+    let mm_oracle_price = market.amm.mm_oracle_price;
+    let mm_exchange_diff_bps =
+        (mm_oracle_price as i128 - oracle_price_data.price as i128).unsigned_abs() * 10_000
+            / oracle_price_data.price.unsigned_abs().max(1) as u128;
+
+    to_ffi_result(Ok(MMOraclePriceData {
+        mm_oracle_price,
+        mm_oracle_delay: clock_slot.saturating_sub(market.amm.mm_oracle_slot) as i64,
+        mm_oracle_validity: types::OracleValidity::default(),
+        mm_exchange_diff_bps,
+        exchange_oracle_price_data: oracle_price_data,
+        safe_oracle_price_data: oracle_price_data,
+    }))
+}
+
+pub unsafe fn perp_market_get_fallback_price(
+    _market: &accounts::PerpMarket,
+    _taker_direction: PositionDirection,
+    _oracle_price: i64,
+    _seconds_til_expiry: i64,
+) -> FfiResult<u64> {
+    to_ffi_result(Err(ErrorCode::DefaultError))
+}
+
+pub unsafe fn perp_position_get_unrealized_pnl(
+    position: &types::PerpPosition,
+    oracle_price: i64,
+) -> FfiResult<i128> {
+    to_ffi_result(unrealized_pnl(position, oracle_price))
+}
+
+pub unsafe fn perp_position_is_available(position: &types::PerpPosition) -> bool {
+    is_perp_position_available(position)
+}
+
+pub unsafe fn perp_position_is_open_position(position: &types::PerpPosition) -> bool {
+    position.base_asset_amount != 0
+}
+
+pub unsafe fn perp_position_worst_case_base_asset_amount(
+    position: &types::PerpPosition,
+    oracle_price: i64,
+    contract_type: ContractType,
+) -> FfiResult<i128> {
+    to_ffi_result(
+        worst_case_liability_value(position, oracle_price, contract_type)
+            .map(|(base_asset_amount, _)| base_asset_amount),
+    )
+}
+
+pub unsafe fn spot_market_get_asset_weight(
+    market: &accounts::SpotMarket,
+    size: u128,
+    oracle_price: i64,
+    margin_requirement_type: MarginRequirementType,
+) -> FfiResult<u32> {
+    to_ffi_result(spot_asset_weight(
+        market,
+        size,
+        oracle_price,
+        margin_requirement_type,
+    ))
+}
+
+pub unsafe fn spot_market_get_liability_weight(
+    market: &accounts::SpotMarket,
+    size: u128,
+    margin_requirement_type: MarginRequirementType,
+) -> FfiResult<u32> {
+    to_ffi_result(spot_liability_weight(market, size, margin_requirement_type))
+}
+
+pub unsafe fn spot_position_is_available(position: &types::SpotPosition) -> bool {
+    is_spot_position_available(position)
+}
+
+pub unsafe fn spot_position_get_signed_token_amount(
+    position: &types::SpotPosition,
+    market: &accounts::SpotMarket,
+) -> FfiResult<i128> {
+    to_ffi_result(signed_token_amount(position, market))
+}
+
+pub unsafe fn spot_position_get_token_amount(
+    position: &types::SpotPosition,
+    market: &accounts::SpotMarket,
+) -> FfiResult<u128> {
+    to_ffi_result(token_amount(position, market))
+}
+
+pub unsafe fn user_get_spot_position(
+    user: &accounts::User,
+    market_index: u16,
+) -> FfiResult<&types::SpotPosition> {
+    to_ffi_result(
+        user.spot_positions
+            .iter()
+            .find(|p| p.market_index == market_index && !is_spot_position_available(p))
+            .ok_or(ErrorCode::CouldNotFindSpotPosition),
+    )
+}
+
+pub unsafe fn user_get_perp_position(
+    user: &accounts::User,
+    market_index: u16,
+) -> FfiResult<&types::PerpPosition> {
+    to_ffi_result(
+        user.perp_positions
+            .iter()
+            .find(|p| p.market_index == market_index && !is_perp_position_available(p))
+            .ok_or(ErrorCode::UserHasNoPositionInMarket),
+    )
+}
+
+pub unsafe fn user_update_perp_position_max_margin_ratio(
+    user: &mut accounts::User,
+    market_index: u16,
+    margin_ratio: u16,
+) -> FfiResult<()> {
+    let existing = user
+        .perp_positions
+        .iter()
+        .position(|p| p.market_index == market_index && !is_perp_position_available(p));
+    let idx = match existing {
+        Some(idx) => Some(idx),
+        None => user
+            .perp_positions
+            .iter()
+            .position(is_perp_position_available),
+    };
+
+    let Some(idx) = idx else {
+        return to_ffi_result(Err(ErrorCode::MaxNumberOfPositions));
+    };
+    let position = &mut user.perp_positions[idx];
+    if existing.is_none() {
+        *position = types::PerpPosition {
+            market_index,
+            ..Default::default()
+        };
+    }
+    position.max_margin_ratio = margin_ratio;
+
+    to_ffi_result(Ok(()))
+}
+
+pub unsafe fn orders_place_perp_order<'a>(
+    _user: &accounts::User,
+    _state: &accounts::State,
+    _order_params: &types::OrderParams,
+    _accounts: &mut AccountsList,
+    _high_leverage_mode_config: Option<&'a AccountInfo<'a>>,
+    _revenue_order_share: &mut Option<&mut RevenueShareOrder>,
+) -> FfiResult<bool> {
+    to_ffi_result(Err(ErrorCode::DefaultError))
+}
+
+pub unsafe fn order_params_will_auction_params_sanitize(
+    _order_params: &types::OrderParams,
+    _perp_market: &accounts::PerpMarket,
+    _oracle_price: i64,
+    _is_signed_msg: bool,
+) -> FfiResult<bool> {
+    to_ffi_result(Err(ErrorCode::DefaultError))
+}
+
+/// Not supported, `order_params` are left unchanged
+pub unsafe fn order_params_update_perp_auction_params(
+    _order_params: &mut types::OrderParams,
+    _perp_market: &accounts::PerpMarket,
+    _oracle_price: i64,
+    _is_signed_msg: bool,
+) {
+}
+
+pub unsafe fn order_calculate_auction_params_for_trigger_order(
+    _order: &types::Order,
+    _oracle_price: &OraclePriceData,
+    _perp_market: Option<&accounts::PerpMarket>,
+) -> FfiResult<(u8, i64, i64)> {
+    to_ffi_result(Err(ErrorCode::DefaultError))
+}
+
+pub unsafe fn margin_calculate_simplified_margin_requirement(
+    user: &accounts::User,
+    market_state: &MarketStateData,
+    margin_type: MarginRequirementType,
+    margin_buffer: u32,
+) -> FfiResult<SimplifiedMarginCalculation> {
+    let params = MarginParams::new(user, margin_type, margin_buffer);
+    let mut total = PositionMargin::default();
+
+    for position in user
+        .spot_positions
+        .iter()
+        .filter(|p| !is_spot_position_available(p))
+    {
+        match spot_position_margin_from_state(position, market_state, &params) {
+            Ok(margin) => total.add(&margin),
+            Err(err) => return to_ffi_result(Err(err)),
+        }
+    }
+    for position in user
+        .perp_positions
+        .iter()
+        .filter(|p| !is_perp_position_available(p))
+    {
+        match perp_position_margin_from_state(position, market_state, &params) {
+            Ok(margin) => total.add(&margin),
+            Err(err) => return to_ffi_result(Err(err)),
+        }
+    }
+
+    to_ffi_result(Ok(SimplifiedMarginCalculation {
+        total_collateral: total.collateral,
+        total_collateral_buffer: total.collateral_buffer,
+        margin_requirement: total.requirement,
+        margin_requirement_plus_buffer: total.requirement_plus_buffer,
+    }))
+}
+
+/// Panics if a position's margin can't be calculated, see the `try_` variant
+pub unsafe fn incremental_margin_calculation_from_user(
+    user: &accounts::User,
+    market_state: &MarketStateData,
+    margin_type: MarginRequirementType,
+    timestamp: u64,
+    margin_buffer: u32,
+) -> IncrementalMarginCalculation {
+    incremental_from_user(user, market_state, margin_type, timestamp, margin_buffer)
+        .unwrap_or_else(|err| panic!("incremental margin calculation failed: {err:?}"))
+}
+
+/// Panics if the position's margin can't be calculated, see the `try_` variant
+pub unsafe fn incremental_margin_calculation_update_spot_position(
+    cached: &mut IncrementalMarginCalculation,
+    spot_position: &types::SpotPosition,
+    market_state: &MarketStateData,
+    timestamp: u64,
+) {
+    incremental_update_spot_position(cached, spot_position, market_state, timestamp)
+        .unwrap_or_else(|err| panic!("incremental margin calculation failed: {err:?}"))
+}
+
+/// Panics if the position's margin can't be calculated, see the `try_` variant
+pub unsafe fn incremental_margin_calculation_update_perp_position(
+    cached: &mut IncrementalMarginCalculation,
+    perp_position: &types::PerpPosition,
+    market_state: &MarketStateData,
+    timestamp: u64,
+) {
+    incremental_update_perp_position(cached, perp_position, market_state, timestamp)
+        .unwrap_or_else(|err| panic!("incremental margin calculation failed: {err:?}"))
+}
+
+pub(crate) fn try_incremental_margin_calculation_from_user(
+    user: &accounts::User,
+    market_state: &MarketStateData,
+    margin_type: MarginRequirementType,
+    timestamp: u64,
+    margin_buffer: u32,
+) -> FfiResult<IncrementalMarginCalculation> {
+    to_ffi_result(incremental_from_user(
+        user,
+        market_state,
+        margin_type,
+        timestamp,
+        margin_buffer,
+    ))
+}
+
+/// `cached` is unchanged on error
+pub(crate) fn try_incremental_margin_calculation_update_spot_position(
+    cached: &mut IncrementalMarginCalculation,
+    spot_position: &types::SpotPosition,
+    market_state: &MarketStateData,
+    timestamp: u64,
+) -> FfiResult<()> {
+    to_ffi_result(incremental_update_spot_position(
+        cached,
+        spot_position,
+        market_state,
+        timestamp,
+    ))
+}
+
+/// `cached` is unchanged on error
+pub(crate) fn try_incremental_margin_calculation_update_perp_position(
+    cached: &mut IncrementalMarginCalculation,
+    perp_position: &types::PerpPosition,
+    market_state: &MarketStateData,
+    timestamp: u64,
+) -> FfiResult<()> {
+    to_ffi_result(incremental_update_perp_position(
+        cached,
+        perp_position,
+        market_state,
+        timestamp,
+    ))
+}
+
+//
+// Orders
+//
+
+fn is_auction_complete(order: &types::Order, slot: u64) -> NativeResult<bool> {
+    if order.auction_duration == 0 {
+        return Ok(true);
+    }
+    let slots_elapsed = slot.checked_sub(order.slot).ok_or(ErrorCode::MathError)?;
+
+    Ok(slots_elapsed > order.auction_duration as u64)
+}
+
+fn has_auction_price(order: &types::Order, slot: u64) -> NativeResult<bool> {
+    let has_auction_prices = order.auction_start_price != 0 || order.auction_end_price != 0;
+
+    Ok(!is_auction_complete(order, slot)? && has_auction_prices)
+}
+
+fn is_resting_limit_order(order: &types::Order, slot: u64) -> NativeResult<bool> {
+    if !matches!(order.order_type, OrderType::Limit | OrderType::TriggerLimit) {
+        return Ok(false);
+    }
+
+    if order.order_type == OrderType::TriggerLimit {
+        return match order.direction {
+            PositionDirection::Long if order.trigger_price < order.price => Ok(false),
+            PositionDirection::Short if order.trigger_price > order.price => Ok(false),
+            _ => is_auction_complete(order, slot),
+        };
+    }
+
+    Ok(order.post_only || is_auction_complete(order, slot)?)
+}
+
+fn standardize_price(
+    price: u64,
+    tick_size: u64,
+    direction: PositionDirection,
+) -> NativeResult<u64> {
+    if price == 0 {
+        return Ok(0);
+    }
+    let remainder = price.checked_rem(tick_size).ok_or(ErrorCode::MathError)?;
+    if remainder == 0 {
+        return Ok(price);
+    }
+
+    match direction {
+        PositionDirection::Long => Ok(price - remainder),
+        PositionDirection::Short => (price + tick_size)
+            .checked_sub(remainder)
+            .ok_or(ErrorCode::MathError),
+    }
+}
+
+fn standardize_price_i64(
+    price: i64,
+    tick_size: i64,
+    direction: PositionDirection,
+) -> NativeResult<i64> {
+    if price == 0 {
+        return Ok(0);
+    }
+    let remainder = price
+        .checked_rem_euclid(tick_size)
+        .ok_or(ErrorCode::MathError)?;
+    if remainder == 0 {
+        return Ok(price);
+    }
+
+    match direction {
+        PositionDirection::Long => Ok(price - remainder),
+        PositionDirection::Short => Ok(price + tick_size - remainder),
+    }
+}
+
+/// Linearly interpolate from auction start to end price
+fn interpolate_auction_price(order: &types::Order, slot: u64) -> NativeResult<i64> {
+    let slots_elapsed = slot.checked_sub(order.slot).ok_or(ErrorCode::MathError)?;
+    let delta_numerator = slots_elapsed.min(order.auction_duration as u64) as i128;
+    let delta_denominator = order.auction_duration as i128;
+    let start = order.auction_start_price as i128;
+    let end = order.auction_end_price as i128;
+
+    if delta_denominator == 0 {
+        return Ok(order.auction_end_price);
+    }
+
+    let price = match order.direction {
+        PositionDirection::Long => start + (end - start) * delta_numerator / delta_denominator,
+        PositionDirection::Short => start - (start - end) * delta_numerator / delta_denominator,
+    };
+
+    i64::try_from(price).map_err(|_| ErrorCode::CastingFailure)
+}
+
+fn calculate_auction_price(
+    order: &types::Order,
+    slot: u64,
+    tick_size: u64,
+    valid_oracle_price: Option<i64>,
+    is_prediction_market: bool,
+) -> NativeResult<u64> {
+    match order.order_type {
+        OrderType::Market
+        | OrderType::TriggerMarket
+        | OrderType::Limit
+        | OrderType::TriggerLimit => {
+            let price = interpolate_auction_price(order, slot)?;
+            let price = u64::try_from(price).map_err(|_| ErrorCode::CastingFailure)?;
+            standardize_price(price, tick_size, order.direction)
+        }
+        OrderType::Oracle => {
+            let oracle_price = valid_oracle_price.ok_or(ErrorCode::OracleNotFound)?;
+            let price_offset = interpolate_auction_price(order, slot)?;
+            let tick_size = i64::try_from(tick_size).map_err(|_| ErrorCode::CastingFailure)?;
+            let price = oracle_price
+                .checked_add(price_offset)
+                .ok_or(ErrorCode::MathError)?
+                .max(tick_size);
+            let mut price = standardize_price_i64(price, tick_size, order.direction)?;
+            if is_prediction_market {
+                price = price.min(MAX_PREDICTION_MARKET_PRICE as i64);
+            }
+
+            u64::try_from(price).map_err(|_| ErrorCode::CastingFailure)
+        }
+    }
+}
+
+fn get_limit_price(
+    order: &types::Order,
+    valid_oracle_price: Option<i64>,
+    fallback_price: Option<u64>,
+    slot: u64,
+    tick_size: u64,
+    is_prediction_market: bool,
+    pmm_params: Option<ProtectedMakerParams>,
+) -> NativeResult<Option<u64>> {
+    if has_auction_price(order, slot)? {
+        return calculate_auction_price(
+            order,
+            slot,
+            tick_size,
+            valid_oracle_price,
+            is_prediction_market,
+        )
+        .map(Some);
+    }
+
+    let price = if order.oracle_price_offset != 0 {
+        let oracle_price = valid_oracle_price.ok_or(ErrorCode::OracleNotFound)?;
+        let tick_size_i64 = i64::try_from(tick_size).map_err(|_| ErrorCode::CastingFailure)?;
+        let price = oracle_price
+            .checked_add(order.oracle_price_offset as i64)
+            .ok_or(ErrorCode::MathError)?
+            .max(tick_size_i64);
+        let price = u64::try_from(price).map_err(|_| ErrorCode::CastingFailure)?;
+        standardize_price(price, tick_size, order.direction)?
+    } else if order.price == 0 {
+        match fallback_price {
+            Some(price) => standardize_price(price, tick_size, order.direction)?,
+            None => return Ok(None),
+        }
+    } else {
+        order.price
+    };
+
+    let price = match pmm_params {
+        Some(params) => {
+            apply_protected_maker_offset(price, order.direction, &params, is_prediction_market)?
+        }
+        None => price,
+    };
+
+    Ok(Some(price))
+}
+
+/// Moves a protected maker's resting limit price away from the oracle
+fn apply_protected_maker_offset(
+    price: u64,
+    direction: PositionDirection,
+    params: &ProtectedMakerParams,
+    is_prediction_market: bool,
+) -> NativeResult<u64> {
+    let divisor = if params.limit_price_divisor > 0 {
+        params.limit_price_divisor as u64
+    } else {
+        1_000 // 10bps
+    };
+    let offset = (price / divisor).max(params.dynamic_offset);
+
+    let price = match direction {
+        PositionDirection::Long => price.saturating_sub(offset).max(params.tick_size),
+        PositionDirection::Short => price.checked_add(offset).ok_or(ErrorCode::MathError)?,
+    };
+    let price = standardize_price(price, params.tick_size.max(1), direction)?;
+
+    if is_prediction_market {
+        Ok(price.min(MAX_PREDICTION_MARKET_PRICE))
+    } else {
+        Ok(price)
+    }
+}
+
+//
+// Markets
+//
+
+fn size_in_amm_reserve_precision(size: u128, decimals: u32) -> NativeResult<u128> {
+    let size_precision = 10_u128.pow(decimals);
+    if size_precision > AMM_RESERVE_PRECISION {
+        Ok(size / (size_precision / AMM_RESERVE_PRECISION))
+    } else {
+        size.checked_mul(AMM_RESERVE_PRECISION)
+            .map(|x| x / size_precision)
+            .ok_or(ErrorCode::MathError)
+    }
+}
+
+fn calculate_size_premium_liability_weight(
+    size: u128,
+    imf_factor: u32,
+    liability_weight: u32,
+    precision: u128,
+) -> NativeResult<u32> {
+    if imf_factor == 0 {
+        return Ok(liability_weight);
+    }
+
+    let size_sqrt = (size * 10 + 1).isqrt();
+    let liability_weight_numerator = (liability_weight - liability_weight / 5) as u128;
+    let denom = 100_000 * SPOT_IMF_PRECISION as u128 / precision;
+    let size_premium_liability_weight = size_sqrt
+        .checked_mul(imf_factor as u128)
+        .map(|x| liability_weight_numerator + x / denom)
+        .ok_or(ErrorCode::MathError)?;
+    let size_premium_liability_weight =
+        u32::try_from(size_premium_liability_weight).map_err(|_| ErrorCode::CastingFailure)?;
+
+    Ok(liability_weight.max(size_premium_liability_weight))
+}
+
+fn calculate_size_discount_asset_weight(
+    size: u128,
+    imf_factor: u32,
+    asset_weight: u32,
+) -> NativeResult<u32> {
+    if imf_factor == 0 {
+        return Ok(asset_weight);
+    }
+
+    let size_sqrt = (size * 10 + 1).isqrt();
+    let imf_numerator = SPOT_IMF_PRECISION as u128 + SPOT_IMF_PRECISION as u128 / 10;
+    let denom = SPOT_IMF_PRECISION as u128
+        + size_sqrt
+            .checked_mul(imf_factor as u128)
+            .ok_or(ErrorCode::MathError)?
+            / 100_000;
+    let size_discount_asset_weight = imf_numerator * SPOT_WEIGHT_PRECISION_U128 / denom;
+    let size_discount_asset_weight =
+        u32::try_from(size_discount_asset_weight).map_err(|_| ErrorCode::CastingFailure)?;
+
+    Ok(asset_weight.min(size_discount_asset_weight))
+}
+
+fn perp_margin_ratio(
+    market: &accounts::PerpMarket,
+    size: u128,
+    margin_type: MarginRequirementType,
+    high_leverage_mode: bool,
+) -> NativeResult<u32> {
+    if market.status == MarketStatus::Settlement {
+        return Ok(0);
+    }
+
+    let (margin_ratio_initial, margin_ratio_maintenance) = if high_leverage_mode
+        && market.high_leverage_margin_ratio_initial > 0
+        && market.high_leverage_margin_ratio_maintenance > 0
+    {
+        (
+            market.high_leverage_margin_ratio_initial as u32,
+            market.high_leverage_margin_ratio_maintenance as u32,
+        )
+    } else {
+        (market.margin_ratio_initial, market.margin_ratio_maintenance)
+    };
+
+    let default_margin_ratio = match margin_type {
+        MarginRequirementType::Initial => margin_ratio_initial,
+        MarginRequirementType::Fill => (margin_ratio_initial + margin_ratio_maintenance) / 2,
+        MarginRequirementType::Maintenance => margin_ratio_maintenance,
+    };
+
+    calculate_size_premium_liability_weight(
+        size,
+        market.imf_factor,
+        default_margin_ratio,
+        MARGIN_PRECISION_U128,
+    )
+}
+
+/// Returns the unrealized pnl asset weight in SPOT_WEIGHT_PRECISION
+fn unrealized_pnl_asset_weight(
+    market: &accounts::PerpMarket,
+    unrealized_pnl: i128,
+    margin_type: MarginRequirementType,
+) -> NativeResult<u32> {
+    if unrealized_pnl <= 0 {
+        return Ok(SPOT_WEIGHT_PRECISION);
+    }
+
+    match margin_type {
+        MarginRequirementType::Initial | MarginRequirementType::Fill => {
+            let mut asset_weight = market.unrealized_pnl_initial_asset_weight;
+            // discount pnl when users are owed more than the market's max imbalance
+            if asset_weight > 0 && market.unrealized_pnl_max_imbalance > 0 {
+                let net_user_pnl = net_user_pnl(
+                    &market.amm,
+                    market.amm.historical_oracle_data.last_oracle_price,
+                )?;
+                let max_imbalance = market.unrealized_pnl_max_imbalance as i128;
+                if net_user_pnl > max_imbalance {
+                    asset_weight = u32::try_from(
+                        asset_weight as u128 * max_imbalance as u128 / net_user_pnl.unsigned_abs(),
+                    )
+                    .map_err(|_| ErrorCode::CastingFailure)?;
+                }
+            }
+            calculate_size_discount_asset_weight(
+                unrealized_pnl.unsigned_abs() * AMM_TO_QUOTE_PRECISION_RATIO,
+                market.unrealized_pnl_imf_factor,
+                asset_weight,
+            )
+        }
+        MarginRequirementType::Maintenance => Ok(market.unrealized_pnl_maintenance_asset_weight),
+    }
+}
+
+/// Net pnl the market owes to users (QUOTE_PRECISION)
+fn net_user_pnl(amm: &types::AMM, oracle_price: i64) -> NativeResult<i128> {
+    if oracle_price <= 0 {
+        return Err(ErrorCode::InvalidOracle);
+    }
+    let net_user_base_asset_value = (amm.base_asset_amount_with_amm.as_i128()
+        + amm.base_asset_amount_with_unsettled_lp.as_i128())
+    .checked_mul(oracle_price as i128)
+    .ok_or(ErrorCode::MathError)?
+        / PRICE_TIMES_AMM_TO_QUOTE_PRECISION_RATIO_I128;
+
+    Ok(net_user_base_asset_value + amm.quote_asset_amount.as_i128()
+        - amm.net_unsettled_funding_pnl as i128)
+}
+
+fn scaled_initial_asset_weight(
+    market: &accounts::SpotMarket,
+    oracle_price: i64,
+) -> NativeResult<u32> {
+    if market.scale_initial_asset_weight_start == 0 {
+        return Ok(market.initial_asset_weight);
+    }
+
+    let deposits = get_token_amount(
+        market.deposit_balance.as_u128(),
+        market,
+        SpotBalanceType::Deposit,
+    )
+    .map_err(|_| ErrorCode::MathError)?;
+    let deposits_value =
+        token_value(deposits as i128, market.decimals, oracle_price)?.max(0) as u128;
+    let scale_start = market.scale_initial_asset_weight_start as u128;
+
+    if deposits_value < scale_start {
+        Ok(market.initial_asset_weight)
+    } else {
+        u32::try_from(market.initial_asset_weight as u128 * scale_start / deposits_value)
+            .map_err(|_| ErrorCode::CastingFailure)
+    }
+}
+
+fn spot_asset_weight(
+    market: &accounts::SpotMarket,
+    size: u128,
+    oracle_price: i64,
+    margin_type: MarginRequirementType,
+) -> NativeResult<u32> {
+    let size = size_in_amm_reserve_precision(size, market.decimals)?;
+    let asset_weight = match margin_type {
+        MarginRequirementType::Initial => scaled_initial_asset_weight(market, oracle_price)?,
+        MarginRequirementType::Fill => {
+            (scaled_initial_asset_weight(market, oracle_price)? + market.maintenance_asset_weight)
+                / 2
+        }
+        MarginRequirementType::Maintenance => market.maintenance_asset_weight,
+    };
+
+    calculate_size_discount_asset_weight(size, market.imf_factor, asset_weight)
+}
+
+fn spot_liability_weight(
+    market: &accounts::SpotMarket,
+    size: u128,
+    margin_type: MarginRequirementType,
+) -> NativeResult<u32> {
+    let size = size_in_amm_reserve_precision(size, market.decimals)?;
+    let liability_weight = match margin_type {
+        MarginRequirementType::Initial => market.initial_liability_weight,
+        MarginRequirementType::Fill => {
+            (market.initial_liability_weight + market.maintenance_liability_weight) / 2
+        }
+        MarginRequirementType::Maintenance => market.maintenance_liability_weight,
+    };
+
+    calculate_size_premium_liability_weight(
+        size,
+        market.imf_factor,
+        liability_weight,
+        SPOT_WEIGHT_PRECISION_U128,
+    )
+}
+
+//
+// Positions
+//
+
+fn is_spot_position_available(position: &types::SpotPosition) -> bool {
+    position.scaled_balance == 0 && position.open_orders == 0
+}
+
+fn is_perp_position_available(position: &types::PerpPosition) -> bool {
+    position.base_asset_amount == 0
+        && position.open_orders == 0
+        && position.open_bids == 0
+        && position.open_asks == 0
+        && position.quote_asset_amount == 0
+        && position.lp_shares == 0
+}
+
+fn token_amount(
+    position: &types::SpotPosition,
+    market: &accounts::SpotMarket,
+) -> NativeResult<u128> {
+    get_token_amount(
+        position.scaled_balance as u128,
+        market,
+        position.balance_type,
+    )
+    .map_err(|_| ErrorCode::MathError)
+}
+
+fn signed_token_amount(
+    position: &types::SpotPosition,
+    market: &accounts::SpotMarket,
+) -> NativeResult<i128> {
+    let amount =
+        i128::try_from(token_amount(position, market)?).map_err(|_| ErrorCode::CastingFailure)?;
+    match position.balance_type {
+        SpotBalanceType::Deposit => Ok(amount),
+        SpotBalanceType::Borrow => Ok(-amount),
+    }
+}
+
+/// Returns token value in QUOTE_PRECISION
+fn token_value(token_amount: i128, decimals: u32, oracle_price: i64) -> NativeResult<i128> {
+    if token_amount == 0 {
+        return Ok(0);
+    }
+    token_amount
+        .checked_mul(oracle_price as i128)
+        .map(|x| x / 10_i128.pow(decimals))
+        .ok_or(ErrorCode::MathError)
+}
+
+fn unrealized_pnl(position: &types::PerpPosition, oracle_price: i64) -> NativeResult<i128> {
+    let base_asset_value = (position.base_asset_amount.unsigned_abs() as u128)
+        .checked_mul(oracle_price.max(0) as u128)
+        .map(|x| (x / AMM_RESERVE_PRECISION) as i128)
+        .ok_or(ErrorCode::MathError)?;
+    let base_asset_value = if position.base_asset_amount < 0 {
+        -base_asset_value
+    } else {
+        base_asset_value
+    };
+
+    Ok(base_asset_value + position.quote_asset_amount as i128)
+}
+
+/// Returns liability value in QUOTE_PRECISION
+fn perp_liability_value(
+    base_asset_amount: i128,
+    oracle_price: i64,
+    contract_type: ContractType,
+) -> NativeResult<u128> {
+    let price = if contract_type == ContractType::Prediction && base_asset_amount < 0 {
+        (MAX_PREDICTION_MARKET_PRICE as i64 - oracle_price).max(0)
+    } else {
+        oracle_price
+    };
+
+    base_asset_amount
+        .unsigned_abs()
+        .checked_mul(price.unsigned_abs() as u128)
+        .map(|x| x / BASE_PRECISION_I128 as u128)
+        .ok_or(ErrorCode::MathError)
+}
+
+/// Returns the (base asset amount, liability value) if all bids or all asks were filled, whichever is worse
+fn worst_case_liability_value(
+    position: &types::PerpPosition,
+    oracle_price: i64,
+    contract_type: ContractType,
+) -> NativeResult<(i128, u128)> {
+    let all_bids_fill = position.base_asset_amount as i128 + position.open_bids as i128;
+    let all_asks_fill = position.base_asset_amount as i128 + position.open_asks as i128;
+    let all_bids_liability = perp_liability_value(all_bids_fill, oracle_price, contract_type)?;
+    let all_asks_liability = perp_liability_value(all_asks_fill, oracle_price, contract_type)?;
+
+    if all_asks_liability >= all_bids_liability {
+        Ok((all_asks_fill, all_asks_liability))
+    } else {
+        Ok((all_bids_fill, all_bids_liability))
+    }
+}
+
+//
+// Oracles
+//
+
+const PYTH_EXPO_OFFSET: usize = 20;
+const PYTH_NUM_OFFSET: usize = 24;
+const PYTH_NUM_QT_OFFSET: usize = 28;
+const PYTH_VALID_SLOT_OFFSET: usize = 40;
+const PYTH_AGG_PRICE_OFFSET: usize = 208;
+const PYTH_AGG_CONF_OFFSET: usize = 216;
+
+const SB_ON_DEMAND_DISCRIMINATOR: [u8; 8] = [196, 27, 108, 196, 10, 215, 219, 40];
+const SB_ON_DEMAND_ACCOUNT_SIZE: usize = 3_208;
+const SB_ON_DEMAND_RESULT_OFFSET: usize = 2_264;
+/// switchboard on-demand values are 18 decimal fixed point
+const SB_ON_DEMAND_PRECISION: i128 = 1_000_000_000_000_000_000;
+
+fn read_bytes<const N: usize>(data: &[u8], offset: usize) -> NativeResult<[u8; N]> {
+    data.get(offset..offset + N)
+        .and_then(|b| b.try_into().ok())
+        .ok_or(ErrorCode::UnableToLoadOracle)
+}
+
+fn read_i64(data: &[u8], offset: usize) -> NativeResult<i64> {
+    read_bytes(data, offset).map(i64::from_le_bytes)
+}
+
+fn read_u64(data: &[u8], offset: usize) -> NativeResult<u64> {
+    read_bytes(data, offset).map(u64::from_le_bytes)
+}
+
+fn read_i32(data: &[u8], offset: usize) -> NativeResult<i32> {
+    read_bytes(data, offset).map(i32::from_le_bytes)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> NativeResult<u32> {
+    read_bytes(data, offset).map(u32::from_le_bytes)
+}
+
+fn read_i128(data: &[u8], offset: usize) -> NativeResult<i128> {
+    read_bytes(data, offset).map(i128::from_le_bytes)
+}
+
+fn read_anchor_account<T: bytemuck::Pod + Discriminator>(data: &[u8]) -> Option<T> {
+    let disc_len = T::DISCRIMINATOR.len();
+    let body = data.get(disc_len..disc_len + std::mem::size_of::<T>())?;
+    if &data[..disc_len] != T::DISCRIMINATOR {
+        return None;
+    }
+
+    Some(bytemuck::pod_read_unaligned(body))
+}
+
+/// Raw pyth style price before scaling to PRICE_PRECISION
+struct RawOraclePrice {
+    price: i64,
+    confidence: u64,
+    exponent: i32,
+    published_slot: u64,
+    has_sufficient_number_of_data_points: bool,
+    sequence_id: Option<u64>,
+}
+
+/// Pyth v2 push oracle account
+fn read_pyth_legacy(data: &[u8]) -> NativeResult<RawOraclePrice> {
+    let num = read_u32(data, PYTH_NUM_OFFSET)?;
+    let num_qt = read_u32(data, PYTH_NUM_QT_OFFSET)?;
+
+    Ok(RawOraclePrice {
+        price: read_i64(data, PYTH_AGG_PRICE_OFFSET)?,
+        confidence: read_u64(data, PYTH_AGG_CONF_OFFSET)?,
+        exponent: read_i32(data, PYTH_EXPO_OFFSET)?,
+        published_slot: read_u64(data, PYTH_VALID_SLOT_OFFSET)?,
+        has_sufficient_number_of_data_points: num_qt >= num.min(3),
+        sequence_id: None,
+    })
+}
+
+/// Pyth receiver `PriceUpdateV2` account (borsh)
+fn read_pyth_pull(data: &[u8]) -> NativeResult<RawOraclePrice> {
+    // discriminator + write_authority
+    let mut offset = 8 + 32;
+    // verification_level: Partial { num_signatures: u8 } | Full
+    offset += match data.get(offset) {
+        Some(0) => 2,
+        Some(1) => 1,
+        _ => return Err(ErrorCode::UnableToLoadOracle),
+    };
+    // feed_id
+    offset += 32;
+
+    let price = read_i64(data, offset)?;
+    let confidence = read_u64(data, offset + 8)?;
+    let exponent = read_i32(data, offset + 16)?;
+    let publish_time = read_i64(data, offset + 20)?;
+    // prev_publish_time, ema_price, ema_conf
+    let published_slot = read_u64(data, offset + 52)?;
+
+    Ok(RawOraclePrice {
+        price,
+        confidence,
+        exponent,
+        published_slot,
+        has_sufficient_number_of_data_points: true,
+        sequence_id: u64::try_from(publish_time).ok(),
+    })
+}
+
+fn read_pyth_lazer(data: &[u8]) -> NativeResult<RawOraclePrice> {
+    let oracle = read_anchor_account::<accounts::PythLazerOracle>(data)
+        .ok_or(ErrorCode::UnableToLoadOracle)?;
+
+    Ok(RawOraclePrice {
+        price: oracle.price,
+        confidence: oracle.conf,
+        exponent: oracle.exponent,
+        published_slot: oracle.posted_slot,
+        has_sufficient_number_of_data_points: true,
+        sequence_id: Some(oracle.publish_time),
+    })
+}
+
+/// Switchboard on-demand `PullFeedAccountData` account
+fn read_switchboard_on_demand(data: &[u8], slot: Slot) -> NativeResult<OraclePriceData> {
+    if data.len() < SB_ON_DEMAND_ACCOUNT_SIZE || data[..8] != SB_ON_DEMAND_DISCRIMINATOR {
+        return Err(ErrorCode::UnableToLoadOracle);
+    }
+    // CurrentResult { value, std_dev, mean, range, min_value, max_value, .., slot, .. }
+    let value = read_i128(data, SB_ON_DEMAND_RESULT_OFFSET)?;
+    let range = read_i128(data, SB_ON_DEMAND_RESULT_OFFSET + 48)?;
+    let result_slot = read_u64(data, SB_ON_DEMAND_RESULT_OFFSET + 104)?;
+    if result_slot == 0 {
+        return Err(ErrorCode::UnableToLoadOracle);
+    }
+
+    let scale_div = SB_ON_DEMAND_PRECISION / PRICE_PRECISION_I128;
+    Ok(OraclePriceData {
+        price: i64::try_from(value / scale_div).map_err(|_| ErrorCode::CastingFailure)?,
+        confidence: i64::try_from(range / scale_div)
+            .map_err(|_| ErrorCode::CastingFailure)?
+            .unsigned_abs(),
+        delay: slot as i64 - result_slot as i64,
+        has_sufficient_number_of_data_points: true,
+        sequence_id: Some(result_slot),
+    })
+}
+
+/// Scale a raw pyth price to PRICE_PRECISION, applying the market's price `multiple` e.g. 1K, 1M
+fn scale_oracle_price(
+    raw: RawOraclePrice,
+    multiple: u128,
+    slot: Slot,
+) -> NativeResult<OraclePriceData> {
+    let oracle_precision = 10_u128.pow(raw.exponent.unsigned_abs());
+    let (scale_mult, scale_div) = if oracle_precision > PRICE_PRECISION {
+        (1, oracle_precision / PRICE_PRECISION)
+    } else {
+        (PRICE_PRECISION / oracle_precision, 1)
+    };
+
+    let price = (raw.price as i128)
+        .checked_mul(multiple as i128 * scale_mult as i128)
+        .map(|x| x / scale_div as i128)
+        .ok_or(ErrorCode::MathError)?;
+    let confidence = (raw.confidence as u128)
+        .checked_mul(multiple * scale_mult)
+        .map(|x| x / scale_div)
+        .ok_or(ErrorCode::MathError)?;
+
+    Ok(OraclePriceData {
+        price: i64::try_from(price).map_err(|_| ErrorCode::CastingFailure)?,
+        confidence: u64::try_from(confidence).map_err(|_| ErrorCode::CastingFailure)?,
+        delay: slot as i64 - raw.published_slot as i64,
+        has_sufficient_number_of_data_points: raw.has_sufficient_number_of_data_points,
+        sequence_id: raw.sequence_id,
+    })
+}
+
+/// Snap stablecoin prices within 5bps (or confidence) of $1 to exactly $1
+fn clamp_stable_coin_price(mut oracle_price_data: OraclePriceData) -> OraclePriceData {
+    let five_bps = 500_i64;
+    let tolerance = (oracle_price_data.confidence.min(i64::MAX as u64) as i64).min(five_bps);
+    if (oracle_price_data.price - PRICE_PRECISION_I64).abs() <= tolerance {
+        oracle_price_data.price = PRICE_PRECISION_I64;
+    }
+
+    oracle_price_data
+}
+
+fn get_oracle_price(
+    oracle_source: OracleSource,
+    data: &[u8],
+    slot: Slot,
+) -> NativeResult<OraclePriceData> {
+    match oracle_source {
+        OracleSource::QuoteAsset => Ok(OraclePriceData {
+            price: PRICE_PRECISION_I64,
+            confidence: 1,
+            delay: 0,
+            has_sufficient_number_of_data_points: true,
+            sequence_id: None,
+        }),
+        OracleSource::Pyth => scale_oracle_price(read_pyth_legacy(data)?, 1, slot),
+        OracleSource::Pyth1K => scale_oracle_price(read_pyth_legacy(data)?, 1_000, slot),
+        OracleSource::Pyth1M => scale_oracle_price(read_pyth_legacy(data)?, 1_000_000, slot),
+        OracleSource::PythStableCoin => {
+            scale_oracle_price(read_pyth_legacy(data)?, 1, slot).map(clamp_stable_coin_price)
+        }
+        OracleSource::PythPull => scale_oracle_price(read_pyth_pull(data)?, 1, slot),
+        OracleSource::Pyth1KPull => scale_oracle_price(read_pyth_pull(data)?, 1_000, slot),
+        OracleSource::Pyth1MPull => scale_oracle_price(read_pyth_pull(data)?, 1_000_000, slot),
+        OracleSource::PythStableCoinPull => {
+            scale_oracle_price(read_pyth_pull(data)?, 1, slot).map(clamp_stable_coin_price)
+        }
+        OracleSource::PythLazer => scale_oracle_price(read_pyth_lazer(data)?, 1, slot),
+        OracleSource::PythLazer1K => scale_oracle_price(read_pyth_lazer(data)?, 1_000, slot),
+        OracleSource::PythLazer1M => scale_oracle_price(read_pyth_lazer(data)?, 1_000_000, slot),
+        OracleSource::PythLazerStableCoin => {
+            scale_oracle_price(read_pyth_lazer(data)?, 1, slot).map(clamp_stable_coin_price)
+        }
+        OracleSource::Prelaunch => {
+            let oracle = read_anchor_account::<accounts::PrelaunchOracle>(data)
+                .ok_or(ErrorCode::UnableToLoadOracle)?;
+            Ok(OraclePriceData {
+                price: oracle.price,
+                confidence: oracle.confidence,
+                delay: oracle.amm_last_update_slot.saturating_sub(slot) as i64,
+                has_sufficient_number_of_data_points: true,
+                sequence_id: None,
+            })
+        }
+        OracleSource::SwitchboardOnDemand => read_switchboard_on_demand(data, slot),
+        // switchboard v2 feeds are no longer accepted by the program
+        OracleSource::Switchboard => Err(ErrorCode::InvalidOracle),
+    }
+}
+
+//
+// Margin
+//
+
+/// Per user context shared by all position calculations
+struct MarginParams {
+    margin_type: MarginRequirementType,
+    user_custom_margin_ratio: u32,
+    user_high_leverage_mode: bool,
+    margin_buffer: u32,
+}
+
+impl MarginParams {
+    fn new(user: &accounts::User, margin_type: MarginRequirementType, margin_buffer: u32) -> Self {
+        Self {
+            margin_type,
+            user_custom_margin_ratio: if margin_type == MarginRequirementType::Initial {
+                user.max_margin_ratio
+            } else {
+                0
+            },
+            user_high_leverage_mode: user.margin_mode.is_high_leverage_mode(margin_type),
+            margin_buffer,
+        }
+    }
+
+    fn from_incremental(cached: &IncrementalMarginCalculation) -> Self {
+        Self {
+            margin_type: cached.margin_type,
+            user_custom_margin_ratio: cached.user_custom_margin_ratio,
+            user_high_leverage_mode: cached.user_high_leverage_mode,
+            margin_buffer: cached.margin_buffer,
+        }
+    }
+}
+
+/// A position's contribution to the account margin, all values in QUOTE_PRECISION
+#[derive(Default, Clone, Copy)]
+struct PositionMargin {
+    collateral: i128,
+    collateral_buffer: i128,
+    requirement: u128,
+    requirement_plus_buffer: u128,
+    open_orders_requirement: u128,
+    spot_asset_value: i128,
+    spot_liability_value: u128,
+    perp_liability_value: u128,
+    perp_pnl: i128,
+    with_spot_isolated_liability: bool,
+    with_perp_isolated_liability: bool,
+}
+
+impl PositionMargin {
+    fn add_collateral(&mut self, value: i128, margin_buffer: u32) {
+        self.collateral += value;
+        if value < 0 {
+            self.collateral_buffer += value * margin_buffer as i128 / MARGIN_PRECISION_U128 as i128;
+        }
+    }
+
+    fn add_requirement(&mut self, requirement: u128, liability_value: u128, margin_buffer: u32) {
+        self.requirement += requirement;
+        self.requirement_plus_buffer +=
+            requirement + liability_value * margin_buffer as u128 / MARGIN_PRECISION_U128;
+    }
+
+    fn add_open_orders_requirement(&mut self, open_orders: u8) {
+        let requirement = open_orders as u128 * OPEN_ORDER_MARGIN_REQUIREMENT;
+        self.requirement += requirement;
+        self.requirement_plus_buffer += requirement;
+        self.open_orders_requirement += requirement;
+    }
+
+    fn add(&mut self, other: &Self) {
+        self.collateral += other.collateral;
+        self.collateral_buffer += other.collateral_buffer;
+        self.requirement += other.requirement;
+        self.requirement_plus_buffer += other.requirement_plus_buffer;
+        self.open_orders_requirement += other.open_orders_requirement;
+        self.spot_asset_value += other.spot_asset_value;
+        self.spot_liability_value += other.spot_liability_value;
+        self.perp_liability_value += other.perp_liability_value;
+        self.perp_pnl += other.perp_pnl;
+        self.with_spot_isolated_liability |= other.with_spot_isolated_liability;
+        self.with_perp_isolated_liability |= other.with_perp_isolated_liability;
+    }
+
+    fn to_collateral(self, market_index: u16, timestamp: u64) -> PositionCollateral {
+        PositionCollateral {
+            collateral_value: self.collateral,
+            collateral_buffer: self.collateral_buffer,
+            liability_value: self.requirement,
+            liability_buffer: self.requirement_plus_buffer - self.requirement,
+            last_updated: timestamp,
+            market_index,
+        }
+    }
+}
+
+/// Spot position valued as if its open bids or asks were filled
+struct OrderFillSimulation {
+    token_amount: i128,
+    orders_value: i128,
+    token_value: i128,
+    weighted_token_value: i128,
+}
+
+impl OrderFillSimulation {
+    fn free_collateral_contribution(&self) -> i128 {
+        self.weighted_token_value + self.orders_value
+    }
+}
+
+fn simulate_order_fill(
+    market: &accounts::SpotMarket,
+    oracle_price: i64,
+    token_amount: i128,
+    orders_value: i128,
+    params: &MarginParams,
+) -> NativeResult<OrderFillSimulation> {
+    let value = token_value(token_amount, market.decimals, oracle_price)?;
+    let weight = if value >= 0 {
+        let weight = spot_asset_weight(
+            market,
+            token_amount.unsigned_abs(),
+            oracle_price,
+            params.margin_type,
+        )?;
+        if params.user_custom_margin_ratio > 0 {
+            weight.min(SPOT_WEIGHT_PRECISION.saturating_sub(params.user_custom_margin_ratio))
+        } else {
+            weight
+        }
+    } else {
+        spot_liability_weight(market, token_amount.unsigned_abs(), params.margin_type)?
+            .max(SPOT_WEIGHT_PRECISION + params.user_custom_margin_ratio)
+    };
+
+    Ok(OrderFillSimulation {
+        token_amount,
+        orders_value,
+        token_value: value,
+        weighted_token_value: value * weight as i128 / SPOT_WEIGHT_PRECISION_I128,
+    })
+}
+
+fn worst_case_order_fill(
+    position: &types::SpotPosition,
+    market: &accounts::SpotMarket,
+    oracle_price: i64,
+    params: &MarginParams,
+) -> NativeResult<OrderFillSimulation> {
+    let token_amount = signed_token_amount(position, market)?;
+    if position.open_bids == 0 && position.open_asks == 0 {
+        return simulate_order_fill(market, oracle_price, token_amount, 0, params);
+    }
+
+    let bids_value = token_value(position.open_bids as i128, market.decimals, oracle_price)?;
+    let asks_value = token_value(position.open_asks as i128, market.decimals, oracle_price)?;
+    let all_bids_fill = simulate_order_fill(
+        market,
+        oracle_price,
+        token_amount + position.open_bids as i128,
+        -bids_value,
+        params,
+    )?;
+    let all_asks_fill = simulate_order_fill(
+        market,
+        oracle_price,
+        token_amount + position.open_asks as i128,
+        -asks_value,
+        params,
+    )?;
+
+    if all_asks_fill.free_collateral_contribution() < all_bids_fill.free_collateral_contribution() {
+        Ok(all_asks_fill)
+    } else {
+        Ok(all_bids_fill)
+    }
+}
+
+fn spot_position_margin(
+    position: &types::SpotPosition,
+    market: &accounts::SpotMarket,
+    oracle_price: i64,
+    params: &MarginParams,
+) -> NativeResult<PositionMargin> {
+    let mut margin = PositionMargin::default();
+
+    if market.market_index == QUOTE_SPOT_MARKET_INDEX {
+        let value = token_value(
+            signed_token_amount(position, market)?,
+            market.decimals,
+            oracle_price,
+        )?;
+        if value >= 0 {
+            margin.add_collateral(value, params.margin_buffer);
+            margin.spot_asset_value += value;
+        } else {
+            let liability_value = value.unsigned_abs();
+            margin.add_requirement(liability_value, liability_value, params.margin_buffer);
+            margin.spot_liability_value += liability_value;
+        }
+        return Ok(margin);
+    }
+
+    let simulation = worst_case_order_fill(position, market, oracle_price, params)?;
+    margin.add_open_orders_requirement(position.open_orders);
+
+    if simulation.token_amount > 0 {
+        margin.add_collateral(simulation.weighted_token_value, params.margin_buffer);
+        margin.spot_asset_value += simulation.token_value;
+    } else if simulation.token_amount < 0 {
+        let liability_value = simulation.token_value.unsigned_abs();
+        margin.add_requirement(
+            simulation.weighted_token_value.unsigned_abs(),
+            liability_value,
+            params.margin_buffer,
+        );
+        margin.spot_liability_value += liability_value;
+        margin.with_spot_isolated_liability |= market.asset_tier == AssetTier::Isolated;
+    }
+
+    if simulation.orders_value > 0 {
+        margin.add_collateral(simulation.orders_value, params.margin_buffer);
+    } else if simulation.orders_value < 0 {
+        let orders_value = simulation.orders_value.unsigned_abs();
+        margin.add_requirement(orders_value, orders_value, params.margin_buffer);
+    }
+
+    Ok(margin)
+}
+
+fn perp_position_margin(
+    position: &types::PerpPosition,
+    market: &accounts::PerpMarket,
+    oracle_price: i64,
+    quote_oracle_price: i64,
+    params: &MarginParams,
+) -> NativeResult<PositionMargin> {
+    let valuation_price = if market.status == MarketStatus::Settlement {
+        market.expiry_price
+    } else {
+        oracle_price
+    };
+
+    let amm_cumulative_funding_rate = if position.base_asset_amount > 0 {
+        market.amm.cumulative_funding_rate_long.as_i128()
+    } else {
+        market.amm.cumulative_funding_rate_short.as_i128()
+    };
+    let funding_payment =
+        crate::math::funding::calculate_funding_payment(amm_cumulative_funding_rate, position)
+            .map_err(|_| ErrorCode::MathError)?;
+    let unrealized_pnl = unrealized_pnl(position, valuation_price)? + funding_payment as i128;
+
+    let (worst_case_base_asset_amount, worst_case_liability) =
+        worst_case_liability_value(position, valuation_price, market.contract_type)?;
+    let mut margin_ratio = perp_margin_ratio(
+        market,
+        worst_case_base_asset_amount.unsigned_abs(),
+        params.margin_type,
+        params.user_high_leverage_mode,
+    )?
+    .max(params.user_custom_margin_ratio);
+    if params.margin_type == MarginRequirementType::Initial {
+        margin_ratio = margin_ratio.max(position.max_margin_ratio as u32);
+    }
+
+    let quote_price = quote_oracle_price.max(0) as u128;
+    let liability_value = worst_case_liability * quote_price / PRICE_PRECISION;
+    let requirement = liability_value * margin_ratio as u128 / MARGIN_PRECISION_U128;
+
+    let asset_weight = unrealized_pnl_asset_weight(market, unrealized_pnl, params.margin_type)?;
+    let weighted_pnl = unrealized_pnl * quote_oracle_price as i128 / PRICE_PRECISION_I128
+        * asset_weight as i128
+        / SPOT_WEIGHT_PRECISION_I128;
+
+    let mut margin = PositionMargin::default();
+    margin.add_requirement(requirement, liability_value, params.margin_buffer);
+    margin.add_open_orders_requirement(position.open_orders);
+    margin.add_collateral(weighted_pnl, params.margin_buffer);
+    margin.perp_liability_value += liability_value;
+    margin.perp_pnl += weighted_pnl;
+    margin.with_perp_isolated_liability =
+        market.contract_tier == ContractTier::Isolated && position.base_asset_amount != 0;
+
+    Ok(margin)
+}
+
+fn find_account_data<T: bytemuck::Pod + Discriminator>(
+    accounts: &[super::AccountWithKey],
+    is_match: impl Fn(&T) -> bool,
+) -> Option<T> {
+    accounts
+        .iter()
+        .filter_map(|a| read_anchor_account::<T>(&a.account.data))
+        .find(|m| is_match(m))
+}
+
+fn load_oracle_price(
+    accounts: &AccountsList,
+    oracle: &Pubkey,
+    oracle_source: OracleSource,
+) -> NativeResult<OraclePriceData> {
+    if oracle_source == OracleSource::QuoteAsset {
+        return get_oracle_price(oracle_source, &[], accounts.latest_slot);
+    }
+    let account = accounts
+        .oracles
+        .iter()
+        .find(|a| &a.key == oracle)
+        .ok_or(ErrorCode::OracleNotFound)?;
+
+    get_oracle_price(oracle_source, &account.account.data, accounts.latest_slot)
+}
+
+fn is_oracle_valid(
+    oracle_price_data: &OraclePriceData,
+    last_oracle_price_twap: i64,
+    accounts: &AccountsList,
+) -> bool {
+    let price = oracle_price_data.price;
+    if price <= 0 || !oracle_price_data.has_sufficient_number_of_data_points {
+        return false;
+    }
+    match accounts.oracle_guard_rails {
+        Some(ref guard_rails) => {
+            let is_too_volatile = price.max(last_oracle_price_twap)
+                / last_oracle_price_twap.min(price).max(1)
+                > guard_rails.validity.too_volatile_ratio;
+            !is_too_volatile
+                && oracle_price_data.delay <= guard_rails.validity.slots_before_stale_for_margin
+        }
+        None => true,
+    }
+}
+
+fn calculate_margin(
+    user: &accounts::User,
+    accounts: &AccountsList,
+    margin_type: MarginRequirementType,
+) -> NativeResult<MarginCalculation> {
+    let params = MarginParams::new(user, margin_type, 0);
+    let mut total = PositionMargin::default();
+    let mut all_oracles_valid = true;
+
+    for position in user
+        .spot_positions
+        .iter()
+        .filter(|p| !is_spot_position_available(p))
+    {
+        let market = find_account_data::<accounts::SpotMarket>(&*accounts.spot_markets, |m| {
+            m.market_index == position.market_index
+        })
+        .ok_or(ErrorCode::SpotMarketNotFound)?;
+        let oracle_price_data = load_oracle_price(accounts, &market.oracle, market.oracle_source)?;
+        all_oracles_valid &= is_oracle_valid(
+            &oracle_price_data,
+            market.historical_oracle_data.last_oracle_price_twap,
+            accounts,
+        );
+
+        total.add(&spot_position_margin(
+            position,
+            &market,
+            oracle_price_data.price,
+            &params,
+        )?);
+    }
+
+    for position in user
+        .perp_positions
+        .iter()
+        .filter(|p| !is_perp_position_available(p))
+    {
+        let market = find_account_data::<accounts::PerpMarket>(&*accounts.perp_markets, |m| {
+            m.market_index == position.market_index
+        })
+        .ok_or(ErrorCode::PerpMarketNotFound)?;
+        let quote_market =
+            find_account_data::<accounts::SpotMarket>(&*accounts.spot_markets, |m| {
+                m.market_index == market.quote_spot_market_index
+            })
+            .ok_or(ErrorCode::SpotMarketNotFound)?;
+        let quote_oracle_price_data =
+            load_oracle_price(accounts, &quote_market.oracle, quote_market.oracle_source)?;
+        let oracle_price_data =
+            load_oracle_price(accounts, &market.amm.oracle, market.amm.oracle_source)?;
+        all_oracles_valid &= is_oracle_valid(
+            &oracle_price_data,
+            market.amm.historical_oracle_data.last_oracle_price_twap,
+            accounts,
+        ) && is_oracle_valid(
+            &quote_oracle_price_data,
+            quote_market.historical_oracle_data.last_oracle_price_twap,
+            accounts,
+        );
+
+        total.add(&perp_position_margin(
+            position,
+            &market,
+            oracle_price_data.price,
+            quote_oracle_price_data.price,
+            &params,
+        )?);
+    }
+
+    Ok(MarginCalculation {
+        total_collateral: total.collateral,
+        margin_requirement: total.requirement,
+        all_oracles_valid,
+        with_perp_isolated_liability: total.with_perp_isolated_liability,
+        with_spot_isolated_liability: total.with_spot_isolated_liability,
+        total_spot_asset_value: total.spot_asset_value,
+        total_spot_liability_value: total.spot_liability_value,
+        total_perp_liability_value: total.perp_liability_value,
+        total_perp_pnl: total.perp_pnl,
+        open_orders_margin_requirement: total.open_orders_requirement,
+    })
+}
+
+/// Resolve an oracle price from `MarketStateData`, preferring the pyth override past the bps threshold
+fn resolve_state_price(
+    oracle_price: Option<&OraclePriceData>,
+    pyth_price: Option<&i64>,
+    pyth_oracle_diff_threshold_bps: u64,
+) -> Option<i64> {
+    match (oracle_price, pyth_price) {
+        (Some(oracle), Some(&pyth)) => {
+            let diff_bps = (pyth - oracle.price).unsigned_abs() as u128 * 10_000
+                / oracle.price.unsigned_abs().max(1) as u128;
+            if diff_bps >= pyth_oracle_diff_threshold_bps as u128 {
+                Some(pyth)
+            } else {
+                Some(oracle.price)
+            }
+        }
+        (Some(oracle), None) => Some(oracle.price),
+        (None, Some(&pyth)) => Some(pyth),
+        (None, None) => None,
+    }
+}
+
+fn spot_price_from_state(market_state: &MarketStateData, market_index: u16) -> NativeResult<i64> {
+    resolve_state_price(
+        market_state.spot_oracle_prices.get(&market_index),
+        market_state.spot_pyth_prices.get(&market_index),
+        market_state.pyth_oracle_diff_threshold_bps,
+    )
+    .ok_or(ErrorCode::OracleNotFound)
+}
+
+fn perp_price_from_state(market_state: &MarketStateData, market_index: u16) -> NativeResult<i64> {
+    resolve_state_price(
+        market_state.perp_oracle_prices.get(&market_index),
+        market_state.perp_pyth_prices.get(&market_index),
+        market_state.pyth_oracle_diff_threshold_bps,
+    )
+    .ok_or(ErrorCode::OracleNotFound)
+}
+
+fn spot_position_margin_from_state(
+    position: &types::SpotPosition,
+    market_state: &MarketStateData,
+    params: &MarginParams,
+) -> NativeResult<PositionMargin> {
+    let market = market_state
+        .spot_markets
+        .get(&position.market_index)
+        .ok_or(ErrorCode::SpotMarketNotFound)?;
+    let oracle_price = spot_price_from_state(market_state, position.market_index)?;
+
+    spot_position_margin(position, market, oracle_price, params)
+}
+
+fn perp_position_margin_from_state(
+    position: &types::PerpPosition,
+    market_state: &MarketStateData,
+    params: &MarginParams,
+) -> NativeResult<PositionMargin> {
+    let market = market_state
+        .perp_markets
+        .get(&position.market_index)
+        .ok_or(ErrorCode::PerpMarketNotFound)?;
+    let oracle_price = perp_price_from_state(market_state, position.market_index)?;
+    let quote_oracle_price = spot_price_from_state(market_state, market.quote_spot_market_index)?;
+
+    perp_position_margin(position, market, oracle_price, quote_oracle_price, params)
+}
+
+fn incremental_from_user(
+    user: &accounts::User,
+    market_state: &MarketStateData,
+    margin_type: MarginRequirementType,
+    timestamp: u64,
+    margin_buffer: u32,
+) -> NativeResult<IncrementalMarginCalculation> {
+    let params = MarginParams::new(user, margin_type, margin_buffer);
+    let mut cached = IncrementalMarginCalculation {
+        total_collateral: 0,
+        total_collateral_buffer: 0,
+        margin_requirement: 0,
+        margin_requirement_plus_buffer: 0,
+        spot_collateral: [PositionCollateral::default(); 8],
+        perp_collateral: [PositionCollateral::default(); 8],
+        last_updated: timestamp,
+        user_custom_margin_ratio: params.user_custom_margin_ratio,
+        margin_buffer,
+        margin_type,
+        user_high_leverage_mode: params.user_high_leverage_mode,
+        user_pool_id: user.pool_id,
+    };
+
+    for (slot, position) in user.spot_positions.iter().enumerate() {
+        if is_spot_position_available(position) {
+            continue;
+        }
+        let margin = spot_position_margin_from_state(position, market_state, &params)?;
+        cached.spot_collateral[slot] = margin.to_collateral(position.market_index, timestamp);
+    }
+    for (slot, position) in user.perp_positions.iter().enumerate() {
+        if is_perp_position_available(position) {
+            continue;
+        }
+        let margin = perp_position_margin_from_state(position, market_state, &params)?;
+        cached.perp_collateral[slot] = margin.to_collateral(position.market_index, timestamp);
+    }
+    refresh_incremental_totals(&mut cached);
+
+    Ok(cached)
+}
+
+fn incremental_update_spot_position(
+    cached: &mut IncrementalMarginCalculation,
+    spot_position: &types::SpotPosition,
+    market_state: &MarketStateData,
+    timestamp: u64,
+) -> NativeResult<()> {
+    let params = MarginParams::from_incremental(cached);
+    let margin = if is_spot_position_available(spot_position) {
+        PositionMargin::default()
+    } else {
+        spot_position_margin_from_state(spot_position, market_state, &params)?
+    };
+
+    if let Some(slot) = find_collateral_slot(&cached.spot_collateral, spot_position.market_index) {
+        cached.spot_collateral[slot] = margin.to_collateral(spot_position.market_index, timestamp);
+    }
+    cached.last_updated = timestamp;
+    refresh_incremental_totals(cached);
+
+    Ok(())
+}
+
+fn incremental_update_perp_position(
+    cached: &mut IncrementalMarginCalculation,
+    perp_position: &types::PerpPosition,
+    market_state: &MarketStateData,
+    timestamp: u64,
+) -> NativeResult<()> {
+    let params = MarginParams::from_incremental(cached);
+    let margin = if is_perp_position_available(perp_position) {
+        PositionMargin::default()
+    } else {
+        perp_position_margin_from_state(perp_position, market_state, &params)?
+    };
+
+    if let Some(slot) = find_collateral_slot(&cached.perp_collateral, perp_position.market_index) {
+        cached.perp_collateral[slot] = margin.to_collateral(perp_position.market_index, timestamp);
+    }
+    cached.last_updated = timestamp;
+    refresh_incremental_totals(cached);
+
+    Ok(())
+}
+
+/// Find the cached slot for `market_index` or the first unused slot
+fn find_collateral_slot(collateral: &[PositionCollateral; 8], market_index: u16) -> Option<usize> {
+    collateral
+        .iter()
+        .position(|c| c.last_updated > 0 && c.market_index == market_index)
+        .or_else(|| collateral.iter().position(|c| c.last_updated == 0))
+}
+
+fn refresh_incremental_totals(cached: &mut IncrementalMarginCalculation) {
+    let positions = cached
+        .spot_collateral
+        .iter()
+        .chain(cached.perp_collateral.iter());
+
+    let mut total_collateral = 0;
+    let mut total_collateral_buffer = 0;
+    let mut margin_requirement = 0;
+    let mut liability_buffer = 0;
+    for p in positions {
+        total_collateral += p.collateral_value;
+        total_collateral_buffer += p.collateral_buffer;
+        margin_requirement += p.liability_value;
+        liability_buffer += p.liability_buffer;
+    }
+
+    cached.total_collateral = total_collateral;
+    cached.total_collateral_buffer = total_collateral_buffer;
+    cached.margin_requirement = margin_requirement;
+    cached.margin_requirement_plus_buffer = margin_requirement + liability_buffer;
+}
+
+/// Parity tests of the native implementation against the linked libdrift
+#[cfg(all(test, not(feature = "native_ffi")))]
+mod tests {
+    use abi_stable::std_types::ROption;
+    use anchor_lang::Discriminator;
+    use solana_sdk::{account::Account, pubkey::Pubkey};
+
+    use crate::{
+        constants::ids::pyth_program,
+        drift_idl::{
+            accounts::{PerpMarket, SpotMarket, User},
+            types::{
+                ContractType, HistoricalOracleData, MarginRequirementType, OracleGuardRails,
+                OracleSource, Order, OrderType, PerpPosition, PositionDirection, SpotBalanceType,
+                SpotPosition, ValidityGuardRails,
+            },
+        },
+        ffi::{
+            self as lib, native, AccountWithKey, AccountsList, MarginContextMode, OraclePriceData,
+        },
+        market_state::MarketStateData,
+        math::constants::{
+            BASE_PRECISION_I64, BASE_PRECISION_U64, MARGIN_PRECISION, PRICE_PRECISION_I64,
+            QUOTE_PRECISION_I64, SPOT_WEIGHT_PRECISION,
+        },
+        utils::test_utils::{get_account_bytes, get_pyth_price},
+        AMM,
+    };
+
+    fn fixture_user() -> User {
+        let data = hex::decode(include_str!("../../../res/9Jtc.hex")).unwrap();
+        bytemuck::pod_read_unaligned(&data[8..8 + std::mem::size_of::<User>()])
+    }
+
+    fn fixture_spot_market() -> SpotMarket {
+        let data = hex::decode(include_str!("../../../res/spot_market_1_76_0.hex")).unwrap();
+        bytemuck::pod_read_unaligned(&data[8..8 + std::mem::size_of::<SpotMarket>()])
+    }
+
+    fn perp_market(market_index: u16, oracle: Pubkey) -> PerpMarket {
+        PerpMarket {
+            market_index,
+            margin_ratio_initial: MARGIN_PRECISION / 10,
+            margin_ratio_maintenance: MARGIN_PRECISION / 20,
+            high_leverage_margin_ratio_initial: 200,
+            high_leverage_margin_ratio_maintenance: 100,
+            imf_factor: 500,
+            unrealized_pnl_initial_asset_weight: SPOT_WEIGHT_PRECISION,
+            unrealized_pnl_maintenance_asset_weight: SPOT_WEIGHT_PRECISION,
+            unrealized_pnl_imf_factor: 500,
+            amm: AMM {
+                oracle,
+                oracle_source: OracleSource::Pyth,
+                order_tick_size: 1_000,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn anchor_account<T: bytemuck::Pod + Discriminator>(key: Pubkey, value: &T) -> AccountWithKey {
+        AccountWithKey {
+            key,
+            account: Account {
+                owner: crate::constants::PROGRAM_ID,
+                data: [T::DISCRIMINATOR, bytemuck::bytes_of(value)].concat(),
+                ..Default::default()
+            },
+        }
+    }
+
+    fn pyth_account(key: Pubkey, price: i64, expo: i32) -> AccountWithKey {
+        AccountWithKey {
+            key,
+            account: Account {
+                data: get_account_bytes(&mut get_pyth_price(price, expo)).to_vec(),
+                owner: pyth_program::ID,
+                ..Default::default()
+            },
+        }
+    }
+
+    fn switchboard_on_demand_account(
+        key: Pubkey,
+        value: i128,
+        range: i128,
+        slot: u64,
+    ) -> AccountWithKey {
+        let mut data = vec![0_u8; native::SB_ON_DEMAND_ACCOUNT_SIZE];
+        let result = native::SB_ON_DEMAND_RESULT_OFFSET;
+        data[..8].copy_from_slice(&native::SB_ON_DEMAND_DISCRIMINATOR);
+        data[result..result + 16].copy_from_slice(&value.to_le_bytes());
+        data[result + 48..result + 64].copy_from_slice(&range.to_le_bytes());
+        data[result + 104..result + 112].copy_from_slice(&slot.to_le_bytes());
+        AccountWithKey {
+            key,
+            account: Account {
+                data,
+                ..Default::default()
+            },
+        }
+    }
+
+    fn oracle_tuple(o: OraclePriceData) -> (i64, u64, i64, bool) {
+        (
+            o.price,
+            o.confidence,
+            o.delay,
+            o.has_sufficient_number_of_data_points,
+        )
+    }
+
+    #[test]
+    fn native_parity_auction_price() {
+        let mut orders = vec![];
+        for direction in [PositionDirection::Long, PositionDirection::Short] {
+            let (start, end) = match direction {
+                PositionDirection::Long => (90 * PRICE_PRECISION_I64, 100 * PRICE_PRECISION_I64),
+                PositionDirection::Short => (100 * PRICE_PRECISION_I64, 90 * PRICE_PRECISION_I64),
+            };
+            for order_type in [
+                OrderType::Market,
+                OrderType::Limit,
+                OrderType::TriggerMarket,
+            ] {
+                orders.push(Order {
+                    slot: 10,
+                    auction_duration: 20,
+                    auction_start_price: start,
+                    auction_end_price: end,
+                    order_type,
+                    direction,
+                    ..Default::default()
+                });
+            }
+            orders.push(Order {
+                slot: 10,
+                auction_duration: 20,
+                auction_start_price: -PRICE_PRECISION_I64 / 2,
+                auction_end_price: PRICE_PRECISION_I64 / 3,
+                order_type: OrderType::Oracle,
+                direction,
+                ..Default::default()
+            });
+        }
+
+        for order in orders.iter() {
+            for slot in [10, 15, 29, 30, 31, 100] {
+                for tick_size in [1, 1_000, 100_000] {
+                    for is_prediction_market in [false, true] {
+                        let oracle_price = ROption::RSome(95 * PRICE_PRECISION_I64);
+                        let expected = unsafe {
+                            lib::math_calculate_auction_price(
+                                order,
+                                slot,
+                                tick_size,
+                                oracle_price,
+                                is_prediction_market,
+                            )
+                        };
+                        let actual = unsafe {
+                            native::math_calculate_auction_price(
+                                order,
+                                slot,
+                                tick_size,
+                                oracle_price,
+                                is_prediction_market,
+                            )
+                        };
+                        assert_eq!(actual, expected, "{order:?} slot: {slot}");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn native_parity_limit_price() {
+        let orders = [
+            Order {
+                price: 95_123_456,
+                order_type: OrderType::Limit,
+                direction: PositionDirection::Long,
+                ..Default::default()
+            },
+            Order {
+                price: 95_123_456,
+                order_type: OrderType::Limit,
+                direction: PositionDirection::Short,
+                post_only: true,
+                ..Default::default()
+            },
+            Order {
+                slot: 90,
+                auction_duration: 20,
+                auction_start_price: 90 * PRICE_PRECISION_I64,
+                auction_end_price: 100 * PRICE_PRECISION_I64,
+                order_type: OrderType::Limit,
+                direction: PositionDirection::Long,
+                ..Default::default()
+            },
+            Order {
+                oracle_price_offset: -5 * PRICE_PRECISION_I64 as i32,
+                order_type: OrderType::Limit,
+                direction: PositionDirection::Short,
+                ..Default::default()
+            },
+            Order {
+                price: 0,
+                order_type: OrderType::Market,
+                direction: PositionDirection::Long,
+                ..Default::default()
+            },
+        ];
+
+        for order in orders.iter() {
+            for fallback_price in [None, Some(95_500_001)] {
+                let expected = unsafe {
+                    lib::order_get_limit_price(
+                        order,
+                        Some(100 * PRICE_PRECISION_I64),
+                        fallback_price,
+                        100,
+                        1_000,
+                        false,
+                        None,
+                    )
+                };
+                let actual = unsafe {
+                    native::order_get_limit_price(
+                        order,
+                        Some(100 * PRICE_PRECISION_I64),
+                        fallback_price,
+                        100,
+                        1_000,
+                        false,
+                        None,
+                    )
+                };
+                assert_eq!(actual, expected, "{order:?}");
+            }
+
+            for slot in [90, 100, 200] {
+                unsafe {
+                    assert_eq!(
+                        native::order_is_resting_limit_order(order, slot),
+                        lib::order_is_resting_limit_order(order, slot)
+                    );
+                    assert_eq!(
+                        native::order_is_limit_order(order),
+                        lib::order_is_limit_order(order)
+                    );
+                    assert_eq!(native::order_triggered(order), lib::order_triggered(order));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn native_parity_perp_margin_ratio() {
+        let market = perp_market(0, Pubkey::new_unique());
+        for size in [
+            0,
+            BASE_PRECISION_U64 as u128,
+            1_000_000 * BASE_PRECISION_U64 as u128,
+        ] {
+            for margin_type in [
+                MarginRequirementType::Initial,
+                MarginRequirementType::Fill,
+                MarginRequirementType::Maintenance,
+            ] {
+                for high_leverage_mode in [false, true] {
+                    unsafe {
+                        assert_eq!(
+                            native::perp_market_get_margin_ratio(
+                                &market,
+                                size,
+                                margin_type,
+                                high_leverage_mode
+                            ),
+                            lib::perp_market_get_margin_ratio(
+                                &market,
+                                size,
+                                margin_type,
+                                high_leverage_mode
+                            ),
+                        );
+                    }
+                }
+            }
+        }
+        unsafe {
+            assert_eq!(
+                native::perp_market_get_open_interest(&market),
+                lib::perp_market_get_open_interest(&market)
+            );
+        }
+    }
+
+    #[test]
+    fn native_parity_spot_market_fixture() {
+        let market = fixture_spot_market();
+        let precision = 10_u128.pow(market.decimals);
+        for size in [0, precision, 1_000 * precision, 10_000_000 * precision] {
+            for margin_type in [
+                MarginRequirementType::Initial,
+                MarginRequirementType::Fill,
+                MarginRequirementType::Maintenance,
+            ] {
+                unsafe {
+                    assert_eq!(
+                        native::spot_market_get_asset_weight(
+                            &market,
+                            size,
+                            150 * PRICE_PRECISION_I64,
+                            margin_type
+                        ),
+                        lib::spot_market_get_asset_weight(
+                            &market,
+                            size,
+                            150 * PRICE_PRECISION_I64,
+                            margin_type
+                        ),
+                    );
+                    assert_eq!(
+                        native::spot_market_get_liability_weight(&market, size, margin_type),
+                        lib::spot_market_get_liability_weight(&market, size, margin_type),
+                    );
+                }
+            }
+        }
+
+        for balance_type in [SpotBalanceType::Deposit, SpotBalanceType::Borrow] {
+            let position = SpotPosition {
+                scaled_balance: 123_456_789_012,
+                market_index: market.market_index,
+                balance_type,
+                ..Default::default()
+            };
+            unsafe {
+                assert_eq!(
+                    native::spot_position_get_token_amount(&position, &market),
+                    lib::spot_position_get_token_amount(&position, &market),
+                );
+                assert_eq!(
+                    native::spot_position_get_signed_token_amount(&position, &market),
+                    lib::spot_position_get_signed_token_amount(&position, &market),
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn native_parity_oracle_price() {
+        let sources = [
+            OracleSource::Pyth,
+            OracleSource::Pyth1K,
+            OracleSource::Pyth1M,
+            OracleSource::PythStableCoin,
+            OracleSource::QuoteAsset,
+        ];
+        for (price, expo) in [(240, 9), (1, 6), (1, 8), (42_000, 5), (3, 2)] {
+            for source in sources {
+                let account = pyth_account(Pubkey::new_unique(), price, expo);
+                let mut oracle = (account.key, account.account);
+                let expected = unsafe { lib::oracle_get_oracle_price(source, &mut oracle, 1_000) };
+                let actual = unsafe { native::oracle_get_oracle_price(source, &mut oracle, 1_000) };
+                assert_eq!(
+                    actual.map(oracle_tuple),
+                    expected.map(oracle_tuple),
+                    "{source:?} {price}e{expo}"
+                );
+            }
+        }
+
+        let sb_precision = 1_000_000_000_000_000_000_i128;
+        for (value, range, slot) in [
+            (123 * sb_precision / 100, sb_precision / 1_000, 990),
+            (42_000 * sb_precision, 3 * sb_precision, 1_000),
+            (sb_precision / 1_000_000_000, 0, 1),
+            (sb_precision, 0, 0),
+        ] {
+            for source in [OracleSource::SwitchboardOnDemand, OracleSource::Switchboard] {
+                let account =
+                    switchboard_on_demand_account(Pubkey::new_unique(), value, range, slot);
+                let mut oracle = (account.key, account.account);
+                let expected = unsafe { lib::oracle_get_oracle_price(source, &mut oracle, 1_000) };
+                let actual = unsafe { native::oracle_get_oracle_price(source, &mut oracle, 1_000) };
+                assert_eq!(
+                    actual.map(oracle_tuple),
+                    expected.map(oracle_tuple),
+                    "{source:?} {value} {range} {slot}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn native_parity_user_fixture_positions() {
+        let user = fixture_user();
+        for market_index in 0..64 {
+            unsafe {
+                assert_eq!(
+                    native::user_get_spot_position(&user, market_index).is_ok(),
+                    lib::user_get_spot_position(&user, market_index).is_ok(),
+                );
+                assert_eq!(
+                    native::user_get_perp_position(&user, market_index).is_ok(),
+                    lib::user_get_perp_position(&user, market_index).is_ok(),
+                );
+            }
+        }
+
+        for position in user.perp_positions.iter() {
+            for contract_type in [ContractType::Perpetual, ContractType::Prediction] {
+                unsafe {
+                    assert_eq!(
+                        native::perp_position_is_available(position),
+                        lib::perp_position_is_available(position)
+                    );
+                    assert_eq!(
+                        native::perp_position_get_unrealized_pnl(
+                            position,
+                            123 * PRICE_PRECISION_I64
+                        ),
+                        lib::perp_position_get_unrealized_pnl(position, 123 * PRICE_PRECISION_I64),
+                    );
+                    assert_eq!(
+                        native::perp_position_worst_case_base_asset_amount(
+                            position,
+                            PRICE_PRECISION_I64 / 3,
+                            contract_type
+                        ),
+                        lib::perp_position_worst_case_base_asset_amount(
+                            position,
+                            PRICE_PRECISION_I64 / 3,
+                            contract_type
+                        ),
+                    );
+                }
+            }
+        }
+        for position in user.spot_positions.iter() {
+            unsafe {
+                assert_eq!(
+                    native::spot_position_is_available(position),
+                    lib::spot_position_is_available(position)
+                );
+            }
+        }
+    }
+
+    /// Margin for the fixture user against synthetic markets for each of its positions
+    #[test]
+    fn native_parity_margin_calculation() {
+        let mut user = fixture_user();
+        // guarantee a spot borrow, perp position with open orders and unrealized pnl
+        user.spot_positions[7] = SpotPosition {
+            market_index: 2,
+            scaled_balance: 5 * BASE_PRECISION_U64,
+            balance_type: SpotBalanceType::Borrow,
+            open_bids: 2 * BASE_PRECISION_I64,
+            open_orders: 1,
+            ..Default::default()
+        };
+        user.perp_positions[7] = PerpPosition {
+            market_index: 3,
+            base_asset_amount: -10 * BASE_PRECISION_I64,
+            quote_asset_amount: 2_000 * QUOTE_PRECISION_I64,
+            open_asks: -BASE_PRECISION_I64,
+            open_orders: 1,
+            ..Default::default()
+        };
+
+        let template = fixture_spot_market();
+        let mut spot_markets = vec![];
+        let mut perp_markets = vec![];
+        let mut oracles = vec![];
+        let mut market_state = MarketStateData::default();
+
+        for position in user
+            .spot_positions
+            .iter()
+            .filter(|p| !native::is_spot_position_available(p))
+        {
+            let oracle = Pubkey::new_unique();
+            let market = SpotMarket {
+                market_index: position.market_index,
+                oracle,
+                oracle_source: if position.market_index == 0 {
+                    OracleSource::QuoteAsset
+                } else {
+                    OracleSource::Pyth
+                },
+                ..template
+            };
+            let price = 20 + position.market_index as i64;
+            spot_markets.push(anchor_account(Pubkey::new_unique(), &market));
+            oracles.push(pyth_account(oracle, price, 6));
+            market_state.set_spot_market(market);
+            market_state.set_spot_oracle_price(
+                position.market_index,
+                OraclePriceData {
+                    price: if position.market_index == 0 {
+                        PRICE_PRECISION_I64
+                    } else {
+                        price * PRICE_PRECISION_I64
+                    },
+                    has_sufficient_number_of_data_points: true,
+                    ..Default::default()
+                },
+            );
+        }
+        if !market_state.spot_markets.contains_key(&0) {
+            let market = SpotMarket {
+                market_index: 0,
+                oracle_source: OracleSource::QuoteAsset,
+                ..template
+            };
+            spot_markets.push(anchor_account(Pubkey::new_unique(), &market));
+            market_state.set_spot_market(market);
+            market_state.set_spot_oracle_price(
+                0,
+                OraclePriceData {
+                    price: PRICE_PRECISION_I64,
+                    ..Default::default()
+                },
+            );
+        }
+        for position in user
+            .perp_positions
+            .iter()
+            .filter(|p| !native::is_perp_position_available(p))
+        {
+            let oracle = Pubkey::new_unique();
+            let market = perp_market(position.market_index, oracle);
+            let price = 100 + position.market_index as i64;
+            perp_markets.push(anchor_account(Pubkey::new_unique(), &market));
+            oracles.push(pyth_account(oracle, price, 6));
+            market_state.set_perp_market(market);
+            market_state.set_perp_oracle_price(
+                position.market_index,
+                OraclePriceData {
+                    price: price * PRICE_PRECISION_I64,
+                    has_sufficient_number_of_data_points: true,
+                    ..Default::default()
+                },
+            );
+        }
+
+        let mut accounts = AccountsList::new(&mut perp_markets, &mut spot_markets, &mut oracles);
+        for mode in [
+            MarginContextMode::StandardMaintenance,
+            MarginContextMode::StandardInitial,
+            MarginContextMode::StandardCustom(MarginRequirementType::Fill),
+        ] {
+            let expected = unsafe {
+                lib::math_calculate_margin_requirement_and_total_collateral_and_liability_info(
+                    &user,
+                    &mut accounts,
+                    mode,
+                )
+            };
+            let actual = unsafe {
+                native::math_calculate_margin_requirement_and_total_collateral_and_liability_info(
+                    &user,
+                    &mut accounts,
+                    mode,
+                )
+            };
+            let expected = expected.unwrap();
+            let actual = actual.unwrap();
+            assert_eq!(
+                actual.total_collateral, expected.total_collateral,
+                "{mode:?}"
+            );
+            assert_eq!(
+                actual.margin_requirement, expected.margin_requirement,
+                "{mode:?}"
+            );
+            assert_eq!(
+                actual.open_orders_margin_requirement, expected.open_orders_margin_requirement,
+                "{mode:?}"
+            );
+        }
+
+        for margin_type in [
+            MarginRequirementType::Initial,
+            MarginRequirementType::Maintenance,
+        ] {
+            for margin_buffer in [0, MARGIN_PRECISION / 100] {
+                let expected = unsafe {
+                    lib::margin_calculate_simplified_margin_requirement(
+                        &user,
+                        &market_state,
+                        margin_type,
+                        margin_buffer,
+                    )
+                };
+                let actual = unsafe {
+                    native::margin_calculate_simplified_margin_requirement(
+                        &user,
+                        &market_state,
+                        margin_type,
+                        margin_buffer,
+                    )
+                };
+                assert_eq!(actual, expected, "{margin_type:?} buffer: {margin_buffer}");
+
+                let expected = unsafe {
+                    lib::incremental_margin_calculation_from_user(
+                        &user,
+                        &market_state,
+                        margin_type,
+                        1,
+                        margin_buffer,
+                    )
+                };
+                let actual = unsafe {
+                    native::incremental_margin_calculation_from_user(
+                        &user,
+                        &market_state,
+                        margin_type,
+                        1,
+                        margin_buffer,
+                    )
+                };
+                assert_eq!(actual.total_collateral, expected.total_collateral);
+                assert_eq!(actual.margin_requirement, expected.margin_requirement);
+            }
+        }
+    }
+
+    #[test]
+    fn native_parity_margin_pnl_imbalance_and_oracle_twap_divergence() {
+        let mut user = User::default();
+        // +100 unrealized pnl
+        user.perp_positions[0] = PerpPosition {
+            market_index: 1,
+            base_asset_amount: 10 * BASE_PRECISION_I64,
+            quote_asset_amount: -900 * QUOTE_PRECISION_I64,
+            ..Default::default()
+        };
+
+        let template = fixture_spot_market();
+        let quote_market = SpotMarket {
+            market_index: 0,
+            oracle_source: OracleSource::QuoteAsset,
+            historical_oracle_data: HistoricalOracleData {
+                last_oracle_price_twap: PRICE_PRECISION_I64,
+                ..template.historical_oracle_data
+            },
+            ..template
+        };
+
+        let oracle = Pubkey::new_unique();
+        let mut market = perp_market(1, oracle);
+        // users are owed 500 net, 5x the max imbalance
+        market.unrealized_pnl_max_imbalance = 100 * QUOTE_PRECISION_I64 as u64;
+        market.amm.base_asset_amount_with_amm = (10 * BASE_PRECISION_I64 as i128).into();
+        market.amm.quote_asset_amount = (-500 * QUOTE_PRECISION_I64 as i128).into();
+        market.amm.historical_oracle_data.last_oracle_price = 100 * PRICE_PRECISION_I64;
+        market.amm.historical_oracle_data.last_oracle_price_twap = 50 * PRICE_PRECISION_I64;
+
+        let mut spot_markets = vec![anchor_account(Pubkey::new_unique(), &quote_market)];
+        let mut perp_markets = vec![anchor_account(Pubkey::new_unique(), &market)];
+        let mut oracles = vec![pyth_account(oracle, 100, 6)];
+
+        for too_volatile_ratio in [1, 5] {
+            let mut accounts =
+                AccountsList::new(&mut perp_markets, &mut spot_markets, &mut oracles);
+            accounts.oracle_guard_rails = Some(OracleGuardRails {
+                validity: ValidityGuardRails {
+                    slots_before_stale_for_amm: 1_000,
+                    slots_before_stale_for_margin: 1_000,
+                    confidence_interval_max_size: 1_000_000,
+                    too_volatile_ratio,
+                },
+                ..Default::default()
+            });
+
+            let mut results = vec![];
+            for mode in [
+                MarginContextMode::StandardMaintenance,
+                MarginContextMode::StandardInitial,
+                MarginContextMode::StandardCustom(MarginRequirementType::Fill),
+            ] {
+                let expected = unsafe {
+                    lib::math_calculate_margin_requirement_and_total_collateral_and_liability_info(
+                        &user,
+                        &mut accounts,
+                        mode,
+                    )
+                }
+                .unwrap();
+                let actual = unsafe {
+                    native::math_calculate_margin_requirement_and_total_collateral_and_liability_info(
+                        &user,
+                        &mut accounts,
+                        mode,
+                    )
+                }
+                .unwrap();
+                assert_eq!(
+                    (
+                        actual.total_collateral,
+                        actual.margin_requirement,
+                        actual.all_oracles_valid
+                    ),
+                    (
+                        expected.total_collateral,
+                        expected.margin_requirement,
+                        expected.all_oracles_valid
+                    ),
+                    "{mode:?} ratio: {too_volatile_ratio}"
+                );
+                assert_eq!(actual.all_oracles_valid, too_volatile_ratio > 1);
+                results.push(actual.total_collateral);
+            }
+            // initial pnl weight is discounted by the imbalance
+            assert!(results[1] < results[0]);
+        }
+    }
+}
@@ -18,7 +18,7 @@ use solana_sdk::{
 
 use crate::{
     accounts::User,
-    build_accounts,
+    build_accounts, compute_budget,
     constants::{self, state_account, JIT_PROXY_ID},
     drift_idl,
     swift_order_subscriber::SignedOrderInfo,
@@ -93,6 +93,8 @@ pub struct JitProxyClient {
     drift_client: DriftClient,
     config: RpcSendTransactionConfig,
     cu_params: Option<ComputeBudgetParams>,
+    /// resolve compute budget at send time, if `cu_params` is unset
+    auto_compute_budget: bool,
 }
 
 impl JitProxyClient {
@@ -105,6 +107,7 @@ impl JitProxyClient {
             drift_client,
             config: config.unwrap_or_default(),
             cu_params,
+            auto_compute_budget: false,
         }
     }
 
@@ -116,6 +119,13 @@ impl JitProxyClient {
         self.cu_params = Some(cu_params);
     }
 
+    /// Toggle automatic compute budget for jit txs (see `TransactionBuilder::auto_compute_budget`)
+    ///
+    /// Ignored if `cu_params` are set
+    pub fn update_auto_compute_budget(&mut self, enabled: bool) {
+        self.auto_compute_budget = enabled;
+    }
+
     /// Build a jit tx
     ///
     /// `taker_params` JIT taker account params
@@ -221,6 +231,8 @@ impl JitProxyClient {
 
             ixs.push(cu_limit_ix);
             ixs.push(cu_price_ix);
+        } else if self.auto_compute_budget {
+            ixs.extend(compute_budget::auto_compute_budget_ixs());
         }
         ixs.push(ix);
 
@@ -323,17 +335,21 @@ impl JitProxyClient {
             data: instruction::JitSignedMsg { params: jit_params }.data(),
         };
 
-        let message = TransactionBuilder::new(
+        let mut tx_builder = TransactionBuilder::new(
             self.drift_client.program_data(),
             &self.drift_client.backend.perp_market_map,
             &self.drift_client.backend.spot_market_map,
             *maker_pubkey,
             Cow::Borrowed(maker_account_data),
             false,
-        )
-        .place_swift_order(signed_order_info, &taker_params.taker)
-        .add_ix(fill_ix)
-        .build();
+        );
+        if self.cu_params.is_none() && self.auto_compute_budget {
+            tx_builder = tx_builder.auto_compute_budget();
+        }
+        let message = tx_builder
+            .place_swift_order(signed_order_info, &taker_params.taker)
+            .add_ix(fill_ix)
+            .build();
 
         Ok(message)
     }
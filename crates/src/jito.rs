@@ -0,0 +1,463 @@
+//!
+//! Jito bundle submission
+//!
+//! Build atomic, tip-paying bundles from `TransactionBuilder` txs and submit them to a Jito block-engine
+//!
+use std::time::{Duration, Instant};
+
+use base64::Engine;
+use log::debug;
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::json;
+use solana_rpc_client_api::{request::RpcRequest, response::Response};
+use solana_sdk::{
+    clock::Slot,
+    hash::Hash,
+    message::{Message, VersionedMessage},
+    pubkey::Pubkey,
+    system_instruction,
+    transaction::VersionedTransaction,
+};
+use solana_transaction_status::TransactionConfirmationStatus;
+
+use crate::{types::SdkResult, RpcClient, SdkError, Wallet};
+
+const LOG_TARGET: &str = "jito";
+
+/// Max. number of txs in a bundle
+pub const MAX_BUNDLE_SIZE: usize = 5;
+
+/// Min. tip accepted by the block-engine (lamports)
+pub const MIN_TIP_LAMPORTS: u64 = 1_000;
+
+/// Jito mainnet tip accounts
+pub const JITO_TIP_ACCOUNTS: [Pubkey; 8] = [
+    solana_sdk::pubkey!("96gYZGLnJYVFmbjzopPSU6QiEV5fGqZNyN9nmNhvrZU5"),
+    solana_sdk::pubkey!("HFqU5x63VTqvQss8hp11i4wVV8bD44PvwucfZ2bU7gRe"),
+    solana_sdk::pubkey!("Cw8CFyM9FkoMi7K7Crf6HNQqf4uEMzpKw6QNghXLvLkY"),
+    solana_sdk::pubkey!("ADaUMid9yfUytqMBgopwjb2DTLSokTSzL1zt6iGPaS49"),
+    solana_sdk::pubkey!("DfXygSm4jCyNCybVYYK6DwvWqjKee8pbDmJGcLWNDXjh"),
+    solana_sdk::pubkey!("ADuUkR4vqLUMWXxW9gh6D6L8pMSawimctcNZ5pGwDcEt"),
+    solana_sdk::pubkey!("DttWaMuVvTiduZRnguLF7jNxTgiMBZ1hyAumKUiL2KRL"),
+    solana_sdk::pubkey!("3AVi9Tg9Uo68tJfuvoKvqKNWKkC5wPdSSdeBnizKZ6jT"),
+];
+
+/// Status of a landed bundle (`getBundleStatuses`)
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct BundleStatus {
+    pub bundle_id: String,
+    /// tx signatures of the bundle
+    pub transactions: Vec<String>,
+    pub slot: Slot,
+    pub confirmation_status: TransactionConfirmationStatus,
+    pub err: Result<(), serde_json::Value>,
+}
+
+/// State of a recently submitted bundle
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq)]
+pub enum InflightBundleState {
+    /// bundle is unknown to the block-engine
+    Invalid,
+    /// bundle has not failed, landed, or been deemed invalid
+    Pending,
+    /// all regions marked the bundle as failed and it was not forwarded
+    Failed,
+    /// bundle landed on-chain
+    Landed,
+}
+
+/// Status of a recently submitted bundle (`getInflightBundleStatuses`)
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct InflightBundleStatus {
+    pub bundle_id: String,
+    pub status: InflightBundleState,
+    pub landed_slot: Option<Slot>,
+}
+
+/// Final state of a submitted bundle
+#[derive(Clone, Debug, PartialEq)]
+pub enum BundleOutcome {
+    /// bundle landed at `slot`
+    Landed { bundle_id: String, slot: Slot },
+    /// bundle failed and was not forwarded
+    Failed { bundle_id: String },
+    /// bundle was not observed landing before timeout
+    Expired { bundle_id: String },
+}
+
+/// Client for the Jito block-engine JSON-RPC API
+///
+/// ```example(no_run)
+/// let block_engine = BlockEngineClient::new("https://mainnet.block-engine.jito.wtf/api/v1/bundles");
+/// let bundle_id = block_engine.send_bundle(&txs).await?;
+/// let outcome = block_engine
+///     .wait_for_bundle(&bundle_id, Duration::from_secs(1), Duration::from_secs(60))
+///     .await?;
+/// ```
+pub struct BlockEngineClient {
+    rpc_client: RpcClient,
+}
+
+impl BlockEngineClient {
+    /// Create a new `BlockEngineClient`
+    ///
+    /// * `url` - block-engine bundles endpoint e.g. `https://mainnet.block-engine.jito.wtf/api/v1/bundles`
+    pub fn new(url: &str) -> Self {
+        Self::with_rpc_client(RpcClient::new(url.to_string()))
+    }
+
+    /// Create a new `BlockEngineClient` using `rpc_client` for transport
+    pub fn with_rpc_client(rpc_client: RpcClient) -> Self {
+        Self { rpc_client }
+    }
+
+    async fn send<T: DeserializeOwned>(
+        &self,
+        method: &'static str,
+        params: serde_json::Value,
+    ) -> SdkResult<T> {
+        self.rpc_client
+            .send(RpcRequest::Custom { method }, params)
+            .await
+            .map_err(Into::into)
+    }
+
+    /// Submit a bundle of signed `txs`, returning the bundle Id
+    pub async fn send_bundle(&self, txs: &[VersionedTransaction]) -> SdkResult<String> {
+        if txs.is_empty() || txs.len() > MAX_BUNDLE_SIZE {
+            return Err(SdkError::InvalidBundleSize(txs.len()));
+        }
+        let encoded_txs = txs
+            .iter()
+            .map(|tx| {
+                bincode::serialize(tx)
+                    .map(|bytes| base64::engine::general_purpose::STANDARD.encode(bytes))
+                    .map_err(|_| SdkError::Deserializing)
+            })
+            .collect::<SdkResult<Vec<String>>>()?;
+
+        let bundle_id: String = self
+            .send("sendBundle", json!([encoded_txs, { "encoding": "base64" }]))
+            .await?;
+        debug!(target: LOG_TARGET, "sent bundle: {bundle_id}");
+
+        Ok(bundle_id)
+    }
+
+    /// Get the status of a landed bundle, `None` if the bundle has not landed
+    pub async fn get_bundle_status(&self, bundle_id: &str) -> SdkResult<Option<BundleStatus>> {
+        let response: Response<Vec<Option<BundleStatus>>> =
+            self.send("getBundleStatuses", json!([[bundle_id]])).await?;
+        Ok(response.value.into_iter().next().flatten())
+    }
+
+    /// Get the status of a recently submitted bundle (last 5 minutes)
+    pub async fn get_inflight_bundle_status(
+        &self,
+        bundle_id: &str,
+    ) -> SdkResult<Option<InflightBundleStatus>> {
+        let response: Response<Vec<Option<InflightBundleStatus>>> = self
+            .send("getInflightBundleStatuses", json!([[bundle_id]]))
+            .await?;
+        Ok(response.value.into_iter().next().flatten())
+    }
+
+    /// Get the block-engine's tip accounts
+    pub async fn get_tip_accounts(&self) -> SdkResult<Vec<Pubkey>> {
+        let tip_accounts: Vec<String> = self.send("getTipAccounts", json!([])).await?;
+        tip_accounts
+            .iter()
+            .map(|s| s.parse().map_err(|_| SdkError::InvalidBase58))
+            .collect()
+    }
+
+    /// Poll the bundle status until it lands, fails, or `timeout` elapses
+    pub async fn wait_for_bundle(
+        &self,
+        bundle_id: &str,
+        poll_interval: Duration,
+        timeout: Duration,
+    ) -> SdkResult<BundleOutcome> {
+        let bundle_id = bundle_id.to_string();
+        let started = Instant::now();
+        let mut poll = tokio::time::interval(poll_interval);
+        loop {
+            poll.tick().await;
+            if let Some(status) = self.get_inflight_bundle_status(&bundle_id).await? {
+                match status.status {
+                    InflightBundleState::Landed => {
+                        return Ok(BundleOutcome::Landed {
+                            bundle_id,
+                            slot: status.landed_slot.unwrap_or_default(),
+                        });
+                    }
+                    InflightBundleState::Failed => return Ok(BundleOutcome::Failed { bundle_id }),
+                    // the bundle may not be indexed immediately after submission
+                    InflightBundleState::Invalid | InflightBundleState::Pending => (),
+                }
+            }
+            if started.elapsed() >= timeout {
+                return Ok(BundleOutcome::Expired { bundle_id });
+            }
+        }
+    }
+}
+
+/// Builds an atomic bundle of txs with a tip transfer
+///
+/// ```example(no_run)
+/// let update_oracle = client.init_tx(&sub_account, false).await?.post_pyth_pull_oracle_update_atomic(..).build();
+/// let fill = client.init_tx(&sub_account, false).await?.fill_perp_order(..).build();
+/// let settle = client.init_tx(&sub_account, false).await?.settle_pnl(..).build();
+///
+/// let txs = BundleBuilder::new()
+///     .add_tx(update_oracle)
+///     .add_tx(fill)
+///     .add_tx(settle)
+///     .tip(10_000)
+///     .build(client.wallet(), recent_blockhash)?;
+/// let bundle_id = block_engine.send_bundle(&txs).await?;
+/// ```
+pub struct BundleBuilder {
+    messages: Vec<VersionedMessage>,
+    tip_lamports: u64,
+    tip_account: Option<Pubkey>,
+}
+
+impl Default for BundleBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BundleBuilder {
+    pub fn new() -> Self {
+        Self {
+            messages: Vec::with_capacity(MAX_BUNDLE_SIZE),
+            tip_lamports: MIN_TIP_LAMPORTS,
+            tip_account: None,
+        }
+    }
+
+    /// Append a tx message to the bundle, txs execute in order of insertion
+    pub fn add_tx(mut self, message: VersionedMessage) -> Self {
+        self.messages.push(message);
+        self
+    }
+
+    /// Set the bundle tip in lamports (default: `MIN_TIP_LAMPORTS`)
+    ///
+    /// tips below `MIN_TIP_LAMPORTS` are raised to the minimum
+    pub fn tip(mut self, lamports: u64) -> Self {
+        self.tip_lamports = lamports.max(MIN_TIP_LAMPORTS);
+        self
+    }
+
+    /// Set the tip account (default: random from `JITO_TIP_ACCOUNTS`)
+    pub fn tip_account(mut self, tip_account: Pubkey) -> Self {
+        self.tip_account = Some(tip_account);
+        self
+    }
+
+    /// Sign the bundle txs, appending a tip transfer tx paid by the `wallet` signer
+    ///
+    /// * `wallet` - signs all bundle txs
+    /// * `recent_blockhash` - blockhash for all bundle txs
+    pub fn build(
+        self,
+        wallet: &Wallet,
+        recent_blockhash: Hash,
+    ) -> SdkResult<Vec<VersionedTransaction>> {
+        let bundle_size = self.messages.len() + 1;
+        if bundle_size > MAX_BUNDLE_SIZE {
+            return Err(SdkError::InvalidBundleSize(bundle_size));
+        }
+        let tip_account = self.tip_account.unwrap_or_else(|| {
+            JITO_TIP_ACCOUNTS[recent_blockhash.as_ref()[0] as usize % JITO_TIP_ACCOUNTS.len()]
+        });
+        let payer = wallet.signer();
+        let tip_tx = VersionedMessage::Legacy(Message::new(
+            &[system_instruction::transfer(
+                &payer,
+                &tip_account,
+                self.tip_lamports,
+            )],
+            Some(&payer),
+        ));
+
+        self.messages
+            .into_iter()
+            .chain(std::iter::once(tip_tx))
+            .map(|message| wallet.sign_tx(message, recent_blockhash))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::signature::Keypair;
+
+    use super::*;
+    use crate::utils::test_utils::mock_http_server;
+
+    /// Minimal block-engine stand-in, responds to JSON-RPC requests by method
+    async fn mock_block_engine(responses: fn(&str) -> serde_json::Value) -> String {
+        let url = mock_http_server(move |_, request| {
+            let body = json!({
+                "jsonrpc": "2.0",
+                "id": request["id"],
+                "result": responses(request["method"].as_str().unwrap()),
+            });
+            (200, body.to_string())
+        })
+        .await;
+        format!("{url}/api/v1/bundles")
+    }
+
+    #[test]
+    fn bundle_builder_appends_tip() {
+        let wallet = Wallet::new(Keypair::new());
+        let recent_blockhash = Hash::new_unique();
+        let tip_account = Pubkey::new_unique();
+        let message = VersionedMessage::Legacy(Message::new(
+            &[system_instruction::transfer(
+                &wallet.signer(),
+                &Pubkey::new_unique(),
+                1,
+            )],
+            Some(&wallet.signer()),
+        ));
+
+        let txs = BundleBuilder::new()
+            .add_tx(message.clone())
+            .add_tx(message.clone())
+            .tip(50_000)
+            .tip_account(tip_account)
+            .build(&wallet, recent_blockhash)
+            .unwrap();
+
+        assert_eq!(txs.len(), 3);
+        assert!(txs
+            .iter()
+            .all(|tx| *tx.message.recent_blockhash() == recent_blockhash));
+        let tip_tx = txs.last().unwrap();
+        assert_eq!(
+            tip_tx.message.static_account_keys(),
+            &[wallet.signer(), tip_account, solana_sdk::system_program::ID]
+        );
+        assert!(tip_tx.verify_with_results().iter().all(|ok| *ok));
+
+        let too_large =
+            (0..MAX_BUNDLE_SIZE).fold(BundleBuilder::new(), |b, _| b.add_tx(message.clone()));
+        assert!(matches!(
+            too_large.build(&wallet, recent_blockhash),
+            Err(SdkError::InvalidBundleSize(6))
+        ));
+    }
+
+    #[test]
+    fn bundle_builder_min_tip() {
+        assert_eq!(BundleBuilder::default().tip_lamports, MIN_TIP_LAMPORTS);
+        assert_eq!(BundleBuilder::new().tip(1).tip_lamports, MIN_TIP_LAMPORTS);
+        assert_eq!(BundleBuilder::new().tip(50_000).tip_lamports, 50_000);
+    }
+
+    #[tokio::test]
+    async fn block_engine_send_and_wait() {
+        let url = mock_block_engine(|method| match method {
+            "sendBundle" => json!("bundle-1"),
+            "getInflightBundleStatuses" => json!({
+                "context": { "slot": 100 },
+                "value": [{ "bundle_id": "bundle-1", "status": "Landed", "landed_slot": 99 }],
+            }),
+            "getBundleStatuses" => json!({
+                "context": { "slot": 100 },
+                "value": [{
+                    "bundle_id": "bundle-1",
+                    "transactions": ["sig1"],
+                    "slot": 99,
+                    "confirmation_status": "confirmed",
+                    "err": { "Ok": null },
+                }],
+            }),
+            "getTipAccounts" => json!([JITO_TIP_ACCOUNTS[0].to_string()]),
+            _ => serde_json::Value::Null,
+        })
+        .await;
+
+        let wallet = Wallet::new(Keypair::new());
+        let txs = BundleBuilder::new()
+            .build(&wallet, Hash::new_unique())
+            .unwrap();
+
+        let block_engine = BlockEngineClient::new(&url);
+        let bundle_id = block_engine.send_bundle(&txs).await.unwrap();
+        assert_eq!(bundle_id, "bundle-1");
+
+        let outcome = block_engine
+            .wait_for_bundle(
+                &bundle_id,
+                Duration::from_millis(10),
+                Duration::from_secs(1),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            outcome,
+            BundleOutcome::Landed {
+                bundle_id: bundle_id.clone(),
+                slot: 99
+            }
+        );
+
+        let status = block_engine
+            .get_bundle_status(&bundle_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(status.slot, 99);
+        assert_eq!(
+            status.confirmation_status,
+            TransactionConfirmationStatus::Confirmed
+        );
+        assert!(status.err.is_ok());
+
+        assert_eq!(
+            block_engine.get_tip_accounts().await.unwrap(),
+            vec![JITO_TIP_ACCOUNTS[0]]
+        );
+
+        assert!(matches!(
+            block_engine.send_bundle(&[]).await,
+            Err(SdkError::InvalidBundleSize(0))
+        ));
+    }
+
+    #[tokio::test]
+    async fn block_engine_bundle_expires() {
+        let url = mock_block_engine(|method| match method {
+            "getInflightBundleStatuses" => json!({
+                "context": { "slot": 100 },
+                "value": [{ "bundle_id": "bundle-1", "status": "Pending", "landed_slot": null }],
+            }),
+            _ => serde_json::Value::Null,
+        })
+        .await;
+
+        let block_engine = BlockEngineClient::new(&url);
+        let outcome = block_engine
+            .wait_for_bundle(
+                "bundle-1",
+                Duration::from_millis(10),
+                Duration::from_millis(50),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            outcome,
+            BundleOutcome::Expired {
+                bundle_id: "bundle-1".into()
+            }
+        );
+    }
+}
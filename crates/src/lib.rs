@@ -12,6 +12,7 @@ use crate::titan::TitanSwapInfo;
 use crate::{
     account_map::AccountMap,
     blockhash_subscriber::BlockhashSubscriber,
+    compute_budget::{ComputeBudgetConfig, MAX_COMPUTE_UNIT_LIMIT},
     constants::{
         derive_perp_market_account, derive_revenue_share_escrow, derive_spot_market_account,
         ids::{drift_oracle_receiver_program, wormhole_program},
@@ -23,8 +24,12 @@ use crate::{
     grpc::grpc_subscriber::{AccountFilter, DriftGrpcClient, GeyserSubscribeOpts},
     jupiter::JupiterSwapInfo,
     marketmap::MarketMap,
+    mock::MockRpc,
     oraclemap::{Oracle, OracleMap},
+    rpc_pool::RpcPool,
+    swap::SwapQuote,
     swift_order_subscriber::{SignedOrderInfo, SwiftOrderStream},
+    tx_sender::{TxSender, TxSenderConfig},
     types::{
         accounts::{PerpMarket, SpotMarket, State, User, UserStats},
         AccountUpdate, DataAndSlot, MarketType, *,
@@ -37,9 +42,8 @@ use base64::Engine;
 use bytemuck::Pod;
 use constants::{
     high_leverage_mode_account, ASSOCIATED_TOKEN_PROGRAM_ID, PROGRAM_ID, SYSTEM_PROGRAM_ID,
-    TOKEN_2022_PROGRAM_ID, TOKEN_PROGRAM_ID,
 };
-pub use drift_pubsub_client::PubsubClient;
+pub use drift_pubsub_client::{ConnectionStatus, PubsubClient};
 use futures_util::TryFutureExt;
 use log::debug;
 use pythnet_sdk::wire::v1::{AccumulatorUpdateData, Proof};
@@ -57,19 +61,25 @@ use solana_sdk::{
     hash::Hash,
     instruction::{AccountMeta, Instruction},
     message::{v0, Message, VersionedMessage},
+    nonce::state::Data as NonceData,
     signature::Signature,
 };
 pub use solana_sdk::{address_lookup_table::AddressLookupTableAccount, pubkey::Pubkey};
 
 // utils
+pub mod alt_manager;
 pub mod async_utils;
+pub mod compute_budget;
+pub mod durable_nonce;
 pub mod ffi;
 pub mod jupiter;
 pub mod market_state;
+pub mod swap;
 pub mod titan;
 pub use market_state::MarketState;
 pub mod math;
 pub mod memcmp;
+pub mod mock;
 pub mod utils;
 pub mod wallet;
 
@@ -89,16 +99,28 @@ pub mod websocket_program_account_subscriber;
 pub mod auction_subscriber;
 pub mod blockhash_subscriber;
 pub mod event_subscriber;
+pub mod priority_fee_strategy;
 pub mod priority_fee_subscriber;
+pub mod swift_client;
 pub mod swift_order_subscriber;
 
 pub mod jit_client;
+pub mod jito;
+pub mod rpc_pool;
+pub mod test_harness;
+pub mod tx_sender;
 
 pub mod account_map;
+pub mod market_maker;
 pub mod marketmap;
 pub mod oraclemap;
+pub mod order_manager;
 
+pub mod pnl_tracker;
+pub mod replay;
 pub mod slot_subscriber;
+pub mod squads;
+pub mod user_state_tracker;
 pub mod usermap;
 
 pub mod dlob;
@@ -136,6 +158,8 @@ pub struct DriftClient {
     pub context: Context,
     backend: &'static DriftClientBackend,
     pub wallet: Wallet,
+    /// config for resolving `TransactionBuilder::auto_compute_budget` txs
+    compute_budget: ComputeBudgetConfig,
 }
 
 impl DriftClient {
@@ -148,11 +172,12 @@ impl DriftClient {
         // check URL format here to fail early, otherwise happens at request time.
         let _ = get_http_url(&rpc_client.url())?;
         Ok(Self {
-            backend: Box::leak(Box::new(
-                DriftClientBackend::new(context, Arc::new(rpc_client)).await?,
-            )),
+            backend: DriftClientBackend::new(context, Arc::new(rpc_client))
+                .await?
+                .into_static(),
             context,
             wallet,
+            compute_budget: Default::default(),
         })
     }
 
@@ -174,19 +199,73 @@ impl DriftClient {
         let _ws_pubsub_url = get_ws_url(ws_pubsub_url)?;
 
         Ok(Self {
-            backend: Box::leak(Box::new(
-                DriftClientBackend::new_with_explicit_ws_url(
-                    context,
-                    Arc::new(rpc_client),
-                    ws_pubsub_url,
-                )
-                .await?,
-            )),
+            backend: DriftClientBackend::new_with_explicit_ws_url(
+                context,
+                Arc::new(rpc_client),
+                ws_pubsub_url,
+            )
+            .await?
+            .into_static(),
             context,
             wallet: wallet.into(),
+            compute_budget: Default::default(),
+        })
+    }
+
+    /// Create a new `DriftClient` instance over a pool of RPC endpoints
+    ///
+    /// Reads are routed to the healthiest endpoint, txs are broadcast to all endpoints,
+    /// and Ws subscriptions fail over to the next endpoint on disconnect or stall
+    ///
+    /// * `context` - devnet or mainnet
+    /// * `rpc_pool` - RPC endpoint pool (see `RpcPool::subscribe`)
+    /// * `wallet` - wallet to use for tx signing convenience
+    pub async fn new_with_rpc_pool(
+        context: Context,
+        rpc_pool: Arc<RpcPool>,
+        wallet: Wallet,
+    ) -> SdkResult<Self> {
+        let ws_urls = rpc_pool.ws_urls()?;
+        let ws_urls: Vec<&str> = ws_urls.iter().map(String::as_str).collect();
+        let pubsub_client = PubsubClient::new_with_failover(&ws_urls).await?;
+
+        Ok(Self {
+            backend: DriftClientBackend::new_with_pubsub_client(
+                context,
+                Arc::new(rpc_pool.rpc_client()),
+                Arc::new(pubsub_client),
+            )
+            .await?
+            .into_static(),
+            context,
+            wallet,
+            compute_budget: Default::default(),
+        })
+    }
+
+    /// Create a new `DriftClient` instance backed by in-memory accounts, for offline testing
+    ///
+    /// Markets, oracles, users, and state are served from `mock` and sent txs are captured by it.
+    /// Ws subscriptions are unavailable
+    ///
+    /// * `context` - devnet or mainnet
+    /// * `mock` - account snapshot (see `MockRpc`)
+    /// * `wallet` - wallet to use for tx signing convenience
+    pub async fn new_mock(context: Context, mock: Arc<MockRpc>, wallet: Wallet) -> SdkResult<Self> {
+        Ok(Self {
+            backend: DriftClientBackend::new_mock(context, mock).await?,
+            context,
+            wallet,
+            compute_budget: Default::default(),
         })
     }
 
+    /// Set the config used to resolve the compute budget of `TransactionBuilder::auto_compute_budget` txs
+    pub fn with_compute_budget_config(mut self, config: ComputeBudgetConfig) -> Self {
+        self.compute_budget = config;
+        self
+    }
+
     pub async fn sync_user_accounts(&self, filters: Vec<RpcFilterType>) -> SdkResult<()> {
         self.backend.account_map.sync_user_accounts(filters).await
     }
@@ -389,6 +468,35 @@ impl DriftClient {
         .await
     }
 
+    /// Subscribe to swift order feed(s) for given `markets`, with connection status updates
+    ///
+    /// The feed reconnects and resubscribes on disconnect or missed heartbeat per `retry_policy`
+    ///
+    /// see `subscribe_swift_orders` for other params
+    ///
+    /// Returns a stream of swift orders and a channel of connection status updates
+    pub async fn subscribe_swift_orders_with_status(
+        &self,
+        markets: &[MarketId],
+        accept_sanitized: Option<bool>,
+        accept_deposit_trades: Option<bool>,
+        swift_ws_url: Option<String>,
+        retry_policy: impl async_utils::retry_policy::TaskRetryPolicy,
+    ) -> SdkResult<(
+        SwiftOrderStream,
+        tokio::sync::watch::Receiver<ConnectionStatus>,
+    )> {
+        swift_order_subscriber::subscribe_swift_orders_with_status(
+            self,
+            markets,
+            accept_sanitized.is_some_and(|x| x),
+            accept_deposit_trades.is_some_and(|x| x),
+            swift_ws_url,
+            retry_policy,
+        )
+        .await
+    }
+
     /// Returns the MarketIds for all active spot markets (ignores de-listed and settled markets)
     ///
     /// Useful for iterating over all spot markets
@@ -699,6 +807,7 @@ impl DriftClient {
     ///
     /// Returns the signature on success
     pub async fn sign_and_send(&self, tx: VersionedMessage) -> SdkResult<Signature> {
+        let tx = self.resolve_compute_budget(tx).await;
         let recent_block_hash = self.backend.get_latest_blockhash().await?;
         self.backend
             .sign_and_send(self.wallet(), tx, recent_block_hash)
@@ -718,6 +827,7 @@ impl DriftClient {
         recent_block_hash: Option<Hash>,
         config: RpcSendTransactionConfig,
     ) -> SdkResult<Signature> {
+        let tx = self.resolve_compute_budget(tx).await;
         let recent_block_hash = match recent_block_hash {
             Some(h) => h,
             None => self.backend.get_latest_blockhash().await?,
@@ -728,6 +838,129 @@ impl DriftClient {
             .map_err(|err| err.to_out_of_sol_error().unwrap_or(err))
     }
 
+    /// Resolve the compute budget of a tx built with `TransactionBuilder::auto_compute_budget`
+    ///
+    /// The CU limit is set from simulation plus the configured margin and the CU price from recent priority fees
+    /// (see `DriftClient::with_compute_budget_config`). Other txs are returned unchanged.
+    ///
+    /// If simulation fails the tx keeps the max. CU limit
+    pub async fn resolve_compute_budget(&self, mut tx: VersionedMessage) -> VersionedMessage {
+        if !compute_budget::is_auto_compute_budget(&tx) {
+            return tx;
+        }
+        let config = &self.compute_budget;
+        let cu_limit = match self.simulate_tx(tx.clone()).await {
+            Ok(RpcSimulateTransactionResult {
+                err: None,
+                units_consumed: Some(units_consumed),
+                ..
+            }) => config.cu_limit(units_consumed),
+            Ok(result) => {
+                log::warn!(target: "compute_budget", "tx simulation failed: {:?}", result.err);
+                MAX_COMPUTE_UNIT_LIMIT
+            }
+            Err(err) => {
+                log::warn!(target: "compute_budget", "tx simulation failed: {err:?}");
+                MAX_COMPUTE_UNIT_LIMIT
+            }
+        };
+        compute_budget::set_compute_budget(&mut tx, cu_limit, config.microlamports_per_cu());
+
+        tx
+    }
+
+    /// Get the data of a durable nonce account from the network
+    ///
+    /// * `nonce_account` - an initialized nonce account
+    pub async fn get_nonce_data(&self, nonce_account: &Pubkey) -> SdkResult<NonceData> {
+        let account = self.rpc().get_account(nonce_account).await?;
+        durable_nonce::nonce_data_from_account(&account)
+    }
+
+    /// Get the current nonce value of a durable nonce account from the network
+    ///
+    /// * `nonce_account` - an initialized nonce account
+    pub async fn get_nonce(&self, nonce_account: &Pubkey) -> SdkResult<Hash> {
+        self.get_nonce_data(nonce_account)
+            .await
+            .map(|data| data.blockhash())
+    }
+
+    /// Create and initialize a durable nonce account funded by the client wallet
+    ///
+    /// * `seed` - nonce account seed, max 32 bytes (see `durable_nonce::derive_nonce_account`)
+    /// * `authority` - authority allowed to advance the nonce
+    ///
+    /// Returns the nonce account address and tx signature
+    pub async fn create_nonce_account(
+        &self,
+        seed: &str,
+        authority: &Pubkey,
+    ) -> SdkResult<(Pubkey, Signature)> {
+        let payer = self.wallet().authority();
+        let lamports = self
+            .rpc()
+            .get_minimum_balance_for_rent_exemption(durable_nonce::NONCE_ACCOUNT_SIZE)
+            .await?;
+        let (nonce_account, ixs) =
+            durable_nonce::create_nonce_account_ixs(payer, seed, authority, lamports)?;
+        let tx = VersionedMessage::Legacy(Message::new(&ixs, Some(payer)));
+        let signature = self.sign_and_send(tx).await?;
+
+        Ok((nonce_account, signature))
+    }
+
+    /// Sign a tx built with `TransactionBuilder::with_durable_nonce` using the current nonce value
+    ///
+    /// Only the wallet's signature is added, other required signatures (e.g. a separate nonce authority)
+    /// are left as default. The tx remains valid until the nonce is advanced so it may be submitted
+    /// after the remaining signatures are collected
+    ///
+    /// * `tx` - the tx message
+    /// * `nonce_account` - the nonce account used by `tx`
+    pub async fn sign_with_nonce(
+        &self,
+        tx: VersionedMessage,
+        nonce_account: &Pubkey,
+    ) -> SdkResult<VersionedTransaction> {
+        let nonce = self.get_nonce(nonce_account).await?;
+        self.wallet().partial_sign_tx(tx, nonce)
+    }
+
+    /// Prepare a Squads vault transaction proposal from `member` for the next transaction index of `multisig`
+    ///
+    /// see `TransactionBuilder::build_multisig_proposal`
+    ///
+    /// * `squads_program` - the Squads v4 program id
+    /// * `multisig` - the multisig account
+    /// * `vault_index` - index of the vault acting as drift authority
+    /// * `member` - multisig member proposing and approving
+    pub async fn multisig_proposal(
+        &self,
+        squads_program: Pubkey,
+        multisig: Pubkey,
+        vault_index: u8,
+        member: Pubkey,
+    ) -> SdkResult<squads::MultisigProposal> {
+        let account = self.rpc().get_account(&multisig).await?;
+        let transaction_index = squads::multisig_transaction_index(&account)?;
+        Ok(squads::MultisigProposal {
+            program_id: squads_program,
+            multisig,
+            vault_index,
+            transaction_index: transaction_index + 1,
+            member,
+            memo: None,
+        })
+    }
+
+    /// Returns a `TxSender` using the client's RPC and Ws connections
+    ///
+    /// Unlike `sign_and_send`, `TxSender::send` rebroadcasts the tx and tracks it until landed, failed, or expired
+    pub fn tx_sender(&self, config: TxSenderConfig) -> TxSender {
+        TxSender::new(self.rpc(), config).with_pubsub(self.ws())
+    }
+
     /// Get spot market account
     ///
     /// * `market_index` - spot market index
@@ -1120,6 +1353,101 @@ pub struct DriftClientBackend {
     grpc_unsub: RwLock<Option<(UnsubHandle, UnsubHandle)>>,
 }
 impl DriftClientBackend {
+    /// Leak the backend for sharing between `DriftClient` instances
+    ///
+    /// Ws subscribed state is resynced via RPC whenever the Ws reconnects
+    fn into_static(self) -> &'static Self {
+        let backend: &'static Self = Box::leak(Box::new(self));
+        backend.resync_on_reconnect();
+        backend
+    }
+
+    /// Spawn a task to resync Ws subscribed markets, oracles, and accounts after Ws reconnects
+    fn resync_on_reconnect(&'static self) {
+        let mut status = self.pubsub_client.status();
+        tokio::spawn(async move {
+            let mut reconnecting = false;
+            while status.changed().await.is_ok() {
+                let current = *status.borrow_and_update();
+                match current {
+                    ConnectionStatus::Reconnecting => reconnecting = true,
+                    ConnectionStatus::Connected if reconnecting => {
+                        reconnecting = false;
+                        log::info!(target: "ws", "Ws reconnected, resyncing subscriptions");
+                        if let Err(err) = self.resync().await {
+                            log::warn!(target: "ws", "resync failed: {err:?}");
+                        }
+                    }
+                    ConnectionStatus::Failed => {
+                        log::error!(target: "ws", "Ws connection failed, subscriptions are stale");
+                        break;
+                    }
+                    _ => (),
+                }
+            }
+        });
+    }
+
+    /// Resync Ws subscribed markets, oracles, and accounts via RPC
+    async fn resync(&self) -> SdkResult<()> {
+        tokio::try_join!(
+            self.perp_market_map.resync(&self.rpc_client),
+            self.spot_market_map.resync(&self.rpc_client),
+            self.oracle_map.resync(&self.rpc_client),
+            self.account_map.resync(),
+        )?;
+
+        Ok(())
+    }
+
+    /// Initialize a `DriftClientBackend` serving accounts from `mock`
+    ///
+    /// Oracles and drift program accounts are preloaded so `try_get_*` queries are served from memory
+    async fn new_mock(context: Context, mock: Arc<MockRpc>) -> SdkResult<&'static Self> {
+        // never connects so Ws subscriptions fail
+        let pubsub_client = Arc::new(
+            PubsubClient::new_with_retry_policy(
+                &[mock::MOCK_WS_URL],
+                async_utils::retry_policy::never(),
+            )
+            .await?,
+        );
+        let backend =
+            Self::new_with_pubsub_client(context, Arc::new(mock.rpc_client()), pubsub_client)
+                .await?;
+
+        let oracle_markets: Vec<MarketId> = backend
+            .perp_market_map
+            .oracles()
+            .iter()
+            .chain(backend.spot_market_map.oracles().iter())
+            .filter(|(_, oracle, _)| mock.account(oracle).is_some())
+            .map(|(market, _, _)| *market)
+            .collect();
+        if !oracle_markets.is_empty() {
+            backend
+                .oracle_map
+                .sync(&oracle_markets, &backend.rpc_client)
+                .await?;
+        }
+
+        let on_account = backend.account_map.on_account_fn();
+        for (pubkey, account) in mock.program_accounts() {
+            on_account(&grpc::AccountUpdate {
+                pubkey,
+                lamports: account.lamports,
+                data: &account.data,
+                owner: account.owner,
+                executable: account.executable,
+                rent_epoch: account.rent_epoch,
+                slot: mock.slot(),
+            });
+        }
+
+        // no Ws to resync so skip `into_static`
+        Ok(Box::leak(Box::new(backend)))
+    }
+
     /// Initialize a new `DriftClientBackend`
     async fn new(context: Context, rpc_client: Arc<RpcClient>) -> SdkResult<Self> {
         let pubsub_client =
@@ -1208,10 +1536,18 @@ impl DriftClientBackend {
         rpc_client: Arc<RpcClient>,
         ws_pubsub_url: &str,
     ) -> SdkResult<Self> {
-        use std::time::Duration;
-
         // Initialize PubsubClient with explicit URL
         let pubsub_client = Arc::new(PubsubClient::new(ws_pubsub_url).await?);
+        Self::new_with_pubsub_client(context, rpc_client, pubsub_client).await
+    }
+
+    /// Initialize a new `DriftClientBackend` using an existing `pubsub_client`
+    async fn new_with_pubsub_client(
+        context: Context,
+        rpc_client: Arc<RpcClient>,
+        pubsub_client: Arc<PubsubClient>,
+    ) -> SdkResult<Self> {
+        use std::time::Duration;
 
         let perp_market_map =
             MarketMap::<PerpMarket>::new(Arc::clone(&pubsub_client), rpc_client.commitment());
@@ -1900,9 +2236,12 @@ pub struct TransactionBuilder<'a> {
     authority: Pubkey,
     /// use legacy transaction mode
     legacy: bool,
+    /// durable nonce account and its authority, if set
+    nonce: Option<(Pubkey, Pubkey)>,
 }
 
 /// Jupiter swap instructions prepared for insertion into a transaction
+#[deprecated = "use SwapQuote"]
 pub struct JupiterSwapInstructions {
     /// Account creation instructions (if needed)
     pub account_creation_instructions: Vec<Instruction>,
@@ -1918,6 +2257,7 @@ pub struct JupiterSwapInstructions {
 
 #[cfg(feature = "titan")]
 /// Titan swap instructions prepared for insertion into a transaction
+#[deprecated = "use SwapQuote"]
 pub struct TitanSwapInstructions {
     /// Account creation instructions (if needed)
     pub account_creation_instructions: Vec<Instruction>,
@@ -1964,6 +2304,7 @@ impl<'a> TransactionBuilder<'a> {
             lookup_tables: program_data.lookup_tables.to_vec(),
             legacy: false,
             force_markets: Default::default(),
+            nonce: None,
         }
     }
     /// Pubkey of sub-account owner
@@ -1994,10 +2335,11 @@ impl<'a> TransactionBuilder<'a> {
 
         self
     }
-    /// Set the priority fee of the tx
+    /// Set the priority fee of the tx, replacing any existing compute budget
     ///
     /// * `microlamports_per_cu` - the price per unit of compute in µ-lamports
     pub fn with_priority_fee(mut self, microlamports_per_cu: u64, cu_limit: Option<u32>) -> Self {
+        self.clear_compute_budget();
         let cu_limit_ix = ComputeBudgetInstruction::set_compute_unit_price(microlamports_per_cu);
         self.ixs.insert(0, cu_limit_ix);
         if let Some(cu_limit) = cu_limit {
@@ -2008,6 +2350,39 @@ impl<'a> TransactionBuilder<'a> {
         self
     }
 
+    /// Set the compute budget of the tx automatically at send time, replacing any existing compute budget
+    ///
+    /// The CU limit is estimated by simulation and the CU price from recent priority fees
+    /// see `DriftClient::resolve_compute_budget`
+    pub fn auto_compute_budget(mut self) -> Self {
+        self.clear_compute_budget();
+        for (idx, ix) in compute_budget::auto_compute_budget_ixs()
+            .into_iter()
+            .enumerate()
+        {
+            self.ixs.insert(idx, ix);
+        }
+
+        self
+    }
+
+    /// Remove all compute budget ixs, the runtime rejects txs with duplicates
+    fn clear_compute_budget(&mut self) {
+        self.ixs
+            .retain(|ix| ix.program_id != solana_sdk::compute_budget::ID);
+    }
+
+    /// Use durable nonce mode, the tx advances `nonce_account` and does not expire with the blockhash
+    ///
+    /// The tx must be signed with the current nonce value as blockhash, see `DriftClient::sign_with_nonce`
+    ///
+    /// * `nonce_account` - an initialized nonce account
+    /// * `nonce_authority` - authority of the nonce account (must sign the tx)
+    pub fn with_durable_nonce(mut self, nonce_account: Pubkey, nonce_authority: Pubkey) -> Self {
+        self.nonce = Some((nonce_account, nonce_authority));
+        self
+    }
+
     /// Append an ix to the Tx
     pub fn add_ix(mut self, ix: Instruction) -> Self {
         self.ixs.push(ix);
@@ -2895,8 +3270,8 @@ impl<'a> TransactionBuilder<'a> {
 
     /// Prepares Jupiter swap instructions for insertion into a transaction
     ///
-    /// This function handles common Jupiter-specific logic and returns a struct containing
-    /// all the instructions that need to be inserted between begin and end wrapper instructions.
+    /// # Panics
+    /// If the route contains unsupported ixs e.g. Jito tips, use `SwapQuote::try_from` to handle the error
     ///
     /// # Arguments
     /// * `jupiter_swap_info` - Jupiter swap route and instructions
@@ -2904,6 +3279,8 @@ impl<'a> TransactionBuilder<'a> {
     /// * `out_market` - Spot market of the output token
     /// * `in_token_account` - Input token account pubkey
     /// * `out_token_account` - Output token account pubkey
+    #[deprecated = "use SwapQuote::try_from and TransactionBuilder::swap"]
+    #[allow(deprecated)]
     pub fn build_jupiter_swap_ixs(
         authority: &Pubkey,
         jupiter_swap_info: JupiterSwapInfo,
@@ -2912,51 +3289,64 @@ impl<'a> TransactionBuilder<'a> {
         in_token_account: &Pubkey,
         out_token_account: &Pubkey,
     ) -> JupiterSwapInstructions {
-        let jupiter_swap_ixs = jupiter_swap_info.ixs;
-
-        // initialize token accounts
-        let account_creation_instructions = if !jupiter_swap_ixs.setup_instructions.is_empty() {
-            // jupiter swap ixs imply account creation is required
-            // provide our own creation ixs
-            vec![
-                Self::create_token_account_instructions(
-                    authority,
-                    in_token_account,
-                    &in_market.mint,
-                    &in_market.token_program(),
-                ),
-                Self::create_token_account_instructions(
-                    authority,
-                    out_token_account,
-                    &out_market.mint,
-                    &out_market.token_program(),
-                ),
-            ]
-        } else {
-            Vec::new()
-        };
-
-        // TODO: support jito bundle
-        if !jupiter_swap_ixs.other_instructions.is_empty() {
-            panic!("jupiter swap unsupported ix: Jito tip");
-        }
-
-        // support SOL unwrap ixs, ignore account delete/reclaim ixs
-        let cleanup_instruction = jupiter_swap_ixs.cleanup_instruction.filter(|ix| {
-            ix.program_id != TOKEN_PROGRAM_ID && ix.program_id != TOKEN_2022_PROGRAM_ID
-        });
-
+        let quote = SwapQuote::try_from(jupiter_swap_info).unwrap_or_else(|err| panic!("{err}"));
+        let account_creation_instructions = Self::swap_token_account_ixs(
+            authority,
+            &quote,
+            in_market,
+            out_market,
+            in_token_account,
+            out_token_account,
+        );
+        let mut ixs = quote.ixs.into_iter();
         JupiterSwapInstructions {
             account_creation_instructions,
-            in_amount: jupiter_swap_info.quote.in_amount,
-            swap_instruction: jupiter_swap_ixs.swap_instruction,
-            cleanup_instruction,
-            luts: jupiter_swap_info.luts,
+            in_amount: quote.in_amount,
+            swap_instruction: ixs.next().expect("jupiter swap ix"),
+            cleanup_instruction: ixs.next(),
+            luts: quote.luts,
+        }
+    }
+
+    #[cfg(feature = "titan")]
+    /// Prepares Titan swap instructions for insertion into a transaction
+    ///
+    /// # Arguments
+    /// * `titan_swap_info` - Titan swap route and instructions
+    /// * `in_market` - Spot market of the input token
+    /// * `out_market` - Spot market of the output token
+    /// * `in_token_account` - Input token account pubkey
+    /// * `out_token_account` - Output token account pubkey
+    #[deprecated = "use SwapQuote::from and TransactionBuilder::swap"]
+    #[allow(deprecated)]
+    pub fn build_titan_swap_ixs(
+        authority: &Pubkey,
+        titan_swap_info: TitanSwapInfo,
+        in_market: &SpotMarket,
+        out_market: &SpotMarket,
+        in_token_account: &Pubkey,
+        out_token_account: &Pubkey,
+    ) -> TitanSwapInstructions {
+        let quote = SwapQuote::from(titan_swap_info);
+        TitanSwapInstructions {
+            account_creation_instructions: Self::swap_token_account_ixs(
+                authority,
+                &quote,
+                in_market,
+                out_market,
+                in_token_account,
+                out_token_account,
+            ),
+            in_amount: quote.in_amount,
+            swap_instructions: quote.ixs,
+            luts: quote.luts,
         }
     }
 
     /// Add a Jupiter token swap to the tx
     ///
+    /// Errors if the route contains unsupported ixs e.g. Jito tips
+    ///
     /// # Arguments
     /// * `jupiter_swap_info` - Jupiter swap route and instructions
     /// * `in_market` - Spot market of the input token
@@ -2966,7 +3356,7 @@ impl<'a> TransactionBuilder<'a> {
     /// * `limit_price` - Set a limit price
     /// * `reduce_only` - Set a reduce only order
     pub fn jupiter_swap(
-        mut self,
+        self,
         jupiter_swap_info: JupiterSwapInfo,
         in_market: &SpotMarket,
         out_market: &SpotMarket,
@@ -2974,49 +3364,24 @@ impl<'a> TransactionBuilder<'a> {
         out_token_account: &Pubkey,
         limit_price: Option<u64>,
         reduce_only: Option<SwapReduceOnly>,
-    ) -> Self {
-        let JupiterSwapInstructions {
-            account_creation_instructions,
-            in_amount,
-            swap_instruction,
-            cleanup_instruction,
-            luts,
-        } = Self::build_jupiter_swap_ixs(
-            &self.authority,
-            jupiter_swap_info,
-            in_market,
-            out_market,
-            in_token_account,
-            out_token_account,
-        );
-        self.ixs.extend(account_creation_instructions);
-
-        self = self.begin_swap(
-            in_amount,
-            in_market,
-            out_market,
-            in_token_account,
-            out_token_account,
-        );
-        self.ixs.push(swap_instruction);
-        if let Some(cleanup_ix) = cleanup_instruction {
-            self.ixs.push(cleanup_ix);
-        }
-        self = self.end_swap(
+    ) -> SdkResult<Self> {
+        Ok(self.swap(
+            SwapQuote::try_from(jupiter_swap_info)?,
             in_market,
             out_market,
             in_token_account,
             out_token_account,
             limit_price,
             reduce_only,
-        );
-        self.lookup_tables(&luts)
+        ))
     }
 
     /// Add a Jupiter token swap to the tx for liquidation
     ///
     /// This wraps the Jupiter swap with `liquidate_spot_with_swap_begin` and `liquidate_spot_with_swap_end`
     ///
+    /// Errors if the route contains unsupported ixs e.g. Jito tips
+    ///
     /// # Arguments
     /// * `jupiter_swap_info` - Jupiter swap route and instructions
     /// * `in_market` - Spot market of the input token (liability market)
@@ -3027,7 +3392,7 @@ impl<'a> TransactionBuilder<'a> {
     /// * `liability_market_index` - Market index of the liability (borrow)
     /// * `user_account` - The user account being liquidated
     pub fn jupiter_swap_liquidate(
-        mut self,
+        self,
         jupiter_swap_info: JupiterSwapInfo,
         in_market: &SpotMarket,
         out_market: &SpotMarket,
@@ -3036,46 +3401,21 @@ impl<'a> TransactionBuilder<'a> {
         asset_market_index: u16,
         liability_market_index: u16,
         user_account: &User,
-    ) -> Self {
-        let JupiterSwapInstructions {
-            account_creation_instructions,
-            in_amount,
-            swap_instruction,
-            cleanup_instruction,
-            luts,
-        } = Self::build_jupiter_swap_ixs(
-            &self.authority,
-            jupiter_swap_info,
+    ) -> SdkResult<Self> {
+        Ok(self.swap_liquidate(
+            SwapQuote::try_from(jupiter_swap_info)?,
             in_market,
             out_market,
             in_token_account,
             out_token_account,
-        );
-        self.ixs.extend(account_creation_instructions);
-        self = self.liquidate_spot_with_swap_begin(
-            asset_market_index,
-            liability_market_index,
-            in_amount,
-            user_account,
-        );
-        self.ixs.push(swap_instruction);
-        if let Some(cleanup_ix) = cleanup_instruction {
-            self.ixs.push(cleanup_ix);
-        }
-        self = self.liquidate_spot_with_swap_end(
             asset_market_index,
             liability_market_index,
             user_account,
-        );
-
-        self.lookup_tables(&luts)
+        ))
     }
 
     #[cfg(feature = "titan")]
-    /// Prepares Titan swap instructions for insertion into a transaction
-    ///
-    /// This function handles common Titan-specific logic and returns a struct containing
-    /// all the instructions that need to be inserted between begin and end wrapper instructions.
+    /// Add a Titan token swap to the tx
     ///
     /// # Arguments
     /// * `titan_swap_info` - Titan swap route and instructions
@@ -3083,63 +3423,81 @@ impl<'a> TransactionBuilder<'a> {
     /// * `out_market` - Spot market of the output token
     /// * `in_token_account` - Input token account pubkey
     /// * `out_token_account` - Output token account pubkey
-    pub fn build_titan_swap_ixs(
-        authority: &Pubkey,
+    /// * `limit_price` - Set a limit price
+    /// * `reduce_only` - Set a reduce only order
+    pub fn titan_swap(
+        self,
         titan_swap_info: TitanSwapInfo,
         in_market: &SpotMarket,
         out_market: &SpotMarket,
         in_token_account: &Pubkey,
         out_token_account: &Pubkey,
-    ) -> TitanSwapInstructions {
-        let swap_response = titan_swap_info.ixs;
-
-        let account_creation_instructions = vec![
-            Self::create_token_account_instructions(
-                authority,
-                in_token_account,
-                &in_market.mint,
-                &in_market.token_program(),
-            ),
-            Self::create_token_account_instructions(
-                authority,
-                out_token_account,
-                &out_market.mint,
-                &out_market.token_program(),
-            ),
-        ];
-
-        let swap_instructions: Vec<Instruction> = swap_response
-            .instructions
-            .into_iter()
-            .filter(|ix| {
-                ix.program_id != TOKEN_PROGRAM_ID
-                    && ix.program_id != TOKEN_2022_PROGRAM_ID
-                    && ix.program_id != ASSOCIATED_TOKEN_PROGRAM_ID
-            })
-            .collect();
-
-        TitanSwapInstructions {
-            account_creation_instructions,
-            in_amount: titan_swap_info.quote.in_amount,
-            swap_instructions,
-            luts: titan_swap_info.luts,
-        }
+        limit_price: Option<u64>,
+        reduce_only: Option<SwapReduceOnly>,
+    ) -> Self {
+        self.swap(
+            SwapQuote::from(titan_swap_info),
+            in_market,
+            out_market,
+            in_token_account,
+            out_token_account,
+            limit_price,
+            reduce_only,
+        )
     }
 
     #[cfg(feature = "titan")]
-    /// Add a Titan token swap to the tx
+    /// Add a Titan token swap to the tx for liquidation
+    ///
+    /// This wraps the Titan swap with `liquidate_spot_with_swap_begin` and `liquidate_spot_with_swap_end`
     ///
     /// # Arguments
     /// * `titan_swap_info` - Titan swap route and instructions
+    /// * `in_market` - Spot market of the input token (liability market)
+    /// * `out_market` - Spot market of the output token (asset market)
+    /// * `in_token_account` - Input token account pubkey (for account creation if needed)
+    /// * `out_token_account` - Output token account pubkey (for account creation if needed)
+    /// * `asset_market_index` - Market index of the asset (collateral)
+    /// * `liability_market_index` - Market index of the liability (borrow)
+    /// * `user_account` - The user account being liquidated
+    pub fn titan_swap_liquidate(
+        self,
+        titan_swap_info: TitanSwapInfo,
+        in_market: &SpotMarket,
+        out_market: &SpotMarket,
+        in_token_account: &Pubkey,
+        out_token_account: &Pubkey,
+        asset_market_index: u16,
+        liability_market_index: u16,
+        user_account: &User,
+    ) -> Self {
+        self.swap_liquidate(
+            SwapQuote::from(titan_swap_info),
+            in_market,
+            out_market,
+            in_token_account,
+            out_token_account,
+            asset_market_index,
+            liability_market_index,
+            user_account,
+        )
+    }
+
+    /// Add a token swap from any `SwapQuoteProvider` to the tx
+    ///
+    /// The quote ixs are wrapped with `begin_swap` and `end_swap`
+    ///
+    /// # Arguments
+    /// * `quote` - swap quote e.g. from `swap::best_quote`
     /// * `in_market` - Spot market of the input token
     /// * `out_market` - Spot market of the output token
     /// * `in_token_account` - Input token account pubkey
     /// * `out_token_account` - Output token account pubkey
     /// * `limit_price` - Set a limit price
     /// * `reduce_only` - Set a reduce only order
-    pub fn titan_swap(
+    pub fn swap(
         mut self,
-        titan_swap_info: TitanSwapInfo,
+        quote: SwapQuote,
         in_market: &SpotMarket,
         out_market: &SpotMarket,
         in_token_account: &Pubkey,
@@ -3147,29 +3505,21 @@ impl<'a> TransactionBuilder<'a> {
         limit_price: Option<u64>,
         reduce_only: Option<SwapReduceOnly>,
     ) -> Self {
-        let TitanSwapInstructions {
-            account_creation_instructions,
-            in_amount,
-            swap_instructions,
-            luts,
-        } = Self::build_titan_swap_ixs(
-            &self.authority,
-            titan_swap_info,
+        self = self.swap_token_accounts(
+            &quote,
             in_market,
             out_market,
             in_token_account,
             out_token_account,
         );
-        self.ixs.extend(account_creation_instructions);
-
         self = self.begin_swap(
-            in_amount,
+            quote.in_amount,
             in_market,
             out_market,
             in_token_account,
             out_token_account,
         );
-        self.ixs.extend(swap_instructions);
+        self.ixs.extend(quote.ixs);
         self = self.end_swap(
             in_market,
             out_market,
@@ -3178,16 +3528,15 @@ impl<'a> TransactionBuilder<'a> {
             limit_price,
             reduce_only,
         );
-        self.lookup_tables(&luts)
+        self.lookup_tables(&quote.luts)
     }
 
-    #[cfg(feature = "titan")]
-    /// Add a Titan token swap to the tx for liquidation
+    /// Add a token swap from any `SwapQuoteProvider` to the tx for liquidation
     ///
-    /// This wraps the Titan swap with `liquidate_spot_with_swap_begin` and `liquidate_spot_with_swap_end`
+    /// The quote ixs are wrapped with `liquidate_spot_with_swap_begin` and `liquidate_spot_with_swap_end`
     ///
     /// # Arguments
-    /// * `titan_swap_info` - Titan swap route and instructions
+    /// * `quote` - swap quote e.g. from `swap::best_quote`
     /// * `in_market` - Spot market of the input token (liability market)
     /// * `out_market` - Spot market of the output token (asset market)
     /// * `in_token_account` - Input token account pubkey (for account creation if needed)
@@ -3195,9 +3544,9 @@ impl<'a> TransactionBuilder<'a> {
     /// * `asset_market_index` - Market index of the asset (collateral)
     /// * `liability_market_index` - Market index of the liability (borrow)
     /// * `user_account` - The user account being liquidated
-    pub fn titan_swap_liquidate(
+    pub fn swap_liquidate(
         mut self,
-        jupiter_swap_info: TitanSwapInfo,
+        quote: SwapQuote,
         in_market: &SpotMarket,
         out_market: &SpotMarket,
         in_token_account: &Pubkey,
@@ -3206,34 +3555,75 @@ impl<'a> TransactionBuilder<'a> {
         liability_market_index: u16,
         user_account: &User,
     ) -> Self {
-        let TitanSwapInstructions {
-            account_creation_instructions,
-            in_amount,
-            swap_instructions,
-            luts,
-        } = Self::build_titan_swap_ixs(
-            &self.authority,
-            jupiter_swap_info,
+        self = self.swap_token_accounts(
+            &quote,
             in_market,
             out_market,
             in_token_account,
             out_token_account,
         );
-        self.ixs.extend(account_creation_instructions);
         self = self.liquidate_spot_with_swap_begin(
             asset_market_index,
             liability_market_index,
-            in_amount,
+            quote.in_amount,
             user_account,
         );
-        self.ixs.extend(swap_instructions);
+        self.ixs.extend(quote.ixs);
         self = self.liquidate_spot_with_swap_end(
             asset_market_index,
             liability_market_index,
             user_account,
         );
 
-        self.lookup_tables(&luts)
+        self.lookup_tables(&quote.luts)
+    }
+
+    /// Add in/out token account creation ixs if required by `quote`
+    fn swap_token_accounts(
+        mut self,
+        quote: &SwapQuote,
+        in_market: &SpotMarket,
+        out_market: &SpotMarket,
+        in_token_account: &Pubkey,
+        out_token_account: &Pubkey,
+    ) -> Self {
+        self.ixs.extend(Self::swap_token_account_ixs(
+            &self.authority,
+            quote,
+            in_market,
+            out_market,
+            in_token_account,
+            out_token_account,
+        ));
+        self
+    }
+
+    /// In/out token account creation ixs, empty unless required by `quote`
+    fn swap_token_account_ixs(
+        authority: &Pubkey,
+        quote: &SwapQuote,
+        in_market: &SpotMarket,
+        out_market: &SpotMarket,
+        in_token_account: &Pubkey,
+        out_token_account: &Pubkey,
+    ) -> Vec<Instruction> {
+        if !quote.create_token_accounts {
+            return Vec::new();
+        }
+        vec![
+            Self::create_token_account_instructions(
+                authority,
+                in_token_account,
+                &in_market.mint,
+                &in_market.token_program(),
+            ),
+            Self::create_token_account_instructions(
+                authority,
+                out_token_account,
+                &out_market.mint,
+                &out_market.token_program(),
+            ),
+        ]
     }
 
     /// Settle perp PnL for some user account and market
@@ -4009,7 +4399,14 @@ impl<'a> TransactionBuilder<'a> {
     }
 
     /// Build the transaction message ready for signing and sending
-    pub fn build(self) -> VersionedMessage {
+    pub fn build(mut self) -> VersionedMessage {
+        if let Some((nonce_account, nonce_authority)) = self.nonce {
+            // advance nonce must be the first ix
+            self.ixs.insert(
+                0,
+                durable_nonce::advance_nonce_ix(&nonce_account, &nonce_authority),
+            );
+        }
         if self.legacy {
             let message = Message::new(self.ixs.as_ref(), Some(&self.authority));
             VersionedMessage::Legacy(message)
@@ -4025,6 +4422,23 @@ impl<'a> TransactionBuilder<'a> {
         }
     }
 
+    /// Build a tx message proposing the ixs as a Squads multisig vault transaction instead of
+    /// executing them directly. The proposal is created then approved by `proposal.member`
+    ///
+    /// The sub-account authority should be the multisig vault e.g. build from `Wallet::read_only(vault)`
+    /// Compute budget ixs remain in the outer tx which is signed by `proposal.member`
+    pub fn build_multisig_proposal(
+        mut self,
+        proposal: &squads::MultisigProposal,
+    ) -> VersionedMessage {
+        let (mut ixs, vault_ixs): (Vec<_>, Vec<_>) =
+            self.ixs.drain(..).partition(squads::is_outer_ix);
+        ixs.extend(proposal.ixs(&vault_ixs));
+        self.ixs = ixs;
+        self.authority = proposal.member;
+        self.build()
+    }
+
     pub fn program_data(&self) -> &ProgramData {
         self.program_data
     }
@@ -4354,9 +4768,156 @@ mod tests {
             context: Context::DevNet,
             backend: Box::leak(Box::new(backend)),
             wallet: Wallet::new(keypair),
+            compute_budget: Default::default(),
         }
     }
 
+    /// Init a new `DriftClient` served from a `MockRpc` with a default sub-account for `wallet`
+    async fn setup_mock(wallet: &Wallet) -> DriftClient {
+        let mock = Arc::new(MockRpc::new(Context::DevNet));
+        mock.set_spot_market(SpotMarket {
+            pubkey: Pubkey::new_unique(),
+            market_index: 0,
+            oracle: Pubkey::new_unique(),
+            ..Default::default()
+        });
+        mock.set_user(
+            wallet.default_sub_account(),
+            User {
+                authority: *wallet.authority(),
+                ..Default::default()
+            },
+        );
+        DriftClient::new_mock(Context::DevNet, mock, wallet.clone())
+            .await
+            .unwrap()
+    }
+
+    fn compute_budget_ix_count(tx: &VersionedMessage) -> usize {
+        let keys = tx.static_account_keys();
+        tx.instructions()
+            .iter()
+            .filter(|ix| keys[ix.program_id_index as usize] == solana_sdk::compute_budget::ID)
+            .count()
+    }
+
+    #[tokio::test]
+    async fn compute_budget_ixs_replaced() {
+        let wallet = Wallet::new(Keypair::new());
+        let client = setup_mock(&wallet).await;
+        let sub_account = wallet.default_sub_account();
+
+        // explicit budget matching the auto placeholders stays explicit
+        let tx = client
+            .init_tx(&sub_account, false)
+            .await
+            .unwrap()
+            .auto_compute_budget()
+            .with_priority_fee(0, Some(MAX_COMPUTE_UNIT_LIMIT))
+            .cancel_all_orders()
+            .build();
+        assert!(!compute_budget::is_auto_compute_budget(&tx));
+        assert_eq!(compute_budget_ix_count(&tx), 2);
+
+        let tx = client
+            .init_tx(&sub_account, false)
+            .await
+            .unwrap()
+            .with_priority_fee(1_000, Some(200_000))
+            .auto_compute_budget()
+            .cancel_all_orders()
+            .build();
+        assert!(compute_budget::is_auto_compute_budget(&tx));
+        assert_eq!(compute_budget_ix_count(&tx), 3);
+    }
+
+    #[tokio::test]
+    async fn multisig_proposal_tx() {
+        let proposal = squads::MultisigProposal {
+            program_id: Pubkey::new_unique(),
+            multisig: Pubkey::new_unique(),
+            vault_index: 0,
+            transaction_index: 1,
+            member: Pubkey::new_unique(),
+            memo: None,
+        };
+        let wallet = Wallet::read_only(proposal.vault());
+        let client = setup_mock(&wallet).await;
+
+        let tx = client
+            .init_tx(&wallet.default_sub_account(), false)
+            .await
+            .unwrap()
+            .with_priority_fee(1_000, Some(200_000))
+            .cancel_all_orders()
+            .build_multisig_proposal(&proposal);
+
+        // compute budget stays in the outer tx, the drift ix is proposed
+        let keys = tx.static_account_keys();
+        assert_eq!(compute_budget_ix_count(&tx), 2);
+        assert_eq!(tx.instructions().len(), 2 + 3);
+        assert!(tx.instructions()[2..]
+            .iter()
+            .all(|ix| keys[ix.program_id_index as usize] == proposal.program_id));
+
+        // member pays and signs, the vault does not
+        assert_eq!(keys[0], proposal.member);
+        assert!(tx.is_signer(0));
+        assert_eq!(tx.header().num_required_signatures, 1);
+        assert!(keys
+            .iter()
+            .position(|k| *k == proposal.vault())
+            .is_none_or(|idx| !tx.is_signer(idx)));
+    }
+
+    #[tokio::test]
+    async fn durable_nonce_tx() {
+        let wallet = Wallet::new(Keypair::new());
+        let client = setup_mock(&wallet).await;
+        let nonce_account = Pubkey::new_unique();
+        let nonce_authority = Pubkey::new_unique();
+
+        let tx = client
+            .init_tx(&wallet.default_sub_account(), false)
+            .await
+            .unwrap()
+            .with_priority_fee(1_000, Some(200_000))
+            .with_durable_nonce(nonce_account, nonce_authority)
+            .cancel_all_orders()
+            .build();
+
+        // advance nonce is the first ix
+        let keys = tx.static_account_keys();
+        let advance_ix = &tx.instructions()[0];
+        assert_eq!(
+            keys[advance_ix.program_id_index as usize],
+            solana_sdk::system_program::ID
+        );
+        assert_eq!(
+            durable_nonce::durable_nonce_account(&tx),
+            Some(nonce_account)
+        );
+
+        // nonce authority signs alongside the payer
+        let authority_idx = keys.iter().position(|k| *k == nonce_authority).unwrap();
+        assert!(tx.is_signer(authority_idx));
+        assert_eq!(keys[0], *wallet.authority());
+
+        // wallet signs its slot, nonce authority slot is left for later
+        let nonce = solana_sdk::hash::Hash::new_unique();
+        let signed = wallet.partial_sign_tx(tx, nonce).unwrap();
+        assert_eq!(*signed.message.recent_blockhash(), nonce);
+        assert_eq!(
+            signed.signatures[authority_idx],
+            solana_sdk::signature::Signature::default()
+        );
+        assert_eq!(
+            signed.verify_with_results(),
+            [true, false],
+            "only the wallet signature is set"
+        );
+    }
+
     #[tokio::test]
     async fn test_backend_send_sync() {
         let account_mocks = Mocks::default();
@@ -0,0 +1,905 @@
+//!
+//! Market maker
+//!
+//! Runtime for perp market making strategies. The strategy is fed oracle, book, fill and position
+//! updates and returns quote ladders which are constrained (inventory skew, position and size
+//! limits, min. spread) and reconciled with open orders by `OrderManager`.
+//!
+use std::time::{Duration, Instant};
+
+use futures_util::StreamExt;
+use log::{debug, error, info, warn};
+use solana_sdk::pubkey::Pubkey;
+
+use crate::{
+    dlob::{L2Book, DLOB},
+    event_subscriber::{DriftEvent, EventSubscriber},
+    ffi::MarginCalculation,
+    math::leverage::UserMargin,
+    oraclemap::Oracle,
+    order_manager::{OrderManager, Quote},
+    swift_order_subscriber::{SignedOrderInfo, SwiftOrderStream},
+    types::{
+        accounts::{PerpMarket, User},
+        MarketId, MarketPrecision, MarketType, OrderParams, PerpPosition, PositionDirection,
+        SdkResult,
+    },
+    DriftClient,
+};
+
+const LOG_TARGET: &str = "marketmaker";
+const BPS: i128 = 10_000;
+
+/// Default interval between requotes
+pub const DEFAULT_REQUOTE_INTERVAL: Duration = Duration::from_millis(400);
+
+/// Limits applied to strategy quotes before they are sent
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct QuoteConstraints {
+    /// max. absolute position (BASE_PRECISION)
+    pub max_position: u64,
+    /// max. size of a single quote (BASE_PRECISION)
+    pub max_order_size: u64,
+    /// quotes smaller than this are dropped (BASE_PRECISION)
+    pub min_order_size: u64,
+    /// quote sizes are rounded down to a multiple of this
+    pub size_tick: u64,
+    /// quote prices are rounded away from the oracle to a multiple of this
+    pub price_tick: u64,
+    /// min. distance of quotes from the oracle price (bps)
+    pub min_spread_bps: u64,
+    /// quote price shift at `max_position` (bps), scales linearly with position
+    pub inventory_skew_bps: u64,
+}
+
+impl Default for QuoteConstraints {
+    fn default() -> Self {
+        Self {
+            max_position: i64::MAX as u64,
+            max_order_size: u64::MAX,
+            min_order_size: 1,
+            size_tick: 1,
+            price_tick: 1,
+            min_spread_bps: 0,
+            inventory_skew_bps: 0,
+        }
+    }
+}
+
+impl QuoteConstraints {
+    /// Constraints with tick sizes and min. order size of `market`
+    pub fn for_market(market: &PerpMarket) -> Self {
+        Self {
+            min_order_size: market.min_order_size(),
+            size_tick: market.quantity_tick(),
+            price_tick: market.price_tick(),
+            ..Default::default()
+        }
+    }
+    /// Set the max. absolute position (BASE_PRECISION)
+    pub fn max_position(mut self, max_position: u64) -> Self {
+        self.max_position = max_position;
+        self
+    }
+    /// Set the max. size of a single quote (BASE_PRECISION)
+    pub fn max_order_size(mut self, max_order_size: u64) -> Self {
+        self.max_order_size = max_order_size;
+        self
+    }
+    /// Set the min. distance of quotes from the oracle price (bps)
+    pub fn min_spread_bps(mut self, bps: u64) -> Self {
+        self.min_spread_bps = bps;
+        self
+    }
+    /// Set the quote price shift at max. position (bps)
+    pub fn inventory_skew_bps(mut self, bps: u64) -> Self {
+        self.inventory_skew_bps = bps;
+        self
+    }
+    /// Remaining size that can be traded in `direction` before hitting `max_position`
+    pub fn capacity(&self, direction: PositionDirection, position: i64) -> u64 {
+        let max = self.max_position as i128;
+        let capacity = match direction {
+            PositionDirection::Long => max - position as i128,
+            PositionDirection::Short => max + position as i128,
+        };
+        capacity.clamp(0, u64::MAX as i128) as u64
+    }
+    /// Apply the constraints to strategy `quotes`
+    ///
+    /// * `oracle_price` - current oracle price (PRICE_PRECISION)
+    /// * `position` - current base position (BASE_PRECISION)
+    ///
+    /// Returns the adjusted quotes, quotes exceeding the position limit are shrunk or dropped
+    pub fn apply(&self, oracle_price: i64, position: i64, mut quotes: Vec<Quote>) -> Vec<Quote> {
+        let oracle = oracle_price.max(0) as i128;
+        let min_edge = oracle * self.min_spread_bps as i128 / BPS;
+        // long inventory lowers quotes to attract sells and vice versa
+        let skew = if self.max_position == 0 {
+            0
+        } else {
+            let max = self.max_position as i128;
+            oracle * self.inventory_skew_bps as i128 * (position as i128).clamp(-max, max)
+                / max
+                / BPS
+        };
+        let mut bid_capacity = self.capacity(PositionDirection::Long, position);
+        let mut ask_capacity = self.capacity(PositionDirection::Short, position);
+
+        // most aggressive quotes take capacity first
+        quotes.sort_by_key(Quote::priority);
+        quotes
+            .into_iter()
+            .filter_map(|mut quote| {
+                let is_bid = quote.direction == PositionDirection::Long;
+                match quote.oracle_price_offset {
+                    Some(offset) => {
+                        let offset = offset as i128 - skew;
+                        let offset = if is_bid {
+                            offset.min(-min_edge)
+                        } else {
+                            offset.max(min_edge)
+                        };
+                        let offset = round_to_tick(offset, self.price_tick, is_bid);
+                        quote.oracle_price_offset = Some(i32::try_from(offset).ok()?);
+                    }
+                    None => {
+                        let price = quote.price as i128 - skew;
+                        let price = if is_bid {
+                            price.min(oracle - min_edge)
+                        } else {
+                            price.max(oracle + min_edge)
+                        };
+                        let price = round_to_tick(price, self.price_tick, is_bid);
+                        if price <= 0 {
+                            return None;
+                        }
+                        quote.price = u64::try_from(price).ok()?;
+                    }
+                }
+
+                let capacity = if is_bid {
+                    &mut bid_capacity
+                } else {
+                    &mut ask_capacity
+                };
+                let size = quote.size.min(self.max_order_size).min(*capacity);
+                let size = size - size % self.size_tick.max(1);
+                if size == 0 || size < self.min_order_size {
+                    return None;
+                }
+                *capacity -= size;
+                quote.size = size;
+                Some(quote)
+            })
+            .collect()
+    }
+}
+
+/// Round `value` to a multiple of `tick`, down or up
+fn round_to_tick(value: i128, tick: u64, down: bool) -> i128 {
+    let tick = tick.max(1) as i128;
+    let rem = value.rem_euclid(tick);
+    if rem == 0 {
+        value
+    } else if down {
+        value - rem
+    } else {
+        value - rem + tick
+    }
+}
+
+/// Conditions which stop quoting
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct KillSwitch {
+    /// max. time without a new oracle update, measured from start until the first update
+    pub max_oracle_age: Duration,
+    /// min. collateral in excess of the maintenance margin requirement (QUOTE_PRECISION)
+    pub min_free_collateral: u128,
+    /// max. consecutive requotes where the margin could not be calculated
+    pub max_margin_failures: u32,
+}
+
+impl Default for KillSwitch {
+    fn default() -> Self {
+        Self {
+            max_oracle_age: Duration::from_secs(10),
+            min_free_collateral: 0,
+            max_margin_failures: 3,
+        }
+    }
+}
+
+impl KillSwitch {
+    /// Check the oracle of `market` last updated at `last_update` is not stale
+    pub fn check_oracle(
+        &self,
+        market: MarketId,
+        last_update: Instant,
+        now: Instant,
+    ) -> Option<KillReason> {
+        (now.saturating_duration_since(last_update) > self.max_oracle_age)
+            .then_some(KillReason::StaleOracle(market))
+    }
+    /// Check the account `margin` is above the configured minimum
+    pub fn check_margin(&self, margin: &MarginCalculation) -> Option<KillReason> {
+        let required = margin
+            .margin_requirement
+            .saturating_add(self.min_free_collateral);
+        (margin.total_collateral < required.min(i128::MAX as u128) as i128).then_some(
+            KillReason::MarginBreach {
+                total_collateral: margin.total_collateral,
+                margin_requirement: margin.margin_requirement,
+            },
+        )
+    }
+    /// Check the margin calculation has not failed `failures` times in a row
+    pub fn check_margin_failures(&self, failures: u32) -> Option<KillReason> {
+        (failures >= self.max_margin_failures).then_some(KillReason::MarginUnavailable)
+    }
+}
+
+/// Reason the market maker stopped
+#[derive(Clone, Debug, PartialEq)]
+pub enum KillReason {
+    /// no oracle update within `KillSwitch::max_oracle_age`
+    StaleOracle(MarketId),
+    /// collateral fell below `KillSwitch::min_free_collateral`
+    MarginBreach {
+        total_collateral: i128,
+        margin_requirement: u128,
+    },
+    /// margin calculation failed `KillSwitch::max_margin_failures` times in a row
+    MarginUnavailable,
+    /// the account event stream ended
+    StreamEnded,
+}
+
+/// Latest state of a quoted market
+#[derive(Clone, Debug)]
+pub struct MarketView {
+    pub market: MarketId,
+    /// latest oracle price (PRICE_PRECISION)
+    pub oracle_price: i64,
+    /// slot of latest oracle price
+    pub oracle_slot: u64,
+    /// latest L2 book, if a DLOB is attached
+    pub book: Option<std::sync::Arc<L2Book>>,
+    /// current base position (BASE_PRECISION)
+    pub position: i64,
+    pub constraints: QuoteConstraints,
+}
+
+/// A fill of one of the sub-account's orders
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fill {
+    pub market: MarketId,
+    pub direction: PositionDirection,
+    /// filled base amount (BASE_PRECISION)
+    pub base_asset_amount: u64,
+    /// filled quote amount (QUOTE_PRECISION)
+    pub quote_asset_amount: u64,
+    pub order_id: u32,
+    /// true if the sub-account was maker
+    pub is_maker: bool,
+    pub signature: String,
+}
+
+impl Fill {
+    /// Get the fill of `sub_account` from `event`, if any
+    pub fn from_event(event: &DriftEvent, sub_account: &Pubkey) -> Option<Self> {
+        let DriftEvent::OrderFill {
+            base_asset_amount_filled,
+            quote_asset_amount_filled,
+            market_index,
+            market_type,
+            signature,
+            ..
+        } = event
+        else {
+            return None;
+        };
+        let side = event.fill_side(sub_account)?;
+        Some(Self {
+            market: MarketId::new(*market_index, *market_type),
+            direction: side.direction,
+            base_asset_amount: *base_asset_amount_filled,
+            quote_asset_amount: *quote_asset_amount_filled,
+            order_id: side.order_id,
+            is_maker: side.is_maker,
+            signature: signature.clone(),
+        })
+    }
+}
+
+/// A market making strategy
+///
+/// Update hooks are called before `quote` on each requote
+pub trait Strategy: Send {
+    /// New oracle price for `market`
+    fn on_oracle(&mut self, _market: MarketId, _oracle: &Oracle) {}
+    /// New L2 book for `market` (requires `MarketMaker::with_dlob`)
+    fn on_book(&mut self, _market: MarketId, _book: &L2Book) {}
+    /// One of the sub-account's orders was filled
+    fn on_fill(&mut self, _fill: &Fill) {}
+    /// The sub-account's position in `market` changed
+    fn on_position(&mut self, _market: MarketId, _position: &PerpPosition) {}
+    /// Return the desired quote ladder for `market`
+    ///
+    /// Quotes are adjusted by the market's `QuoteConstraints` before reconciling with open orders
+    fn quote(&mut self, market: &MarketView) -> Vec<Quote>;
+    /// Return maker order params to fill a swift taker order, or `None` to ignore it
+    ///
+    /// The order size is limited to the market's remaining position capacity
+    fn on_swift_order(
+        &mut self,
+        _order: &SignedOrderInfo,
+        _market: &MarketView,
+    ) -> Option<OrderParams> {
+        None
+    }
+}
+
+struct MarketState {
+    view: MarketView,
+    /// time of the latest oracle update, `None` until the first one
+    last_oracle_update: Option<Instant>,
+}
+
+impl MarketState {
+    /// Returns true once the market has an oracle price to quote around
+    fn has_oracle(&self) -> bool {
+        self.last_oracle_update.is_some()
+    }
+    /// Check the oracle is not stale, a missing oracle is stale `max_oracle_age` after `started`
+    fn check_oracle(
+        &self,
+        kill_switch: &KillSwitch,
+        started: Instant,
+        now: Instant,
+    ) -> Option<KillReason> {
+        kill_switch.check_oracle(
+            self.view.market,
+            self.last_oracle_update.unwrap_or(started),
+            now,
+        )
+    }
+}
+
+/// Runs a `Strategy` on a sub-account's perp markets
+///
+/// ```example(no_run)
+///   let sol_perp = drift.market_lookup("sol-perp").unwrap();
+///   let market = drift.try_get_perp_market_account(sol_perp.index())?;
+///   let reason = MarketMaker::new(drift.clone(), drift.wallet().default_sub_account(), MyStrategy::default())
+///       .market(
+///           sol_perp,
+///           QuoteConstraints::for_market(&market)
+///               .max_position(100 * BASE_PRECISION_U64)
+///               .inventory_skew_bps(10),
+///       )
+///       .run()
+///       .await?;
+///   println!("market maker stopped: {reason:?}");
+/// ```
+pub struct MarketMaker<'a> {
+    drift: DriftClient,
+    sub_account: Pubkey,
+    strategy: Box<dyn Strategy>,
+    markets: Vec<MarketState>,
+    orders: OrderManager,
+    kill_switch: KillSwitch,
+    requote_interval: Duration,
+    priority_fee: Option<(u64, Option<u32>)>,
+    dlob: Option<&'a DLOB>,
+    swift_orders: Option<SwiftOrderStream>,
+    /// consecutive failed margin calculations
+    margin_failures: u32,
+}
+
+impl<'a> MarketMaker<'a> {
+    /// Create a new market maker quoting `strategy` from `sub_account`
+    pub fn new(drift: DriftClient, sub_account: Pubkey, strategy: impl Strategy + 'static) -> Self {
+        Self {
+            drift,
+            sub_account,
+            strategy: Box::new(strategy),
+            markets: Default::default(),
+            orders: OrderManager::new(sub_account),
+            kill_switch: KillSwitch::default(),
+            requote_interval: DEFAULT_REQUOTE_INTERVAL,
+            priority_fee: None,
+            dlob: None,
+            swift_orders: None,
+            margin_failures: 0,
+        }
+    }
+    /// Quote `market` subject to `constraints`
+    ///
+    /// Panics if `market` is not a perp market
+    pub fn market(mut self, market: MarketId, constraints: QuoteConstraints) -> Self {
+        assert!(market.is_perp(), "only perp markets are supported");
+        self.markets.push(MarketState {
+            view: MarketView {
+                market,
+                oracle_price: 0,
+                oracle_slot: 0,
+                book: None,
+                position: 0,
+                constraints,
+            },
+            last_oracle_update: None,
+        });
+        self
+    }
+    /// Set the kill switch conditions
+    pub fn kill_switch(mut self, kill_switch: KillSwitch) -> Self {
+        self.kill_switch = kill_switch;
+        self
+    }
+    /// Set the requote interval (default: 400ms)
+    pub fn requote_interval(mut self, interval: Duration) -> Self {
+        self.requote_interval = interval;
+        self
+    }
+    /// Set the priority fee of order txs
+    pub fn priority_fee(mut self, microlamports_per_cu: u64, cu_limit: Option<u32>) -> Self {
+        self.priority_fee = Some((microlamports_per_cu, cu_limit));
+        self
+    }
+    /// Feed L2 book updates from `dlob` to the strategy
+    pub fn with_dlob(mut self, dlob: &'a DLOB) -> Self {
+        self.dlob = Some(dlob);
+        self
+    }
+    /// Offer swift taker orders from `stream` to the strategy
+    pub fn with_swift_orders(mut self, stream: SwiftOrderStream) -> Self {
+        self.swift_orders = Some(stream);
+        self
+    }
+    /// Run the market maker until the kill switch triggers
+    ///
+    /// Open orders in the quoted markets are cancelled before returning
+    pub async fn run(mut self) -> SdkResult<KillReason> {
+        let user = self.drift.get_user_account(&self.sub_account).await?;
+        let markets = margin_markets(&user, self.markets.iter().map(|m| m.view.market).collect());
+        self.drift.subscribe_markets(&markets).await?;
+        self.drift.subscribe_oracles(&markets).await?;
+        self.drift.subscribe_account(&self.sub_account).await?;
+        let mut events = EventSubscriber::subscribe(self.drift.ws(), self.sub_account).await?;
+        let mut swift_orders = self.swift_orders.take();
+
+        let started = Instant::now();
+        let mut requote = tokio::time::interval(self.requote_interval);
+        requote.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+        let reason = loop {
+            tokio::select! {
+                biased;
+                event = events.next() => {
+                    let Some(event) = event else {
+                        break KillReason::StreamEnded;
+                    };
+                    self.orders.on_event(&event);
+                    if let Some(fill) = Fill::from_event(&event, &self.sub_account) {
+                        self.strategy.on_fill(&fill);
+                    }
+                }
+                order = next_swift_order(swift_orders.as_mut()) => {
+                    match order {
+                        Some(order) => self.make_swift_order(order),
+                        None => {
+                            warn!(target: LOG_TARGET, "swift order stream finished");
+                            swift_orders = None;
+                        }
+                    }
+                }
+                _ = requote.tick() => {
+                    if let Some(reason) = self.requote(started).await {
+                        break reason;
+                    }
+                }
+            }
+        };
+
+        error!(target: LOG_TARGET, "kill switch triggered: {reason:?}");
+        self.cancel_all().await;
+        Ok(reason)
+    }
+    /// Update market state, check the kill switch, and send order changes
+    ///
+    /// * `started` - time the market maker started, markets are quoted from their first oracle update
+    async fn requote(&mut self, started: Instant) -> Option<KillReason> {
+        let now = Instant::now();
+        let user: User = match self.drift.try_get_account(&self.sub_account) {
+            Ok(user) => user,
+            Err(err) => {
+                warn!(target: LOG_TARGET, "sub-account unavailable: {err:?}");
+                return None;
+            }
+        };
+
+        for state in self.markets.iter_mut() {
+            let market = state.view.market;
+            if let Some(oracle) = self.drift.try_get_oracle_price_data_and_slot(market) {
+                if oracle.slot != state.view.oracle_slot {
+                    state.view.oracle_price = oracle.data.price;
+                    state.view.oracle_slot = oracle.slot;
+                    state.last_oracle_update = Some(now);
+                    self.strategy.on_oracle(market, &oracle);
+                }
+            }
+            if let Some(reason) = state.check_oracle(&self.kill_switch, started, now) {
+                return Some(reason);
+            }
+            if let Some(book) = self
+                .dlob
+                .and_then(|dlob| dlob.get_l2_snapshot_safe(market.index(), market.kind()))
+            {
+                if state.view.book.as_ref().map(|b| b.slot) != Some(book.slot) {
+                    self.strategy.on_book(market, &book);
+                    state.view.book = Some(book);
+                }
+            }
+            let position = user.get_perp_position(market.index()).unwrap_or_default();
+            if position.base_asset_amount != state.view.position {
+                state.view.position = position.base_asset_amount;
+                self.strategy.on_position(market, &position);
+            }
+        }
+
+        match self.drift.calculate_margin_info(&user) {
+            Ok(margin) => {
+                self.margin_failures = 0;
+                if let Some(reason) = self.kill_switch.check_margin(&margin) {
+                    return Some(reason);
+                }
+            }
+            Err(err) => {
+                self.margin_failures += 1;
+                warn!(
+                    target: LOG_TARGET,
+                    "margin calculation failed ({}): {err:?}", self.margin_failures
+                );
+                if let Some(reason) = self.kill_switch.check_margin_failures(self.margin_failures) {
+                    return Some(reason);
+                }
+            }
+        }
+
+        let quotes: Vec<(MarketId, Vec<Quote>)> = self
+            .markets
+            .iter()
+            .map(|state| {
+                let view = &state.view;
+                if !state.has_oracle() {
+                    // pull quotes until there's a price to quote around
+                    return (view.market, vec![]);
+                }
+                let quotes = self.strategy.quote(view);
+                (
+                    view.market,
+                    view.constraints
+                        .apply(view.oracle_price, view.position, quotes),
+                )
+            })
+            .collect();
+        let batch = self.orders.reconcile(&user, &quotes);
+        if batch.is_empty() {
+            return None;
+        }
+
+        match self
+            .drift
+            .init_tx(&self.sub_account, self.drift.wallet().is_delegated())
+            .await
+        {
+            Ok(mut tx) => {
+                if let Some((cu_price, cu_limit)) = self.priority_fee {
+                    tx = tx.with_priority_fee(cu_price, cu_limit);
+                }
+                let tx = batch.apply(tx).build();
+                match self.drift.sign_and_send(tx).await {
+                    Ok(sig) => debug!(target: LOG_TARGET, "sent quotes: {sig}"),
+                    // in-flight changes expire with the `OrderManager` pending timeout
+                    Err(err) => warn!(target: LOG_TARGET, "send quotes failed: {err:?}"),
+                }
+            }
+            Err(err) => warn!(target: LOG_TARGET, "build quotes tx failed: {err:?}"),
+        }
+
+        None
+    }
+    /// Offer a swift taker `order` to the strategy and send the fill in the background
+    fn make_swift_order(&mut self, order: SignedOrderInfo) {
+        let taker_params = order.order_params();
+        let market = MarketId::perp(taker_params.market_index);
+        let Some(state) = self
+            .markets
+            .iter()
+            .find(|m| m.view.market == market && m.has_oracle())
+        else {
+            return;
+        };
+        let Some(mut maker_params) = self.strategy.on_swift_order(&order, &state.view) else {
+            return;
+        };
+        let capacity = state
+            .view
+            .constraints
+            .capacity(maker_params.direction, state.view.position);
+        maker_params.base_asset_amount = maker_params.base_asset_amount.min(capacity);
+        if maker_params.base_asset_amount < state.view.constraints.min_order_size.max(1) {
+            return;
+        }
+
+        let drift = self.drift.clone();
+        let sub_account = self.sub_account;
+        let priority_fee = self.priority_fee;
+        tokio::spawn(async move {
+            let taker_subaccount = order.taker_subaccount();
+            let delegated = drift.wallet().is_delegated();
+            let res = tokio::try_join!(
+                drift.get_user_account(&taker_subaccount),
+                drift.get_user_stats(&order.taker_authority),
+                drift.init_tx(&sub_account, delegated),
+            );
+            let (taker_account, taker_stats, mut tx) = match res {
+                Ok(res) => res,
+                Err(err) => {
+                    warn!(target: LOG_TARGET, "swift fill setup failed: {err:?}");
+                    return;
+                }
+            };
+            if let Some((cu_price, cu_limit)) = priority_fee {
+                tx = tx.with_priority_fee(cu_price, cu_limit);
+            }
+            let tx = tx
+                .place_and_make_swift_order(
+                    maker_params,
+                    &order,
+                    &taker_account,
+                    &taker_stats.referrer,
+                )
+                .build();
+            match drift.sign_and_send(tx).await {
+                Ok(sig) => info!(target: LOG_TARGET, "sent swift fill: {sig}"),
+                Err(err) => warn!(target: LOG_TARGET, "swift fill failed: {err:?}"),
+            }
+        });
+    }
+    /// Cancel all orders in the quoted markets
+    async fn cancel_all(&self) {
+        let delegated = self.drift.wallet().is_delegated();
+        match self.drift.init_tx(&self.sub_account, delegated).await {
+            Ok(mut tx) => {
+                for state in &self.markets {
+                    tx = tx.cancel_orders((state.view.market.index(), MarketType::Perp), None);
+                }
+                if let Err(err) = self.drift.sign_and_send(tx.build()).await {
+                    error!(target: LOG_TARGET, "cancel orders failed: {err:?}");
+                }
+            }
+            Err(err) => error!(target: LOG_TARGET, "cancel orders failed: {err:?}"),
+        }
+    }
+}
+
+/// Markets required to calculate the margin of `user` quoting perp `markets`
+///
+/// i.e. `markets`, the quote spot market, and the markets of `user`'s open positions
+fn margin_markets(user: &User, mut markets: Vec<MarketId>) -> Vec<MarketId> {
+    let spot = user
+        .spot_positions
+        .iter()
+        .filter(|p| !p.is_available())
+        .map(|p| MarketId::spot(p.market_index))
+        .chain(std::iter::once(MarketId::QUOTE_SPOT));
+    let perp = user
+        .perp_positions
+        .iter()
+        .filter(|p| !p.is_available())
+        .map(|p| MarketId::perp(p.market_index));
+    for market in spot.chain(perp) {
+        if !markets.contains(&market) {
+            markets.push(market);
+        }
+    }
+    markets
+}
+
+/// Next order from `stream`, pending forever if there is none
+async fn next_swift_order(stream: Option<&mut SwiftOrderStream>) -> Option<SignedOrderInfo> {
+    match stream {
+        Some(stream) => stream.next().await,
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        math::constants::{BASE_PRECISION_U64, PRICE_PRECISION_U64},
+        types::PostOnlyParam,
+    };
+
+    const ORACLE: u64 = 100 * PRICE_PRECISION_U64;
+
+    fn bid(price: u64, size: u64) -> Quote {
+        Quote::new(PositionDirection::Long, price, size)
+    }
+
+    fn ask(price: u64, size: u64) -> Quote {
+        Quote::new(PositionDirection::Short, price, size)
+    }
+
+    #[test]
+    fn constraints_spread_and_ticks() {
+        let constraints = QuoteConstraints {
+            price_tick: 100,
+            size_tick: 10,
+            min_order_size: 50,
+            ..Default::default()
+        }
+        .min_spread_bps(10);
+
+        let quotes = constraints.apply(
+            ORACLE as i64,
+            0,
+            vec![
+                bid(ORACLE, 1_005),
+                ask(ORACLE + 50_001, 105),
+                ask(ORACLE + 200_000, 45),
+                Quote::oracle_offset(PositionDirection::Long, 0, 100),
+            ],
+        );
+        // bids capped at oracle - 10bps, asks rounded up to tick, small quote dropped
+        assert_eq!(
+            quotes,
+            vec![
+                bid(ORACLE - 100_000, 1_000),
+                Quote::oracle_offset(PositionDirection::Long, -100_000, 100),
+                ask(ORACLE + 100_000, 100),
+            ]
+        );
+    }
+
+    #[test]
+    fn constraints_inventory() {
+        let constraints = QuoteConstraints::default()
+            .max_position(10 * BASE_PRECISION_U64)
+            .inventory_skew_bps(100);
+        let position = 5 * BASE_PRECISION_U64 as i64;
+
+        let quotes = constraints.apply(
+            ORACLE as i64,
+            position,
+            vec![
+                bid(ORACLE - PRICE_PRECISION_U64, 4 * BASE_PRECISION_U64),
+                bid(ORACLE - 2 * PRICE_PRECISION_U64, 4 * BASE_PRECISION_U64),
+                ask(ORACLE + PRICE_PRECISION_U64, 4 * BASE_PRECISION_U64)
+                    .post_only(PostOnlyParam::None),
+            ],
+        );
+        // half max position long: quotes shifted down 50bps, bids limited to 5 more
+        let skew = ORACLE / 200;
+        assert_eq!(
+            quotes,
+            vec![
+                bid(ORACLE - PRICE_PRECISION_U64 - skew, 4 * BASE_PRECISION_U64),
+                bid(ORACLE - 2 * PRICE_PRECISION_U64 - skew, BASE_PRECISION_U64),
+                ask(ORACLE + PRICE_PRECISION_U64 - skew, 4 * BASE_PRECISION_U64)
+                    .post_only(PostOnlyParam::None),
+            ]
+        );
+
+        // at max position only reducing quotes remain
+        let quotes = constraints.apply(
+            ORACLE as i64,
+            -10 * BASE_PRECISION_U64 as i64,
+            vec![bid(ORACLE, 1), ask(ORACLE, 1)],
+        );
+        assert_eq!(quotes.len(), 1);
+        assert_eq!(quotes[0].direction, PositionDirection::Long);
+    }
+
+    #[test]
+    fn kill_switch() {
+        let kill_switch = KillSwitch {
+            max_oracle_age: Duration::from_secs(5),
+            min_free_collateral: 100,
+            max_margin_failures: 2,
+        };
+        let market = MarketId::perp(0);
+        let now = Instant::now();
+        assert!(kill_switch.check_oracle(market, now, now).is_none());
+        assert_eq!(
+            kill_switch.check_oracle(market, now, now + Duration::from_secs(6)),
+            Some(KillReason::StaleOracle(market))
+        );
+
+        let mut margin = MarginCalculation {
+            total_collateral: 1_100,
+            margin_requirement: 1_000,
+            all_oracles_valid: true,
+            with_perp_isolated_liability: false,
+            with_spot_isolated_liability: false,
+            total_spot_asset_value: 0,
+            total_spot_liability_value: 0,
+            total_perp_liability_value: 0,
+            total_perp_pnl: 0,
+            open_orders_margin_requirement: 0,
+        };
+        assert!(kill_switch.check_margin(&margin).is_none());
+        margin.total_collateral = 1_099;
+        assert!(kill_switch.check_margin(&margin).is_some());
+
+        assert!(kill_switch.check_margin_failures(1).is_none());
+        assert_eq!(
+            kill_switch.check_margin_failures(2),
+            Some(KillReason::MarginUnavailable)
+        );
+    }
+
+    #[test]
+    fn margin_markets_include_positions() {
+        let mut user = User::default();
+        user.spot_positions[0].market_index = 1;
+        user.spot_positions[0].scaled_balance = 1;
+        user.perp_positions[0].market_index = 2;
+        user.perp_positions[0].base_asset_amount = 1;
+        user.perp_positions[1].market_index = 0;
+        user.perp_positions[1].base_asset_amount = -1;
+
+        assert_eq!(
+            margin_markets(&user, vec![MarketId::perp(0)]),
+            vec![
+                MarketId::perp(0),
+                MarketId::spot(1),
+                MarketId::QUOTE_SPOT,
+                MarketId::perp(2),
+            ]
+        );
+    }
+
+    #[test]
+    fn oracle_staleness_from_first_update() {
+        let kill_switch = KillSwitch {
+            max_oracle_age: Duration::from_secs(5),
+            ..Default::default()
+        };
+        let market = MarketId::perp(0);
+        let mut state = MarketState {
+            view: MarketView {
+                market,
+                oracle_price: 0,
+                oracle_slot: 0,
+                book: None,
+                position: 0,
+                constraints: QuoteConstraints::default(),
+            },
+            last_oracle_update: None,
+        };
+        let started = Instant::now();
+
+        // no quoting without an oracle, missing oracle goes stale
+        assert!(!state.has_oracle());
+        assert!(state.check_oracle(&kill_switch, started, started).is_none());
+        assert_eq!(
+            state.check_oracle(&kill_switch, started, started + Duration::from_secs(6)),
+            Some(KillReason::StaleOracle(market))
+        );
+
+        // clock runs from the latest update
+        let updated = started + Duration::from_secs(4);
+        state.last_oracle_update = Some(updated);
+        assert!(state.has_oracle());
+        assert!(state
+            .check_oracle(&kill_switch, started, updated + Duration::from_secs(5))
+            .is_none());
+        assert!(state
+            .check_oracle(&kill_switch, started, updated + Duration::from_secs(6))
+            .is_some());
+    }
+}
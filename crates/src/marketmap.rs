@@ -285,6 +285,31 @@ where
         Ok(())
     }
 
+    /// Resync Ws subscribed markets via RPC e.g. to fill updates missed during a Ws reconnect
+    ///
+    /// Markets with newer data are not overwritten
+    pub async fn resync(&self, rpc: &RpcClient) -> SdkResult<()> {
+        if self.subscriptions.is_empty() {
+            return Ok(());
+        }
+        let (markets, latest_slot) = get_market_accounts_with_fallback::<T>(rpc).await?;
+        for market in markets {
+            if !self.is_subscribed(market.market_index()) {
+                continue;
+            }
+            if let Some(mut entry) = self.marketmap.get_mut(&market.market_index()) {
+                if entry.slot < latest_slot {
+                    *entry = DataAndSlot {
+                        data: market,
+                        slot: latest_slot,
+                    };
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn get_latest_slot(&self) -> u64 {
         self.latest_slot.load(Ordering::Relaxed)
     }
@@ -1,16 +1,167 @@
+use std::str::FromStr;
+
 use ahash::{HashMap, HashMapExt};
+use anchor_lang::{AccountDeserialize, Discriminator};
 use arrayvec::ArrayVec;
-use solana_sdk::{account::Account, pubkey::Pubkey};
+use base64::Engine;
+use serde::Deserialize;
+use solana_account_decoder_client_types::{UiAccount, UiAccountData, UiAccountEncoding};
+use solana_sdk::{account::Account, clock::Slot, pubkey::Pubkey};
 
 use crate::{
-    accounts::State,
+    accounts::{PerpMarket, SpotMarket, State},
     constants::{self, oracle_source_to_owner, state_account},
     ffi::{AccountWithKey, AccountsList},
-    types::accounts::User,
+    types::{accounts::User, OracleSource},
     utils::zero_account_to_bytes,
     DriftClient, MarketId, SdkError, SdkResult,
 };
 
+/// Static snapshot of a user and its market, oracle (and optionally state) accounts
+///
+/// Allows building an `AccountsList` for margin calculations without a `DriftClient` or network access
+/// e.g. for offline analytics or unit tests
+///
+/// ```example(no_run)
+/// let bundle = AccountsBundle::from_json(&std::fs::read_to_string("bundle.json")?)?;
+/// let mut builder = AccountsListBuilder::default();
+/// let mut accounts = builder.build_offline(&bundle, &[])?;
+/// let margin = calculate_margin_requirement_and_total_collateral_and_liability_info(
+///     &bundle.user,
+///     &mut accounts,
+///     MarginContextMode::StandardMaintenance,
+/// )?;
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct AccountsBundle {
+    /// the user to build against
+    pub user: User,
+    /// market, oracle, and state accounts by address
+    pub accounts: HashMap<Pubkey, Account>,
+    /// slot the accounts were fetched at
+    pub slot: Slot,
+}
+
+/// JSON representation of an `AccountsBundle`
+///
+/// ```json
+/// {
+///   "slot": 123,
+///   "user": "<USER_PUBKEY>",
+///   "accounts": { "<PUBKEY>": <UiAccount (base64)>, ... }
+/// }
+/// ```
+/// `accounts` entries are in the same format as the RPC `getMultipleAccounts` response values
+#[derive(Deserialize)]
+struct AccountsBundleJson {
+    slot: Slot,
+    user: String,
+    accounts: std::collections::HashMap<String, UiAccount>,
+}
+
+impl AccountsBundle {
+    pub fn new(user: User, accounts: HashMap<Pubkey, Account>, slot: Slot) -> Self {
+        Self {
+            user,
+            accounts,
+            slot,
+        }
+    }
+
+    /// Construct a bundle from the result of an RPC `getMultipleAccounts` query
+    ///
+    /// * `user` - address of the user account, must be included in `pubkeys`
+    /// * `pubkeys` - queried addresses
+    /// * `accounts` - query result, in the same order as `pubkeys`
+    /// * `slot` - context slot of the query
+    pub fn from_multiple_accounts(
+        user: &Pubkey,
+        pubkeys: &[Pubkey],
+        accounts: Vec<Option<Account>>,
+        slot: Slot,
+    ) -> SdkResult<Self> {
+        let accounts: HashMap<Pubkey, Account> = pubkeys
+            .iter()
+            .zip(accounts)
+            .filter_map(|(pubkey, account)| account.map(|a| (*pubkey, a)))
+            .collect();
+        let user_account = accounts.get(user).ok_or(SdkError::NoAccountData(*user))?;
+        let user = User::try_deserialize(&mut user_account.data.as_slice())?;
+
+        Ok(Self::new(user, accounts, slot))
+    }
+
+    /// Construct a bundle from its JSON representation (see `AccountsBundleJson`)
+    pub fn from_json(json: &str) -> SdkResult<Self> {
+        let bundle: AccountsBundleJson =
+            serde_json::from_str(json).map_err(|err| SdkError::Generic(err.to_string()))?;
+        let user = Pubkey::from_str(&bundle.user).map_err(|_| SdkError::InvalidBase58)?;
+
+        let mut pubkeys = Vec::with_capacity(bundle.accounts.len());
+        let mut accounts = Vec::with_capacity(bundle.accounts.len());
+        for (pubkey, ui_account) in bundle.accounts {
+            pubkeys.push(Pubkey::from_str(&pubkey).map_err(|_| SdkError::InvalidBase58)?);
+            accounts.push(Some(decode_ui_account(ui_account)?));
+        }
+
+        Self::from_multiple_accounts(&user, &pubkeys, accounts, bundle.slot)
+    }
+
+    /// Find the drift program account `T` satisfying `f`
+    fn find_program_account<T: AccountDeserialize + Discriminator>(
+        &self,
+        f: impl Fn(&T) -> bool,
+    ) -> Option<(Pubkey, T)> {
+        self.accounts.iter().find_map(|(pubkey, account)| {
+            if account.owner != constants::PROGRAM_ID || !account.data.starts_with(T::DISCRIMINATOR)
+            {
+                return None;
+            }
+            T::try_deserialize(&mut account.data.as_slice())
+                .ok()
+                .filter(|a| f(a))
+                .map(|a| (*pubkey, a))
+        })
+    }
+
+    /// Get the spot market account at `market_index`
+    pub fn spot_market(&self, market_index: u16) -> SdkResult<(Pubkey, SpotMarket)> {
+        self.find_program_account::<SpotMarket>(|m| m.market_index == market_index)
+            .ok_or(SdkError::NoMarketData(MarketId::spot(market_index)))
+    }
+
+    /// Get the perp market account at `market_index`
+    pub fn perp_market(&self, market_index: u16) -> SdkResult<(Pubkey, PerpMarket)> {
+        self.find_program_account::<PerpMarket>(|m| m.market_index == market_index)
+            .ok_or(SdkError::NoMarketData(MarketId::perp(market_index)))
+    }
+
+    /// Get the drift state account, if included in the bundle
+    pub fn state(&self) -> Option<State> {
+        self.accounts
+            .get(state_account())
+            .and_then(|a| State::try_deserialize(&mut a.data.as_slice()).ok())
+    }
+}
+
+/// Decode a base64 encoded `UiAccount`
+fn decode_ui_account(ui_account: UiAccount) -> SdkResult<Account> {
+    let data = match ui_account.data {
+        UiAccountData::Binary(data, UiAccountEncoding::Base64) => {
+            base64::engine::general_purpose::STANDARD.decode(data)?
+        }
+        _ => return Err(SdkError::UnsupportedAccountData),
+    };
+
+    Ok(Account {
+        lamports: ui_account.lamports,
+        data,
+        owner: Pubkey::from_str(&ui_account.owner).map_err(|_| SdkError::InvalidBase58)?,
+        executable: ui_account.executable,
+        rent_epoch: ui_account.rent_epoch,
+    })
+}
+
 /// Builds a list of users's associated spot, perp, and oracle accounts
 ///
 /// ```example(no_run)
@@ -235,4 +386,204 @@ impl AccountsListBuilder {
             latest_slot: latest_oracle_slot,
         })
     }
+
+    /// Constructs an accounts list from a static `bundle` of accounts
+    ///
+    /// * `bundle` - the user and its associated market/oracle accounts
+    /// * `force_markets` - additional market accounts that should be included in the account list
+    ///
+    /// like `try_build` but requires no client or network access.
+    /// `oracle_guard_rails` are set if the bundle includes the drift state account
+    pub fn build_offline(
+        &mut self,
+        bundle: &AccountsBundle,
+        force_markets: &[MarketId],
+    ) -> SdkResult<AccountsList<'_>> {
+        let mut oracles = HashMap::<Pubkey, OracleSource>::with_capacity(16);
+        let user = &bundle.user;
+
+        let force_spot_iter = force_markets
+            .iter()
+            .filter(|m| m.is_spot())
+            .map(|m| m.index());
+        let spot_market_idxs = ahash::HashSet::from_iter(
+            user.spot_positions
+                .iter()
+                .filter(|p| !p.is_available())
+                .map(|p| p.market_index)
+                .chain(force_spot_iter)
+                .chain(std::iter::once(MarketId::QUOTE_SPOT.index())),
+        );
+
+        for idx in spot_market_idxs {
+            let (pubkey, market) = bundle.spot_market(idx)?;
+            oracles.insert(market.oracle, market.oracle_source);
+            self.spot_accounts.push(
+                (
+                    pubkey,
+                    Account {
+                        data: zero_account_to_bytes(market),
+                        owner: constants::PROGRAM_ID,
+                        ..Default::default()
+                    },
+                )
+                    .into(),
+            );
+        }
+
+        let force_perp_iter = force_markets
+            .iter()
+            .filter(|m| m.is_perp())
+            .map(|m| m.index());
+        let perp_market_idxs = ahash::HashSet::from_iter(
+            user.perp_positions
+                .iter()
+                .filter(|p| !p.is_available())
+                .map(|p| p.market_index)
+                .chain(force_perp_iter),
+        );
+
+        for idx in perp_market_idxs {
+            let (pubkey, market) = bundle.perp_market(idx)?;
+            oracles.insert(market.amm.oracle, market.amm.oracle_source);
+            self.perp_accounts.push(
+                (
+                    pubkey,
+                    Account {
+                        data: zero_account_to_bytes(market),
+                        owner: constants::PROGRAM_ID,
+                        ..Default::default()
+                    },
+                )
+                    .into(),
+            );
+        }
+
+        for (oracle_key, source) in oracles.iter() {
+            match bundle.accounts.get(oracle_key) {
+                Some(account) => self
+                    .oracle_accounts
+                    .push((*oracle_key, account.clone()).into()),
+                // quote asset price is constant, no account required
+                None if *source == OracleSource::QuoteAsset => continue,
+                None => return Err(SdkError::NoAccountData(*oracle_key)),
+            }
+        }
+
+        Ok(AccountsList {
+            perp_markets: self.perp_accounts.as_mut_slice(),
+            spot_markets: self.spot_accounts.as_mut_slice(),
+            oracles: self.oracle_accounts.as_mut_slice(),
+            oracle_guard_rails: bundle.state().map(|s| s.oracle_guard_rails),
+            latest_slot: bundle.slot,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::PerpPosition;
+
+    fn program_account<T: bytemuck::Pod + Discriminator>(account: T) -> Account {
+        Account {
+            data: zero_account_to_bytes(account),
+            owner: constants::PROGRAM_ID,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn build_offline_from_bundle() {
+        let sol_oracle = Pubkey::new_unique();
+        let usdc = SpotMarket {
+            market_index: 0,
+            oracle_source: OracleSource::QuoteAsset,
+            ..Default::default()
+        };
+        let mut sol_perp = PerpMarket {
+            market_index: 0,
+            ..Default::default()
+        };
+        sol_perp.amm.oracle = sol_oracle;
+        sol_perp.amm.oracle_source = OracleSource::PythPull;
+
+        let mut user = User::default();
+        user.perp_positions[0] = PerpPosition {
+            market_index: 0,
+            base_asset_amount: 1_000_000_000,
+            ..Default::default()
+        };
+
+        let mut accounts = HashMap::<Pubkey, Account>::default();
+        accounts.insert(Pubkey::new_unique(), program_account(usdc));
+        accounts.insert(Pubkey::new_unique(), program_account(sol_perp));
+
+        // missing perp oracle
+        let bundle = AccountsBundle::new(user, accounts.clone(), 123);
+        let mut builder = AccountsListBuilder::default();
+        assert!(matches!(
+            builder.build_offline(&bundle, &[]),
+            Err(SdkError::NoAccountData(k)) if k == sol_oracle
+        ));
+
+        // missing forced market
+        let mut builder = AccountsListBuilder::default();
+        assert!(matches!(
+            builder.build_offline(&bundle, &[MarketId::spot(1)]),
+            Err(SdkError::NoMarketData(m)) if m == MarketId::spot(1)
+        ));
+
+        accounts.insert(sol_oracle, Account::default());
+        let bundle = AccountsBundle::new(user, accounts, 123);
+        let mut builder = AccountsListBuilder::default();
+        let accounts_list = builder.build_offline(&bundle, &[]).unwrap();
+        assert_eq!(accounts_list.spot_markets.len(), 1);
+        assert_eq!(accounts_list.perp_markets.len(), 1);
+        assert_eq!(accounts_list.oracles.len(), 1);
+        assert_eq!(accounts_list.oracles[0].key, sol_oracle);
+        assert_eq!(accounts_list.latest_slot, 123);
+        assert!(accounts_list.oracle_guard_rails.is_none());
+    }
+
+    #[test]
+    fn accounts_bundle_from_json() {
+        let user_key = Pubkey::new_unique();
+        let usdc_key = Pubkey::new_unique();
+        let usdc = SpotMarket {
+            market_index: 0,
+            oracle_source: OracleSource::QuoteAsset,
+            ..Default::default()
+        };
+        let encode = |data: Vec<u8>| base64::engine::general_purpose::STANDARD.encode(data);
+
+        let json = serde_json::json!({
+            "slot": 456,
+            "user": user_key.to_string(),
+            "accounts": {
+                user_key.to_string(): {
+                    "lamports": 1,
+                    "data": [encode(zero_account_to_bytes(User::default())), "base64"],
+                    "owner": constants::PROGRAM_ID.to_string(),
+                    "executable": false,
+                    "rentEpoch": 0,
+                },
+                usdc_key.to_string(): {
+                    "lamports": 1,
+                    "data": [encode(zero_account_to_bytes(usdc)), "base64"],
+                    "owner": constants::PROGRAM_ID.to_string(),
+                    "executable": false,
+                    "rentEpoch": 0,
+                },
+            }
+        });
+
+        let bundle = AccountsBundle::from_json(&json.to_string()).unwrap();
+        assert_eq!(bundle.slot, 456);
+        assert_eq!(bundle.user, User::default());
+        assert_eq!(bundle.accounts.len(), 2);
+        assert_eq!(bundle.spot_market(0).unwrap(), (usdc_key, usdc));
+        assert!(bundle.perp_market(0).is_err());
+        assert!(bundle.state().is_none());
+    }
 }
@@ -91,3 +91,18 @@ pub const AMM_TIMES_PEG_TO_QUOTE_PRECISION_RATIO: u128 =
 pub const AMM_TO_QUOTE_PRECISION_RATIO: u128 = AMM_RESERVE_PRECISION / QUOTE_PRECISION; // expo: 3
 
 pub const LIQUIDATION_FEE_ADJUST_GRACE_PERIOD_SLOTS: u64 = 1_500; // ~10 minutes
+
+// MARGIN
+pub const QUOTE_SPOT_MARKET_INDEX: u16 = 0;
+pub const OPEN_ORDER_MARGIN_REQUIREMENT: u128 = QUOTE_PRECISION / 100; // $0.01 per open order
+pub const MAX_PREDICTION_MARKET_PRICE: u64 = PRICE_PRECISION_U64; // expo = -6
+
+// TIME
+pub const ONE_HOUR: i64 = 3_600;
+pub const ONE_YEAR: u128 = 31_536_000;
+pub const HOURS_PER_YEAR: i64 = 24 * 365;
+
+// FUNDING
+pub const FUNDING_RATE_TO_QUOTE_PRECISION_RATIO: u128 = AMM_RESERVE_PRECISION * FUNDING_RATE_BUFFER; // expo 12
+pub const QUOTE_TO_BASE_AMT_FUNDING_PRECISION: i128 =
+    (AMM_RESERVE_PRECISION * FUNDING_RATE_PRECISION / QUOTE_PRECISION) as i128; // expo 12
@@ -0,0 +1,516 @@
+//!
+//! perp funding rate helpers
+//!
+//! mirrors the program's `UpdateFundingRate` instruction and position funding settlement
+//!
+
+use crate::{
+    math::constants::{
+        FUNDING_RATE_BUFFER, FUNDING_RATE_BUFFER_I128, FUNDING_RATE_TO_QUOTE_PRECISION_RATIO,
+        HOURS_PER_YEAR, ONE_HOUR, QUOTE_TO_BASE_AMT_FUNDING_PRECISION,
+    },
+    types::{
+        accounts::{PerpMarket, User},
+        ContractTier, PerpPosition,
+    },
+    SdkError, SdkResult,
+};
+
+/// Estimate of a perp market's next funding update
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FundingRateEstimate {
+    /// market funding rate, before any capping by the AMM fee pool
+    ///
+    /// FUNDING_RATE_PRECISION (PRICE_PRECISION * FUNDING_RATE_BUFFER)
+    pub funding_rate: i64,
+    /// funding rate applied to longs
+    pub funding_rate_long: i64,
+    /// funding rate applied to shorts
+    pub funding_rate_short: i64,
+    /// mark TWAP used for the estimate (PRICE_PRECISION)
+    pub mark_price_twap: u64,
+    /// oracle TWAP used for the estimate (PRICE_PRECISION)
+    pub oracle_price_twap: i64,
+    /// length of one funding period in seconds
+    pub funding_period: i64,
+}
+
+impl FundingRateEstimate {
+    /// Funding rate for longs as a fraction of the oracle TWAP, per funding period
+    /// e.g. 0.0001 = 0.01%
+    pub fn long_rate_pct(&self) -> f64 {
+        funding_rate_to_pct(self.funding_rate_long, self.oracle_price_twap)
+    }
+    /// Funding rate for shorts as a fraction of the oracle TWAP, per funding period
+    pub fn short_rate_pct(&self) -> f64 {
+        funding_rate_to_pct(self.funding_rate_short, self.oracle_price_twap)
+    }
+    /// Annualized funding rate for longs (fraction, e.g. 0.1 = 10% APR)
+    pub fn long_rate_annualized(&self) -> f64 {
+        annualize_funding_rate_pct(self.long_rate_pct(), self.funding_period)
+    }
+    /// Annualized funding rate for shorts (fraction, e.g. 0.1 = 10% APR)
+    pub fn short_rate_annualized(&self) -> f64 {
+        annualize_funding_rate_pct(self.short_rate_pct(), self.funding_period)
+    }
+}
+
+/// Projected funding payment for one of a user's perp positions
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FundingPaymentProjection {
+    pub market_index: u16,
+    /// BASE_PRECISION
+    pub base_asset_amount: i64,
+    /// funding accrued on-chain but not yet settled into the position (QUOTE_PRECISION)
+    ///
+    /// positive = user receives
+    pub pending_payment: i64,
+    /// estimated payment at the market's next funding update (QUOTE_PRECISION)
+    ///
+    /// positive = user receives
+    pub next_payment: i64,
+}
+
+/// Return the max. allowed mark/oracle TWAP divergence for funding by `contract_tier`
+///
+/// 3% for tier A/B, 5% for tier C and 10% otherwise
+pub fn max_price_divergence_for_funding_rate(
+    contract_tier: ContractTier,
+    oracle_price_twap: i64,
+) -> i64 {
+    match contract_tier {
+        ContractTier::A | ContractTier::B => oracle_price_twap / 33,
+        ContractTier::C => oracle_price_twap / 20,
+        _ => oracle_price_twap / 10,
+    }
+}
+
+/// Calculate the funding rate for one period from mark and oracle TWAPs
+///
+/// * `mark_price_twap` - mark price TWAP (PRICE_PRECISION)
+/// * `oracle_price_twap` - oracle price TWAP (PRICE_PRECISION)
+/// * `funding_period` - funding period in seconds (e.g. `market.amm.funding_period`)
+/// * `contract_tier` - the market's contract tier, determines the divergence clamp
+///
+/// Returns funding rate in FUNDING_RATE_PRECISION, positive = longs pay shorts
+pub fn calculate_funding_rate(
+    mark_price_twap: u64,
+    oracle_price_twap: i64,
+    funding_period: i64,
+    contract_tier: ContractTier,
+) -> i64 {
+    // funding period = 1 hour, window = 1 day
+    // low periodicity => quickly updating/settled funding rates
+    //                 => lower funding rate payment per interval
+    let period_adjustment = (24 * ONE_HOUR) / funding_period.max(ONE_HOUR);
+
+    let price_spread = mark_price_twap as i64 - oracle_price_twap;
+    let max_price_spread = max_price_divergence_for_funding_rate(contract_tier, oracle_price_twap);
+    let clamped_price_spread = price_spread.clamp(-max_price_spread, max_price_spread);
+
+    ((clamped_price_spread as i128 * FUNDING_RATE_BUFFER_I128) / period_adjustment as i128) as i64
+}
+
+/// Calculate the funding rates applied to longs and shorts
+///
+/// When the AMM's net position would pay funding, the receiving side is capped to what the
+/// paying side plus the AMM fee pool budget can cover
+///
+/// Returns (funding_rate_long, funding_rate_short)
+pub fn calculate_funding_rate_long_short(
+    market: &PerpMarket,
+    funding_rate: i64,
+) -> SdkResult<(i64, i64)> {
+    let funding_rate = funding_rate as i128;
+    let net_market_position = market.amm.base_asset_amount_with_amm.as_i128()
+        + market.amm.base_asset_amount_with_unsettled_lp.as_i128();
+
+    // the amm takes the other side of the users' net position
+    let uncapped_funding_pnl =
+        -calculate_funding_payment_in_quote_precision(funding_rate, net_market_position)?;
+
+    // the protocol receives funding, nothing to cap
+    if uncapped_funding_pnl >= 0 {
+        return Ok((funding_rate as i64, funding_rate as i64));
+    }
+
+    let funding_rate_pool = funding_rate_pool(market);
+    if uncapped_funding_pnl >= -funding_rate_pool {
+        return Ok((funding_rate as i64, funding_rate as i64));
+    }
+
+    let capped_funding_rate = if funding_rate < 0 {
+        // longs receive, paid by shorts + the amm budget
+        let paid_by_shorts = calculate_funding_payment_in_quote_precision(
+            funding_rate,
+            market.amm.base_asset_amount_short.as_i128(),
+        )?
+        .abs();
+        let calc = calculate_funding_rate_from_pnl_limit(
+            -(paid_by_shorts + funding_rate_pool),
+            market.amm.base_asset_amount_long.as_i128(),
+        )?;
+        calc.max(funding_rate)
+    } else {
+        // shorts receive, paid by longs + the amm budget
+        let paid_by_longs = calculate_funding_payment_in_quote_precision(
+            funding_rate,
+            market.amm.base_asset_amount_long.as_i128(),
+        )?
+        .abs();
+        let calc = calculate_funding_rate_from_pnl_limit(
+            -(paid_by_longs + funding_rate_pool),
+            market.amm.base_asset_amount_short.as_i128(),
+        )?;
+        calc.min(funding_rate)
+    };
+
+    let (funding_rate_long, funding_rate_short) = if funding_rate < 0 {
+        (capped_funding_rate, funding_rate)
+    } else {
+        (funding_rate, capped_funding_rate)
+    };
+
+    Ok((funding_rate_long as i64, funding_rate_short as i64))
+}
+
+/// Estimate the next funding rate of `market` from its current mark and oracle TWAPs
+pub fn estimate_next_funding_rate(market: &PerpMarket) -> SdkResult<FundingRateEstimate> {
+    estimate_funding_rate(
+        market,
+        market.amm.last_mark_price_twap,
+        market.amm.historical_oracle_data.last_oracle_price_twap,
+    )
+}
+
+/// Estimate the funding rate of `market` given explicit mark and oracle TWAPs
+///
+/// useful when the caller maintains fresher TWAPs than the last on-chain update
+pub fn estimate_funding_rate(
+    market: &PerpMarket,
+    mark_price_twap: u64,
+    oracle_price_twap: i64,
+) -> SdkResult<FundingRateEstimate> {
+    if oracle_price_twap <= 0 {
+        return Err(SdkError::MathError("invalid oracle twap"));
+    }
+    let funding_period = market.amm.funding_period.max(ONE_HOUR);
+    let funding_rate = calculate_funding_rate(
+        mark_price_twap,
+        oracle_price_twap,
+        funding_period,
+        market.contract_tier,
+    );
+    let (funding_rate_long, funding_rate_short) =
+        calculate_funding_rate_long_short(market, funding_rate)?;
+
+    Ok(FundingRateEstimate {
+        funding_rate,
+        funding_rate_long,
+        funding_rate_short,
+        mark_price_twap,
+        oracle_price_twap,
+        funding_period,
+    })
+}
+
+/// Convert a raw funding rate into a fraction of `oracle_price_twap` per funding period
+pub fn funding_rate_to_pct(funding_rate: i64, oracle_price_twap: i64) -> f64 {
+    if oracle_price_twap == 0 {
+        return 0.0;
+    }
+    funding_rate as f64 / (oracle_price_twap as f64 * FUNDING_RATE_BUFFER as f64)
+}
+
+/// Annualize a per-period funding rate fraction
+pub fn annualize_funding_rate_pct(rate_pct: f64, funding_period: i64) -> f64 {
+    let periods_per_hour = ONE_HOUR as f64 / funding_period.max(1) as f64;
+    rate_pct * periods_per_hour * HOURS_PER_YEAR as f64
+}
+
+/// Calculate the funding owed to/by `position` since its last settlement
+///
+/// * `amm_cumulative_funding_rate` - the market's cumulative funding rate for the position side
+///
+/// Returns funding payment in QUOTE_PRECISION, positive = user receives
+pub fn calculate_funding_payment(
+    amm_cumulative_funding_rate: i128,
+    position: &PerpPosition,
+) -> SdkResult<i64> {
+    let funding_rate_delta =
+        amm_cumulative_funding_rate - position.last_cumulative_funding_rate as i128;
+    if funding_rate_delta == 0 {
+        return Ok(0);
+    }
+
+    let payment = calculate_funding_payment_in_quote_precision(
+        funding_rate_delta,
+        position.base_asset_amount as i128,
+    )?;
+
+    i64::try_from(payment).map_err(|_| SdkError::MathError("funding payment overflow"))
+}
+
+/// Calculate the funding accrued on-chain but not yet settled into `position`
+///
+/// Returns funding payment in QUOTE_PRECISION, positive = user receives
+pub fn calculate_pending_funding_payment(
+    market: &PerpMarket,
+    position: &PerpPosition,
+) -> SdkResult<i64> {
+    if position.base_asset_amount == 0 {
+        return Ok(0);
+    }
+    let amm_cumulative_funding_rate = if position.base_asset_amount > 0 {
+        market.amm.cumulative_funding_rate_long.as_i128()
+    } else {
+        market.amm.cumulative_funding_rate_short.as_i128()
+    };
+
+    calculate_funding_payment(amm_cumulative_funding_rate, position)
+}
+
+/// Project the pending and next funding payment for `position` given a funding `estimate`
+pub fn project_funding_payment(
+    market: &PerpMarket,
+    position: &PerpPosition,
+    estimate: &FundingRateEstimate,
+) -> SdkResult<FundingPaymentProjection> {
+    let pending_payment = calculate_pending_funding_payment(market, position)?;
+
+    let next_funding_rate = if position.base_asset_amount > 0 {
+        estimate.funding_rate_long
+    } else {
+        estimate.funding_rate_short
+    };
+    let next_payment = calculate_funding_payment_in_quote_precision(
+        next_funding_rate as i128,
+        position.base_asset_amount as i128,
+    )?;
+
+    Ok(FundingPaymentProjection {
+        market_index: position.market_index,
+        base_asset_amount: position.base_asset_amount,
+        pending_payment,
+        next_payment: i64::try_from(next_payment)
+            .map_err(|_| SdkError::MathError("funding payment overflow"))?,
+    })
+}
+
+/// Project funding payments for all of `user`'s open perp positions
+///
+/// * `perp_markets` - market accounts for (at least) the user's open positions
+///
+/// Returns `SdkError::NoMarketData` if a position's market is missing
+pub fn project_user_funding_payments(
+    user: &User,
+    perp_markets: &[PerpMarket],
+) -> SdkResult<Vec<FundingPaymentProjection>> {
+    user.perp_positions
+        .iter()
+        .filter(|p| p.base_asset_amount != 0)
+        .map(|position| {
+            let market = perp_markets
+                .iter()
+                .find(|m| m.market_index == position.market_index)
+                .ok_or(SdkError::NoMarketData(crate::MarketId::perp(
+                    position.market_index,
+                )))?;
+            let estimate = estimate_next_funding_rate(market)?;
+            project_funding_payment(market, position, &estimate)
+        })
+        .collect()
+}
+
+/// funding budget the AMM is willing to pay out this period (QUOTE_PRECISION)
+///
+/// limited to 2/3 of fees above the fee pool lower bound
+fn funding_rate_pool(market: &PerpMarket) -> i128 {
+    let total_fee_minus_distributions = market.amm.total_fee_minus_distributions.as_i128();
+    let total_fee_lower_bound = (market.amm.total_exchange_fee.as_u128() / 2) as i128;
+
+    if total_fee_minus_distributions > total_fee_lower_bound {
+        (total_fee_minus_distributions - total_fee_lower_bound) * 2 / 3
+    } else {
+        0
+    }
+}
+
+/// Funding rate that pays out exactly `pnl_limit` over `base_asset_amount`
+fn calculate_funding_rate_from_pnl_limit(
+    pnl_limit: i128,
+    base_asset_amount: i128,
+) -> SdkResult<i128> {
+    if base_asset_amount == 0 {
+        return Ok(0);
+    }
+    let pnl_limit_biased = if pnl_limit < 0 {
+        pnl_limit + 1
+    } else {
+        pnl_limit
+    };
+
+    pnl_limit_biased
+        .checked_mul(QUOTE_TO_BASE_AMT_FUNDING_PRECISION)
+        .map(|x| x / base_asset_amount)
+        .ok_or(SdkError::MathError("funding rate overflow"))
+}
+
+/// Funding payment for `base_asset_amount` at `funding_rate` (QUOTE_PRECISION)
+///
+/// positive rate = longs pay shorts
+fn calculate_funding_payment_in_quote_precision(
+    funding_rate: i128,
+    base_asset_amount: i128,
+) -> SdkResult<i128> {
+    let magnitude = funding_rate
+        .unsigned_abs()
+        .checked_mul(base_asset_amount.unsigned_abs())
+        .ok_or(SdkError::MathError("funding payment overflow"))?
+        / FUNDING_RATE_TO_QUOTE_PRECISION_RATIO;
+
+    let funding_rate_sign: i128 = if funding_rate > 0 { 1 } else { -1 };
+    let position_sign: i128 = if base_asset_amount > 0 { -1 } else { 1 };
+
+    Ok(magnitude as i128 * funding_rate_sign * position_sign)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        drift_idl::types::{HistoricalOracleData, AMM},
+        math::constants::{BASE_PRECISION_I64, PRICE_PRECISION_I64, PRICE_PRECISION_U64},
+    };
+
+    fn sol_perp_market(mark_twap: u64, oracle_twap: i64) -> PerpMarket {
+        PerpMarket {
+            amm: AMM {
+                last_mark_price_twap: mark_twap,
+                historical_oracle_data: HistoricalOracleData {
+                    last_oracle_price_twap: oracle_twap,
+                    ..Default::default()
+                },
+                funding_period: ONE_HOUR,
+                ..AMM::default()
+            },
+            contract_tier: ContractTier::A,
+            ..PerpMarket::default()
+        }
+    }
+
+    #[test]
+    fn funding_rate_basic() {
+        // mark 1% above oracle => 1%/24 per hourly period
+        let rate = calculate_funding_rate(
+            101 * PRICE_PRECISION_U64,
+            100 * PRICE_PRECISION_I64,
+            ONE_HOUR,
+            ContractTier::A,
+        );
+        assert_eq!(rate, 41_666_666);
+        let pct = funding_rate_to_pct(rate, 100 * PRICE_PRECISION_I64);
+        assert!((pct - 0.01 / 24.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn funding_rate_clamped_by_contract_tier() {
+        let oracle_twap = 100 * PRICE_PRECISION_I64;
+        let mark_twap = 150 * PRICE_PRECISION_U64;
+
+        let rate_a = calculate_funding_rate(mark_twap, oracle_twap, ONE_HOUR, ContractTier::A);
+        let rate_c = calculate_funding_rate(mark_twap, oracle_twap, ONE_HOUR, ContractTier::C);
+        let rate_spec =
+            calculate_funding_rate(mark_twap, oracle_twap, ONE_HOUR, ContractTier::Speculative);
+
+        assert_eq!(rate_a, (oracle_twap / 33) * 1_000 / 24);
+        assert_eq!(rate_c, (oracle_twap / 20) * 1_000 / 24);
+        assert_eq!(rate_spec, (oracle_twap / 10) * 1_000 / 24);
+
+        // symmetric for negative spreads
+        let rate_neg = calculate_funding_rate(
+            50 * PRICE_PRECISION_U64,
+            oracle_twap,
+            ONE_HOUR,
+            ContractTier::A,
+        );
+        assert_eq!(rate_neg, -rate_a);
+    }
+
+    #[test]
+    fn funding_rate_uncapped_when_amm_receives() {
+        let mut market = sol_perp_market(101 * PRICE_PRECISION_U64, 100 * PRICE_PRECISION_I64);
+        // users net long, longs pay => amm receives
+        market.amm.base_asset_amount_with_amm = (10 * BASE_PRECISION_I64 as i128).into();
+        let estimate = estimate_next_funding_rate(&market).unwrap();
+        assert_eq!(estimate.funding_rate_long, estimate.funding_rate);
+        assert_eq!(estimate.funding_rate_short, estimate.funding_rate);
+        assert!(estimate.long_rate_annualized() > 0.0);
+    }
+
+    #[test]
+    fn funding_rate_capped_when_amm_pays() {
+        let mut market = sol_perp_market(101 * PRICE_PRECISION_U64, 100 * PRICE_PRECISION_I64);
+        // users net short, longs pay shorts => amm (long) pays the net imbalance
+        market.amm.base_asset_amount_with_amm = (-10 * BASE_PRECISION_I64 as i128).into();
+        market.amm.base_asset_amount_long = (5 * BASE_PRECISION_I64 as i128).into();
+        market.amm.base_asset_amount_short = (-15 * BASE_PRECISION_I64 as i128).into();
+        // no fee pool budget
+        let estimate = estimate_next_funding_rate(&market).unwrap();
+        assert_eq!(estimate.funding_rate_long, estimate.funding_rate);
+        // shorts only receive what longs pay: 5/15 of the full rate
+        assert!(estimate.funding_rate_short < estimate.funding_rate);
+        assert!((estimate.funding_rate_short - estimate.funding_rate / 3).abs() < 1_000);
+    }
+
+    #[test]
+    fn funding_payment_projection() {
+        let market = PerpMarket {
+            amm: AMM {
+                cumulative_funding_rate_long: (2_000_000_000_i128).into(),
+                cumulative_funding_rate_short: (2_000_000_000_i128).into(),
+                ..sol_perp_market(101 * PRICE_PRECISION_U64, 100 * PRICE_PRECISION_I64).amm
+            },
+            ..sol_perp_market(101 * PRICE_PRECISION_U64, 100 * PRICE_PRECISION_I64)
+        };
+        let long = PerpPosition {
+            base_asset_amount: BASE_PRECISION_I64,
+            last_cumulative_funding_rate: 1_000_000_000,
+            ..Default::default()
+        };
+        let short = PerpPosition {
+            base_asset_amount: -BASE_PRECISION_I64,
+            last_cumulative_funding_rate: 1_000_000_000,
+            ..Default::default()
+        };
+
+        // 1e9 rate delta on 1 base => $1
+        assert_eq!(
+            calculate_pending_funding_payment(&market, &long).unwrap(),
+            -1_000_000
+        );
+        assert_eq!(
+            calculate_pending_funding_payment(&market, &short).unwrap(),
+            1_000_000
+        );
+
+        let estimate = estimate_next_funding_rate(&market).unwrap();
+        let projection = project_funding_payment(&market, &long, &estimate).unwrap();
+        assert_eq!(projection.pending_payment, -1_000_000);
+        // 1% / 24 of $100 notional
+        assert_eq!(projection.next_payment, -41_666);
+
+        let mut user = User::default();
+        user.perp_positions[0] = long;
+        user.perp_positions[1] = PerpPosition {
+            market_index: 1,
+            ..short
+        };
+        let mut market_1 = market;
+        market_1.market_index = 1;
+        let projections = project_user_funding_payments(&user, &[market, market_1]).unwrap();
+        assert_eq!(projections.len(), 2);
+        assert_eq!(projections[1].pending_payment, 1_000_000);
+        assert!(project_user_funding_payments(&user, &[market]).is_err());
+    }
+}
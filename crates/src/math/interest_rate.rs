@@ -0,0 +1,498 @@
+//!
+//! spot market borrow/lend interest rate model
+//!
+//! mirrors the program's `UpdateSpotMarketCumulativeInterest` instruction
+//!
+
+use crate::{
+    math::constants::{
+        ONE_YEAR, PERCENTAGE_PRECISION, SPOT_RATE_PRECISION, SPOT_UTILIZATION_PRECISION,
+    },
+    types::{
+        accounts::{SpotMarket, User},
+        SpotBalanceType,
+    },
+    MarketId, SdkError, SdkResult,
+};
+
+/// Interest accrued per unit of cumulative interest since the market's last update
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct InterestAccumulated {
+    /// SPOT_CUMULATIVE_INTEREST_PRECISION
+    pub deposit_interest: u128,
+    /// SPOT_CUMULATIVE_INTEREST_PRECISION
+    pub borrow_interest: u128,
+}
+
+/// Snapshot of a spot market's interest rate curve
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SpotMarketRates {
+    /// SPOT_UTILIZATION_PRECISION
+    pub utilization: u128,
+    /// annual deposit rate (SPOT_RATE_PRECISION)
+    pub deposit_rate: u128,
+    /// annual borrow rate (SPOT_RATE_PRECISION)
+    pub borrow_rate: u128,
+}
+
+impl SpotMarketRates {
+    /// Deposit APR as a fraction e.g. 0.05 = 5%
+    pub fn deposit_apr(&self) -> f64 {
+        self.deposit_rate as f64 / SPOT_RATE_PRECISION as f64
+    }
+    /// Borrow APR as a fraction e.g. 0.05 = 5%
+    pub fn borrow_apr(&self) -> f64 {
+        self.borrow_rate as f64 / SPOT_RATE_PRECISION as f64
+    }
+    /// Utilization as a fraction e.g. 0.8 = 80%
+    pub fn utilization_pct(&self) -> f64 {
+        self.utilization as f64 / SPOT_UTILIZATION_PRECISION as f64
+    }
+}
+
+/// Projected interest for one of a user's spot positions
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpotInterestProjection {
+    pub market_index: u16,
+    pub balance_type: SpotBalanceType,
+    /// current token amount (in market decimals)
+    pub token_amount: u128,
+    /// token amount at the end of the projection horizon (in market decimals)
+    pub projected_token_amount: u128,
+    /// interest earned (deposit) or owed (borrow) over the horizon (in market decimals)
+    pub interest: u128,
+    /// annual rate applicable to the position (SPOT_RATE_PRECISION)
+    pub rate: u128,
+}
+
+/// Convert a market-level scaled spot balance into a token amount (in market decimals)
+///
+/// borrows are rounded up to match the program. Market balances can exceed the u64
+/// `SpotPosition::scaled_balance`, prefer `SpotPosition::get_token_amount` for positions
+pub(crate) fn get_token_amount(
+    balance: u128,
+    spot_market: &SpotMarket,
+    balance_type: SpotBalanceType,
+) -> SdkResult<u128> {
+    let precision_decrease = 10_u128.pow(19_u32.saturating_sub(spot_market.decimals));
+
+    let token_amount = match balance_type {
+        SpotBalanceType::Deposit => balance
+            .checked_mul(spot_market.cumulative_deposit_interest.as_u128())
+            .map(|x| x / precision_decrease),
+        SpotBalanceType::Borrow => balance
+            .checked_mul(spot_market.cumulative_borrow_interest.as_u128())
+            .map(|x| x.div_ceil(precision_decrease)),
+    };
+
+    token_amount.ok_or(SdkError::MathError("token amount overflow"))
+}
+
+/// Calculate utilization given total deposit and borrow token amounts
+///
+/// Returns utilization in SPOT_UTILIZATION_PRECISION
+pub fn calculate_utilization(deposit_token_amount: u128, borrow_token_amount: u128) -> u128 {
+    match borrow_token_amount
+        .saturating_mul(SPOT_UTILIZATION_PRECISION)
+        .checked_div(deposit_token_amount)
+    {
+        Some(utilization) => utilization,
+        None if borrow_token_amount == 0 => 0,
+        // borrows without deposits defaults to max. utilization
+        None => SPOT_UTILIZATION_PRECISION,
+    }
+}
+
+/// Calculate the current utilization of `spot_market`
+///
+/// Returns utilization in SPOT_UTILIZATION_PRECISION
+pub fn calculate_spot_market_utilization(spot_market: &SpotMarket) -> SdkResult<u128> {
+    let deposit_token_amount = get_token_amount(
+        spot_market.deposit_balance.as_u128(),
+        spot_market,
+        SpotBalanceType::Deposit,
+    )?;
+    let borrow_token_amount = get_token_amount(
+        spot_market.borrow_balance.as_u128(),
+        spot_market,
+        SpotBalanceType::Borrow,
+    )?;
+
+    Ok(calculate_utilization(
+        deposit_token_amount,
+        borrow_token_amount,
+    ))
+}
+
+/// Utilization breakpoints above optimal and their share of the optimal to max rate increase
+///
+/// (SPOT_UTILIZATION_PRECISION, parts of `BORROW_RATE_SEGMENT_WEIGHTS_DIVISOR`)
+const BORROW_RATE_SEGMENTS: [(u128, u128); 6] = [
+    (850_000, 50),
+    (900_000, 100),
+    (950_000, 150),
+    (990_000, 200),
+    (995_000, 250),
+    (1_000_000, 250),
+];
+const BORROW_RATE_SEGMENT_WEIGHTS_DIVISOR: u128 = 1_000;
+
+/// Calculate the annual borrow rate at `utilization` from the market's rate curve
+///
+/// linear up to optimal utilization, then piecewise linear through `BORROW_RATE_SEGMENTS` up to the max rate
+///
+/// Returns borrow rate in SPOT_RATE_PRECISION
+pub fn calculate_borrow_rate(spot_market: &SpotMarket, utilization: u128) -> u128 {
+    let optimal_utilization = spot_market.optimal_utilization as u128;
+    let optimal_borrow_rate = spot_market.optimal_borrow_rate as u128;
+    let max_borrow_rate = spot_market.max_borrow_rate as u128;
+
+    let borrow_rate = if utilization > optimal_utilization {
+        let total_extra_rate = max_borrow_rate.saturating_sub(optimal_borrow_rate);
+        let mut borrow_rate = optimal_borrow_rate;
+        let mut segment_start = optimal_utilization;
+        for (breakpoint, weight) in BORROW_RATE_SEGMENTS {
+            if utilization <= segment_start {
+                break;
+            }
+            let segment_end = breakpoint.min(SPOT_UTILIZATION_PRECISION);
+            if segment_end <= segment_start {
+                continue;
+            }
+            let segment_rate = total_extra_rate * weight / BORROW_RATE_SEGMENT_WEIGHTS_DIVISOR;
+            let segment_utilization = utilization.min(segment_end) - segment_start;
+            borrow_rate += segment_rate * segment_utilization / (segment_end - segment_start);
+            segment_start = segment_end;
+        }
+
+        borrow_rate.min(max_borrow_rate)
+    } else {
+        let borrow_rate_slope = (optimal_borrow_rate * SPOT_UTILIZATION_PRECISION)
+            .checked_div(optimal_utilization)
+            .unwrap_or(0);
+
+        (utilization * borrow_rate_slope) / SPOT_UTILIZATION_PRECISION
+    };
+
+    borrow_rate.max(min_borrow_rate(spot_market))
+}
+
+/// Calculate the annual deposit rate given `utilization` and the market's `borrow_rate`
+///
+/// depositors receive borrow interest pro-rata, less the insurance fund's share
+///
+/// Returns deposit rate in SPOT_RATE_PRECISION
+pub fn calculate_deposit_rate(
+    spot_market: &SpotMarket,
+    utilization: u128,
+    borrow_rate: u128,
+) -> u128 {
+    let if_factor = (spot_market.insurance_fund.total_factor as u128).min(PERCENTAGE_PRECISION);
+
+    borrow_rate * (PERCENTAGE_PRECISION - if_factor) * utilization
+        / SPOT_UTILIZATION_PRECISION
+        / PERCENTAGE_PRECISION
+}
+
+/// Calculate the current utilization, deposit and borrow rates of `spot_market`
+pub fn calculate_spot_market_rates(spot_market: &SpotMarket) -> SdkResult<SpotMarketRates> {
+    let utilization = calculate_spot_market_utilization(spot_market)?;
+    let borrow_rate = calculate_borrow_rate(spot_market, utilization);
+    let deposit_rate = calculate_deposit_rate(spot_market, utilization, borrow_rate);
+
+    Ok(SpotMarketRates {
+        utilization,
+        deposit_rate,
+        borrow_rate,
+    })
+}
+
+/// Calculate the interest accumulated by `spot_market` between its last update and `now`
+///
+/// * `now` - unix timestamp (seconds)
+pub fn calculate_accumulated_interest(
+    spot_market: &SpotMarket,
+    now: i64,
+) -> SdkResult<InterestAccumulated> {
+    let time_since_last_update = (now.max(0) as u64).saturating_sub(spot_market.last_interest_ts);
+    if time_since_last_update == 0 {
+        return Ok(InterestAccumulated::default());
+    }
+
+    let SpotMarketRates {
+        utilization,
+        borrow_rate,
+        deposit_rate,
+    } = calculate_spot_market_rates(spot_market)?;
+    // no interest accrues without borrows
+    if utilization == 0 {
+        return Ok(InterestAccumulated::default());
+    }
+
+    let borrow_rate_time_adjusted = borrow_rate * time_since_last_update as u128;
+    let deposit_rate_time_adjusted = deposit_rate * time_since_last_update as u128;
+
+    let borrow_interest = spot_market
+        .cumulative_borrow_interest
+        .as_u128()
+        .checked_mul(borrow_rate_time_adjusted)
+        .ok_or(SdkError::MathError("borrow interest overflow"))?
+        / ONE_YEAR
+        / SPOT_RATE_PRECISION
+        + 1;
+
+    let deposit_interest = spot_market
+        .cumulative_deposit_interest
+        .as_u128()
+        .checked_mul(deposit_rate_time_adjusted)
+        .ok_or(SdkError::MathError("deposit interest overflow"))?
+        / ONE_YEAR
+        / SPOT_RATE_PRECISION;
+
+    Ok(InterestAccumulated {
+        deposit_interest,
+        borrow_interest,
+    })
+}
+
+/// Return a copy of `spot_market` with cumulative interest updated to `now`
+///
+/// rates are held constant over the period i.e. this matches a single on-chain update.
+/// Like the program, `last_interest_ts` only advances when interest accrues
+pub fn update_cumulative_interest(spot_market: &SpotMarket, now: i64) -> SdkResult<SpotMarket> {
+    let interest = calculate_accumulated_interest(spot_market, now)?;
+    let mut spot_market = *spot_market;
+
+    if interest.deposit_interest > 0 && interest.borrow_interest > 1 {
+        spot_market.cumulative_deposit_interest =
+            (spot_market.cumulative_deposit_interest.as_u128() + interest.deposit_interest).into();
+        spot_market.cumulative_borrow_interest =
+            (spot_market.cumulative_borrow_interest.as_u128() + interest.borrow_interest).into();
+        spot_market.last_interest_ts = now as u64;
+    }
+
+    Ok(spot_market)
+}
+
+/// Project interest for all of `user`'s spot positions from `now` over `horizon_secs`
+///
+/// * `spot_markets` - market accounts for (at least) the user's open positions
+///
+/// Returns `SdkError::NoMarketData` if a position's market is missing
+pub fn project_user_spot_interest(
+    user: &User,
+    spot_markets: &[SpotMarket],
+    now: i64,
+    horizon_secs: i64,
+) -> SdkResult<Vec<SpotInterestProjection>> {
+    user.spot_positions
+        .iter()
+        .filter(|p| p.scaled_balance != 0)
+        .map(|position| {
+            let market = spot_markets
+                .iter()
+                .find(|m| m.market_index == position.market_index)
+                .ok_or(SdkError::NoMarketData(MarketId::spot(
+                    position.market_index,
+                )))?;
+
+            // bring market up to date before projecting forward
+            let current = update_cumulative_interest(market, now)?;
+            let projected = update_cumulative_interest(&current, now + horizon_secs.max(0))?;
+
+            let token_amount = position.get_token_amount(&current)?;
+            let projected_token_amount = position.get_token_amount(&projected)?;
+
+            let rates = calculate_spot_market_rates(&current)?;
+            let rate = match position.balance_type {
+                SpotBalanceType::Deposit => rates.deposit_rate,
+                SpotBalanceType::Borrow => rates.borrow_rate,
+            };
+
+            Ok(SpotInterestProjection {
+                market_index: position.market_index,
+                balance_type: position.balance_type,
+                token_amount,
+                projected_token_amount,
+                interest: projected_token_amount.saturating_sub(token_amount),
+                rate,
+            })
+        })
+        .collect()
+}
+
+/// min. borrow rate configured in 0.5% increments (SPOT_RATE_PRECISION)
+fn min_borrow_rate(spot_market: &SpotMarket) -> u128 {
+    spot_market.min_borrow_rate as u128 * (PERCENTAGE_PRECISION / 200)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        drift_idl::types::{InsuranceFund, SpotPosition},
+        math::constants::{
+            SPOT_BALANCE_PRECISION, SPOT_CUMULATIVE_INTEREST_PRECISION, SPOT_RATE_PRECISION_U32,
+        },
+    };
+
+    fn usdc_spot_market(deposits: u128, borrows: u128) -> SpotMarket {
+        SpotMarket {
+            market_index: 0,
+            decimals: 6,
+            cumulative_deposit_interest: SPOT_CUMULATIVE_INTEREST_PRECISION.into(),
+            cumulative_borrow_interest: SPOT_CUMULATIVE_INTEREST_PRECISION.into(),
+            deposit_balance: (deposits * SPOT_BALANCE_PRECISION).into(),
+            borrow_balance: (borrows * SPOT_BALANCE_PRECISION).into(),
+            // 80% optimal utilization @ 10% APR, 100% max
+            optimal_utilization: 800_000,
+            optimal_borrow_rate: SPOT_RATE_PRECISION_U32 / 10,
+            max_borrow_rate: SPOT_RATE_PRECISION_U32,
+            insurance_fund: InsuranceFund {
+                total_factor: 100_000, // 10%
+                ..Default::default()
+            },
+            ..SpotMarket::default()
+        }
+    }
+
+    #[test]
+    fn utilization() {
+        assert_eq!(calculate_utilization(0, 0), 0);
+        assert_eq!(calculate_utilization(0, 1), SPOT_UTILIZATION_PRECISION);
+        assert_eq!(calculate_utilization(100, 50), 500_000);
+
+        let market = usdc_spot_market(1_000, 400);
+        assert_eq!(calculate_spot_market_utilization(&market).unwrap(), 400_000);
+    }
+
+    #[test]
+    fn borrow_rate_curve() {
+        let market = usdc_spot_market(1_000, 0);
+        assert_eq!(calculate_borrow_rate(&market, 0), 0);
+        // below the kink, linear up to optimal rate
+        assert_eq!(calculate_borrow_rate(&market, 400_000), 50_000);
+        assert_eq!(calculate_borrow_rate(&market, 800_000), 100_000);
+        // above the kink, segmented from optimal to max
+        assert_eq!(calculate_borrow_rate(&market, 825_000), 122_500);
+        assert_eq!(calculate_borrow_rate(&market, 850_000), 145_000);
+        assert_eq!(calculate_borrow_rate(&market, 900_000), 235_000);
+        assert_eq!(calculate_borrow_rate(&market, 990_000), 550_000);
+        assert_eq!(
+            calculate_borrow_rate(&market, SPOT_UTILIZATION_PRECISION),
+            1_000_000
+        );
+
+        // min borrow rate in 0.5% increments
+        let market = SpotMarket {
+            min_borrow_rate: 2,
+            ..market
+        };
+        assert_eq!(calculate_borrow_rate(&market, 0), 10_000);
+    }
+
+    #[test]
+    fn spot_market_rates() {
+        let market = usdc_spot_market(1_000, 400);
+        let rates = calculate_spot_market_rates(&market).unwrap();
+        assert_eq!(rates.utilization, 400_000);
+        assert_eq!(rates.borrow_rate, 50_000);
+        // 5% * 40% * 90%
+        assert_eq!(rates.deposit_rate, 18_000);
+        assert!((rates.deposit_apr() - 0.018).abs() < 1e-9);
+        assert!((rates.borrow_apr() - 0.05).abs() < 1e-9);
+    }
+
+    #[test]
+    fn accumulated_interest_and_projection() {
+        let market = usdc_spot_market(1_000, 400);
+
+        let interest = calculate_accumulated_interest(&market, ONE_YEAR as i64).unwrap();
+        // 5% borrow, 1.8% deposit over 1yr (+1 rounding on borrows)
+        assert_eq!(
+            interest.borrow_interest,
+            SPOT_CUMULATIVE_INTEREST_PRECISION / 20 + 1
+        );
+        assert_eq!(
+            interest.deposit_interest,
+            SPOT_CUMULATIVE_INTEREST_PRECISION * 18 / 1_000
+        );
+
+        // no borrows, no interest (even with a min borrow rate)
+        let idle_market = SpotMarket {
+            min_borrow_rate: 2,
+            ..usdc_spot_market(1_000, 0)
+        };
+        let interest = calculate_accumulated_interest(&idle_market, ONE_YEAR as i64).unwrap();
+        assert_eq!(interest.borrow_interest, 0);
+        assert_eq!(interest.deposit_interest, 0);
+
+        let mut user = User::default();
+        user.spot_positions[0] = SpotPosition {
+            market_index: 0,
+            scaled_balance: 100 * SPOT_BALANCE_PRECISION as u64,
+            balance_type: SpotBalanceType::Deposit,
+            ..Default::default()
+        };
+        user.spot_positions[1] = SpotPosition {
+            market_index: 0,
+            scaled_balance: 10 * SPOT_BALANCE_PRECISION as u64,
+            balance_type: SpotBalanceType::Borrow,
+            ..Default::default()
+        };
+
+        let projections = project_user_spot_interest(&user, &[market], 0, ONE_YEAR as i64).unwrap();
+        assert_eq!(projections.len(), 2);
+
+        let deposit = projections[0];
+        assert_eq!(deposit.token_amount, 100_000_000);
+        assert_eq!(deposit.interest, 1_800_000);
+        assert_eq!(deposit.rate, 18_000);
+
+        let borrow = projections[1];
+        assert_eq!(borrow.token_amount, 10_000_000);
+        assert_eq!(borrow.interest, 500_001);
+        assert_eq!(borrow.rate, 50_000);
+
+        assert!(project_user_spot_interest(&user, &[], 0, ONE_YEAR as i64).is_err());
+    }
+
+    #[test]
+    fn borrow_rate_curve_optimal_above_breakpoint() {
+        // segments below optimal utilization are skipped
+        let market = SpotMarket {
+            optimal_utilization: 900_000,
+            ..usdc_spot_market(1_000, 0)
+        };
+        assert_eq!(calculate_borrow_rate(&market, 900_000), 100_000);
+        // 50% of the 15% segment weight
+        assert_eq!(calculate_borrow_rate(&market, 925_000), 167_500);
+        assert_eq!(
+            calculate_borrow_rate(&market, SPOT_UTILIZATION_PRECISION),
+            865_000
+        );
+    }
+
+    #[test]
+    fn update_cumulative_interest_last_ts() {
+        let market = SpotMarket {
+            last_interest_ts: 100,
+            ..usdc_spot_market(1_000, 400)
+        };
+        let updated = update_cumulative_interest(&market, 100 + ONE_YEAR as i64).unwrap();
+        assert_eq!(updated.last_interest_ts, 100 + ONE_YEAR as u64);
+        assert!(
+            updated.cumulative_borrow_interest.as_u128()
+                > market.cumulative_borrow_interest.as_u128()
+        );
+
+        // no interest accrued, ts is unchanged
+        let idle_market = SpotMarket {
+            last_interest_ts: 100,
+            ..usdc_spot_market(1_000, 0)
+        };
+        let updated = update_cumulative_interest(&idle_market, 100 + ONE_YEAR as i64).unwrap();
+        assert_eq!(updated.last_interest_ts, 100);
+        assert_eq!(updated, idle_market);
+    }
+}
@@ -15,6 +15,8 @@ use crate::{
 pub mod account_list_builder;
 pub mod auction;
 pub mod constants;
+pub mod funding;
+pub mod interest_rate;
 pub mod leverage;
 pub mod liquidation;
 pub mod order;
@@ -0,0 +1,497 @@
+//!
+//! In-memory RPC for offline testing
+//!
+//! `MockRpc` serves accounts from a snapshot and captures sent txs, use with `DriftClient::new_mock`
+//! to unit test code built on `DriftClient` without a network
+//!
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex, RwLock,
+};
+
+use async_trait::async_trait;
+use base64::Engine;
+use dashmap::DashMap;
+use serde_json::{json, Value};
+use solana_account_decoder_client_types::{UiAccount, UiAccountData, UiAccountEncoding};
+use solana_rpc_client::{
+    nonblocking::rpc_client::RpcClient,
+    rpc_client::RpcClientConfig,
+    rpc_sender::{RpcSender, RpcTransportStats},
+};
+use solana_rpc_client_api::{
+    client_error::Result as ClientResult,
+    config::RpcProgramAccountsConfig,
+    filter::RpcFilterType,
+    request::{RpcError, RpcRequest},
+    response::{
+        Response, RpcBlockhash, RpcKeyedAccount, RpcResponseContext, RpcSimulateTransactionResult,
+    },
+};
+use solana_sdk::{
+    account::Account, clock::Slot, commitment_config::CommitmentConfig, hash::Hash, pubkey::Pubkey,
+    rent::Rent, transaction::VersionedTransaction,
+};
+
+use crate::{
+    constants::{high_leverage_mode_account, state_account, PROGRAM_ID},
+    drift_idl::accounts::{HighLeverageModeConfig, PerpMarket, SpotMarket, State, User, UserStats},
+    types::Context,
+    utils::zero_account_to_bytes,
+    Wallet,
+};
+
+/// Placeholder Ws endpoint of mocked clients, never connects
+pub(crate) const MOCK_WS_URL: &str = "ws://127.0.0.1:0";
+
+/// Size of an address lookup table account without addresses
+const LOOKUP_TABLE_META_SIZE: usize = 56;
+
+/// CUs reported by simulated txs
+const SIMULATED_UNITS_CONSUMED: u64 = 200_000;
+
+/// In-memory RPC node serving a snapshot of accounts
+///
+/// ```example(no_run)
+/// let mock = Arc::new(MockRpc::new(Context::DevNet));
+/// mock.set_perp_market(sol_perp);
+/// mock.set_user(wallet.default_sub_account(), user);
+///
+/// let client = DriftClient::new_mock(Context::DevNet, Arc::clone(&mock), wallet).await?;
+/// run_bot_iteration(&client).await?;
+///
+/// assert_eq!(mock.sent_transactions().len(), 1);
+/// ```
+pub struct MockRpc {
+    accounts: DashMap<Pubkey, Account>,
+    slot: AtomicU64,
+    blockhash: RwLock<Hash>,
+    sent: Mutex<Vec<VersionedTransaction>>,
+}
+
+impl MockRpc {
+    /// Create a new `MockRpc` with empty lookup tables for `context` and default program `State`
+    pub fn new(context: Context) -> Self {
+        let mock = Self {
+            accounts: DashMap::default(),
+            slot: AtomicU64::new(1),
+            blockhash: RwLock::new(Hash::new_unique()),
+            sent: Mutex::default(),
+        };
+        for lut in context.luts() {
+            mock.set_account(
+                *lut,
+                Account {
+                    lamports: 1,
+                    data: vec![0; LOOKUP_TABLE_META_SIZE],
+                    owner: solana_sdk::address_lookup_table::program::id(),
+                    ..Default::default()
+                },
+            );
+        }
+        mock.set_state(State::default());
+        mock.set_program_account(
+            *high_leverage_mode_account(),
+            zero_account_to_bytes(HighLeverageModeConfig::default()),
+        );
+        mock
+    }
+
+    /// Set the data of `pubkey`
+    pub fn set_account(&self, pubkey: Pubkey, account: Account) {
+        self.accounts.insert(pubkey, account);
+    }
+
+    /// Remove `pubkey`, queries return no account
+    pub fn remove_account(&self, pubkey: &Pubkey) {
+        self.accounts.remove(pubkey);
+    }
+
+    /// Returns the data of `pubkey`, if it exists
+    pub fn account(&self, pubkey: &Pubkey) -> Option<Account> {
+        self.accounts.get(pubkey).map(|a| a.clone())
+    }
+
+    /// Set the drift program `State`
+    pub fn set_state(&self, state: State) {
+        self.set_program_account(*state_account(), zero_account_to_bytes(state));
+    }
+
+    /// Set a perp market, keyed by `market.pubkey`
+    pub fn set_perp_market(&self, market: PerpMarket) {
+        self.set_program_account(market.pubkey, zero_account_to_bytes(market));
+    }
+
+    /// Set a spot market, keyed by `market.pubkey`
+    pub fn set_spot_market(&self, market: SpotMarket) {
+        self.set_program_account(market.pubkey, zero_account_to_bytes(market));
+    }
+
+    /// Set the drift `User` account at `pubkey`
+    pub fn set_user(&self, pubkey: Pubkey, user: User) {
+        self.set_program_account(pubkey, zero_account_to_bytes(user));
+    }
+
+    /// Set the drift `UserStats` account of `authority`
+    pub fn set_user_stats(&self, authority: &Pubkey, user_stats: UserStats) {
+        self.set_program_account(
+            Wallet::derive_stats_account(authority),
+            zero_account_to_bytes(user_stats),
+        );
+    }
+
+    fn set_program_account(&self, pubkey: Pubkey, data: Vec<u8>) {
+        self.set_account(
+            pubkey,
+            Account {
+                lamports: Rent::default().minimum_balance(data.len()),
+                data,
+                owner: PROGRAM_ID,
+                ..Default::default()
+            },
+        );
+    }
+
+    /// Set the slot reported by queries
+    pub fn set_slot(&self, slot: Slot) {
+        self.slot.store(slot, Ordering::Relaxed);
+    }
+
+    /// Returns the slot reported by queries
+    pub fn slot(&self) -> Slot {
+        self.slot.load(Ordering::Relaxed)
+    }
+
+    /// Set the latest blockhash
+    pub fn set_blockhash(&self, blockhash: Hash) {
+        *self.blockhash.write().expect("acquired") = blockhash;
+    }
+
+    /// Returns all txs sent so far
+    pub fn sent_transactions(&self) -> Vec<VersionedTransaction> {
+        self.sent.lock().expect("acquired").clone()
+    }
+
+    /// Returns and clears all txs sent so far
+    pub fn take_sent_transactions(&self) -> Vec<VersionedTransaction> {
+        std::mem::take(&mut *self.sent.lock().expect("acquired"))
+    }
+
+    /// Returns all accounts
+    pub fn accounts(&self) -> Vec<(Pubkey, Account)> {
+        self.accounts
+            .iter()
+            .map(|a| (*a.key(), a.value().clone()))
+            .collect()
+    }
+
+    /// Returns all drift program owned accounts
+    pub(crate) fn program_accounts(&self) -> Vec<(Pubkey, Account)> {
+        self.accounts
+            .iter()
+            .filter(|a| a.owner == PROGRAM_ID)
+            .map(|a| (*a.key(), a.value().clone()))
+            .collect()
+    }
+
+    /// Returns an `RpcClient` backed by this mock
+    pub fn rpc_client(self: &Arc<Self>) -> RpcClient {
+        RpcClient::new_sender(
+            MockSender(Arc::clone(self)),
+            RpcClientConfig::with_commitment(CommitmentConfig::confirmed()),
+        )
+    }
+
+    fn context(&self) -> RpcResponseContext {
+        RpcResponseContext::new(self.slot())
+    }
+
+    fn ui_account(&self, pubkey: &Pubkey) -> Option<UiAccount> {
+        self.accounts.get(pubkey).map(|a| to_ui_account(a.value()))
+    }
+
+    fn handle(&self, request: RpcRequest, params: Value) -> Result<Value, RpcError> {
+        let value = match request {
+            RpcRequest::GetAccountInfo => {
+                let pubkey = parse_pubkey(&params[0])?;
+                json!(Response {
+                    context: self.context(),
+                    value: self.ui_account(&pubkey),
+                })
+            }
+            RpcRequest::GetMultipleAccounts => {
+                let pubkeys: Vec<String> = parse(&params[0])?;
+                let accounts = pubkeys
+                    .iter()
+                    .map(|p| parse_pubkey(&json!(p)).map(|p| self.ui_account(&p)))
+                    .collect::<Result<Vec<_>, _>>()?;
+                json!(Response {
+                    context: self.context(),
+                    value: accounts,
+                })
+            }
+            RpcRequest::GetProgramAccounts => {
+                let program = parse_pubkey(&params[0])?;
+                let config: RpcProgramAccountsConfig = if params[1].is_null() {
+                    RpcProgramAccountsConfig::default()
+                } else {
+                    parse(&params[1])?
+                };
+                let filters = config.filters.unwrap_or_default();
+                let accounts: Vec<RpcKeyedAccount> = self
+                    .accounts
+                    .iter()
+                    .filter(|a| a.owner == program && filters.iter().all(|f| allows(f, &a.data)))
+                    .map(|a| RpcKeyedAccount {
+                        pubkey: a.key().to_string(),
+                        account: to_ui_account(a.value()),
+                    })
+                    .collect();
+                if config.with_context.unwrap_or_default() {
+                    json!(Response {
+                        context: self.context(),
+                        value: accounts,
+                    })
+                } else {
+                    json!(accounts)
+                }
+            }
+            RpcRequest::GetBalance => {
+                let pubkey = parse_pubkey(&params[0])?;
+                json!(Response {
+                    context: self.context(),
+                    value: self
+                        .accounts
+                        .get(&pubkey)
+                        .map(|a| a.lamports)
+                        .unwrap_or_default(),
+                })
+            }
+            RpcRequest::GetLatestBlockhash => json!(Response {
+                context: self.context(),
+                value: RpcBlockhash {
+                    blockhash: self.blockhash.read().expect("acquired").to_string(),
+                    last_valid_block_height: self.slot() + 150,
+                },
+            }),
+            RpcRequest::GetSlot | RpcRequest::GetBlockHeight => json!(self.slot()),
+            RpcRequest::GetMinimumBalanceForRentExemption => {
+                let len: usize = parse(&params[0])?;
+                json!(Rent::default().minimum_balance(len))
+            }
+            RpcRequest::GetRecentPrioritizationFees => json!([]),
+            RpcRequest::SendTransaction => {
+                let tx = decode_tx(&params[0])?;
+                let signature = tx.signatures[0];
+                self.sent.lock().expect("acquired").push(tx);
+                json!(signature.to_string())
+            }
+            RpcRequest::SimulateTransaction => {
+                decode_tx(&params[0])?;
+                json!(Response {
+                    context: self.context(),
+                    value: RpcSimulateTransactionResult {
+                        err: None,
+                        logs: Some(vec![]),
+                        accounts: None,
+                        units_consumed: Some(SIMULATED_UNITS_CONSUMED),
+                        return_data: None,
+                        inner_instructions: None,
+                        replacement_blockhash: None,
+                        loaded_accounts_data_size: None,
+                    },
+                })
+            }
+            RpcRequest::GetSignatureStatuses => {
+                let signatures: Vec<String> = parse(&params[0])?;
+                let sent = self.sent.lock().expect("acquired");
+                let statuses: Vec<Value> = signatures
+                    .iter()
+                    .map(|s| {
+                        if sent.iter().any(|tx| tx.signatures[0].to_string() == *s) {
+                            json!({
+                                "slot": self.slot(),
+                                "confirmations": null,
+                                "err": null,
+                                "status": { "Ok": null },
+                                "confirmationStatus": "confirmed",
+                            })
+                        } else {
+                            Value::Null
+                        }
+                    })
+                    .collect();
+                json!(Response {
+                    context: self.context(),
+                    value: statuses,
+                })
+            }
+            _ => {
+                return Err(RpcError::ForUser(format!(
+                    "request unsupported by mock: {request}"
+                )))
+            }
+        };
+
+        Ok(value)
+    }
+}
+
+fn to_ui_account(account: &Account) -> UiAccount {
+    UiAccount {
+        lamports: account.lamports,
+        data: UiAccountData::Binary(
+            base64::engine::general_purpose::STANDARD.encode(&account.data),
+            UiAccountEncoding::Base64,
+        ),
+        owner: account.owner.to_string(),
+        executable: account.executable,
+        rent_epoch: account.rent_epoch,
+        space: Some(account.data.len() as u64),
+    }
+}
+
+/// Returns true if account `data` passes the gPA `filter`
+fn allows(filter: &RpcFilterType, data: &[u8]) -> bool {
+    match filter {
+        RpcFilterType::DataSize(size) => data.len() as u64 == *size,
+        RpcFilterType::Memcmp(memcmp) => memcmp.bytes_match(data),
+        RpcFilterType::TokenAccountState => false,
+    }
+}
+
+fn parse<T: serde::de::DeserializeOwned>(value: &Value) -> Result<T, RpcError> {
+    serde_json::from_value(value.clone())
+        .map_err(|err| RpcError::ParseError(format!("invalid params: {err}")))
+}
+
+fn parse_pubkey(value: &Value) -> Result<Pubkey, RpcError> {
+    let pubkey: String = parse(value)?;
+    pubkey
+        .parse()
+        .map_err(|_| RpcError::ParseError(format!("invalid pubkey: {pubkey}")))
+}
+
+fn decode_tx(value: &Value) -> Result<VersionedTransaction, RpcError> {
+    let encoded: String = parse(value)?;
+    let raw = base64::engine::general_purpose::STANDARD
+        .decode(encoded)
+        .map_err(|err| RpcError::ParseError(format!("invalid tx encoding: {err}")))?;
+    bincode::deserialize(&raw).map_err(|err| RpcError::ParseError(format!("invalid tx: {err}")))
+}
+
+/// `RpcSender` serving requests from a `MockRpc`
+struct MockSender(Arc<MockRpc>);
+
+#[async_trait]
+impl RpcSender for MockSender {
+    async fn send(&self, request: RpcRequest, params: Value) -> ClientResult<Value> {
+        self.0.handle(request, params).map_err(Into::into)
+    }
+    fn get_transport_stats(&self) -> RpcTransportStats {
+        RpcTransportStats::default()
+    }
+    fn url(&self) -> String {
+        "mock".into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::{signature::Keypair, signer::Signer};
+
+    use super::*;
+    use crate::{
+        constants::ids::pyth_program,
+        drift_idl::types::OracleSource,
+        types::MarketId,
+        utils::test_utils::{get_account_bytes, get_pyth_price},
+        DriftClient,
+    };
+
+    #[tokio::test]
+    async fn drift_client_served_from_mock() {
+        let wallet = Wallet::new(Keypair::new());
+        let mock = Arc::new(MockRpc::new(Context::DevNet));
+        mock.set_slot(1_000);
+
+        let oracle = Pubkey::new_unique();
+        let mut perp_market = PerpMarket {
+            pubkey: Pubkey::new_unique(),
+            market_index: 1,
+            ..Default::default()
+        };
+        perp_market.amm.oracle = oracle;
+        perp_market.amm.oracle_source = OracleSource::Pyth;
+        mock.set_perp_market(perp_market);
+        mock.set_spot_market(SpotMarket {
+            pubkey: Pubkey::new_unique(),
+            market_index: 0,
+            oracle: Pubkey::new_unique(),
+            ..Default::default()
+        });
+        mock.set_account(
+            oracle,
+            Account {
+                lamports: 1,
+                data: get_account_bytes(&mut get_pyth_price(240, 9)).to_vec(),
+                owner: pyth_program::ID,
+                ..Default::default()
+            },
+        );
+        let user = User {
+            authority: *wallet.authority(),
+            ..Default::default()
+        };
+        mock.set_user(wallet.default_sub_account(), user);
+
+        let client = DriftClient::new_mock(Context::DevNet, Arc::clone(&mock), wallet.clone())
+            .await
+            .unwrap();
+
+        let market = client.try_get_perp_market_account(1).unwrap();
+        assert_eq!(market.pubkey, perp_market.pubkey);
+        assert!(client.try_get_spot_market_account(0).is_ok());
+        assert!(client
+            .try_get_oracle_price_data_and_slot(MarketId::perp(1))
+            .is_some_and(|o| o.slot == 1_000));
+        assert!(client
+            .try_get_oracle_price_data_and_slot(MarketId::spot(0))
+            .is_none());
+        assert_eq!(
+            client
+                .try_get_account::<User>(&wallet.default_sub_account())
+                .unwrap()
+                .authority,
+            *wallet.authority()
+        );
+        assert!(client.state_account().is_ok());
+
+        // not preloaded, falls back to the mock RPC
+        let other = Pubkey::new_unique();
+        mock.set_user(other, user);
+        assert!(client.get_user_account(&other).await.is_ok());
+        assert!(client
+            .get_user_account(&Pubkey::new_unique())
+            .await
+            .is_err());
+
+        let tx = client
+            .init_tx(&wallet.default_sub_account(), false)
+            .await
+            .unwrap()
+            .cancel_all_orders()
+            .build();
+        let signature = client.sign_and_send(tx).await.unwrap();
+
+        let sent = mock.take_sent_transactions();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].signatures[0], signature);
+        assert!(sent[0].verify_with_results().iter().all(|ok| *ok));
+        assert!(sent[0]
+            .message
+            .static_account_keys()
+            .contains(&wallet.signer()));
+        assert!(mock.sent_transactions().is_empty());
+    }
+}
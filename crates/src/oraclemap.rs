@@ -335,6 +335,21 @@ impl OracleMap {
         Ok(())
     }
 
+    /// Resync Ws subscribed oracles via RPC e.g. to fill updates missed during a Ws reconnect
+    pub async fn resync(&self, rpc: &RpcClient) -> SdkResult<()> {
+        let markets: Vec<MarketId> = self
+            .oracle_by_market
+            .keys()
+            .filter(|m| self.is_subscribed(m))
+            .copied()
+            .collect();
+        if markets.is_empty() {
+            return Ok(());
+        }
+
+        self.sync(&markets, rpc).await
+    }
+
     /// Number of oracles known to the `OracleMap`
     #[allow(dead_code)]
     pub fn len(&self) -> usize {
@@ -0,0 +1,710 @@
+//!
+//! Order manager
+//!
+//! Reconciles a desired set of quotes with the sub-account's open orders, emitting the minimal
+//! cancel/modify/place batch rather than cancelling and replacing every order.
+//!
+use std::time::{Duration, Instant};
+
+use ahash::{HashMap, HashMapExt};
+use log::debug;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::{
+    event_subscriber::DriftEvent,
+    types::{
+        accounts::User, MarketId, ModifyOrderParams, Order, OrderParams, OrderStatus, OrderType,
+        PositionDirection, PostOnlyParam,
+    },
+    TransactionBuilder,
+};
+
+const LOG_TARGET: &str = "ordermanager";
+
+/// Default time to wait for an in-flight change before it is assumed dropped
+pub const DEFAULT_PENDING_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A desired resting limit order
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Quote {
+    pub direction: PositionDirection,
+    /// limit price (PRICE_PRECISION), ignored if `oracle_price_offset` is set
+    pub price: u64,
+    /// price offset from oracle (PRICE_PRECISION)
+    pub oracle_price_offset: Option<i32>,
+    /// unfilled order size (BASE_PRECISION or spot market decimals)
+    pub size: u64,
+    pub post_only: PostOnlyParam,
+    pub reduce_only: bool,
+}
+
+impl Quote {
+    /// Create a fixed price post-only quote
+    pub fn new(direction: PositionDirection, price: u64, size: u64) -> Self {
+        Self {
+            direction,
+            price,
+            oracle_price_offset: None,
+            size,
+            post_only: PostOnlyParam::MustPostOnly,
+            reduce_only: false,
+        }
+    }
+    /// Create an oracle offset post-only quote
+    pub fn oracle_offset(direction: PositionDirection, offset: i32, size: u64) -> Self {
+        Self {
+            oracle_price_offset: Some(offset),
+            ..Self::new(direction, 0, size)
+        }
+    }
+    /// Set reduce only (default: false)
+    pub fn reduce_only(mut self, flag: bool) -> Self {
+        self.reduce_only = flag;
+        self
+    }
+    /// Set post-only (default: MustPostOnly)
+    pub fn post_only(mut self, value: PostOnlyParam) -> Self {
+        self.post_only = value;
+        self
+    }
+    fn from_order(order: &Order) -> Self {
+        Self {
+            direction: order.direction,
+            price: order.price,
+            oracle_price_offset: (order.oracle_price_offset != 0)
+                .then_some(order.oracle_price_offset),
+            size: order
+                .base_asset_amount
+                .saturating_sub(order.base_asset_amount_filled),
+            post_only: if order.post_only {
+                PostOnlyParam::MustPostOnly
+            } else {
+                PostOnlyParam::None
+            },
+            reduce_only: order.reduce_only,
+        }
+    }
+    /// Returns true if a resting order `live` already satisfies this quote
+    fn is_satisfied_by(&self, live: &Quote) -> bool {
+        let same_price = match self.oracle_price_offset {
+            Some(offset) => live.oracle_price_offset == Some(offset),
+            None => live.oracle_price_offset.is_none() && live.price == self.price,
+        };
+        same_price
+            && self.direction == live.direction
+            && self.size == live.size
+            && self.reduce_only == live.reduce_only
+            && (self.post_only == PostOnlyParam::None) == (live.post_only == PostOnlyParam::None)
+    }
+    /// Sort key, most aggressive quote first
+    pub(crate) fn priority(&self) -> i128 {
+        let price = match self.oracle_price_offset {
+            Some(offset) => offset as i128,
+            None => self.price as i128,
+        };
+        match self.direction {
+            PositionDirection::Long => -price,
+            PositionDirection::Short => price,
+        }
+    }
+    fn to_order_params(self, market: MarketId, user_order_id: u8) -> OrderParams {
+        OrderParams {
+            order_type: OrderType::Limit,
+            market_index: market.index(),
+            market_type: market.kind(),
+            direction: self.direction,
+            price: self.price,
+            oracle_price_offset: self.oracle_price_offset,
+            base_asset_amount: self.size,
+            post_only: self.post_only,
+            reduce_only: self.reduce_only,
+            user_order_id,
+            ..Default::default()
+        }
+    }
+    /// Returns true if priced as an offset from oracle rather than fixed
+    fn is_oracle_offset(&self) -> bool {
+        self.oracle_price_offset.is_some()
+    }
+    /// Modify params moving an order of the same price kind to this quote
+    ///
+    /// the price kind can't be changed by modify (`oracle_price_offset: None` leaves it unchanged)
+    fn to_modify_params(self) -> ModifyOrderParams {
+        ModifyOrderParams {
+            price: (!self.is_oracle_offset()).then_some(self.price),
+            oracle_price_offset: self.oracle_price_offset,
+            base_asset_amount: Some(self.size),
+            post_only: Some(self.post_only),
+            reduce_only: Some(self.reduce_only),
+            ..Default::default()
+        }
+    }
+}
+
+/// Minimal set of changes to move the open orders to the desired quotes
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OrderBatch {
+    /// user order ids to cancel
+    pub cancel: Vec<u8>,
+    /// (user order id, changes) to modify
+    pub modify: Vec<(u8, ModifyOrderParams)>,
+    /// new orders to place
+    pub place: Vec<OrderParams>,
+}
+
+impl OrderBatch {
+    /// Returns true if there are no changes
+    pub fn is_empty(&self) -> bool {
+        self.cancel.is_empty() && self.modify.is_empty() && self.place.is_empty()
+    }
+    /// Add the batch ixs to `tx` (cancels, then modifies, then places)
+    pub fn apply<'a>(self, mut tx: TransactionBuilder<'a>) -> TransactionBuilder<'a> {
+        if !self.cancel.is_empty() {
+            tx = tx.cancel_orders_by_user_id(self.cancel);
+        }
+        if !self.modify.is_empty() {
+            tx = tx.modify_orders_by_user_id(&self.modify);
+        }
+        if !self.place.is_empty() {
+            tx = tx.place_orders(self.place);
+        }
+        tx
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum OrderState {
+    /// resting on-chain
+    Open,
+    /// place sent, awaiting confirmation
+    Placing(Instant),
+    /// modify sent, awaiting confirmation (holds the pre-modify quote)
+    Modifying(Instant, Quote),
+    /// cancel sent, awaiting confirmation
+    Cancelling(Instant),
+}
+
+#[derive(Copy, Clone, Debug)]
+struct ManagedOrder {
+    market: MarketId,
+    quote: Quote,
+    order_id: Option<u32>,
+    state: OrderState,
+}
+
+/// Reconciles desired quotes with a sub-account's open orders
+///
+/// Orders are tracked by `user_order_id`, orders without one (i.e. 0) are not managed
+///
+/// ```example(no_run)
+///   let mut manager = OrderManager::new(sub_account);
+///   // on account/event updates
+///   manager.on_event(&event);
+///   // on requote
+///   let user = drift.backend().account_map().account_data::<User>(&sub_account).unwrap();
+///   let batch = manager.reconcile(&user, &[(market, vec![bid, ask])]);
+///   if !batch.is_empty() {
+///       let tx = batch.apply(drift.init_tx(&sub_account, false).await?).build();
+///       drift.sign_and_send(tx).await?;
+///   }
+/// ```
+pub struct OrderManager {
+    sub_account: Pubkey,
+    orders: HashMap<u8, ManagedOrder>,
+    pending_timeout: Duration,
+}
+
+impl OrderManager {
+    /// Create a new order manager for `sub_account`
+    pub fn new(sub_account: Pubkey) -> Self {
+        Self {
+            sub_account,
+            orders: HashMap::new(),
+            pending_timeout: DEFAULT_PENDING_TIMEOUT,
+        }
+    }
+    /// Set the time to wait for in-flight changes before they are assumed dropped
+    pub fn with_pending_timeout(mut self, timeout: Duration) -> Self {
+        self.pending_timeout = timeout;
+        self
+    }
+    /// The managed sub-account
+    pub fn sub_account(&self) -> Pubkey {
+        self.sub_account
+    }
+    /// Returns true if any changes are awaiting confirmation
+    pub fn has_pending(&self) -> bool {
+        self.orders
+            .values()
+            .any(|o| !matches!(o.state, OrderState::Open))
+    }
+    /// Diff `quotes` against the open orders of `user` and return the batch of changes
+    ///
+    /// The returned changes are tracked as in-flight until confirmed by `on_event` or a
+    /// later `user` account state. Markets not in `quotes` are left untouched, pass an empty
+    /// list to pull all quotes in a market.
+    ///
+    /// * `user` - latest sub-account data
+    /// * `quotes` - desired quotes per market
+    pub fn reconcile(&mut self, user: &User, quotes: &[(MarketId, Vec<Quote>)]) -> OrderBatch {
+        self.sync(user);
+
+        let mut batch = OrderBatch::default();
+        let now = Instant::now();
+        for (market, desired) in quotes {
+            for direction in [PositionDirection::Long, PositionDirection::Short] {
+                let mut desired: Vec<Quote> = desired
+                    .iter()
+                    .filter(|q| q.direction == direction)
+                    .copied()
+                    .collect();
+                let mut live: Vec<(u8, Quote)> = self
+                    .orders
+                    .iter()
+                    .filter(|(_, o)| {
+                        o.market == *market
+                            && o.quote.direction == direction
+                            && !matches!(o.state, OrderState::Cancelling(_))
+                    })
+                    .map(|(id, o)| (*id, o.quote))
+                    .collect();
+
+                // keep orders which already satisfy a quote
+                desired.retain(
+                    |q| match live.iter().position(|(_, l)| q.is_satisfied_by(l)) {
+                        Some(idx) => {
+                            live.swap_remove(idx);
+                            false
+                        }
+                        None => true,
+                    },
+                );
+                // in-flight places are reconciled once confirmed
+                live.retain(|(id, _)| !matches!(self.orders[id].state, OrderState::Placing(_)));
+
+                // pair remaining by price kind and priority, modify the pairs
+                // changing the price kind requires cancel and place
+                desired.sort_by_key(|q| (q.is_oracle_offset(), q.priority()));
+                live.sort_by_key(|(_, q)| (q.is_oracle_offset(), q.priority()));
+                let mut desired = desired.into_iter().peekable();
+                let mut live = live.into_iter().peekable();
+                loop {
+                    let pair = match (desired.peek(), live.peek()) {
+                        (Some(d), Some((_, l))) if d.is_oracle_offset() != l.is_oracle_offset() => {
+                            // kinds sort fixed price first, drain the side still on fixed price
+                            if l.is_oracle_offset() {
+                                (desired.next(), None)
+                            } else {
+                                (None, live.next())
+                            }
+                        }
+                        _ => (desired.next(), live.next()),
+                    };
+                    match pair {
+                        (Some(quote), Some((id, old))) => {
+                            // a modify in-flight already has its new quote
+                            let order = self.orders.get_mut(&id).expect("managed");
+                            order.quote = quote;
+                            order.state = match order.state {
+                                OrderState::Modifying(_, prev) => OrderState::Modifying(now, prev),
+                                _ => OrderState::Modifying(now, old),
+                            };
+                            batch.modify.push((id, quote.to_modify_params()));
+                        }
+                        (None, Some((id, _))) => {
+                            let order = self.orders.get_mut(&id).expect("managed");
+                            order.state = OrderState::Cancelling(now);
+                            batch.cancel.push(id);
+                        }
+                        (Some(quote), None) => {
+                            let Some(id) = self.next_user_order_id(user) else {
+                                log::warn!(target: LOG_TARGET, "no free user order ids");
+                                continue;
+                            };
+                            self.orders.insert(
+                                id,
+                                ManagedOrder {
+                                    market: *market,
+                                    quote,
+                                    order_id: None,
+                                    state: OrderState::Placing(now),
+                                },
+                            );
+                            batch.place.push(quote.to_order_params(*market, id));
+                        }
+                        (None, None) => break,
+                    }
+                }
+            }
+        }
+
+        debug!(target: LOG_TARGET, "reconciled: {batch:?}");
+        batch
+    }
+    /// Update in-flight changes from a `DriftEvent` of the sub-account
+    pub fn on_event(&mut self, event: &DriftEvent) {
+        let subject = Some(self.sub_account);
+        match event {
+            DriftEvent::OrderCreate { order, user, .. } if *user == self.sub_account => {
+                if order.user_order_id == 0 {
+                    return;
+                }
+                self.orders.insert(
+                    order.user_order_id,
+                    ManagedOrder {
+                        market: MarketId::new(order.market_index, order.market_type),
+                        quote: Quote::from_order(order),
+                        order_id: Some(order.order_id),
+                        state: OrderState::Open,
+                    },
+                );
+            }
+            DriftEvent::OrderCancel {
+                maker,
+                taker,
+                maker_order_id,
+                taker_order_id,
+                ..
+            } => {
+                let order_id = if *maker == subject {
+                    *maker_order_id
+                } else if *taker == subject {
+                    *taker_order_id
+                } else {
+                    return;
+                };
+                if let Some(id) = self.user_order_id(order_id) {
+                    // a modify cancels then re-creates the order
+                    if !matches!(self.orders[&id].state, OrderState::Modifying(..)) {
+                        self.orders.remove(&id);
+                    }
+                }
+            }
+            DriftEvent::OrderCancelMissing { user_order_id, .. } => {
+                if self
+                    .orders
+                    .get(user_order_id)
+                    .is_some_and(|o| matches!(o.state, OrderState::Cancelling(_)))
+                {
+                    self.orders.remove(user_order_id);
+                }
+            }
+            DriftEvent::OrderExpire { order_id, user, .. } if *user == subject => {
+                if let Some(id) = self.user_order_id(*order_id) {
+                    self.orders.remove(&id);
+                }
+            }
+            DriftEvent::OrderFill {
+                base_asset_amount_filled,
+                ..
+            } => {
+                let Some(fill) = event.fill_side(&self.sub_account) else {
+                    return;
+                };
+                if let Some(id) = self.user_order_id(fill.order_id) {
+                    let order = self.orders.get_mut(&id).expect("managed");
+                    order.quote.size = order.quote.size.saturating_sub(*base_asset_amount_filled);
+                    if order.quote.size == 0 {
+                        self.orders.remove(&id);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    /// Sync tracked orders with `user` account state, expiring stale in-flight changes
+    fn sync(&mut self, user: &User) {
+        let now = Instant::now();
+        let open: HashMap<u8, &Order> = user
+            .orders
+            .iter()
+            .filter(|o| o.status == OrderStatus::Open && o.user_order_id != 0)
+            .map(|o| (o.user_order_id, o))
+            .collect();
+
+        // an order confirmed by event may be newer than the `user` snapshot
+        let is_newer = |managed: &ManagedOrder| {
+            managed
+                .order_id
+                .is_some_and(|order_id| order_id >= user.next_order_id)
+        };
+
+        let timeout = self.pending_timeout;
+        self.orders.retain(|id, managed| {
+            let live = open.get(id);
+            match managed.state {
+                OrderState::Open => live.is_some() || is_newer(managed),
+                OrderState::Placing(since) => {
+                    live.is_some_and(|o| managed.quote.is_satisfied_by(&Quote::from_order(o)))
+                        || now.duration_since(since) < timeout
+                }
+                OrderState::Cancelling(since) => {
+                    live.is_some() && now.duration_since(since) < timeout
+                }
+                OrderState::Modifying(..) => true,
+            }
+        });
+        for (id, managed) in self.orders.iter_mut() {
+            let live = open.get(id).map(|o| Quote::from_order(o));
+            match managed.state {
+                OrderState::Placing(_)
+                    if live.is_some_and(|l| managed.quote.is_satisfied_by(&l)) =>
+                {
+                    managed.state = OrderState::Open;
+                }
+                OrderState::Modifying(since, prev) => {
+                    if live.is_some_and(|l| managed.quote.is_satisfied_by(&l)) {
+                        managed.state = OrderState::Open;
+                    } else if now.duration_since(since) >= timeout {
+                        // assume dropped, revert to on-chain state
+                        managed.quote = live.unwrap_or(prev);
+                        managed.state = OrderState::Open;
+                    }
+                }
+                _ => (),
+            }
+        }
+        self.orders
+            .retain(|id, m| m.state != OrderState::Open || open.contains_key(id) || is_newer(m));
+
+        // adopt untracked orders e.g. on startup
+        for (id, order) in open {
+            let managed = self.orders.entry(id).or_insert(ManagedOrder {
+                market: MarketId::new(order.market_index, order.market_type),
+                quote: Quote::from_order(order),
+                order_id: Some(order.order_id),
+                state: OrderState::Open,
+            });
+            if managed.state == OrderState::Open {
+                managed.quote = Quote::from_order(order);
+                managed.order_id = Some(order.order_id);
+            }
+        }
+    }
+    /// Find the user order id of program assigned `order_id`
+    fn user_order_id(&self, order_id: u32) -> Option<u8> {
+        self.orders
+            .iter()
+            .find(|(_, o)| o.order_id == Some(order_id))
+            .map(|(id, _)| *id)
+    }
+    /// Allocate an unused user order id
+    fn next_user_order_id(&self, user: &User) -> Option<u8> {
+        (1..=u8::MAX).find(|id| {
+            !self.orders.contains_key(id)
+                && !user
+                    .orders
+                    .iter()
+                    .any(|o| o.status == OrderStatus::Open && o.user_order_id == *id)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event_subscriber::FillSide;
+
+    const SOL_PERP: MarketId = MarketId::perp(0);
+
+    fn bid(price: u64, size: u64) -> Quote {
+        Quote::new(PositionDirection::Long, price, size)
+    }
+
+    fn ask(price: u64, size: u64) -> Quote {
+        Quote::new(PositionDirection::Short, price, size)
+    }
+
+    /// `user` with open orders for each (user order id, quote)
+    fn user_with_orders(orders: &[(u8, Quote)]) -> User {
+        let mut user = User::default();
+        for (idx, (id, quote)) in orders.iter().enumerate() {
+            user.orders[idx] = Order {
+                status: OrderStatus::Open,
+                order_type: OrderType::Limit,
+                market_index: SOL_PERP.index(),
+                market_type: SOL_PERP.kind(),
+                order_id: 100 + idx as u32,
+                user_order_id: *id,
+                direction: quote.direction,
+                price: quote.price,
+                base_asset_amount: quote.size,
+                post_only: true,
+                ..Default::default()
+            };
+        }
+        user.next_order_id = 100 + orders.len() as u32;
+        user
+    }
+
+    #[test]
+    fn places_then_idles() {
+        let sub_account = Pubkey::new_unique();
+        let mut manager = OrderManager::new(sub_account);
+        let quotes = [(SOL_PERP, vec![bid(99, 10), ask(101, 10)])];
+
+        let batch = manager.reconcile(&User::default(), &quotes);
+        assert!(batch.cancel.is_empty() && batch.modify.is_empty());
+        assert_eq!(batch.place.len(), 2);
+        assert_eq!(batch.place[0].user_order_id, 1);
+        assert_eq!(batch.place[1].user_order_id, 2);
+        assert!(manager.has_pending());
+
+        // in-flight orders are not re-placed
+        assert!(manager.reconcile(&User::default(), &quotes).is_empty());
+
+        // confirmed by account state
+        let user = user_with_orders(&[(1, bid(99, 10)), (2, ask(101, 10))]);
+        assert!(manager.reconcile(&user, &quotes).is_empty());
+        assert!(!manager.has_pending());
+    }
+
+    #[test]
+    fn modifies_and_cancels_minimal() {
+        let sub_account = Pubkey::new_unique();
+        let mut manager = OrderManager::new(sub_account);
+        let user = user_with_orders(&[(1, bid(99, 10)), (2, bid(98, 10)), (3, ask(101, 10))]);
+
+        // bid @99 unchanged, bid @98 -> @97, ask cancelled
+        let batch = manager.reconcile(&user, &[(SOL_PERP, vec![bid(99, 10), bid(97, 10)])]);
+        assert!(batch.place.is_empty());
+        assert_eq!(batch.cancel, vec![3]);
+        assert_eq!(batch.modify.len(), 1);
+        assert_eq!(batch.modify[0].0, 2);
+        assert_eq!(batch.modify[0].1.price, Some(97));
+
+        // untouched markets are ignored
+        let user = user_with_orders(&[(1, bid(99, 10)), (2, bid(97, 10))]);
+        assert!(manager.reconcile(&user, &[]).is_empty());
+        assert!(!manager.has_pending());
+    }
+
+    #[test]
+    fn price_kind_change_replaces() {
+        let sub_account = Pubkey::new_unique();
+        let mut manager = OrderManager::new(sub_account);
+        let user = user_with_orders(&[(1, bid(99, 10)), (2, ask(101, 10))]);
+
+        // bid fixed -> oracle offset, ask stays fixed
+        let oracle_bid = Quote::oracle_offset(PositionDirection::Long, -100, 10);
+        let batch = manager.reconcile(&user, &[(SOL_PERP, vec![oracle_bid, ask(102, 10)])]);
+        assert_eq!(batch.cancel, vec![1]);
+        assert_eq!(batch.place.len(), 1);
+        assert_eq!(batch.place[0].user_order_id, 3);
+        assert_eq!(batch.place[0].oracle_price_offset, Some(-100));
+        assert_eq!(batch.modify.len(), 1);
+        assert_eq!(batch.modify[0].0, 2);
+        assert_eq!(batch.modify[0].1.price, Some(102));
+        assert_eq!(batch.modify[0].1.oracle_price_offset, None);
+    }
+
+    #[test]
+    fn in_flight_places_not_modified() {
+        let sub_account = Pubkey::new_unique();
+        let mut manager = OrderManager::new(sub_account);
+        assert_eq!(
+            manager
+                .reconcile(&User::default(), &[(SOL_PERP, vec![bid(99, 10)])])
+                .place
+                .len(),
+            1
+        );
+
+        // requote before the place lands
+        let quotes = [(SOL_PERP, vec![bid(98, 10)])];
+        let batch = manager.reconcile(&User::default(), &quotes);
+        assert!(batch.modify.is_empty() && batch.cancel.is_empty());
+        assert_eq!(batch.place.len(), 1);
+        assert_eq!(batch.place[0].user_order_id, 2);
+
+        // stale order is cancelled once confirmed
+        let user = user_with_orders(&[(1, bid(99, 10)), (2, bid(98, 10))]);
+        let batch = manager.reconcile(&user, &quotes);
+        assert_eq!(batch.cancel, vec![1]);
+        assert!(batch.modify.is_empty() && batch.place.is_empty());
+    }
+
+    #[test]
+    fn tracks_events() {
+        let sub_account = Pubkey::new_unique();
+        let mut manager = OrderManager::new(sub_account);
+        let quotes = [(SOL_PERP, vec![bid(99, 10)])];
+        let batch = manager.reconcile(&User::default(), &quotes);
+        assert_eq!(batch.place.len(), 1);
+
+        let user = user_with_orders(&[(1, bid(99, 10))]);
+        manager.on_event(&DriftEvent::OrderCreate {
+            order: user.orders[0],
+            user: sub_account,
+            ts: 0,
+            signature: Default::default(),
+            tx_idx: 0,
+        });
+        assert!(!manager.has_pending());
+
+        // partial fill, top up the quote
+        manager.on_event(&DriftEvent::test_fill(
+            Some(FillSide {
+                user: sub_account,
+                order_id: user.orders[0].order_id,
+                direction: PositionDirection::Long,
+                is_maker: true,
+                fee: 0,
+                cumulative_base_filled: None,
+            }),
+            None,
+            4,
+            0,
+        ));
+        let mut filled = user;
+        filled.orders[0].base_asset_amount_filled = 4;
+        let batch = manager.reconcile(&filled, &quotes);
+        assert_eq!(batch.modify.len(), 1);
+        assert_eq!(batch.modify[0].1.base_asset_amount, Some(10));
+    }
+
+    #[test]
+    fn event_confirmed_order_survives_stale_snapshot() {
+        let sub_account = Pubkey::new_unique();
+        let mut manager = OrderManager::new(sub_account);
+        let quotes = [(SOL_PERP, vec![bid(99, 10)])];
+        assert_eq!(manager.reconcile(&User::default(), &quotes).place.len(), 1);
+
+        let user = user_with_orders(&[(1, bid(99, 10))]);
+        manager.on_event(&DriftEvent::OrderCreate {
+            order: user.orders[0],
+            user: sub_account,
+            ts: 0,
+            signature: Default::default(),
+            tx_idx: 0,
+        });
+
+        // snapshot predates the order, no duplicate place
+        let stale = User {
+            next_order_id: user.orders[0].order_id,
+            ..Default::default()
+        };
+        assert!(manager.reconcile(&stale, &quotes).is_empty());
+
+        // snapshot past the order without it, order is gone
+        let closed = User {
+            next_order_id: user.next_order_id,
+            ..Default::default()
+        };
+        let batch = manager.reconcile(&closed, &quotes);
+        assert_eq!(batch.place.len(), 1);
+    }
+
+    #[test]
+    fn dropped_changes_expire() {
+        let sub_account = Pubkey::new_unique();
+        let mut manager = OrderManager::new(sub_account).with_pending_timeout(Duration::ZERO);
+        let quotes = [(SOL_PERP, vec![ask(101, 10)])];
+
+        assert_eq!(manager.reconcile(&User::default(), &quotes).place.len(), 1);
+        // tx never landed, retry
+        let batch = manager.reconcile(&User::default(), &quotes);
+        assert_eq!(batch.place.len(), 1);
+        assert_eq!(batch.place[0].user_order_id, 1);
+    }
+}
@@ -0,0 +1,572 @@
+//!
+//! Realized/unrealized PnL ledger built from drift events
+//!
+//! Feed `DriftEvent`s from an `EventSubscriber` into `PnlTracker::on_event` to track per market
+//! realized pnl, fees, funding and cost basis for a set of sub-accounts.
+//!
+//! Position accounting mirrors the program's `update_position_with_base_asset_amount` so the
+//! tracked `quote_entry_amount`/`quote_break_even_amount` can be reconciled against the on-chain `PerpPosition`.
+//!
+use std::collections::BTreeMap;
+
+use ahash::HashMap;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::{
+    drift_idl::{
+        accounts::User,
+        types::{
+            LiquidationType, MarketType, PerpPosition, PositionDirection, SettlePnlExplanation,
+        },
+    },
+    event_subscriber::DriftEvent,
+    math::constants::BASE_PRECISION_I128,
+};
+
+/// PnL ledger for a single perp market
+///
+/// All quote values in QUOTE_PRECISION
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MarketPnl {
+    pub market_index: u16,
+    /// signed position size in BASE_PRECISION
+    pub base_asset_amount: i64,
+    /// cost basis of the open position, excluding fees and funding (negative for longs)
+    pub quote_entry_amount: i64,
+    /// cost basis of the open position, including fees and funding
+    pub quote_break_even_amount: i64,
+    /// pnl realized by reducing, closing or flipping the position, excluding fees and funding
+    pub realized_pnl: i64,
+    /// fees paid as taker or maker
+    pub fees_paid: u64,
+    /// maker rebates received
+    pub fees_rebated: u64,
+    /// funding payments, positive = received
+    pub funding: i64,
+    /// pnl settled into the quote spot balance
+    pub settled_pnl: i64,
+    /// insurance fund fees paid on liquidation
+    pub liquidation_fees: u64,
+    /// number of fills applied to the ledger
+    pub fills: u64,
+}
+
+impl MarketPnl {
+    fn new(market_index: u16) -> Self {
+        Self {
+            market_index,
+            ..Default::default()
+        }
+    }
+
+    /// Average entry price of the open position in PRICE_PRECISION
+    pub fn average_entry_price(&self) -> u64 {
+        Self::price_of(self.quote_entry_amount, self.base_asset_amount)
+    }
+
+    /// Break even price of the open position (including fees and funding) in PRICE_PRECISION
+    pub fn break_even_price(&self) -> u64 {
+        Self::price_of(self.quote_break_even_amount, self.base_asset_amount)
+    }
+
+    fn price_of(quote_amount: i64, base_asset_amount: i64) -> u64 {
+        if base_asset_amount == 0 {
+            return 0;
+        }
+        (quote_amount.unsigned_abs() as u128 * BASE_PRECISION_I128 as u128
+            / base_asset_amount.unsigned_abs() as u128) as u64
+    }
+
+    /// Unrealized pnl of the open position at `oracle_price` (PRICE_PRECISION), excluding fees and funding
+    pub fn unrealized_pnl(&self, oracle_price: i64) -> i64 {
+        let base_asset_value =
+            self.base_asset_amount as i128 * oracle_price as i128 / BASE_PRECISION_I128;
+        (base_asset_value + self.quote_entry_amount as i128) as i64
+    }
+
+    /// Realized pnl net of fees, rebates, funding and liquidation fees
+    pub fn net_realized_pnl(&self) -> i64 {
+        self.realized_pnl - self.fees_paid as i64 + self.fees_rebated as i64 + self.funding
+            - self.liquidation_fees as i64
+    }
+
+    /// Total pnl at `oracle_price` i.e. net realized + unrealized
+    pub fn total_pnl(&self, oracle_price: i64) -> i64 {
+        self.net_realized_pnl() + self.unrealized_pnl(oracle_price)
+    }
+
+    /// Apply a signed base/quote delta to the position, returning the realized pnl
+    ///
+    /// `quote_asset_amount` is negative when paying (buying) and positive when receiving (selling)
+    fn apply_fill(&mut self, base_asset_amount: i64, quote_asset_amount: i64) -> i64 {
+        if base_asset_amount == 0 {
+            return 0;
+        }
+
+        let base = self.base_asset_amount as i128;
+        let delta_base = base_asset_amount as i128;
+        let delta_quote = quote_asset_amount as i128;
+        let quote_entry = self.quote_entry_amount as i128;
+        let quote_break_even = self.quote_break_even_amount as i128;
+
+        let (new_quote_entry, new_quote_break_even, pnl) =
+            if base == 0 || base.signum() == delta_base.signum() {
+                // open or increase
+                (quote_entry + delta_quote, quote_break_even + delta_quote, 0)
+            } else if delta_base.abs() <= base.abs() {
+                // reduce or close
+                let new_quote_entry = quote_entry - quote_entry * delta_base.abs() / base.abs();
+                let new_quote_break_even =
+                    quote_break_even - quote_break_even * delta_base.abs() / base.abs();
+                let pnl = quote_entry - new_quote_entry + delta_quote;
+                (new_quote_entry, new_quote_break_even, pnl)
+            } else {
+                // flip
+                let new_quote_entry = delta_quote - delta_quote * base.abs() / delta_base.abs();
+                let pnl = quote_entry + (delta_quote - new_quote_entry);
+                (new_quote_entry, new_quote_entry, pnl)
+            };
+
+        self.base_asset_amount += base_asset_amount;
+        self.quote_entry_amount = new_quote_entry as i64;
+        self.quote_break_even_amount = new_quote_break_even as i64;
+        self.realized_pnl += pnl as i64;
+
+        pnl as i64
+    }
+
+    fn apply_fee(&mut self, fee: i64) {
+        if fee >= 0 {
+            self.fees_paid += fee as u64;
+        } else {
+            self.fees_rebated += fee.unsigned_abs();
+        }
+        self.quote_break_even_amount -= fee;
+    }
+}
+
+/// Aggregate PnL across all markets of a sub-account, values in QUOTE_PRECISION
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PnlSummary {
+    pub realized_pnl: i64,
+    pub fees_paid: u64,
+    pub fees_rebated: u64,
+    pub funding: i64,
+    pub settled_pnl: i64,
+    pub liquidation_fees: u64,
+    pub net_realized_pnl: i64,
+}
+
+/// Difference between tracked and on-chain position state (tracked - on-chain)
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PositionReconciliation {
+    pub market_index: u16,
+    pub base_asset_amount_diff: i64,
+    pub quote_entry_amount_diff: i64,
+    pub quote_break_even_amount_diff: i64,
+}
+
+impl PositionReconciliation {
+    /// True if tracked position state matches on-chain within `tolerance` (QUOTE_PRECISION)
+    pub fn is_consistent(&self, tolerance: u64) -> bool {
+        self.base_asset_amount_diff == 0
+            && self.quote_entry_amount_diff.unsigned_abs() <= tolerance
+            && self.quote_break_even_amount_diff.unsigned_abs() <= tolerance
+    }
+}
+
+/// Tracks realized PnL, fees, funding and cost basis per perp market for a set of sub-accounts
+///
+/// ```ignore
+/// let mut tracker = PnlTracker::new(&[sub_account]);
+/// tracker.sync_from_user(&sub_account, &user); // seed open positions
+/// while let Some(event) = event_stream.next().await {
+///     tracker.on_event(&event);
+/// }
+/// let summary = tracker.summary(&sub_account);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct PnlTracker {
+    ledgers: HashMap<Pubkey, BTreeMap<u16, MarketPnl>>,
+}
+
+impl PnlTracker {
+    /// Create a tracker for the given sub-accounts
+    pub fn new(sub_accounts: &[Pubkey]) -> Self {
+        Self {
+            ledgers: sub_accounts
+                .iter()
+                .map(|s| (*s, BTreeMap::default()))
+                .collect(),
+        }
+    }
+
+    /// Start tracking `sub_account`
+    pub fn track(&mut self, sub_account: Pubkey) {
+        self.ledgers.entry(sub_account).or_default();
+    }
+
+    /// Stop tracking `sub_account`, returning its ledger
+    pub fn untrack(&mut self, sub_account: &Pubkey) -> Option<BTreeMap<u16, MarketPnl>> {
+        self.ledgers.remove(sub_account)
+    }
+
+    /// Returns true if `sub_account` is tracked
+    pub fn is_tracked(&self, sub_account: &Pubkey) -> bool {
+        self.ledgers.contains_key(sub_account)
+    }
+
+    /// Get the ledger of `sub_account` for perp `market_index`
+    pub fn market_pnl(&self, sub_account: &Pubkey, market_index: u16) -> Option<&MarketPnl> {
+        self.ledgers.get(sub_account)?.get(&market_index)
+    }
+
+    /// Iterate the per market ledgers of `sub_account`
+    pub fn markets(&self, sub_account: &Pubkey) -> impl Iterator<Item = &MarketPnl> {
+        self.ledgers
+            .get(sub_account)
+            .into_iter()
+            .flat_map(|m| m.values())
+    }
+
+    /// Aggregate PnL of `sub_account` across all markets
+    pub fn summary(&self, sub_account: &Pubkey) -> PnlSummary {
+        self.markets(sub_account)
+            .fold(PnlSummary::default(), |mut summary, m| {
+                summary.realized_pnl += m.realized_pnl;
+                summary.fees_paid += m.fees_paid;
+                summary.fees_rebated += m.fees_rebated;
+                summary.funding += m.funding;
+                summary.settled_pnl += m.settled_pnl;
+                summary.liquidation_fees += m.liquidation_fees;
+                summary.net_realized_pnl += m.net_realized_pnl();
+                summary
+            })
+    }
+
+    /// Seed (or reset) the open position state of `sub_account` from its on-chain account
+    ///
+    /// Accumulated realized pnl, fees and funding are kept
+    pub fn sync_from_user(&mut self, sub_account: &Pubkey, user: &User) {
+        let ledger = self.ledgers.entry(*sub_account).or_default();
+        for m in ledger.values_mut() {
+            m.base_asset_amount = 0;
+            m.quote_entry_amount = 0;
+            m.quote_break_even_amount = 0;
+        }
+        for p in user
+            .perp_positions
+            .iter()
+            .filter(|p| p.base_asset_amount != 0)
+        {
+            let m = ledger
+                .entry(p.market_index)
+                .or_insert_with(|| MarketPnl::new(p.market_index));
+            m.base_asset_amount = p.base_asset_amount;
+            m.quote_entry_amount = p.quote_entry_amount;
+            m.quote_break_even_amount = p.quote_break_even_amount;
+        }
+    }
+
+    /// Compare tracked positions of `sub_account` against its on-chain account
+    ///
+    /// Returns an entry for every market open either on-chain or in the tracker
+    pub fn reconcile(&self, sub_account: &Pubkey, user: &User) -> Vec<PositionReconciliation> {
+        let empty = BTreeMap::default();
+        let ledger = self.ledgers.get(sub_account).unwrap_or(&empty);
+        let onchain: BTreeMap<u16, &PerpPosition> = user
+            .perp_positions
+            .iter()
+            .filter(|p| p.base_asset_amount != 0)
+            .map(|p| (p.market_index, p))
+            .collect();
+
+        let mut market_indexes: Vec<u16> = ledger
+            .values()
+            .filter(|m| m.base_asset_amount != 0)
+            .map(|m| m.market_index)
+            .chain(onchain.keys().copied())
+            .collect();
+        market_indexes.sort_unstable();
+        market_indexes.dedup();
+
+        market_indexes
+            .into_iter()
+            .map(|market_index| {
+                let tracked = ledger.get(&market_index).cloned().unwrap_or_default();
+                let actual = onchain
+                    .get(&market_index)
+                    .copied()
+                    .copied()
+                    .unwrap_or_default();
+                PositionReconciliation {
+                    market_index,
+                    base_asset_amount_diff: tracked.base_asset_amount - actual.base_asset_amount,
+                    quote_entry_amount_diff: tracked.quote_entry_amount - actual.quote_entry_amount,
+                    quote_break_even_amount_diff: tracked.quote_break_even_amount
+                        - actual.quote_break_even_amount,
+                }
+            })
+            .collect()
+    }
+
+    fn ledger_mut(&mut self, sub_account: &Pubkey, market_index: u16) -> Option<&mut MarketPnl> {
+        Some(
+            self.ledgers
+                .get_mut(sub_account)?
+                .entry(market_index)
+                .or_insert_with(|| MarketPnl::new(market_index)),
+        )
+    }
+
+    /// Apply a drift event to the ledger, events for untracked sub-accounts are ignored
+    pub fn on_event(&mut self, event: &DriftEvent) {
+        match event {
+            DriftEvent::OrderFill {
+                maker,
+                taker,
+                base_asset_amount_filled,
+                quote_asset_amount_filled,
+                market_index,
+                market_type: MarketType::Perp,
+                ..
+            } => {
+                for user in [maker, taker].into_iter().flatten() {
+                    let Some(fill) = event.fill_side(user) else {
+                        continue;
+                    };
+                    let Some(ledger) = self.ledger_mut(user, *market_index) else {
+                        continue;
+                    };
+                    let base = *base_asset_amount_filled as i64;
+                    let quote = *quote_asset_amount_filled as i64;
+                    match fill.direction {
+                        PositionDirection::Long => ledger.apply_fill(base, -quote),
+                        PositionDirection::Short => ledger.apply_fill(-base, quote),
+                    };
+                    ledger.apply_fee(fill.fee);
+                    ledger.fills += 1;
+                }
+            }
+            DriftEvent::FundingPayment {
+                amount,
+                market_index,
+                user,
+                ..
+            } => {
+                if let Some(ledger) = self.ledger_mut(user, *market_index) {
+                    ledger.funding += amount;
+                    ledger.quote_break_even_amount += amount;
+                }
+            }
+            DriftEvent::SettlePnl {
+                user,
+                market_index,
+                pnl,
+                settle_price,
+                explanation,
+                ..
+            } => {
+                if let Some(ledger) = self.ledger_mut(user, *market_index) {
+                    if *explanation == SettlePnlExplanation::ExpiredPosition {
+                        // position is closed at the settlement price
+                        let base = ledger.base_asset_amount;
+                        let quote =
+                            (base as i128 * *settle_price as i128 / BASE_PRECISION_I128) as i64;
+                        ledger.apply_fill(-base, quote);
+                    }
+                    ledger.settled_pnl += *pnl as i64;
+                }
+            }
+            DriftEvent::Liquidation {
+                user,
+                liquidator,
+                liquidation_type: LiquidationType::LiquidatePerp,
+                liquidate_perp,
+                ..
+            } => {
+                let base = liquidate_perp.base_asset_amount;
+                let quote = liquidate_perp.quote_asset_amount;
+                if let Some(ledger) = self.ledger_mut(user, liquidate_perp.market_index) {
+                    ledger.apply_fill(base, quote);
+                    ledger.liquidation_fees += liquidate_perp.if_fee;
+                    ledger.quote_break_even_amount -= liquidate_perp.if_fee as i64;
+                }
+                // liquidator takes over the position
+                if let Some(ledger) = self.ledger_mut(liquidator, liquidate_perp.market_index) {
+                    ledger.apply_fill(-base, -quote);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        drift_idl::types::LiquidatePerpRecord,
+        event_subscriber::FillSide,
+        math::constants::{BASE_PRECISION_I64, PRICE_PRECISION_I64, QUOTE_PRECISION_I64},
+    };
+
+    fn fill(
+        maker: Pubkey,
+        taker: Pubkey,
+        taker_side: PositionDirection,
+        base: i64,
+        price: i64,
+    ) -> DriftEvent {
+        DriftEvent::test_fill(
+            Some(FillSide {
+                user: maker,
+                order_id: 1,
+                direction: match taker_side {
+                    PositionDirection::Long => PositionDirection::Short,
+                    PositionDirection::Short => PositionDirection::Long,
+                },
+                is_maker: true,
+                fee: -QUOTE_PRECISION_I64 / 100,
+                cumulative_base_filled: None,
+            }),
+            Some(FillSide {
+                user: taker,
+                order_id: 2,
+                direction: taker_side,
+                is_maker: false,
+                fee: QUOTE_PRECISION_I64 / 10,
+                cumulative_base_filled: None,
+            }),
+            (base * BASE_PRECISION_I64) as u64,
+            (base * price * QUOTE_PRECISION_I64) as u64,
+        )
+    }
+
+    #[test]
+    fn pnl_tracker_open_reduce_flip() {
+        let maker = Pubkey::new_unique();
+        let taker = Pubkey::new_unique();
+        let mut tracker = PnlTracker::new(&[taker]);
+
+        // open long 2 @ 100
+        tracker.on_event(&fill(maker, taker, PositionDirection::Long, 2, 100));
+        let m = tracker.market_pnl(&taker, 0).unwrap();
+        assert_eq!(m.base_asset_amount, 2 * BASE_PRECISION_I64);
+        assert_eq!(m.quote_entry_amount, -200 * QUOTE_PRECISION_I64);
+        assert_eq!(m.average_entry_price(), 100 * PRICE_PRECISION_I64 as u64);
+        assert_eq!(m.fees_paid, QUOTE_PRECISION_I64 as u64 / 10);
+        assert!(m.break_even_price() > m.average_entry_price());
+
+        // sell 1 @ 110, realize +10
+        tracker.on_event(&fill(maker, taker, PositionDirection::Short, 1, 110));
+        let m = tracker.market_pnl(&taker, 0).unwrap();
+        assert_eq!(m.realized_pnl, 10 * QUOTE_PRECISION_I64);
+        assert_eq!(m.base_asset_amount, BASE_PRECISION_I64);
+        assert_eq!(m.quote_entry_amount, -100 * QUOTE_PRECISION_I64);
+
+        // sell 3 @ 90, close long for -10, open short 2 @ 90
+        tracker.on_event(&fill(maker, taker, PositionDirection::Short, 3, 90));
+        let m = tracker.market_pnl(&taker, 0).unwrap();
+        assert_eq!(m.realized_pnl, 0);
+        assert_eq!(m.base_asset_amount, -2 * BASE_PRECISION_I64);
+        assert_eq!(m.quote_entry_amount, 180 * QUOTE_PRECISION_I64);
+        assert_eq!(m.average_entry_price(), 90 * PRICE_PRECISION_I64 as u64);
+        assert_eq!(
+            m.unrealized_pnl(80 * PRICE_PRECISION_I64),
+            20 * QUOTE_PRECISION_I64
+        );
+        assert_eq!(m.fills, 3);
+
+        let summary = tracker.summary(&taker);
+        assert_eq!(summary.fees_paid, 3 * QUOTE_PRECISION_I64 as u64 / 10);
+        assert_eq!(summary.net_realized_pnl, -3 * QUOTE_PRECISION_I64 / 10);
+        // maker is untracked
+        assert!(tracker.market_pnl(&maker, 0).is_none());
+    }
+
+    #[test]
+    fn pnl_tracker_maker_rebates_and_funding() {
+        let maker = Pubkey::new_unique();
+        let taker = Pubkey::new_unique();
+        let mut tracker = PnlTracker::new(&[maker]);
+
+        tracker.on_event(&fill(maker, taker, PositionDirection::Long, 1, 100));
+        tracker.on_event(&DriftEvent::FundingPayment {
+            amount: -QUOTE_PRECISION_I64,
+            market_index: 0,
+            user: maker,
+            ts: 0,
+            signature: String::new(),
+            tx_idx: 0,
+        });
+
+        let m = tracker.market_pnl(&maker, 0).unwrap();
+        assert_eq!(m.base_asset_amount, -BASE_PRECISION_I64);
+        assert_eq!(m.fees_rebated, QUOTE_PRECISION_I64 as u64 / 100);
+        assert_eq!(m.funding, -QUOTE_PRECISION_I64);
+        assert_eq!(
+            m.quote_break_even_amount,
+            100 * QUOTE_PRECISION_I64 + QUOTE_PRECISION_I64 / 100 - QUOTE_PRECISION_I64
+        );
+        assert_eq!(
+            m.net_realized_pnl(),
+            QUOTE_PRECISION_I64 / 100 - QUOTE_PRECISION_I64
+        );
+    }
+
+    #[test]
+    fn pnl_tracker_liquidation_and_reconcile() {
+        let user = Pubkey::new_unique();
+        let liquidator = Pubkey::new_unique();
+        let mut tracker = PnlTracker::new(&[user, liquidator]);
+
+        let mut account = User::default();
+        account.perp_positions[0] = PerpPosition {
+            market_index: 0,
+            base_asset_amount: 2 * BASE_PRECISION_I64,
+            quote_entry_amount: -200 * QUOTE_PRECISION_I64,
+            quote_break_even_amount: -201 * QUOTE_PRECISION_I64,
+            ..Default::default()
+        };
+        tracker.sync_from_user(&user, &account);
+        assert!(tracker
+            .reconcile(&user, &account)
+            .iter()
+            .all(|r| r.is_consistent(0)));
+
+        tracker.on_event(&DriftEvent::Liquidation {
+            user,
+            liquidator,
+            liquidation_type: LiquidationType::LiquidatePerp,
+            bankrupt: false,
+            liquidate_perp: LiquidatePerpRecord {
+                market_index: 0,
+                oracle_price: 80 * PRICE_PRECISION_I64,
+                base_asset_amount: -BASE_PRECISION_I64,
+                quote_asset_amount: 79 * QUOTE_PRECISION_I64,
+                if_fee: QUOTE_PRECISION_I64 as u64 / 2,
+                ..Default::default()
+            },
+            perp_bankruptcy: Default::default(),
+            ts: 0,
+            signature: String::new(),
+            tx_idx: 0,
+        });
+
+        let m = tracker.market_pnl(&user, 0).unwrap();
+        assert_eq!(m.base_asset_amount, BASE_PRECISION_I64);
+        assert_eq!(m.realized_pnl, -21 * QUOTE_PRECISION_I64);
+        assert_eq!(m.liquidation_fees, QUOTE_PRECISION_I64 as u64 / 2);
+
+        let l = tracker.market_pnl(&liquidator, 0).unwrap();
+        assert_eq!(l.base_asset_amount, BASE_PRECISION_I64);
+        assert_eq!(l.average_entry_price(), 79 * PRICE_PRECISION_I64 as u64);
+
+        // on-chain state not updated, reconciliation should flag the difference
+        let diffs = tracker.reconcile(&user, &account);
+        assert_eq!(diffs.len(), 1);
+        assert_eq!(diffs[0].base_asset_amount_diff, -BASE_PRECISION_I64);
+        assert!(!diffs[0].is_consistent(1_000));
+    }
+}
@@ -0,0 +1,329 @@
+//!
+//! Priority fee strategies
+//!
+//! Composable priority fee estimation e.g. market specific percentiles with EMA smoothing, caps,
+//! and adaptive bumps after txs fail to land. Strategies are selectable per tx kind (see `PriorityFeeStrategies`)
+//!
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use crate::{priority_fee_subscriber::PriorityFeeSubscriber, tx_sender::TxOutcome};
+
+/// Estimates the priority fee of a tx
+pub trait PriorityFeeStrategy: Send + Sync {
+    /// Returns the CU price in µ-lamports or None if unavailable
+    fn microlamports_per_cu(&self) -> Option<u64>;
+    /// Feedback on the outcome of a tx sent using this strategy
+    fn on_tx_outcome(&self, _outcome: &TxOutcome) {}
+}
+
+impl<T: PriorityFeeStrategy + ?Sized> PriorityFeeStrategy for Arc<T> {
+    fn microlamports_per_cu(&self) -> Option<u64> {
+        self.as_ref().microlamports_per_cu()
+    }
+    fn on_tx_outcome(&self, outcome: &TxOutcome) {
+        self.as_ref().on_tx_outcome(outcome)
+    }
+}
+
+/// Constant priority fee in µ-lamports per CU
+#[derive(Copy, Clone, Debug)]
+pub struct Fixed(pub u64);
+
+impl PriorityFeeStrategy for Fixed {
+    fn microlamports_per_cu(&self) -> Option<u64> {
+        Some(self.0)
+    }
+}
+
+/// n-th percentile of recent priority fees
+///
+/// use `PriorityFeeSubscriber::for_markets` for market specific fees
+pub struct Percentile {
+    subscriber: Arc<PriorityFeeSubscriber>,
+    percentile: f32,
+}
+
+impl Percentile {
+    /// * `subscriber` - source of recent priority fees
+    /// * `percentile` - given as decimal 0.0 < n <= 1.0
+    pub fn new(subscriber: Arc<PriorityFeeSubscriber>, percentile: f32) -> Self {
+        Self {
+            subscriber,
+            percentile,
+        }
+    }
+}
+
+impl PriorityFeeStrategy for Percentile {
+    fn microlamports_per_cu(&self) -> Option<u64> {
+        self.subscriber.priority_fee_nth_safe(self.percentile)
+    }
+}
+
+/// Exponential moving average of the `inner` strategy's estimates
+///
+/// The average is updated on each query
+pub struct Ema<S> {
+    inner: S,
+    /// weight of the latest estimate 0.0 < alpha <= 1.0
+    alpha: f64,
+    value: Mutex<Option<f64>>,
+}
+
+impl<S: PriorityFeeStrategy> Ema<S> {
+    /// * `alpha` - weight of the latest estimate, given as decimal 0.0 < n <= 1.0
+    pub fn new(inner: S, alpha: f64) -> Self {
+        Self {
+            inner,
+            alpha: alpha.clamp(f64::EPSILON, 1.0),
+            value: Mutex::default(),
+        }
+    }
+}
+
+impl<S: PriorityFeeStrategy> PriorityFeeStrategy for Ema<S> {
+    fn microlamports_per_cu(&self) -> Option<u64> {
+        let mut value = self.value.lock().expect("acquired");
+        if let Some(latest) = self.inner.microlamports_per_cu() {
+            let ema = match *value {
+                Some(ema) => self.alpha * latest as f64 + (1.0 - self.alpha) * ema,
+                None => latest as f64,
+            };
+            value.replace(ema);
+        }
+        value.map(|ema| ema.round() as u64)
+    }
+    fn on_tx_outcome(&self, outcome: &TxOutcome) {
+        self.inner.on_tx_outcome(outcome)
+    }
+}
+
+/// Bounds the `inner` strategy's estimate to [`min`, `max`]
+///
+/// `min` is used when the `inner` estimate is unavailable
+pub struct Capped<S> {
+    inner: S,
+    min: u64,
+    max: u64,
+}
+
+impl<S: PriorityFeeStrategy> Capped<S> {
+    /// * `min` - lower bound in µ-lamports per CU
+    /// * `max` - upper bound in µ-lamports per CU
+    pub fn new(inner: S, min: u64, max: u64) -> Self {
+        Self {
+            inner,
+            min,
+            max: max.max(min),
+        }
+    }
+}
+
+impl<S: PriorityFeeStrategy> PriorityFeeStrategy for Capped<S> {
+    fn microlamports_per_cu(&self) -> Option<u64> {
+        Some(
+            self.inner
+                .microlamports_per_cu()
+                .unwrap_or(self.min)
+                .clamp(self.min, self.max),
+        )
+    }
+    fn on_tx_outcome(&self, outcome: &TxOutcome) {
+        self.inner.on_tx_outcome(outcome)
+    }
+}
+
+/// Scales the `inner` strategy's estimate after txs fail to land
+///
+/// Each expired tx raises the multiplier by `step` up to `max_multiplier`,
+/// each landed tx lowers it by `step` down to 1.0
+pub struct Adaptive<S> {
+    inner: S,
+    step: f64,
+    max_multiplier: f64,
+    multiplier: Mutex<f64>,
+}
+
+impl<S: PriorityFeeStrategy> Adaptive<S> {
+    /// * `step` - multiplier change per tx outcome e.g. 0.25 = +/-25%
+    /// * `max_multiplier` - upper bound of the multiplier e.g. 4.0
+    pub fn new(inner: S, step: f64, max_multiplier: f64) -> Self {
+        Self {
+            inner,
+            step,
+            max_multiplier: max_multiplier.max(1.0),
+            multiplier: Mutex::new(1.0),
+        }
+    }
+    /// Returns the current fee multiplier
+    pub fn multiplier(&self) -> f64 {
+        *self.multiplier.lock().expect("acquired")
+    }
+}
+
+impl<S: PriorityFeeStrategy> PriorityFeeStrategy for Adaptive<S> {
+    fn microlamports_per_cu(&self) -> Option<u64> {
+        let multiplier = self.multiplier();
+        self.inner
+            .microlamports_per_cu()
+            .map(|fee| (fee as f64 * multiplier).round() as u64)
+    }
+    fn on_tx_outcome(&self, outcome: &TxOutcome) {
+        {
+            let mut multiplier = self.multiplier.lock().expect("acquired");
+            *multiplier = match outcome {
+                TxOutcome::Expired { .. } => (*multiplier + self.step).min(self.max_multiplier),
+                // tx landed (possibly failing execution) so the fee was sufficient
+                TxOutcome::Landed { .. } | TxOutcome::Failed { .. } => {
+                    (*multiplier - self.step).max(1.0)
+                }
+            };
+        }
+        self.inner.on_tx_outcome(outcome)
+    }
+}
+
+/// Kind of tx, used to select a priority fee strategy
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TxKind {
+    Fill,
+    Cancel,
+    Liquidation,
+    Other,
+}
+
+/// Priority fee strategies selected by tx kind
+///
+/// ```example(no_run)
+/// let strategies = PriorityFeeStrategies::new(Fixed(1_000))
+///     .with(TxKind::Fill, Capped::new(Adaptive::new(fill_fees, 0.25, 4.0), 1_000, 500_000))
+///     .with(TxKind::Cancel, Fixed(10_000));
+/// let tx = tx_builder
+///     .with_priority_fee(strategies.microlamports_per_cu(TxKind::Fill), None)
+///     .build();
+/// ```
+#[derive(Clone)]
+pub struct PriorityFeeStrategies {
+    default: Arc<dyn PriorityFeeStrategy>,
+    strategies: HashMap<TxKind, Arc<dyn PriorityFeeStrategy>>,
+}
+
+impl PriorityFeeStrategies {
+    /// * `default` - strategy used for tx kinds without a specific strategy
+    pub fn new(default: impl PriorityFeeStrategy + 'static) -> Self {
+        Self {
+            default: Arc::new(default),
+            strategies: Default::default(),
+        }
+    }
+    /// Set the strategy for tx `kind`
+    pub fn with(mut self, kind: TxKind, strategy: impl PriorityFeeStrategy + 'static) -> Self {
+        self.strategies.insert(kind, Arc::new(strategy));
+        self
+    }
+    /// Returns the strategy for tx `kind`
+    pub fn get(&self, kind: TxKind) -> &Arc<dyn PriorityFeeStrategy> {
+        self.strategies.get(&kind).unwrap_or(&self.default)
+    }
+    /// Returns the CU price in µ-lamports for tx `kind` (0 if unavailable)
+    pub fn microlamports_per_cu(&self, kind: TxKind) -> u64 {
+        self.get(kind).microlamports_per_cu().unwrap_or_default()
+    }
+    /// Feedback the `outcome` of a tx of `kind` e.g. from `TxSender::send`
+    pub fn on_tx_outcome(&self, kind: TxKind, outcome: &TxOutcome) {
+        self.get(kind).on_tx_outcome(outcome)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use solana_sdk::signature::Signature;
+
+    use super::*;
+
+    /// strategy returning a settable fee
+    #[derive(Default)]
+    struct Settable(AtomicU64);
+
+    impl PriorityFeeStrategy for Settable {
+        fn microlamports_per_cu(&self) -> Option<u64> {
+            match self.0.load(Ordering::Relaxed) {
+                0 => None,
+                fee => Some(fee),
+            }
+        }
+    }
+
+    fn expired() -> TxOutcome {
+        TxOutcome::Expired {
+            signature: Signature::default(),
+        }
+    }
+
+    fn landed() -> TxOutcome {
+        TxOutcome::Landed {
+            signature: Signature::default(),
+            slot: 1,
+            compute_units_consumed: None,
+        }
+    }
+
+    #[test]
+    fn ema_and_capped() {
+        let source = Arc::new(Settable::default());
+        let ema = Ema::new(Arc::clone(&source), 0.5);
+        assert_eq!(ema.microlamports_per_cu(), None);
+
+        source.0.store(100, Ordering::Relaxed);
+        assert_eq!(ema.microlamports_per_cu(), Some(100));
+        source.0.store(200, Ordering::Relaxed);
+        assert_eq!(ema.microlamports_per_cu(), Some(150));
+        // keeps last value while the source is unavailable
+        source.0.store(0, Ordering::Relaxed);
+        assert_eq!(ema.microlamports_per_cu(), Some(150));
+
+        let capped = Capped::new(Arc::clone(&source), 50, 120);
+        assert_eq!(capped.microlamports_per_cu(), Some(50));
+        source.0.store(500, Ordering::Relaxed);
+        assert_eq!(capped.microlamports_per_cu(), Some(120));
+    }
+
+    #[test]
+    fn adaptive_raises_after_non_landing() {
+        let adaptive = Adaptive::new(Fixed(1_000), 0.5, 2.0);
+        assert_eq!(adaptive.microlamports_per_cu(), Some(1_000));
+
+        adaptive.on_tx_outcome(&expired());
+        assert_eq!(adaptive.microlamports_per_cu(), Some(1_500));
+        adaptive.on_tx_outcome(&expired());
+        adaptive.on_tx_outcome(&expired());
+        assert_eq!(adaptive.microlamports_per_cu(), Some(2_000));
+
+        adaptive.on_tx_outcome(&landed());
+        assert_eq!(adaptive.multiplier(), 1.5);
+        adaptive.on_tx_outcome(&landed());
+        adaptive.on_tx_outcome(&landed());
+        assert_eq!(adaptive.microlamports_per_cu(), Some(1_000));
+    }
+
+    #[test]
+    fn strategies_by_tx_kind() {
+        let strategies = PriorityFeeStrategies::new(Fixed(1))
+            .with(TxKind::Fill, Adaptive::new(Fixed(100), 1.0, 3.0))
+            .with(TxKind::Cancel, Fixed(50));
+
+        assert_eq!(strategies.microlamports_per_cu(TxKind::Fill), 100);
+        assert_eq!(strategies.microlamports_per_cu(TxKind::Cancel), 50);
+        assert_eq!(strategies.microlamports_per_cu(TxKind::Liquidation), 1);
+
+        strategies.on_tx_outcome(TxKind::Fill, &expired());
+        strategies.on_tx_outcome(TxKind::Cancel, &expired());
+        assert_eq!(strategies.microlamports_per_cu(TxKind::Fill), 200);
+        assert_eq!(strategies.microlamports_per_cu(TxKind::Cancel), 50);
+    }
+}
@@ -8,6 +8,8 @@ use solana_rpc_client::nonblocking::rpc_client::RpcClient;
 use solana_sdk::{clock::Slot, pubkey::Pubkey};
 use tokio::sync::oneshot;
 
+use crate::{constants::ProgramData, types::MarketId};
+
 pub const DEFAULT_REFRESH_FREQUENCY: Duration = Duration::from_millis(5 * 400);
 pub const DEFAULT_SLOT_WINDOW: Slot = 30;
 
@@ -71,6 +73,11 @@ impl PriorityFeeSubscriber {
         )
     }
 
+    /// Create new `PriorityFeeSubscriber` assuming a tx will lock the accounts of `markets`
+    pub fn for_markets(endpoint: String, program_data: &ProgramData, markets: &[MarketId]) -> Self {
+        Self::new(endpoint, &program_data.markets_to_accounts(markets))
+    }
+
     /// Create new `PriorityFeeSubscriber` assuming a tx will lock `writeable_accounts`
     pub fn with_config(
         rpc_client: RpcClient,
@@ -187,6 +194,16 @@ impl PriorityFeeSubscriber {
         self.priority_fee_nth(0.5)
     }
 
+    /// Returns the n-th percentile priority fee in micro-lamports over the look-back window, or None if unsubscribed or not yet populated.
+    /// `percentile` given as decimal 0.0 < n <= 1.0
+    pub fn priority_fee_nth_safe(&self, percentile: f32) -> Option<u64> {
+        if self.is_subscribed() && !self.latest_fees.read().expect("acquired").is_empty() {
+            Some(self.priority_fee_nth(percentile))
+        } else {
+            None
+        }
+    }
+
     /// Returns the n-th percentile priority fee in micro-lamports over the look-back window.
     /// `percentile` given as decimal 0.0 < n <= 1.0
     ///
@@ -0,0 +1,589 @@
+//!
+//! Record and replay account streams
+//!
+//! Capture account, slot and transaction updates from the gRPC or Ws subscribers to a file
+//! and replay them later through the same callbacks e.g. for deterministic regression tests
+//!
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use solana_sdk::{clock::Slot, pubkey::Pubkey};
+use yellowstone_grpc_proto::{geyser::SubscribeUpdateAccountInfo, prost::Message};
+
+use crate::{
+    grpc::{
+        grpc_subscriber::AccountFilter, AccountUpdate, GrpcSubscribeOpts, OnAccountFn, OnOracleFn,
+        OnSlotFn, OnTransactionFn, TransactionUpdate,
+    },
+    types::{self, SdkError, SdkResult},
+};
+
+const LOG_TARGET: &str = "replay";
+
+/// A single recorded stream update
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum RecordedUpdate {
+    Account {
+        /// base58 account pubkey
+        pubkey: String,
+        /// base58 owner pubkey
+        owner: String,
+        lamports: u64,
+        /// base64 account data
+        data: String,
+        #[serde(default)]
+        executable: bool,
+        #[serde(default)]
+        rent_epoch: u64,
+        slot: Slot,
+        /// true if received by the oracle subscription (see `GrpcSubscribeOpts::on_oracle_update`)
+        #[serde(default)]
+        oracle: bool,
+    },
+    Slot {
+        slot: Slot,
+    },
+    Transaction {
+        slot: Slot,
+        is_vote: bool,
+        /// base64 protobuf encoded `Transaction`
+        transaction: String,
+        /// base64 protobuf encoded `TransactionStatusMeta`
+        meta: String,
+    },
+}
+
+/// A recorded update and its offset from the start of the recording
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+    /// microseconds since the recording started
+    pub t: u64,
+    #[serde(flatten)]
+    pub update: RecordedUpdate,
+}
+
+struct RecorderInner {
+    writer: Box<dyn Write + Send>,
+    started: Instant,
+}
+
+/// Tees account, slot and transaction updates to a file of JSON lines
+///
+/// ```example(no_run)
+///   let recorder = StreamRecorder::create("mainnet.jsonl")?;
+///   let opts = recorder.tee_grpc_opts(GrpcSubscribeOpts::default().usermap_on());
+///   drift.grpc_subscribe(endpoint, x_token, opts, true).await?;
+///   // ...
+///   recorder.flush()?;
+/// ```
+#[derive(Clone)]
+pub struct StreamRecorder {
+    inner: Arc<Mutex<RecorderInner>>,
+}
+
+impl StreamRecorder {
+    /// Create a recorder writing to the file at `path` (truncates existing)
+    pub fn create(path: impl AsRef<Path>) -> SdkResult<Self> {
+        let file = File::create(path).map_err(|err| SdkError::Generic(err.to_string()))?;
+        Ok(Self::new(BufWriter::new(file)))
+    }
+    /// Create a recorder writing to `writer`
+    pub fn new(writer: impl Write + Send + 'static) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(RecorderInner {
+                writer: Box::new(writer),
+                started: Instant::now(),
+            })),
+        }
+    }
+    /// Record a gRPC account update
+    pub fn record_account(&self, update: &AccountUpdate) {
+        self.write_account(update, false);
+    }
+    /// Record a gRPC oracle account update
+    pub fn record_oracle(&self, update: &AccountUpdate) {
+        self.write_account(update, true);
+    }
+    /// Record a Ws/polled account update
+    pub fn record_ws_account(&self, update: &types::AccountUpdate) {
+        self.write(RecordedUpdate::Account {
+            pubkey: update.pubkey.to_string(),
+            owner: update.owner.to_string(),
+            lamports: update.lamports,
+            data: BASE64.encode(&update.data),
+            executable: false,
+            rent_epoch: 0,
+            slot: update.slot,
+            oracle: false,
+        });
+    }
+    /// Record a slot update
+    pub fn record_slot(&self, slot: Slot) {
+        self.write(RecordedUpdate::Slot { slot });
+    }
+    /// Record a gRPC transaction update
+    pub fn record_transaction(&self, update: &TransactionUpdate) {
+        self.write(RecordedUpdate::Transaction {
+            slot: update.slot,
+            is_vote: update.is_vote,
+            transaction: BASE64.encode(update.transaction.encode_to_vec()),
+            meta: BASE64.encode(update.meta.encode_to_vec()),
+        });
+    }
+    /// Flush buffered records to the underlying writer
+    pub fn flush(&self) -> SdkResult<()> {
+        let mut inner = self.inner.lock().expect("acquired");
+        inner
+            .writer
+            .flush()
+            .map_err(|err| SdkError::Generic(err.to_string()))
+    }
+    /// Wrap a gRPC account callback, recording each update before invoking `f`
+    pub fn on_account(
+        &self,
+        f: impl Fn(&AccountUpdate) + Send + Sync + 'static,
+    ) -> impl Fn(&AccountUpdate) + Send + Sync + 'static {
+        let recorder = self.clone();
+        move |update| {
+            recorder.record_account(update);
+            f(update);
+        }
+    }
+    /// Wrap a gRPC oracle callback, recording each update before invoking `f`
+    pub fn on_oracle_update(
+        &self,
+        f: impl Fn(&AccountUpdate) + Send + Sync + 'static,
+    ) -> impl Fn(&AccountUpdate) + Send + Sync + 'static {
+        let recorder = self.clone();
+        move |update| {
+            recorder.record_oracle(update);
+            f(update);
+        }
+    }
+    /// Wrap a Ws/polled account callback, recording each update before invoking `f`
+    pub fn on_ws_account(
+        &self,
+        f: impl Fn(&types::AccountUpdate) + Send + Sync + 'static,
+    ) -> impl Fn(&types::AccountUpdate) + Send + Sync + 'static {
+        let recorder = self.clone();
+        move |update| {
+            recorder.record_ws_account(update);
+            f(update);
+        }
+    }
+    /// Wrap a slot callback, recording each update before invoking `f`
+    pub fn on_slot(
+        &self,
+        f: impl Fn(Slot) + Send + Sync + 'static,
+    ) -> impl Fn(Slot) + Send + Sync + 'static {
+        let recorder = self.clone();
+        move |slot| {
+            recorder.record_slot(slot);
+            f(slot);
+        }
+    }
+    /// Wrap a transaction callback, recording each update before invoking `f`
+    pub fn on_transaction(
+        &self,
+        f: impl Fn(&TransactionUpdate) + Send + Sync + 'static,
+    ) -> impl Fn(&TransactionUpdate) + Send + Sync + 'static {
+        let recorder = self.clone();
+        move |update| {
+            recorder.record_transaction(update);
+            f(update);
+        }
+    }
+    /// Tee all updates received by a gRPC subscription with `opts`
+    ///
+    /// Records every account (incl. oracle) update, slot update and transaction update (if `opts` subscribes to txs)
+    pub fn tee_grpc_opts(&self, mut opts: GrpcSubscribeOpts) -> GrpcSubscribeOpts {
+        let on_slot = opts.on_slot.take();
+        let on_oracle = opts.on_oracle_update.take();
+        opts = opts.on_oracle_update(self.on_oracle_update(move |update| {
+            if let Some(f) = on_oracle.as_ref() {
+                f(update);
+            }
+        }));
+        opts = opts.on_slot(self.on_slot(move |slot| {
+            if let Some(f) = on_slot.as_ref() {
+                f(slot);
+            }
+        }));
+        if let Some(on_transaction) = opts.on_transaction.take() {
+            opts.on_transaction = Some(Box::new(self.on_transaction(on_transaction)));
+        }
+        let recorder = self.clone();
+        opts.on_account(AccountFilter::firehose(), move |update| {
+            recorder.record_account(update)
+        })
+    }
+    fn write_account(&self, update: &AccountUpdate, oracle: bool) {
+        self.write(RecordedUpdate::Account {
+            pubkey: update.pubkey.to_string(),
+            owner: update.owner.to_string(),
+            lamports: update.lamports,
+            data: BASE64.encode(update.data),
+            executable: update.executable,
+            rent_epoch: update.rent_epoch,
+            slot: update.slot,
+            oracle,
+        });
+    }
+    fn write(&self, update: RecordedUpdate) {
+        let mut inner = self.inner.lock().expect("acquired");
+        let record = Record {
+            t: inner.started.elapsed().as_micros() as u64,
+            update,
+        };
+        let res = serde_json::to_writer(&mut inner.writer, &record)
+            .map_err(std::io::Error::from)
+            .and_then(|_| inner.writer.write_all(b"\n"));
+        if let Err(err) = res {
+            warn!(target: LOG_TARGET, "failed to write record: {err:?}");
+        }
+    }
+}
+
+/// Pacing of a replay
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum ReplaySpeed {
+    /// replay with the original timing between updates
+    #[default]
+    Realtime,
+    /// replay `n` times faster than the original timing
+    Accelerated(f64),
+    /// replay updates back-to-back without delay
+    Unthrottled,
+}
+
+/// Replays a recorded stream through gRPC style callbacks
+///
+/// ```example(no_run)
+///   let replay = StreamReplay::open("mainnet.jsonl")?
+///       .speed(ReplaySpeed::Unthrottled)
+///       .on_account(AccountFilter::firehose(), account_map.on_account_fn())
+///       .on_slot(move |slot| println!("slot: {slot}"));
+///   let n = replay.run().await?;
+/// ```
+#[derive(Default)]
+pub struct StreamReplay {
+    records: Vec<Record>,
+    speed: ReplaySpeed,
+    on_slot: Option<Box<OnSlotFn>>,
+    on_account: Vec<(AccountFilter, Box<OnAccountFn>)>,
+    on_oracle_update: Option<Box<OnOracleFn>>,
+    on_transaction: Option<Box<OnTransactionFn>>,
+}
+
+impl StreamReplay {
+    /// Load a recording from the file at `path`
+    pub fn open(path: impl AsRef<Path>) -> SdkResult<Self> {
+        let file = File::open(path).map_err(|err| SdkError::Generic(err.to_string()))?;
+        Self::from_reader(BufReader::new(file))
+    }
+    /// Load a recording from `reader`
+    pub fn from_reader(reader: impl BufRead) -> SdkResult<Self> {
+        let mut records = Vec::new();
+        for line in reader.lines() {
+            let line = line.map_err(|err| SdkError::Generic(err.to_string()))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            records.push(serde_json::from_str(&line).map_err(|_| SdkError::Deserializing)?);
+        }
+        Ok(Self::new(records))
+    }
+    /// Create a replay of `records`
+    pub fn new(records: Vec<Record>) -> Self {
+        Self {
+            records,
+            ..Default::default()
+        }
+    }
+    /// The recorded updates
+    pub fn records(&self) -> &[Record] {
+        self.records.as_slice()
+    }
+    /// Set the replay pacing (default: realtime)
+    pub fn speed(mut self, speed: ReplaySpeed) -> Self {
+        self.speed = speed;
+        self
+    }
+    /// Set a callback to invoke on replayed slot updates
+    pub fn on_slot(mut self, on_slot: impl Fn(Slot) + Send + Sync + 'static) -> Self {
+        self.on_slot = Some(Box::new(on_slot));
+        self
+    }
+    /// Register a callback for replayed account updates matching `filter`
+    pub fn on_account(
+        mut self,
+        filter: AccountFilter,
+        callback: impl Fn(&AccountUpdate) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_account.push((filter, Box::new(callback)));
+        self
+    }
+    /// Set a callback to invoke on replayed oracle account updates
+    ///
+    /// oracle updates are not passed to `on_account` callbacks, as with a live gRPC subscription
+    pub fn on_oracle_update(
+        mut self,
+        on_oracle_update: impl Fn(&AccountUpdate) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_oracle_update = Some(Box::new(on_oracle_update));
+        self
+    }
+    /// Set a callback to invoke on replayed transaction updates
+    pub fn on_transaction(
+        mut self,
+        on_transaction: impl Fn(&TransactionUpdate) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_transaction = Some(Box::new(on_transaction));
+        self
+    }
+    /// Drive the custom slot, account, oracle and transaction callbacks of `opts`
+    pub fn with_grpc_opts(mut self, opts: GrpcSubscribeOpts) -> Self {
+        self.on_slot = opts.on_slot;
+        self.on_account.extend(opts.on_account.unwrap_or_default());
+        self.on_oracle_update = opts.on_oracle_update;
+        self.on_transaction = opts.on_transaction;
+        self
+    }
+    /// Replay all records in order, invoking the registered callbacks
+    ///
+    /// Returns the number of updates replayed
+    pub async fn run(&self) -> SdkResult<usize> {
+        let start = tokio::time::Instant::now();
+        for record in &self.records {
+            let offset = Duration::from_micros(record.t);
+            match self.speed {
+                ReplaySpeed::Realtime => tokio::time::sleep_until(start + offset).await,
+                ReplaySpeed::Accelerated(n) => {
+                    tokio::time::sleep_until(start + offset.div_f64(n.max(f64::EPSILON))).await
+                }
+                ReplaySpeed::Unthrottled => (),
+            }
+            self.dispatch(&record.update)?;
+        }
+
+        Ok(self.records.len())
+    }
+    fn dispatch(&self, update: &RecordedUpdate) -> SdkResult<()> {
+        match update {
+            RecordedUpdate::Slot { slot } => {
+                if let Some(f) = self.on_slot.as_ref() {
+                    f(*slot);
+                }
+            }
+            RecordedUpdate::Account {
+                pubkey,
+                owner,
+                lamports,
+                data,
+                executable,
+                rent_epoch,
+                slot,
+                oracle,
+            } => {
+                if self.on_account.is_empty() && self.on_oracle_update.is_none() {
+                    return Ok(());
+                }
+                let pubkey: Pubkey = pubkey.parse().map_err(|_| SdkError::Deserializing)?;
+                let owner: Pubkey = owner.parse().map_err(|_| SdkError::Deserializing)?;
+                let data = BASE64.decode(data).map_err(|_| SdkError::Deserializing)?;
+                // filters match on the raw geyser account
+                let account = SubscribeUpdateAccountInfo {
+                    pubkey: pubkey.to_bytes().to_vec(),
+                    lamports: *lamports,
+                    owner: owner.to_bytes().to_vec(),
+                    executable: *executable,
+                    rent_epoch: *rent_epoch,
+                    data,
+                    ..Default::default()
+                };
+                let update = AccountUpdate {
+                    pubkey,
+                    lamports: *lamports,
+                    data: &account.data,
+                    owner,
+                    executable: *executable,
+                    rent_epoch: *rent_epoch,
+                    slot: *slot,
+                };
+                if *oracle {
+                    if let Some(f) = self.on_oracle_update.as_ref() {
+                        f(&update);
+                    }
+                    return Ok(());
+                }
+                for (filter, f) in &self.on_account {
+                    if filter.matches(&pubkey, &account) {
+                        f(&update);
+                    }
+                }
+            }
+            RecordedUpdate::Transaction {
+                slot,
+                is_vote,
+                transaction,
+                meta,
+            } => {
+                if let Some(f) = self.on_transaction.as_ref() {
+                    let decode =
+                        |b64: &str| BASE64.decode(b64).map_err(|_| SdkError::Deserializing);
+                    let update = TransactionUpdate {
+                        slot: *slot,
+                        is_vote: *is_vote,
+                        transaction: Message::decode(decode(transaction)?.as_slice())
+                            .map_err(|_| SdkError::Deserializing)?,
+                        meta: Message::decode(decode(meta)?.as_slice())
+                            .map_err(|_| SdkError::Deserializing)?,
+                    };
+                    f(&update);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+    use anchor_lang::Discriminator;
+
+    use super::*;
+    use crate::{constants, types::accounts::User};
+
+    /// `Write` handle to a shared buffer
+    #[derive(Clone, Default)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn record_and_replay_round_trip() {
+        let buf = SharedBuf::default();
+        let recorder = StreamRecorder::new(buf.clone());
+
+        let user = Pubkey::new_unique();
+        let mut user_data = User::DISCRIMINATOR.to_vec();
+        user_data.extend_from_slice(&[1, 2, 3, 4]);
+        let other = Pubkey::new_unique();
+
+        let on_slot = recorder.on_slot(|_| {});
+        on_slot(100);
+        recorder.record_account(&AccountUpdate {
+            pubkey: user,
+            lamports: 1_000,
+            data: &user_data,
+            owner: constants::PROGRAM_ID,
+            executable: false,
+            rent_epoch: 5,
+            slot: 100,
+        });
+        recorder.record_ws_account(&types::AccountUpdate {
+            pubkey: other,
+            owner: Pubkey::default(),
+            lamports: 1,
+            data: vec![0_u8; 16],
+            slot: 101,
+        });
+        let oracle = Pubkey::new_unique();
+        recorder.record_oracle(&AccountUpdate {
+            pubkey: oracle,
+            lamports: 1,
+            data: &[7_u8; 8],
+            owner: Pubkey::new_unique(),
+            executable: false,
+            rent_epoch: 0,
+            slot: 101,
+        });
+        on_slot(101);
+        recorder.flush().unwrap();
+
+        let recording = buf.0.lock().unwrap().clone();
+        let replay = StreamReplay::from_reader(recording.as_slice()).unwrap();
+        assert_eq!(replay.records().len(), 5);
+        assert!(replay.records().windows(2).all(|w| w[0].t <= w[1].t));
+
+        let last_slot = Arc::new(AtomicU64::default());
+        let all_accounts = Arc::new(AtomicUsize::default());
+        let users = Arc::new(Mutex::new(Vec::<(Pubkey, Vec<u8>, u64)>::default()));
+        let oracles = Arc::new(Mutex::new(Vec::<Pubkey>::default()));
+        let opts = GrpcSubscribeOpts::default().on_oracle_update({
+            let oracles = Arc::clone(&oracles);
+            move |update| oracles.lock().unwrap().push(update.pubkey)
+        });
+        let replay = replay
+            .with_grpc_opts(opts)
+            .speed(ReplaySpeed::Unthrottled)
+            .on_slot({
+                let last_slot = Arc::clone(&last_slot);
+                move |slot| last_slot.store(slot, Ordering::Relaxed)
+            })
+            .on_account(AccountFilter::firehose(), {
+                let all_accounts = Arc::clone(&all_accounts);
+                move |_| {
+                    all_accounts.fetch_add(1, Ordering::Relaxed);
+                }
+            })
+            .on_account(
+                AccountFilter::partial().with_discriminator(User::DISCRIMINATOR),
+                {
+                    let users = Arc::clone(&users);
+                    move |update| {
+                        users.lock().unwrap().push((
+                            update.pubkey,
+                            update.data.to_vec(),
+                            update.slot,
+                        ))
+                    }
+                },
+            );
+
+        assert_eq!(replay.run().await.unwrap(), 5);
+        assert_eq!(last_slot.load(Ordering::Relaxed), 101);
+        assert_eq!(all_accounts.load(Ordering::Relaxed), 2);
+        assert_eq!(*users.lock().unwrap(), vec![(user, user_data, 100)]);
+        assert_eq!(*oracles.lock().unwrap(), vec![oracle]);
+    }
+
+    #[tokio::test]
+    async fn replay_accelerated_timing() {
+        let records = vec![
+            Record {
+                t: 0,
+                update: RecordedUpdate::Slot { slot: 1 },
+            },
+            Record {
+                t: 1_000_000,
+                update: RecordedUpdate::Slot { slot: 2 },
+            },
+        ];
+        let start = Instant::now();
+        let replay = StreamReplay::new(records).speed(ReplaySpeed::Accelerated(100.0));
+        assert_eq!(replay.run().await.unwrap(), 2);
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(10));
+        assert!(elapsed < Duration::from_millis(500));
+    }
+}
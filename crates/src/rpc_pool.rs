@@ -0,0 +1,525 @@
+//!
+//! RPC endpoint pool
+//!
+//! Tracks the health of multiple RPC endpoints (latency, error rate, slot lag), routing reads to the healthiest
+//! endpoint and broadcasting txs to all endpoints. Use via `RpcPool::rpc_client` or `DriftClient::new_with_rpc_pool`
+//!
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use futures_util::future::{join_all, select_ok};
+use log::warn;
+use serde_json::{json, Value};
+use solana_rpc_client::{
+    http_sender::HttpSender,
+    nonblocking::rpc_client::RpcClient,
+    rpc_client::RpcClientConfig,
+    rpc_sender::{RpcSender, RpcTransportStats},
+};
+use solana_rpc_client_api::{
+    client_error::{ErrorKind as ClientErrorKind, Result as ClientResult},
+    request::{RpcError, RpcRequest},
+};
+use solana_sdk::{clock::Slot, commitment_config::CommitmentConfig};
+use tokio::sync::oneshot;
+
+use crate::{types::SdkResult, utils::get_ws_url};
+
+/// JSON-RPC error codes indicating the node (rather than the request) is at fault
+const NODE_ERROR_CODES: &[i64] = &[
+    -32004, // block not available
+    -32005, // node unhealthy
+    -32016, // min context slot not reached
+];
+
+/// Config for `RpcPool`
+#[derive(Clone, Debug)]
+pub struct RpcPoolConfig {
+    /// commitment of `RpcClient`s created by the pool
+    pub commitment: CommitmentConfig,
+    /// interval between endpoint health checks
+    pub health_check_interval: Duration,
+    /// max. slots an endpoint may trail the most recent endpoint and be considered healthy
+    pub max_slot_lag: u64,
+    /// max. error rate (0.0 - 1.0) of a healthy endpoint
+    pub max_error_rate: f64,
+    /// weight of the latest sample in latency and error rate averages
+    pub ema_alpha: f64,
+    /// max. time to wait for an endpoint response, timeouts count as endpoint errors
+    pub request_timeout: Duration,
+}
+
+impl Default for RpcPoolConfig {
+    fn default() -> Self {
+        Self {
+            commitment: CommitmentConfig::default(),
+            health_check_interval: Duration::from_secs(5),
+            max_slot_lag: 25,
+            max_error_rate: 0.25,
+            ema_alpha: 0.2,
+            request_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Health metrics of an RPC endpoint
+#[derive(Clone, Debug, Default)]
+pub struct EndpointHealth {
+    /// avg. request latency
+    pub latency: Duration,
+    /// avg. error rate 0.0 - 1.0
+    pub error_rate: f64,
+    /// latest slot observed by health checks
+    pub slot: Slot,
+    /// slots behind the most recent endpoint
+    pub slot_lag: u64,
+    /// total requests
+    pub requests: u64,
+    /// total failed requests
+    pub errors: u64,
+}
+
+impl EndpointHealth {
+    fn record(&mut self, alpha: f64, latency: Duration, is_error: bool) {
+        if self.requests == 0 {
+            self.latency = latency;
+        } else {
+            self.latency = latency.mul_f64(alpha) + self.latency.mul_f64(1.0 - alpha);
+        }
+        self.error_rate = alpha * (is_error as u8 as f64) + (1.0 - alpha) * self.error_rate;
+        self.requests += 1;
+        self.errors += is_error as u64;
+    }
+}
+
+struct Endpoint {
+    url: String,
+    sender: Box<dyn RpcSender + Send + Sync>,
+    health: Mutex<EndpointHealth>,
+}
+
+/// Pool of RPC endpoints with health tracking and failover
+///
+/// ```example(no_run)
+/// let pool = RpcPool::new(&[provider_a, provider_b, provider_c], RpcPoolConfig::default()).subscribe();
+/// let client = DriftClient::new_with_rpc_pool(Context::MainNet, pool, wallet).await?;
+/// ```
+pub struct RpcPool {
+    config: RpcPoolConfig,
+    endpoints: Vec<Endpoint>,
+    /// health check unsubscriber handle
+    unsub: Mutex<Option<oneshot::Sender<()>>>,
+}
+
+impl RpcPool {
+    /// Create a new `RpcPool` over HTTP endpoint `urls`
+    pub fn new(urls: &[String], config: RpcPoolConfig) -> Self {
+        Self::with_senders(
+            urls.iter()
+                .map(|url| Box::new(HttpSender::new(url)) as Box<dyn RpcSender + Send + Sync>)
+                .collect(),
+            config,
+        )
+    }
+
+    /// Create a new `RpcPool` over custom RPC `senders`
+    pub fn with_senders(
+        senders: Vec<Box<dyn RpcSender + Send + Sync>>,
+        config: RpcPoolConfig,
+    ) -> Self {
+        assert!(!senders.is_empty(), "RpcPool requires an endpoint");
+        Self {
+            config,
+            endpoints: senders
+                .into_iter()
+                .map(|sender| Endpoint {
+                    url: sender.url(),
+                    sender,
+                    health: Mutex::default(),
+                })
+                .collect(),
+            unsub: Mutex::default(),
+        }
+    }
+
+    /// Start the endpoint health check task
+    ///
+    /// The task stops on `unsubscribe` or once all handles to the pool are dropped
+    ///
+    /// Returns a handle to the pool
+    pub fn subscribe(self) -> Arc<RpcPool> {
+        let (unsub_tx, mut unsub_rx) = oneshot::channel();
+        {
+            let mut guard = self.unsub.try_lock().expect("uncontested");
+            guard.replace(unsub_tx);
+        }
+
+        let health_check_interval = self.config.health_check_interval;
+        let arc = Arc::new(self);
+        tokio::spawn({
+            let pool = Arc::downgrade(&arc);
+            async move {
+                let mut refresh = tokio::time::interval(health_check_interval);
+                loop {
+                    let _ = refresh.tick().await;
+                    let Some(this) = pool.upgrade() else {
+                        break;
+                    };
+                    this.check_health().await;
+                    if unsub_rx.try_recv().is_ok() {
+                        warn!(target: "rpc", "unsubscribing RPC pool health checks");
+                        break;
+                    }
+                }
+            }
+        });
+
+        arc
+    }
+
+    /// Stop the endpoint health check task
+    pub fn unsubscribe(&self) {
+        let mut guard = self.unsub.lock().expect("acquired");
+        if let Some(unsub) = guard.take() {
+            if unsub.send(()).is_err() {
+                log::error!("couldn't unsubscribe");
+            }
+        }
+    }
+
+    /// Returns an `RpcClient` backed by the pool
+    pub fn rpc_client(self: &Arc<Self>) -> RpcClient {
+        RpcClient::new_sender(
+            PoolSender(Arc::clone(self)),
+            RpcClientConfig::with_commitment(self.config.commitment),
+        )
+    }
+
+    /// Returns the Ws URLs of the pool endpoints, healthiest first
+    pub fn ws_urls(&self) -> SdkResult<Vec<String>> {
+        self.ranked()
+            .into_iter()
+            .map(|idx| get_ws_url(&self.endpoints[idx].url))
+            .collect()
+    }
+
+    /// Returns the URL and health of each endpoint
+    pub fn health(&self) -> Vec<(String, EndpointHealth)> {
+        self.endpoints
+            .iter()
+            .map(|e| (e.url.clone(), e.health.lock().expect("acquired").clone()))
+            .collect()
+    }
+
+    /// Returns the URL of the healthiest endpoint
+    pub fn healthiest(&self) -> &str {
+        self.endpoints[self.ranked()[0]].url.as_str()
+    }
+
+    /// Returns endpoint indexes, healthy endpoints first then by latency
+    fn ranked(&self) -> Vec<usize> {
+        let health: Vec<EndpointHealth> = self
+            .endpoints
+            .iter()
+            .map(|e| e.health.lock().expect("acquired").clone())
+            .collect();
+        let mut ranked: Vec<usize> = (0..self.endpoints.len()).collect();
+        ranked.sort_by_key(|idx| {
+            let h = &health[*idx];
+            let unhealthy =
+                h.slot_lag > self.config.max_slot_lag || h.error_rate > self.config.max_error_rate;
+            (unhealthy, h.latency)
+        });
+
+        ranked
+    }
+
+    /// Send `request` to endpoint `idx` and record its health
+    async fn send_to(&self, idx: usize, request: RpcRequest, params: Value) -> ClientResult<Value> {
+        let endpoint = &self.endpoints[idx];
+        let started = Instant::now();
+        let result = tokio::time::timeout(
+            self.config.request_timeout,
+            endpoint.sender.send(request, params),
+        )
+        .await
+        .unwrap_or_else(|_| {
+            Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "request timed out").into())
+        });
+        let is_error = result.as_ref().is_err_and(is_node_error);
+        endpoint.health.lock().expect("acquired").record(
+            self.config.ema_alpha,
+            started.elapsed(),
+            is_error,
+        );
+
+        result
+    }
+
+    /// Fetch the latest slot of each endpoint and update slot lag
+    async fn check_health(&self) {
+        let slots = join_all((0..self.endpoints.len()).map(|idx| {
+            self.send_to(
+                idx,
+                RpcRequest::GetSlot,
+                json!([{ "commitment": "processed" }]),
+            )
+        }))
+        .await;
+
+        let slots: Vec<Option<Slot>> = slots
+            .into_iter()
+            .map(|r| r.ok().and_then(|v| v.as_u64()))
+            .collect();
+        let max_slot = slots.iter().flatten().max().copied().unwrap_or_default();
+        for (endpoint, slot) in self.endpoints.iter().zip(slots) {
+            let mut health = endpoint.health.lock().expect("acquired");
+            if let Some(slot) = slot {
+                health.slot = slot;
+            }
+            health.slot_lag = max_slot.saturating_sub(health.slot);
+        }
+    }
+
+    /// Send `request` to the healthiest endpoint, failing over to the next on node errors
+    async fn route(&self, request: RpcRequest, params: Value) -> ClientResult<Value> {
+        let ranked = self.ranked();
+        let mut result = None;
+        for idx in ranked {
+            let response = self.send_to(idx, request, params.clone()).await;
+            match response {
+                Err(ref err) if is_node_error(err) => {
+                    warn!(target: "rpc", "{request} failed on {}: {err:?}", self.endpoints[idx].url);
+                    result = Some(response);
+                }
+                response => return response,
+            }
+        }
+
+        result.expect("at least 1 endpoint")
+    }
+
+    /// Send `request` to all endpoints, returns the first success or the last error
+    ///
+    /// Sends continue in the background after the first success so every endpoint receives the request
+    async fn broadcast(
+        self: &Arc<Self>,
+        request: RpcRequest,
+        params: Value,
+    ) -> ClientResult<Value> {
+        let sends = (0..self.endpoints.len()).map(|idx| {
+            let this = Arc::clone(self);
+            let params = params.clone();
+            let send = tokio::spawn(async move { this.send_to(idx, request, params).await });
+            Box::pin(async move {
+                send.await
+                    .unwrap_or_else(|err| Err(std::io::Error::other(err).into()))
+            })
+        });
+
+        select_ok(sends).await.map(|(value, _pending)| value)
+    }
+}
+
+/// True if the `err` is attributable to the endpoint rather than the request
+fn is_node_error(err: &solana_rpc_client_api::client_error::Error) -> bool {
+    match err.kind() {
+        ClientErrorKind::RpcError(RpcError::RpcResponseError { code, .. }) => {
+            NODE_ERROR_CODES.contains(code)
+        }
+        ClientErrorKind::Io(_) | ClientErrorKind::Reqwest(_) => true,
+        _ => false,
+    }
+}
+
+/// `RpcSender` routing requests over an `RpcPool`
+struct PoolSender(Arc<RpcPool>);
+
+#[async_trait]
+impl RpcSender for PoolSender {
+    async fn send(&self, request: RpcRequest, params: Value) -> ClientResult<Value> {
+        match request {
+            RpcRequest::SendTransaction => self.0.broadcast(request, params).await,
+            _ => self.0.route(request, params).await,
+        }
+    }
+
+    fn get_transport_stats(&self) -> RpcTransportStats {
+        self.0
+            .endpoints
+            .iter()
+            .map(|e| e.sender.get_transport_stats())
+            .fold(RpcTransportStats::default(), |mut acc, stats| {
+                acc.request_count += stats.request_count;
+                acc.elapsed_time += stats.elapsed_time;
+                acc.rate_limited_time += stats.rate_limited_time;
+                acc
+            })
+    }
+
+    fn url(&self) -> String {
+        self.0.healthiest().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    /// sender returning fixed responses
+    struct TestSender {
+        url: String,
+        slot: u64,
+        fails: bool,
+        sent: Arc<AtomicUsize>,
+    }
+
+    impl TestSender {
+        fn new(
+            url: &str,
+            slot: u64,
+            fails: bool,
+        ) -> (Box<dyn RpcSender + Send + Sync>, Arc<AtomicUsize>) {
+            let sent = Arc::new(AtomicUsize::default());
+            (
+                Box::new(Self {
+                    url: url.into(),
+                    slot,
+                    fails,
+                    sent: Arc::clone(&sent),
+                }),
+                sent,
+            )
+        }
+    }
+
+    #[async_trait]
+    impl RpcSender for TestSender {
+        async fn send(&self, request: RpcRequest, _params: Value) -> ClientResult<Value> {
+            self.sent.fetch_add(1, Ordering::Relaxed);
+            if self.fails {
+                return Err(std::io::Error::other("connection refused").into());
+            }
+            match request {
+                RpcRequest::GetSlot => Ok(json!(self.slot)),
+                _ => Ok(json!(self.url)),
+            }
+        }
+        fn get_transport_stats(&self) -> RpcTransportStats {
+            RpcTransportStats::default()
+        }
+        fn url(&self) -> String {
+            self.url.clone()
+        }
+    }
+
+    /// sender that never responds
+    struct HangingSender;
+
+    #[async_trait]
+    impl RpcSender for HangingSender {
+        async fn send(&self, _request: RpcRequest, _params: Value) -> ClientResult<Value> {
+            std::future::pending().await
+        }
+        fn get_transport_stats(&self) -> RpcTransportStats {
+            RpcTransportStats::default()
+        }
+        fn url(&self) -> String {
+            "https://hanging.xyz".into()
+        }
+    }
+
+    #[tokio::test]
+    async fn rpc_pool_routes_to_healthy_endpoint() {
+        let (a, _) = TestSender::new("https://a.xyz", 100, true);
+        let (b, _) = TestSender::new("https://b.xyz", 50, false);
+        let (c, _) = TestSender::new("https://c.xyz", 100, false);
+        let pool = Arc::new(RpcPool::with_senders(
+            vec![a, b, c],
+            RpcPoolConfig {
+                max_slot_lag: 10,
+                ..Default::default()
+            },
+        ));
+
+        pool.check_health().await;
+        let health = pool.health();
+        assert_eq!(health[0].1.errors, 1);
+        assert_eq!(health[1].1.slot_lag, 50);
+        assert_eq!(health[2].1.slot_lag, 0);
+        assert_eq!(pool.healthiest(), "https://c.xyz");
+        assert_eq!(pool.ws_urls().unwrap()[0].as_str(), "wss://c.xyz");
+
+        let response = pool.route(RpcRequest::GetBalance, json!([])).await;
+        assert_eq!(response.unwrap(), json!("https://c.xyz"));
+    }
+
+    #[tokio::test]
+    async fn rpc_pool_fails_over_and_broadcasts() {
+        let (a, a_sent) = TestSender::new("https://a.xyz", 100, true);
+        let (b, b_sent) = TestSender::new("https://b.xyz", 100, false);
+        let (c, c_sent) = TestSender::new("https://c.xyz", 100, false);
+        let pool = Arc::new(RpcPool::with_senders(vec![a, b, c], Default::default()));
+
+        // a fails before its error rate is known
+        let response = pool.route(RpcRequest::GetBalance, json!([])).await;
+        assert!(response.is_ok());
+        assert_eq!(a_sent.load(Ordering::Relaxed), 1);
+        assert!(pool.health()[0].1.error_rate > 0.0);
+
+        let response = PoolSender(Arc::clone(&pool))
+            .send(RpcRequest::SendTransaction, json!([]))
+            .await;
+        assert!(response.is_ok());
+        // let the background sends complete
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(a_sent.load(Ordering::Relaxed), 2);
+        assert_eq!(
+            b_sent.load(Ordering::Relaxed) + c_sent.load(Ordering::Relaxed),
+            3
+        );
+    }
+
+    #[tokio::test]
+    async fn rpc_pool_times_out_requests() {
+        let (b, _) = TestSender::new("https://b.xyz", 100, false);
+        let (c, _) = TestSender::new("https://c.xyz", 100, true);
+        let pool = Arc::new(RpcPool::with_senders(
+            vec![Box::new(HangingSender), b],
+            RpcPoolConfig {
+                request_timeout: Duration::from_millis(10),
+                ..Default::default()
+            },
+        ));
+
+        let response = pool.route(RpcRequest::GetBalance, json!([])).await;
+        assert_eq!(response.unwrap(), json!("https://b.xyz"));
+        assert_eq!(pool.health()[0].1.errors, 1);
+
+        // broadcast fails instead of waiting forever
+        let pool = Arc::new(RpcPool::with_senders(
+            vec![Box::new(HangingSender), c],
+            RpcPoolConfig {
+                request_timeout: Duration::from_millis(10),
+                ..Default::default()
+            },
+        ));
+        let response = pool.broadcast(RpcRequest::SendTransaction, json!([])).await;
+        assert!(response.is_err());
+    }
+
+    #[tokio::test]
+    async fn rpc_pool_health_check_does_not_retain_pool() {
+        let (a, a_sent) = TestSender::new("https://a.xyz", 100, false);
+        let pool = RpcPool::with_senders(vec![a], Default::default()).subscribe();
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(a_sent.load(Ordering::Relaxed), 1);
+        assert_eq!(Arc::strong_count(&pool), 1);
+    }
+}
@@ -0,0 +1,307 @@
+//!
+//! Squads v4 multisig proposals
+//!
+//! Wraps drift ixs in a Squads vault transaction proposal (create + approve) instead of signing them,
+//! for sub-accounts whose authority is a multisig vault.
+//!
+//! see `TransactionBuilder::build_multisig_proposal`
+//!
+use anchor_lang::AnchorSerialize;
+use solana_sdk::{
+    account::Account,
+    compute_budget,
+    instruction::{AccountMeta, Instruction},
+    message::Message,
+    pubkey::Pubkey,
+    system_program,
+};
+
+use crate::{SdkError, SdkResult};
+
+const SEED_PREFIX: &[u8] = b"multisig";
+const SEED_VAULT: &[u8] = b"vault";
+const SEED_TRANSACTION: &[u8] = b"transaction";
+const SEED_PROPOSAL: &[u8] = b"proposal";
+
+/// anchor discriminator of the `Multisig` account
+const MULTISIG_DISCRIMINATOR: [u8; 8] = [224, 116, 121, 186, 68, 161, 79, 236];
+/// byte offset of `Multisig::transaction_index`
+const MULTISIG_TRANSACTION_INDEX_OFFSET: usize = 8 + 32 + 32 + 2 + 4;
+
+const VAULT_TRANSACTION_CREATE_DISCRIMINATOR: [u8; 8] = [48, 250, 78, 168, 208, 226, 218, 211];
+const PROPOSAL_CREATE_DISCRIMINATOR: [u8; 8] = [220, 60, 73, 224, 30, 108, 79, 159];
+const PROPOSAL_APPROVE_DISCRIMINATOR: [u8; 8] = [144, 37, 164, 136, 188, 216, 42, 248];
+
+/// Parameters of a Squads vault transaction proposal
+#[derive(Clone, Debug, PartialEq)]
+pub struct MultisigProposal {
+    /// the deployed Squads v4 program
+    pub program_id: Pubkey,
+    /// the multisig account
+    pub multisig: Pubkey,
+    /// index of the vault that acts as drift authority
+    pub vault_index: u8,
+    /// index of the new vault transaction i.e. the multisig's current `transaction_index` + 1
+    pub transaction_index: u64,
+    /// multisig member creating and approving the proposal, pays tx fees and rent
+    pub member: Pubkey,
+    /// optional memo attached to the vault transaction and approval
+    pub memo: Option<String>,
+}
+
+impl MultisigProposal {
+    /// Address of the multisig vault i.e. the drift authority
+    pub fn vault(&self) -> Pubkey {
+        derive_vault(&self.program_id, &self.multisig, self.vault_index)
+    }
+    /// Address of the vault transaction account
+    pub fn transaction(&self) -> Pubkey {
+        derive_transaction(&self.program_id, &self.multisig, self.transaction_index)
+    }
+    /// Address of the proposal account
+    pub fn proposal(&self) -> Pubkey {
+        derive_proposal(&self.program_id, &self.multisig, self.transaction_index)
+    }
+    /// Build the ixs to create the vault transaction executing `ixs`, create its proposal and approve it
+    ///
+    /// compute budget ixs should be excluded from `ixs`, they apply to the outer tx
+    pub fn ixs(&self, ixs: &[Instruction]) -> Vec<Instruction> {
+        let message = compile_vault_message(&self.vault(), ixs);
+        vec![
+            self.vault_transaction_create_ix(message),
+            self.proposal_create_ix(),
+            self.proposal_approve_ix(),
+        ]
+    }
+    fn vault_transaction_create_ix(&self, transaction_message: Vec<u8>) -> Instruction {
+        let mut data = VAULT_TRANSACTION_CREATE_DISCRIMINATOR.to_vec();
+        (
+            self.vault_index,
+            0_u8, // ephemeral signers
+            transaction_message,
+            self.memo.clone(),
+        )
+            .serialize(&mut data)
+            .expect("serializes");
+        Instruction {
+            program_id: self.program_id,
+            accounts: vec![
+                AccountMeta::new(self.multisig, false),
+                AccountMeta::new(self.transaction(), false),
+                AccountMeta::new_readonly(self.member, true),
+                AccountMeta::new(self.member, true),
+                AccountMeta::new_readonly(system_program::ID, false),
+            ],
+            data,
+        }
+    }
+    fn proposal_create_ix(&self) -> Instruction {
+        let mut data = PROPOSAL_CREATE_DISCRIMINATOR.to_vec();
+        (self.transaction_index, false)
+            .serialize(&mut data)
+            .expect("serializes");
+        Instruction {
+            program_id: self.program_id,
+            accounts: vec![
+                AccountMeta::new_readonly(self.multisig, false),
+                AccountMeta::new(self.proposal(), false),
+                AccountMeta::new_readonly(self.member, true),
+                AccountMeta::new(self.member, true),
+                AccountMeta::new_readonly(system_program::ID, false),
+            ],
+            data,
+        }
+    }
+    fn proposal_approve_ix(&self) -> Instruction {
+        let mut data = PROPOSAL_APPROVE_DISCRIMINATOR.to_vec();
+        self.memo.serialize(&mut data).expect("serializes");
+        Instruction {
+            program_id: self.program_id,
+            accounts: vec![
+                AccountMeta::new_readonly(self.multisig, false),
+                AccountMeta::new(self.member, true),
+                AccountMeta::new(self.proposal(), false),
+            ],
+            data,
+        }
+    }
+}
+
+/// Calculate the address of a multisig vault
+pub fn derive_vault(program_id: &Pubkey, multisig: &Pubkey, vault_index: u8) -> Pubkey {
+    let (vault, _bump) = Pubkey::find_program_address(
+        &[SEED_PREFIX, multisig.as_ref(), SEED_VAULT, &[vault_index]],
+        program_id,
+    );
+    vault
+}
+
+/// Calculate the address of a multisig vault transaction
+pub fn derive_transaction(
+    program_id: &Pubkey,
+    multisig: &Pubkey,
+    transaction_index: u64,
+) -> Pubkey {
+    let (transaction, _bump) = Pubkey::find_program_address(
+        &[
+            SEED_PREFIX,
+            multisig.as_ref(),
+            SEED_TRANSACTION,
+            &transaction_index.to_le_bytes(),
+        ],
+        program_id,
+    );
+    transaction
+}
+
+/// Calculate the address of a multisig proposal
+pub fn derive_proposal(program_id: &Pubkey, multisig: &Pubkey, transaction_index: u64) -> Pubkey {
+    let (proposal, _bump) = Pubkey::find_program_address(
+        &[
+            SEED_PREFIX,
+            multisig.as_ref(),
+            SEED_TRANSACTION,
+            &transaction_index.to_le_bytes(),
+            SEED_PROPOSAL,
+        ],
+        program_id,
+    );
+    proposal
+}
+
+/// Get the latest transaction index from a multisig `account`
+///
+/// Returns error if the account is not a Squads v4 multisig
+pub fn multisig_transaction_index(account: &Account) -> SdkResult<u64> {
+    if !account.data.starts_with(&MULTISIG_DISCRIMINATOR) {
+        return Err(SdkError::InvalidAccount);
+    }
+    account
+        .data
+        .get(MULTISIG_TRANSACTION_INDEX_OFFSET..MULTISIG_TRANSACTION_INDEX_OFFSET + 8)
+        .map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+        .ok_or(SdkError::Deserializing)
+}
+
+/// Returns true if `ix` belongs in the outer (proposing) tx rather than the vault transaction
+pub(crate) fn is_outer_ix(ix: &Instruction) -> bool {
+    ix.program_id == compute_budget::ID
+}
+
+/// Compile `ixs` into a Squads `VaultTransactionMessage` with `vault` as payer
+///
+/// The message format matches Solana's legacy message with u8/u16 length prefixes
+fn compile_vault_message(vault: &Pubkey, ixs: &[Instruction]) -> Vec<u8> {
+    let message = Message::new(ixs, Some(vault));
+    let header = message.header;
+    let num_keys = message.account_keys.len() as u8;
+    let num_signers = header.num_required_signatures;
+
+    let mut buf = Vec::with_capacity(256);
+    buf.extend_from_slice(&[
+        num_signers,
+        num_signers - header.num_readonly_signed_accounts,
+        num_keys - num_signers - header.num_readonly_unsigned_accounts,
+        num_keys,
+    ]);
+    for key in &message.account_keys {
+        buf.extend_from_slice(key.as_ref());
+    }
+    buf.push(message.instructions.len() as u8);
+    for ix in &message.instructions {
+        buf.push(ix.program_id_index);
+        buf.push(ix.accounts.len() as u8);
+        buf.extend_from_slice(&ix.accounts);
+        buf.extend_from_slice(&(ix.data.len() as u16).to_le_bytes());
+        buf.extend_from_slice(&ix.data);
+    }
+    // no address table lookups
+    buf.push(0);
+
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn proposal() -> MultisigProposal {
+        MultisigProposal {
+            program_id: Pubkey::new_unique(),
+            multisig: Pubkey::new_unique(),
+            vault_index: 0,
+            transaction_index: 7,
+            member: Pubkey::new_unique(),
+            memo: None,
+        }
+    }
+
+    #[test]
+    fn multisig_transaction_index_parses() {
+        let mut data = MULTISIG_DISCRIMINATOR.to_vec();
+        data.resize(MULTISIG_TRANSACTION_INDEX_OFFSET, 0);
+        data.extend_from_slice(&6_u64.to_le_bytes());
+        data.extend_from_slice(&[0_u8; 64]);
+        let account = Account {
+            data,
+            ..Default::default()
+        };
+        assert_eq!(multisig_transaction_index(&account).unwrap(), 6);
+
+        let not_multisig = Account {
+            data: vec![0_u8; 128],
+            ..Default::default()
+        };
+        assert!(multisig_transaction_index(&not_multisig).is_err());
+    }
+
+    #[test]
+    fn vault_message_layout() {
+        let vault = Pubkey::new_unique();
+        let target = Pubkey::new_unique();
+        let program = Pubkey::new_unique();
+        let ix = Instruction {
+            program_id: program,
+            accounts: vec![
+                AccountMeta::new(target, false),
+                AccountMeta::new_readonly(vault, true),
+            ],
+            data: vec![1, 2, 3],
+        };
+        let message = compile_vault_message(&vault, &[ix]);
+
+        // header: 1 signer (vault, writable as payer), 1 writable non-signer, 3 keys
+        assert_eq!(&message[..4], &[1, 1, 1, 3]);
+        assert_eq!(&message[4..36], vault.as_ref());
+        assert_eq!(&message[36..68], target.as_ref());
+        assert_eq!(&message[68..100], program.as_ref());
+        // 1 ix: program idx, 2 accounts (target, vault), 3 data bytes (u16 len), no LUTs
+        assert_eq!(&message[100..], &[1, 2, 2, 1, 0, 3, 0, 1, 2, 3, 0][..]);
+    }
+
+    #[test]
+    fn proposal_ixs() {
+        let proposal = proposal();
+        let ixs = proposal.ixs(&[]);
+        assert_eq!(ixs.len(), 3);
+        assert!(ixs.iter().all(|ix| ix.program_id == proposal.program_id));
+
+        let create = &ixs[0];
+        assert_eq!(&create.data[..8], &VAULT_TRANSACTION_CREATE_DISCRIMINATOR);
+        assert_eq!(create.accounts[1].pubkey, proposal.transaction());
+
+        let create_proposal = &ixs[1];
+        assert_eq!(&create_proposal.data[..8], &PROPOSAL_CREATE_DISCRIMINATOR);
+        assert_eq!(&create_proposal.data[8..16], &7_u64.to_le_bytes());
+        assert_eq!(create_proposal.data[16], 0); // not draft
+        assert_eq!(create_proposal.accounts[1].pubkey, proposal.proposal());
+
+        let approve = &ixs[2];
+        assert_eq!(
+            approve.data,
+            [PROPOSAL_APPROVE_DISCRIMINATOR.as_slice(), &[0]].concat()
+        );
+        assert!(approve.accounts[1].is_signer);
+        assert_eq!(approve.accounts[1].pubkey, proposal.member);
+    }
+}
@@ -0,0 +1,442 @@
+//!
+//! Swap quotes
+//!
+//! Common interface over swap aggregators (Jupiter, Titan) returning normalized quotes that
+//! can be added to a tx with `TransactionBuilder::swap`
+//!
+use futures_util::future::{join_all, BoxFuture};
+use solana_sdk::{instruction::Instruction, message::AddressLookupTableAccount, pubkey::Pubkey};
+
+#[cfg(feature = "titan")]
+use crate::{
+    constants::ASSOCIATED_TOKEN_PROGRAM_ID,
+    titan::{TitanSwapApi, TitanSwapInfo},
+};
+use crate::{
+    constants::{TOKEN_2022_PROGRAM_ID, TOKEN_PROGRAM_ID},
+    jupiter::{JupiterSwapApi, JupiterSwapInfo, TransactionConfig},
+    types::{SdkError, SdkResult},
+    DriftClient,
+};
+
+/// Swap amount semantics
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum SwapMode {
+    /// `amount` is the exact input amount
+    #[default]
+    ExactIn,
+    /// `amount` is the exact output amount
+    ExactOut,
+}
+
+/// Parameters of a swap quote request
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SwapRequest {
+    /// wallet that will execute the swap
+    pub user_authority: Pubkey,
+    /// swap amount in native units, in or out depending on `swap_mode`
+    pub amount: u64,
+    pub swap_mode: SwapMode,
+    /// spot market index of the input token
+    pub in_market: u16,
+    /// spot market index of the output token
+    pub out_market: u16,
+    /// max. slippage (bps)
+    pub slippage_bps: u16,
+    /// only consider direct routes between the tokens
+    pub only_direct_routes: Option<bool>,
+    /// comma-separated list of dexes to exclude from routing
+    pub excluded_dexes: Option<String>,
+    /// max. accounts used by the swap route
+    pub max_accounts: Option<usize>,
+}
+
+/// Swap quote normalized across providers
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SwapQuote {
+    /// name of the quoting provider
+    pub provider: &'static str,
+    /// input amount in native units
+    pub in_amount: u64,
+    /// output amount in native units
+    pub out_amount: u64,
+    /// price impact as a fraction e.g. 0.01 = 1%, if reported
+    pub price_impact_pct: Option<f64>,
+    /// venue labels of the route, if reported
+    pub route: Vec<String>,
+    /// in/out token accounts must be created before the swap
+    pub create_token_accounts: bool,
+    /// swap ixs, wrapped by `begin_swap`/`end_swap` when added to a tx
+    pub ixs: Vec<Instruction>,
+    /// lookup tables required by `ixs`
+    pub luts: Vec<AddressLookupTableAccount>,
+}
+
+impl TryFrom<JupiterSwapInfo> for SwapQuote {
+    type Error = SdkError;
+    fn try_from(value: JupiterSwapInfo) -> SdkResult<Self> {
+        let JupiterSwapInfo { quote, ixs, luts } = value;
+        // TODO: support jito bundle
+        if !ixs.other_instructions.is_empty() {
+            return Err(SdkError::Generic(
+                "jupiter swap unsupported ix: Jito tip".into(),
+            ));
+        }
+
+        let mut swap_ixs = vec![ixs.swap_instruction];
+        // support SOL unwrap ixs, ignore account delete/reclaim ixs
+        swap_ixs.extend(ixs.cleanup_instruction.filter(|ix| {
+            ix.program_id != TOKEN_PROGRAM_ID && ix.program_id != TOKEN_2022_PROGRAM_ID
+        }));
+
+        Ok(Self {
+            provider: JupiterProvider::NAME,
+            in_amount: quote.in_amount,
+            out_amount: quote.out_amount,
+            price_impact_pct: quote.price_impact_pct.to_string().parse().ok(),
+            route: quote
+                .route_plan
+                .iter()
+                .map(|step| step.swap_info.label.clone())
+                .collect(),
+            // jupiter setup ixs imply account creation is required, provide our own creation ixs
+            create_token_accounts: !ixs.setup_instructions.is_empty(),
+            ixs: swap_ixs,
+            luts,
+        })
+    }
+}
+
+#[cfg(feature = "titan")]
+impl From<TitanSwapInfo> for SwapQuote {
+    fn from(value: TitanSwapInfo) -> Self {
+        let TitanSwapInfo { quote, ixs, luts } = value;
+        Self {
+            provider: TitanProvider::NAME,
+            in_amount: quote.in_amount,
+            out_amount: quote.out_amount,
+            price_impact_pct: None,
+            route: Vec::new(),
+            create_token_accounts: true,
+            ixs: ixs
+                .instructions
+                .into_iter()
+                .filter(|ix| {
+                    ix.program_id != TOKEN_PROGRAM_ID
+                        && ix.program_id != TOKEN_2022_PROGRAM_ID
+                        && ix.program_id != ASSOCIATED_TOKEN_PROGRAM_ID
+                })
+                .collect(),
+            luts,
+        }
+    }
+}
+
+/// Provides swap quotes e.g. from an aggregator API
+pub trait SwapQuoteProvider: Send + Sync {
+    /// Name of the provider
+    fn name(&self) -> &'static str;
+    /// Fetch a quote with swap ixs for `request`
+    fn quote<'a>(&'a self, request: &'a SwapRequest) -> BoxFuture<'a, SdkResult<SwapQuote>>;
+}
+
+/// Jupiter swap quotes, requires `JUPITER_API_KEY` env var
+pub struct JupiterProvider {
+    drift: DriftClient,
+    transaction_config: Option<TransactionConfig>,
+}
+
+impl JupiterProvider {
+    pub const NAME: &'static str = "jupiter";
+    pub fn new(drift: DriftClient) -> Self {
+        Self {
+            drift,
+            transaction_config: None,
+        }
+    }
+    /// Set the Jupiter swap tx config
+    pub fn with_transaction_config(mut self, config: TransactionConfig) -> Self {
+        self.transaction_config = Some(config);
+        self
+    }
+}
+
+impl SwapQuoteProvider for JupiterProvider {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+    fn quote<'a>(&'a self, request: &'a SwapRequest) -> BoxFuture<'a, SdkResult<SwapQuote>> {
+        Box::pin(async move {
+            let swap_mode = match request.swap_mode {
+                SwapMode::ExactIn => crate::jupiter::SwapMode::ExactIn,
+                SwapMode::ExactOut => crate::jupiter::SwapMode::ExactOut,
+            };
+            let info = self
+                .drift
+                .jupiter_swap_query(
+                    &request.user_authority,
+                    request.amount,
+                    swap_mode,
+                    request.in_market,
+                    request.out_market,
+                    request.slippage_bps,
+                    request.only_direct_routes,
+                    request.excluded_dexes.clone(),
+                    self.transaction_config.clone(),
+                    request.max_accounts,
+                )
+                .await?;
+            SwapQuote::try_from(info)
+        })
+    }
+}
+
+/// Titan swap quotes, requires `TITAN_AUTH_TOKEN` env var
+#[cfg(feature = "titan")]
+pub struct TitanProvider {
+    drift: DriftClient,
+    providers: Option<crate::titan::Provider>,
+}
+
+#[cfg(feature = "titan")]
+impl TitanProvider {
+    pub const NAME: &'static str = "titan";
+    pub fn new(drift: DriftClient) -> Self {
+        Self {
+            drift,
+            providers: None,
+        }
+    }
+    /// Restrict the Titan liquidity providers
+    pub fn with_providers(mut self, providers: crate::titan::Provider) -> Self {
+        self.providers = Some(providers);
+        self
+    }
+}
+
+#[cfg(feature = "titan")]
+impl SwapQuoteProvider for TitanProvider {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+    fn quote<'a>(&'a self, request: &'a SwapRequest) -> BoxFuture<'a, SdkResult<SwapQuote>> {
+        Box::pin(async move {
+            let swap_mode = match request.swap_mode {
+                SwapMode::ExactIn => crate::titan::SwapMode::ExactIn,
+                SwapMode::ExactOut => crate::titan::SwapMode::ExactOut,
+            };
+            let info = self
+                .drift
+                .titan_swap_query(
+                    &request.user_authority,
+                    request.amount,
+                    request.max_accounts,
+                    swap_mode,
+                    request.slippage_bps,
+                    request.in_market,
+                    request.out_market,
+                    request.only_direct_routes,
+                    request.excluded_dexes.clone(),
+                    self.providers.clone(),
+                )
+                .await?;
+            Ok(SwapQuote::from(info))
+        })
+    }
+}
+
+/// All providers enabled by crate features
+pub fn default_providers(drift: &DriftClient) -> Vec<Box<dyn SwapQuoteProvider>> {
+    #[allow(unused_mut)]
+    let mut providers: Vec<Box<dyn SwapQuoteProvider>> =
+        vec![Box::new(JupiterProvider::new(drift.clone()))];
+    #[cfg(feature = "titan")]
+    providers.push(Box::new(TitanProvider::new(drift.clone())));
+    providers
+}
+
+/// Query all `providers` concurrently and return the best quote
+///
+/// i.e. the max. output amount for `SwapMode::ExactIn` or min. input amount for `SwapMode::ExactOut`
+///
+/// Returns an error if no provider returned a quote
+pub async fn best_quote(
+    providers: &[Box<dyn SwapQuoteProvider>],
+    request: &SwapRequest,
+) -> SdkResult<SwapQuote> {
+    let results = join_all(providers.iter().map(|p| p.quote(request))).await;
+
+    let mut errors = Vec::new();
+    let mut best: Option<SwapQuote> = None;
+    for (provider, result) in providers.iter().zip(results) {
+        match result {
+            Ok(quote) => {
+                let is_better = best.as_ref().is_none_or(|best| match request.swap_mode {
+                    SwapMode::ExactIn => quote.out_amount > best.out_amount,
+                    SwapMode::ExactOut => quote.in_amount < best.in_amount,
+                });
+                if is_better {
+                    best = Some(quote);
+                }
+            }
+            Err(err) => {
+                log::warn!("swap quote from {} failed: {err:?}", provider.name());
+                errors.push(format!("{}: {err}", provider.name()));
+            }
+        }
+    }
+
+    best.ok_or_else(|| {
+        SdkError::Generic(format!("no swap quotes available [{}]", errors.join(", ")))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use solana_sdk::signature::Keypair;
+
+    use super::*;
+    use crate::{
+        constants::PROGRAM_ID,
+        mock::MockRpc,
+        types::{
+            accounts::{SpotMarket, User},
+            Context,
+        },
+        Wallet,
+    };
+
+    /// Offline provider returning a fixed quote
+    struct MockProvider {
+        name: &'static str,
+        quote: Option<SwapQuote>,
+    }
+
+    impl MockProvider {
+        fn ok(name: &'static str, in_amount: u64, out_amount: u64) -> Box<dyn SwapQuoteProvider> {
+            Box::new(Self {
+                name,
+                quote: Some(SwapQuote {
+                    provider: name,
+                    in_amount,
+                    out_amount,
+                    ixs: vec![Instruction::new_with_bytes(
+                        Pubkey::new_unique(),
+                        &[],
+                        vec![],
+                    )],
+                    ..Default::default()
+                }),
+            })
+        }
+        fn err(name: &'static str) -> Box<dyn SwapQuoteProvider> {
+            Box::new(Self { name, quote: None })
+        }
+    }
+
+    impl SwapQuoteProvider for MockProvider {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+        fn quote<'a>(&'a self, _request: &'a SwapRequest) -> BoxFuture<'a, SdkResult<SwapQuote>> {
+            Box::pin(async move {
+                self.quote
+                    .clone()
+                    .ok_or_else(|| SdkError::Generic("no route".into()))
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn best_quote_by_swap_mode() {
+        let providers = vec![
+            MockProvider::ok("a", 100, 50),
+            MockProvider::err("b"),
+            MockProvider::ok("c", 90, 51),
+            MockProvider::ok("d", 80, 45),
+        ];
+        let mut request = SwapRequest::default();
+        assert_eq!(
+            best_quote(&providers, &request).await.unwrap().provider,
+            "c"
+        );
+
+        request.swap_mode = SwapMode::ExactOut;
+        assert_eq!(
+            best_quote(&providers, &request).await.unwrap().provider,
+            "d"
+        );
+
+        let err = best_quote(&[MockProvider::err("b")], &request)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("b: "));
+    }
+
+    #[tokio::test]
+    async fn swap_wraps_quote_ixs() {
+        let wallet = Wallet::new(Keypair::new());
+        let mock = Arc::new(MockRpc::new(Context::DevNet));
+        for market_index in 0..2 {
+            mock.set_spot_market(SpotMarket {
+                pubkey: Pubkey::new_unique(),
+                market_index,
+                mint: Pubkey::new_unique(),
+                vault: Pubkey::new_unique(),
+                oracle: Pubkey::new_unique(),
+                ..Default::default()
+            });
+        }
+        mock.set_user(
+            wallet.default_sub_account(),
+            User {
+                authority: *wallet.authority(),
+                ..Default::default()
+            },
+        );
+        let drift = DriftClient::new_mock(Context::DevNet, Arc::clone(&mock), wallet.clone())
+            .await
+            .unwrap();
+
+        let providers = vec![MockProvider::ok("mock", 1_000, 990)];
+        let quote = best_quote(
+            &providers,
+            &SwapRequest {
+                user_authority: wallet.signer(),
+                amount: 1_000,
+                out_market: 1,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        let swap_program = quote.ixs[0].program_id;
+
+        let in_market = drift.try_get_spot_market_account(0).unwrap();
+        let out_market = drift.try_get_spot_market_account(1).unwrap();
+        let tx = drift
+            .init_tx(&wallet.default_sub_account(), false)
+            .await
+            .unwrap()
+            .swap(
+                quote,
+                &in_market,
+                &out_market,
+                &Wallet::derive_associated_token_address(wallet.authority(), &in_market),
+                &Wallet::derive_associated_token_address(wallet.authority(), &out_market),
+                None,
+                None,
+            )
+            .build();
+
+        let keys = tx.static_account_keys();
+        let programs: Vec<Pubkey> = tx
+            .instructions()
+            .iter()
+            .map(|ix| keys[ix.program_id_index as usize])
+            .collect();
+        assert_eq!(programs, vec![PROGRAM_ID, swap_program, PROGRAM_ID]);
+    }
+}
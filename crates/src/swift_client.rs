@@ -0,0 +1,402 @@
+//!
+//! Swift taker client
+//!
+//! Build, sign, and submit swift (signed msg) orders to the swift server then track them until placed onchain
+//!
+use std::{
+    collections::hash_map::RandomState,
+    hash::BuildHasher,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use base64::Engine;
+use futures_util::{Stream, StreamExt};
+use serde_json::json;
+use solana_sdk::clock::Slot;
+
+use crate::{
+    event_subscriber::DriftEvent,
+    swift_order_subscriber::{SignedDelegateOrder, SignedOrder, SignedOrderInfo, SignedOrderType},
+    types::{Context, OrderParams, SdkError, SdkResult},
+    Wallet,
+};
+
+pub const SWIFT_DEVNET_URL: &str = "https://master.swift.drift.trade";
+pub const SWIFT_MAINNET_URL: &str = "https://swift.drift.trade";
+
+const LOG_TARGET: &str = "swift";
+
+/// Interval to check pending orders for expiry (~1 slot)
+const EXPIRY_CHECK_INTERVAL: Duration = Duration::from_millis(400);
+
+/// Chars used for swift order UUIDs
+const UUID_ALPHABET: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+/// Final state of a submitted swift order
+#[derive(Clone, Debug, PartialEq)]
+pub enum SwiftOrderOutcome {
+    /// Order was placed onchain by a maker/filler
+    Placed {
+        /// the placed order's user order Id
+        user_order_id: u32,
+        /// signature of the placing tx
+        signature: String,
+    },
+    /// Order was not placed before its max slot
+    Expired,
+    /// The event stream ended before the order was placed or expired, its state is unknown
+    EventsEnded,
+}
+
+/// Submits swift taker orders to the swift server
+///
+/// ```example(no_run)
+/// let swift = SwiftClient::new(Context::MainNet, wallet);
+/// let slot = slot_subscriber.current_slot();
+/// let order = swift.place_order(0, order_params, slot).await?;
+///
+/// let mut events = EventSubscriber::subscribe(drift.ws(), wallet.default_sub_account()).await?;
+/// match swift.wait_for_order(&mut events, &order, || slot_subscriber.current_slot()).await {
+///     SwiftOrderOutcome::Placed { user_order_id, .. } => println!("placed: {user_order_id}"),
+///     SwiftOrderOutcome::Expired => println!("expired"),
+///     SwiftOrderOutcome::EventsEnded => println!("unknown, resubscribe to events"),
+/// }
+/// ```
+#[derive(Clone)]
+pub struct SwiftClient {
+    http: reqwest::Client,
+    url: String,
+    wallet: Wallet,
+}
+
+impl SwiftClient {
+    /// Create a new `SwiftClient` for the `context` swift server
+    ///
+    /// * `wallet` - taker wallet, orders are delegate signed if it is delegated
+    pub fn new(context: Context, wallet: Wallet) -> Self {
+        let url = if context == Context::MainNet {
+            SWIFT_MAINNET_URL
+        } else {
+            SWIFT_DEVNET_URL
+        };
+        Self::with_url(url, wallet)
+    }
+
+    /// Create a new `SwiftClient` with custom swift server `url`
+    pub fn with_url(url: &str, wallet: Wallet) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            url: url.trim_end_matches('/').to_string(),
+            wallet,
+        }
+    }
+
+    /// Return the taker wallet
+    pub fn wallet(&self) -> &Wallet {
+        &self.wallet
+    }
+
+    /// Build a swift order message for `sub_account_id` with a new random UUID
+    ///
+    /// * `slot` - the current slot, orders with stale slots are rejected
+    pub fn build_order(
+        &self,
+        sub_account_id: u16,
+        order_params: OrderParams,
+        slot: Slot,
+    ) -> SignedOrderType {
+        let uuid = new_uuid();
+        if self.wallet.is_delegated() {
+            SignedOrderType::delegated(SignedDelegateOrder {
+                signed_msg_order_params: order_params,
+                taker_pubkey: self.wallet.sub_account(sub_account_id),
+                slot,
+                uuid,
+                take_profit_order_params: None,
+                stop_loss_order_params: None,
+                max_margin_ratio: None,
+                builder_idx: None,
+                builder_fee_tenth_bps: None,
+                isolated_position_deposit: None,
+            })
+        } else {
+            SignedOrderType::authority(SignedOrder {
+                sub_account_id,
+                signed_msg_order_params: order_params,
+                slot,
+                uuid,
+                take_profit_order_params: None,
+                stop_loss_order_params: None,
+                max_margin_ratio: None,
+                builder_idx: None,
+                builder_fee_tenth_bps: None,
+                isolated_position_deposit: None,
+            })
+        }
+    }
+
+    /// Sign a swift order message with the wallet
+    pub fn sign_order(&self, order: SignedOrderType) -> SdkResult<SignedOrderInfo> {
+        let message = hex::encode(order.to_borsh());
+        let signature = self.wallet.sign_message(message.as_bytes())?;
+        let authority = *self.wallet.authority();
+        Ok(match order {
+            SignedOrderType::Authority { inner, .. } => {
+                SignedOrderInfo::authority(authority, inner, signature)
+            }
+            SignedOrderType::Delegated { inner, .. } => {
+                SignedOrderInfo::delegated(authority, self.wallet.signer(), inner, signature)
+            }
+        })
+    }
+
+    /// Build, sign, and submit a swift order for `sub_account_id`
+    ///
+    /// * `slot` - the current slot, orders with stale slots are rejected
+    ///
+    /// Returns the submitted order
+    pub async fn place_order(
+        &self,
+        sub_account_id: u16,
+        order_params: OrderParams,
+        slot: Slot,
+    ) -> SdkResult<SignedOrderInfo> {
+        let order = self.sign_order(self.build_order(sub_account_id, order_params, slot))?;
+        self.submit_order(&order).await?;
+        Ok(order)
+    }
+
+    /// Submit a signed swift order to the swift server
+    pub async fn submit_order(&self, order: &SignedOrderInfo) -> SdkResult<()> {
+        let mut request = json!({
+            "message": String::from_utf8(order.encode_for_signing()).expect("hex encoded"),
+            "taker_authority": order.taker_authority.to_string(),
+            "taker_pubkey": order.taker_subaccount().to_string(),
+            "signature": base64::engine::general_purpose::STANDARD.encode(order.signature.as_ref()),
+        });
+        if order.using_delegate_signing() {
+            request["signing_authority"] = json!(order.signer.to_string());
+        }
+        log::debug!(target: LOG_TARGET, "submit order: {}", order.order_uuid_str());
+
+        let response = self
+            .http
+            .post(format!("{}/orders", self.url))
+            .json(&request)
+            .send()
+            .await?;
+        let status = response.status();
+        if status.is_success() {
+            Ok(())
+        } else {
+            let body = response.text().await.unwrap_or_default();
+            log::warn!(target: LOG_TARGET, "order rejected: {status}, {body}");
+            Err(SdkError::SwiftOrderRejected(format!("{status}: {body}")))
+        }
+    }
+
+    /// Wait for `order` to be placed onchain, observed via `SwiftOrder` events
+    ///
+    /// The order expires once the current slot passes its max slot (see `SignedOrderInfo::max_slot`)
+    ///
+    /// * `events` - drift events of the taker sub-account e.g. from `EventSubscriber`
+    /// * `current_slot` - returns the latest slot e.g. from `SlotSubscriber`
+    pub async fn wait_for_order(
+        &self,
+        events: &mut (impl Stream<Item = DriftEvent> + Unpin),
+        order: &SignedOrderInfo,
+        current_slot: impl Fn() -> Slot,
+    ) -> SwiftOrderOutcome {
+        let uuid = order.order_uuid();
+        let taker = order.taker_subaccount();
+        let max_slot = order.max_slot();
+        let mut expiry_check = tokio::time::interval(EXPIRY_CHECK_INTERVAL);
+        loop {
+            tokio::select! {
+                biased;
+                event = events.next() => match event {
+                    Some(DriftEvent::SwiftOrder {
+                        user,
+                        uuid: placed_uuid,
+                        user_order_id,
+                        signature,
+                        ..
+                    }) if user == taker && placed_uuid == uuid => {
+                        return SwiftOrderOutcome::Placed {
+                            user_order_id,
+                            signature,
+                        };
+                    }
+                    Some(_) => (),
+                    None => {
+                        log::warn!(target: LOG_TARGET, "event stream ended");
+                        return SwiftOrderOutcome::EventsEnded;
+                    }
+                },
+                _ = expiry_check.tick() => {
+                    if current_slot() > max_slot {
+                        log::debug!(target: LOG_TARGET, "order expired: {}", order.order_uuid_str());
+                        return SwiftOrderOutcome::Expired;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Generate a random, printable swift order UUID
+fn new_uuid() -> [u8; 8] {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let mut seed = RandomState::new().hash_one(nanos);
+    let mut uuid = [0_u8; 8];
+    for c in uuid.iter_mut() {
+        *c = UUID_ALPHABET[(seed % UUID_ALPHABET.len() as u64) as usize];
+        seed /= UUID_ALPHABET.len() as u64;
+    }
+    uuid
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use solana_sdk::{pubkey::Pubkey, signature::Keypair, signer::Signer};
+
+    use super::*;
+    use crate::utils::test_utils::mock_http_server;
+
+    /// Minimal swift server stand-in, responds with `status` and records request bodies
+    async fn mock_swift_server(status: u16) -> (String, Arc<Mutex<Vec<serde_json::Value>>>) {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let received = Arc::clone(&requests);
+        let url = mock_http_server(move |request_line, body| {
+            assert!(request_line.starts_with("POST /orders"));
+            received.lock().unwrap().push(body);
+            (status, "\"ok\"".into())
+        })
+        .await;
+
+        (url, requests)
+    }
+
+    fn swift_order_event(user: Pubkey, uuid: [u8; 8], user_order_id: u32) -> DriftEvent {
+        DriftEvent::SwiftOrder {
+            user,
+            hash: String::new(),
+            uuid,
+            user_order_id,
+            max_slot: 0,
+            ts: 0,
+            signature: "sig".into(),
+            tx_idx: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn submit_and_track_order() {
+        let (url, requests) = mock_swift_server(200).await;
+        let wallet = Wallet::new(Keypair::new());
+        let swift = SwiftClient::with_url(&url, wallet.clone());
+
+        let order = swift
+            .place_order(
+                1,
+                OrderParams {
+                    auction_duration: Some(10),
+                    ..Default::default()
+                },
+                1_234,
+            )
+            .await
+            .unwrap();
+        assert_eq!(order.slot(), 1_234);
+        assert_eq!(order.max_slot(), 1_244);
+        assert!(order.order_uuid().iter().all(|c| c.is_ascii_alphanumeric()));
+        assert_eq!(order.taker_subaccount(), wallet.sub_account(1));
+
+        let request = requests.lock().unwrap().pop().unwrap();
+        let message = request["message"].as_str().unwrap();
+        assert_eq!(message.as_bytes(), order.encode_for_signing());
+        assert_eq!(request["taker_pubkey"], wallet.sub_account(1).to_string());
+        assert!(request.get("signing_authority").is_none());
+        assert!(order
+            .signature
+            .verify(wallet.authority().as_ref(), message.as_bytes()));
+
+        let mut events = futures_util::stream::iter([
+            swift_order_event(wallet.sub_account(1), *b"otheruid", 1),
+            swift_order_event(wallet.sub_account(0), order.order_uuid(), 2),
+            swift_order_event(wallet.sub_account(1), order.order_uuid(), 3),
+        ]);
+        assert_eq!(
+            swift.wait_for_order(&mut events, &order, || 1_244).await,
+            SwiftOrderOutcome::Placed {
+                user_order_id: 3,
+                signature: "sig".into(),
+            }
+        );
+
+        // closed event stream is not mistaken for expiry
+        let mut events = futures_util::stream::empty();
+        assert_eq!(
+            swift.wait_for_order(&mut events, &order, || 1_244).await,
+            SwiftOrderOutcome::EventsEnded
+        );
+
+        // expires after the max slot
+        let mut events = futures_util::stream::pending();
+        assert_eq!(
+            tokio::time::timeout(
+                Duration::from_secs(1),
+                swift.wait_for_order(&mut events, &order, || 1_245)
+            )
+            .await
+            .expect("expired"),
+            SwiftOrderOutcome::Expired
+        );
+        assert!(tokio::time::timeout(
+            Duration::from_millis(100),
+            swift.wait_for_order(&mut events, &order, || 1_244)
+        )
+        .await
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn submit_delegated_order() {
+        let (url, requests) = mock_swift_server(200).await;
+        let delegate = Keypair::new();
+        let delegate_pubkey = delegate.pubkey();
+        let authority = Pubkey::new_unique();
+        let swift = SwiftClient::with_url(&url, Wallet::delegated(delegate, authority));
+
+        let order = swift
+            .place_order(0, OrderParams::default(), 1)
+            .await
+            .unwrap();
+        assert!(order.using_delegate_signing());
+        assert_eq!(order.signer, delegate_pubkey);
+
+        let request = requests.lock().unwrap().pop().unwrap();
+        assert_eq!(request["signing_authority"], delegate_pubkey.to_string());
+        assert_eq!(request["taker_authority"], authority.to_string());
+        assert!(order.signature.verify(
+            delegate_pubkey.as_ref(),
+            request["message"].as_str().unwrap().as_bytes()
+        ));
+    }
+
+    #[tokio::test]
+    async fn rejected_order() {
+        let (url, _requests) = mock_swift_server(400).await;
+        let swift = SwiftClient::with_url(&url, Wallet::new(Keypair::new()));
+
+        assert!(matches!(
+            swift.place_order(0, OrderParams::default(), 1).await,
+            Err(SdkError::SwiftOrderRejected(_))
+        ));
+    }
+}
@@ -1,4 +1,4 @@
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use anchor_lang::{AnchorDeserialize, AnchorSerialize, Space};
 use base64::Engine;
@@ -6,17 +6,25 @@ use futures_util::{SinkExt, StreamExt};
 use serde::Deserialize;
 use serde_json::{json, Value};
 use solana_sdk::{clock::Slot, pubkey::Pubkey, signature::Signature};
+use tokio::{
+    net::TcpStream,
+    sync::{
+        mpsc::{error::TrySendError, Sender},
+        watch,
+    },
+};
 use tokio_stream::wrappers::ReceiverStream;
-use tokio_tungstenite::{connect_async, tungstenite::Message};
+use tokio_tungstenite::{connect_async, tungstenite::Message, MaybeTlsStream, WebSocketStream};
 
 pub use crate::types::{
     SignedMsgOrderParamsDelegateMessage as SignedDelegateOrder,
     SignedMsgOrderParamsMessage as SignedOrder,
 };
 use crate::{
+    async_utils::retry_policy::{self, TaskRetryPolicy},
     constants::MarketExt,
     types::{Context, MarketId, OrderParams, SdkError, SdkResult},
-    DriftClient, Wallet,
+    ConnectionStatus, DriftClient, Wallet,
 };
 
 /// Swift message discriminator (Anchor)
@@ -180,6 +188,11 @@ impl SignedOrderInfo {
             SignedOrderType::Delegated { inner, .. } => inner.slot,
         }
     }
+    /// Last slot the order can be placed onchain i.e. signed slot + auction duration
+    pub fn max_slot(&self) -> Slot {
+        self.slot()
+            .saturating_add(self.order_params().auction_duration.unwrap_or(0) as Slot)
+    }
     /// The order's UUID (stringified)
     pub fn order_uuid_str(&self) -> &str {
         self.uuid.as_ref()
@@ -330,8 +343,16 @@ impl SignedOrderInfo {
 /// Emits swift orders from the Ws server
 pub type SwiftOrderStream = ReceiverStream<SignedOrderInfo>;
 
+/// Max. time without a message (incl. heartbeats) from the swift server before reconnecting
+pub const SWIFT_HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(30);
+
+type SwiftWs = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
 /// Subscribe to the Swift WebSocket server, authenticate, and listen to new orders
 ///
+/// The connection is supervised, reconnecting indefinitely on disconnect or missed heartbeat
+/// (see `subscribe_swift_orders_with_status`)
+///
 /// * `client` - Drift client instance
 /// * `markets` - markets to listen on for new swift orders
 /// * `accept_sanitized` - set to true to receive *sanitized order flow (default: false)
@@ -352,6 +373,37 @@ pub async fn subscribe_swift_orders(
     accept_deposit_trades: bool,
     swift_ws_override: Option<String>,
 ) -> SdkResult<SwiftOrderStream> {
+    subscribe_swift_orders_with_status(
+        client,
+        markets,
+        accept_sanitized,
+        accept_deposit_trades,
+        swift_ws_override,
+        retry_policy::forever(1),
+    )
+    .await
+    .map(|(orders, _status)| orders)
+}
+
+/// Subscribe to the Swift WebSocket server, authenticate, and listen to new orders
+///
+/// On disconnect or missed heartbeat the connection is re-established, re-authenticated, and
+/// resubscribed to `markets`. Orders published while reconnecting are missed.
+///
+/// * `retry_policy` - decides whether to retry after a failed reconnect attempt,
+///   once exhausted the status is `Failed` and the order stream ends
+///
+/// see `subscribe_swift_orders` for other params
+///
+/// Returns a stream of new Swift order messages and a channel of connection status updates
+pub async fn subscribe_swift_orders_with_status(
+    client: &DriftClient,
+    markets: &[MarketId],
+    accept_sanitized: bool,
+    accept_deposit_trades: bool,
+    swift_ws_override: Option<String>,
+    retry_policy: impl TaskRetryPolicy,
+) -> SdkResult<(SwiftOrderStream, watch::Receiver<ConnectionStatus>)> {
     let base_url = if let Some(custom_base_url) = swift_ws_override {
         custom_base_url
     } else if client.context == Context::MainNet {
@@ -362,15 +414,112 @@ pub async fn subscribe_swift_orders(
 
     let maker_pubkey = client.wallet().authority().to_string();
     let uri = format!("{base_url}/ws?pubkey={maker_pubkey}");
-    let (ws_stream, _) = connect_async(uri).await.map_err(|err| {
+    supervise_swift_orders(
+        client,
+        uri,
+        markets,
+        accept_sanitized,
+        accept_deposit_trades,
+        retry_policy,
+        SWIFT_HEARTBEAT_TIMEOUT,
+    )
+    .await
+}
+
+/// Connect to the swift server at `uri` and forward its orders, reconnecting on disconnect or
+/// when no message is received within `heartbeat_timeout`
+async fn supervise_swift_orders(
+    client: &DriftClient,
+    uri: String,
+    markets: &[MarketId],
+    accept_sanitized: bool,
+    accept_deposit_trades: bool,
+    mut retry_policy: impl TaskRetryPolicy,
+    heartbeat_timeout: Duration,
+) -> SdkResult<(SwiftOrderStream, watch::Receiver<ConnectionStatus>)> {
+    let mut ws = connect_swift(client, &uri, markets, heartbeat_timeout).await?;
+
+    let (tx, rx) = tokio::sync::mpsc::channel(256);
+    let (status_tx, status_rx) = watch::channel(ConnectionStatus::Connected);
+    let client = client.clone();
+    let markets = markets.to_vec();
+
+    // handle swift orders, reconnecting as needed
+    tokio::spawn(async move {
+        loop {
+            match forward_orders(
+                &mut ws,
+                &tx,
+                accept_sanitized,
+                accept_deposit_trades,
+                heartbeat_timeout,
+            )
+            .await
+            {
+                Ok(()) => {
+                    log::debug!(target: LOG_TARGET, "order stream dropped");
+                    break;
+                }
+                Err(err) => log::warn!(target: LOG_TARGET, "connection lost: {err:?}"),
+            }
+            let _ = status_tx.send(ConnectionStatus::Reconnecting);
+
+            let mut attempts = 0;
+            ws = loop {
+                if tx.is_closed() {
+                    return;
+                }
+                match connect_swift(&client, &uri, &markets, heartbeat_timeout).await {
+                    Ok(ws) => break ws,
+                    Err(err) => {
+                        log::warn!(target: LOG_TARGET, "couldn't reconnect: {err:?}");
+                        if !retry_policy.check(attempts).await {
+                            log::error!(target: LOG_TARGET, "reached max reconnect attempts");
+                            let _ = status_tx.send(ConnectionStatus::Failed);
+                            return;
+                        }
+                        attempts += 1;
+                    }
+                }
+            };
+            log::info!(target: LOG_TARGET, "reconnected");
+            let _ = status_tx.send(ConnectionStatus::Connected);
+        }
+    });
+
+    Ok((ReceiverStream::new(rx), status_rx))
+}
+
+/// Connect to the swift server at `uri`, authenticate, and subscribe to `markets`
+///
+/// Returns error if not subscribed within `timeout`
+async fn connect_swift(
+    client: &DriftClient,
+    uri: &str,
+    markets: &[MarketId],
+    timeout: Duration,
+) -> SdkResult<SwiftWs> {
+    tokio::time::timeout(timeout, connect_and_subscribe(client, uri, markets))
+        .await
+        .map_err(|_| {
+            log::error!(target: LOG_TARGET, "connect timed out");
+            SdkError::WebsocketError
+        })?
+}
+
+async fn connect_and_subscribe(
+    client: &DriftClient,
+    uri: &str,
+    markets: &[MarketId],
+) -> SdkResult<SwiftWs> {
+    let (mut ws, _) = connect_async(uri).await.map_err(|err| {
         log::error!(target: LOG_TARGET, "couldn't connect to server: {err:?}");
         SdkError::WsClient(Box::new(err))
     })?;
-
-    let (mut outgoing, mut incoming) = ws_stream.split();
+    let maker_pubkey = client.wallet().authority().to_string();
 
     // handle authentication and subscription
-    while let Some(msg) = incoming.next().await {
+    while let Some(msg) = ws.next().await {
         let msg = msg.map_err(|err| {
             log::error!(target: LOG_TARGET, "failed reading swift msg: {err:?}");
             SdkError::WsClient(Box::new(err))
@@ -378,7 +527,10 @@ pub async fn subscribe_swift_orders(
 
         if let Message::Text(text) = msg {
             log::debug!(target: LOG_TARGET, "msg: {text}");
-            let message: Value = serde_json::from_str(&text).expect("Failed to parse message");
+            let message: Value = serde_json::from_str(&text).map_err(|err| {
+                log::error!(target: LOG_TARGET, "{text}. invalid json: {err:?}");
+                SdkError::Deserializing
+            })?;
 
             if let Some(err) = message.get("error") {
                 log::error!(target: LOG_TARGET, "swift server error: {err:?}");
@@ -388,10 +540,7 @@ pub async fn subscribe_swift_orders(
             // authenticate with Ws server
             if message["channel"] == "auth" && message.get("nonce").is_some() {
                 let nonce = message["nonce"].as_str().expect("got nonce");
-                let signature = client
-                    .wallet()
-                    .sign_message(nonce.as_bytes())
-                    .expect("infallible");
+                let signature = client.wallet().sign_message(nonce.as_bytes())?;
                 let signature_b64 =
                     base64::engine::general_purpose::STANDARD.encode(signature.as_ref());
 
@@ -400,7 +549,7 @@ pub async fn subscribe_swift_orders(
                     "signature": signature_b64,
                 })
                 .to_string();
-                outgoing.send(Message::Text(auth_message.into())).await?;
+                ws.send(Message::Text(auth_message.into())).await?;
                 continue;
             }
 
@@ -430,89 +579,104 @@ pub async fn subscribe_swift_orders(
                     })
                     .collect();
 
-                outgoing
-                    .send_all(&mut futures_util::stream::iter(subscribe_msgs))
+                ws.send_all(&mut futures_util::stream::iter(subscribe_msgs))
                     .await?;
-                break;
+                return Ok(ws);
             }
         }
     }
 
-    let (tx, rx) = tokio::sync::mpsc::channel(256);
+    log::error!(target: LOG_TARGET, "server closed connection before subscribing");
+    Err(SdkError::WebsocketError)
+}
 
-    // handle swift orders
-    tokio::spawn(async move {
-        while let Some(msg) = incoming.next().await {
-            match msg {
-                Ok(Message::Text(ref text)) => {
-                    match serde_json::from_str::<OrderNotification>(text) {
-                        Ok(OrderNotification {
-                            channel: _,
-                            mut order,
-                            deposit,
-                        }) => {
+/// Forward swift orders from `ws` to `tx` until the connection fails
+///
+/// Returns error on disconnect or missed heartbeat, Ok if the order receiver was dropped
+async fn forward_orders(
+    ws: &mut SwiftWs,
+    tx: &Sender<SignedOrderInfo>,
+    accept_sanitized: bool,
+    accept_deposit_trades: bool,
+    heartbeat_timeout: Duration,
+) -> SdkResult<()> {
+    loop {
+        let msg = match tokio::time::timeout(heartbeat_timeout, ws.next()).await {
+            Ok(Some(Ok(msg))) => msg,
+            Ok(Some(Err(err))) => {
+                log::error!(target: LOG_TARGET, "failed reading swift msg: {err:?}");
+                return Err(SdkError::WsClient(Box::new(err)));
+            }
+            Ok(None) => return Err(SdkError::WebsocketError),
+            Err(_) => {
+                log::error!(target: LOG_TARGET, "missed heartbeat");
+                return Err(SdkError::MissedHeartbeat);
+            }
+        };
+
+        match msg {
+            Message::Text(ref text) => match serde_json::from_str::<OrderNotification>(text) {
+                Ok(OrderNotification {
+                    channel: _,
+                    mut order,
+                    deposit,
+                }) => {
+                    log::debug!(
+                        target: LOG_TARGET,
+                        "uuid: {}, latency: {}ms",
+                        order.uuid,
+                        unix_now_ms().saturating_sub(order.ts)
+                    );
+
+                    if let Some(deposit) = deposit {
+                        if !accept_deposit_trades {
                             log::debug!(
                                 target: LOG_TARGET,
-                                "uuid: {}, latency: {}ms",
-                                order.uuid,
-                                unix_now_ms().saturating_sub(order.ts)
+                                "skipping deposit+trade order: {}",
+                                order.uuid
                             );
-
-                            if let Some(deposit) = deposit {
-                                if !accept_deposit_trades {
-                                    log::debug!(
-                                        target: LOG_TARGET,
-                                        "skipping deposit+trade order: {}",
-                                        order.uuid
-                                    );
-                                    continue;
-                                }
-                                order.pre_deposit = Some(deposit.to_string());
-                            }
-
-                            if !accept_sanitized {
-                                log::debug!(
-                                    target: LOG_TARGET,
-                                    "skipping sanitized order: {}",
-                                    order.uuid
-                                );
-                                continue;
-                            }
-                            if let Err(err) = tx.try_send(order) {
-                                log::error!(target: LOG_TARGET, "order chan failed: {err:?}");
-                                break;
-                            }
+                            continue;
                         }
+                        order.pre_deposit = Some(deposit.to_string());
+                    }
+
+                    if !accept_sanitized {
+                        log::debug!(
+                            target: LOG_TARGET,
+                            "skipping sanitized order: {}",
+                            order.uuid
+                        );
+                        continue;
+                    }
+                    match tx.try_send(order) {
+                        Ok(()) => (),
+                        Err(TrySendError::Closed(_)) => return Ok(()),
                         Err(err) => {
-                            if text.contains("heartbeat") {
-                                if let Ok(heartbeat) = serde_json::from_str::<Heartbeat>(text) {
-                                    log::debug!(
-                                        target: LOG_TARGET,
-                                        "heartbeat latency: {}",
-                                        unix_now_ms().saturating_sub(heartbeat.ts)
-                                    );
-                                    continue;
-                                }
-                            }
-                            log::error!(target: LOG_TARGET, "{text}. invalid json: {err:?}");
-                            break;
+                            log::error!(target: LOG_TARGET, "order chan failed: {err:?}");
                         }
                     }
                 }
-                Ok(Message::Close(_)) => {
-                    log::error!(target: LOG_TARGET, "server closed connection");
-                    break;
-                }
-                Ok(_) => continue,
                 Err(err) => {
-                    log::error!(target: LOG_TARGET, "failed reading swift msg: {err:?}");
-                    break;
+                    if text.contains("heartbeat") {
+                        if let Ok(heartbeat) = serde_json::from_str::<Heartbeat>(text) {
+                            log::debug!(
+                                target: LOG_TARGET,
+                                "heartbeat latency: {}",
+                                unix_now_ms().saturating_sub(heartbeat.ts)
+                            );
+                            continue;
+                        }
+                    }
+                    log::error!(target: LOG_TARGET, "{text}. invalid json: {err:?}");
                 }
+            },
+            Message::Close(_) => {
+                log::error!(target: LOG_TARGET, "server closed connection");
+                return Err(SdkError::WebsocketError);
             }
+            _ => continue,
         }
-    });
-
-    Ok(ReceiverStream::new(rx))
+    }
 }
 
 fn unix_now_ms() -> u64 {
@@ -586,12 +750,183 @@ where
 
 #[cfg(test)]
 mod tests {
+    use std::sync::Arc;
+
+    use solana_sdk::{account::Account, signature::Keypair};
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::accept_async;
+
     use super::*;
     use crate::{
-        drift_idl,
+        constants::ids::pyth_program,
+        drift_idl::{
+            self,
+            accounts::{PerpMarket, SpotMarket},
+            types::OracleSource,
+        },
+        mock::MockRpc,
         types::{MarketType, OrderTriggerCondition, OrderType, PositionDirection, PostOnlyParam},
+        utils::test_utils::{get_account_bytes, get_pyth_price},
     };
 
+    /// Mocked client with a SOL-PERP market (index 0)
+    async fn setup_client() -> DriftClient {
+        let mock = Arc::new(MockRpc::new(Context::DevNet));
+        let oracle = Pubkey::new_unique();
+        let mut name = [b' '; 32];
+        name[..8].copy_from_slice(b"SOL-PERP");
+        let mut perp_market = PerpMarket {
+            pubkey: Pubkey::new_unique(),
+            market_index: 0,
+            name,
+            ..Default::default()
+        };
+        perp_market.amm.oracle = oracle;
+        perp_market.amm.oracle_source = OracleSource::Pyth;
+        mock.set_perp_market(perp_market);
+        mock.set_account(
+            oracle,
+            Account {
+                lamports: 1,
+                data: get_account_bytes(&mut get_pyth_price(240, 9)).to_vec(),
+                owner: pyth_program::ID,
+                ..Default::default()
+            },
+        );
+        mock.set_spot_market(SpotMarket {
+            pubkey: Pubkey::new_unique(),
+            market_index: 0,
+            oracle: Pubkey::new_unique(),
+            ..Default::default()
+        });
+        DriftClient::new_mock(Context::DevNet, mock, Wallet::new(Keypair::new()))
+            .await
+            .unwrap()
+    }
+
+    /// Accept a connection on `listener` and play the server side of auth and subscribe
+    ///
+    /// Returns the connection and the subscribe message
+    async fn accept_swift(listener: &TcpListener) -> (WebSocketStream<TcpStream>, String) {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut ws = accept_async(stream).await.unwrap();
+        ws.send(Message::Text(
+            json!({ "channel": "auth", "nonce": "swift-nonce" })
+                .to_string()
+                .into(),
+        ))
+        .await
+        .unwrap();
+        let _auth = ws.next().await.unwrap().unwrap();
+        ws.send(Message::Text(
+            json!({ "channel": "auth", "message": "Authenticated" })
+                .to_string()
+                .into(),
+        ))
+        .await
+        .unwrap();
+        let subscribe = ws.next().await.unwrap().unwrap().into_text().unwrap();
+
+        (ws, subscribe.to_string())
+    }
+
+    #[tokio::test]
+    async fn swift_orders_resubscribe_on_disconnect_and_missed_heartbeat() {
+        let client = setup_client().await;
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let uri = format!("ws://{}/ws", listener.local_addr().unwrap());
+        let heartbeat_timeout = Duration::from_millis(500);
+
+        let (subscribed_tx, mut subscribed_rx) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let mut open = vec![];
+            for conn in 0..3 {
+                if conn > 0 {
+                    // keep the client reconnecting for a while
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                }
+                let (ws, subscribe) = accept_swift(&listener).await;
+                subscribed_tx.send(subscribe).unwrap();
+                // 1st connection drops, others go silent i.e. miss heartbeats
+                if conn > 0 {
+                    open.push(ws);
+                }
+            }
+            std::future::pending::<()>().await;
+        });
+
+        let (_orders, mut status) = supervise_swift_orders(
+            &client,
+            uri,
+            &[MarketId::perp(0)],
+            true,
+            false,
+            retry_policy::never(),
+            heartbeat_timeout,
+        )
+        .await
+        .unwrap();
+        assert_eq!(*status.borrow_and_update(), ConnectionStatus::Connected);
+
+        tokio::time::timeout(Duration::from_secs(5), async {
+            let subscribe = subscribed_rx.recv().await.unwrap();
+            assert!(subscribe.contains("SOL-PERP"));
+            // disconnect, then missed heartbeat
+            for _ in 0..2 {
+                status
+                    .wait_for(|s| *s == ConnectionStatus::Reconnecting)
+                    .await
+                    .unwrap();
+                status
+                    .wait_for(|s| *s == ConnectionStatus::Connected)
+                    .await
+                    .unwrap();
+                let subscribe = subscribed_rx.recv().await.unwrap();
+                assert!(subscribe.contains("SOL-PERP"));
+            }
+        })
+        .await
+        .expect("resubscribed");
+    }
+
+    #[tokio::test]
+    async fn swift_orders_reconnect_times_out_without_auth() {
+        let client = setup_client().await;
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let uri = format!("ws://{}/ws", listener.local_addr().unwrap());
+        let heartbeat_timeout = Duration::from_millis(500);
+
+        tokio::spawn(async move {
+            // 1st connection drops
+            let _ = accept_swift(&listener).await;
+            // 2nd connection accepts the handshake but never sends auth
+            let (stream, _) = listener.accept().await.unwrap();
+            let _ws = accept_async(stream).await.unwrap();
+            std::future::pending::<()>().await;
+        });
+
+        let (_orders, mut status) = supervise_swift_orders(
+            &client,
+            uri,
+            &[MarketId::perp(0)],
+            true,
+            false,
+            retry_policy::never(),
+            heartbeat_timeout,
+        )
+        .await
+        .unwrap();
+
+        tokio::time::timeout(Duration::from_secs(5), async {
+            status
+                .wait_for(|s| *s == ConnectionStatus::Failed)
+                .await
+                .unwrap();
+        })
+        .await
+        .expect("reconnect attempt timed out");
+    }
+
     #[test]
     fn test_swift_order_deser_bad_message() {
         let msg = r#"{
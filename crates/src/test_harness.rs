@@ -0,0 +1,310 @@
+#![cfg(feature = "test-harness")]
+//!
+//! Local SVM test harness
+//!
+//! Executes `TransactionBuilder` txs against the drift program binary in-process (LiteSVM)
+//! so tests can assert on resulting account state and emitted events
+//!
+use std::path::Path;
+
+use anchor_lang::AccountDeserialize;
+pub use litesvm::LiteSVM;
+use solana_sdk::{
+    account::Account, clock::Slot, hash::Hash, message::VersionedMessage, pubkey::Pubkey,
+    signature::Signature, transaction::TransactionError,
+};
+
+use crate::{
+    constants::PROGRAM_ID,
+    event_subscriber::{try_parse_log, DriftEvent},
+    mock::MockRpc,
+    types::{accounts::User, SdkError, SdkResult},
+    Wallet,
+};
+
+/// Env var with the path of the drift program binary e.g. `target/deploy/drift.so`
+pub const DRIFT_PROGRAM_SO_ENV: &str = "DRIFT_PROGRAM_SO";
+
+/// Result of executing a tx in the harness
+#[derive(Debug)]
+pub struct TxExecution {
+    pub signature: Signature,
+    /// Ok if the tx executed successfully
+    pub result: Result<(), TransactionError>,
+    pub logs: Vec<String>,
+    /// drift events emitted by the tx
+    pub events: Vec<DriftEvent>,
+    pub compute_units_consumed: u64,
+}
+
+impl TxExecution {
+    /// Returns true if the tx executed successfully
+    pub fn is_ok(&self) -> bool {
+        self.result.is_ok()
+    }
+}
+
+/// In-process SVM loaded with the drift program
+///
+/// ```example(no_run)
+/// let mock = Arc::new(MockRpc::new(Context::DevNet));
+/// mock.set_perp_market(sol_perp);
+/// mock.set_user(wallet.default_sub_account(), user);
+///
+/// let mut harness = DriftTestHarness::from_env()?;
+/// harness.load(&mock)?;
+/// harness.airdrop(wallet.authority(), LAMPORTS_PER_SOL)?;
+///
+/// let client = DriftClient::new_mock(Context::DevNet, mock, wallet.clone()).await?;
+/// let tx = client
+///     .init_tx(&wallet.default_sub_account(), false)
+///     .await?
+///     .place_orders(orders)
+///     .build();
+///
+/// let execution = harness.execute(&wallet, tx)?;
+/// assert!(execution.is_ok(), "{:?}", execution.logs);
+/// let user = harness.user(&wallet.default_sub_account())?;
+/// ```
+pub struct DriftTestHarness {
+    svm: LiteSVM,
+}
+
+impl DriftTestHarness {
+    /// Create a new harness loading the drift program from `program_so`
+    pub fn new(program_so: impl AsRef<Path>) -> SdkResult<Self> {
+        let mut svm = LiteSVM::new();
+        svm.add_program_from_file(PROGRAM_ID, program_so.as_ref())
+            .map_err(|err| {
+                SdkError::Generic(format!(
+                    "couldn't load program: {}, {err:?}",
+                    program_so.as_ref().display()
+                ))
+            })?;
+        Ok(Self::from_svm(svm))
+    }
+
+    /// Create a new harness from a configured `svm`, the drift program must be added to run drift txs
+    pub fn from_svm(svm: LiteSVM) -> Self {
+        Self { svm }
+    }
+
+    /// Create a new harness loading the drift program from `$DRIFT_PROGRAM_SO`
+    pub fn from_env() -> SdkResult<Self> {
+        let path = std::env::var(DRIFT_PROGRAM_SO_ENV)
+            .map_err(|_| SdkError::Generic(format!("{DRIFT_PROGRAM_SO_ENV} not set")))?;
+        Self::new(path)
+    }
+
+    /// Returns the underlying SVM e.g. to set sysvars
+    pub fn svm(&mut self) -> &mut LiteSVM {
+        &mut self.svm
+    }
+
+    /// Load all accounts of the `mock` fixture e.g. markets, oracles, users, and state
+    pub fn load(&mut self, mock: &MockRpc) -> SdkResult<()> {
+        for (pubkey, account) in mock.accounts() {
+            self.set_account(pubkey, account)?;
+        }
+        Ok(())
+    }
+
+    /// Write back `accounts` to the `mock` fixture e.g. to refresh a mocked `DriftClient`
+    pub fn export(&self, mock: &MockRpc, accounts: &[Pubkey]) {
+        for pubkey in accounts {
+            match self.svm.get_account(pubkey) {
+                Some(account) => mock.set_account(*pubkey, account),
+                None => mock.remove_account(pubkey),
+            }
+        }
+    }
+
+    /// Set the data of `pubkey`
+    pub fn set_account(&mut self, pubkey: Pubkey, account: Account) -> SdkResult<()> {
+        self.svm
+            .set_account(pubkey, account)
+            .map_err(|err| SdkError::Generic(format!("couldn't set account: {pubkey}, {err:?}")))
+    }
+
+    /// Returns the data of `pubkey`, if it exists
+    pub fn account(&self, pubkey: &Pubkey) -> Option<Account> {
+        self.svm.get_account(pubkey)
+    }
+
+    /// Returns the drift `User` account at `pubkey`
+    pub fn user(&self, pubkey: &Pubkey) -> SdkResult<User> {
+        let account = self
+            .account(pubkey)
+            .ok_or(SdkError::NoAccountData(*pubkey))?;
+        User::try_deserialize(&mut account.data.as_slice()).map_err(Into::into)
+    }
+
+    /// Fund `pubkey` with `lamports`
+    pub fn airdrop(&mut self, pubkey: &Pubkey, lamports: u64) -> SdkResult<()> {
+        self.svm
+            .airdrop(pubkey, lamports)
+            .map(|_| ())
+            .map_err(|err| SdkError::Generic(format!("airdrop failed: {:?}", err.err)))
+    }
+
+    /// Advance the SVM clock to `slot`
+    pub fn warp_to_slot(&mut self, slot: Slot) {
+        self.svm.warp_to_slot(slot);
+    }
+
+    /// Returns the latest blockhash of the SVM
+    pub fn latest_blockhash(&self) -> Hash {
+        self.svm.latest_blockhash()
+    }
+
+    /// Sign `message` with `wallet` and execute it
+    ///
+    /// Returns the execution result, logs, and emitted drift events
+    pub fn execute(
+        &mut self,
+        wallet: &Wallet,
+        message: VersionedMessage,
+    ) -> SdkResult<TxExecution> {
+        let tx = wallet.sign_tx(message, self.svm.latest_blockhash())?;
+        let signature = tx.signatures[0];
+        let (result, meta) = match self.svm.send_transaction(tx) {
+            Ok(meta) => (Ok(()), meta),
+            Err(failed) => (Err(failed.err), failed.meta),
+        };
+        // txs with the same message and blockhash are rejected as duplicates
+        self.svm.expire_blockhash();
+
+        Ok(TxExecution {
+            signature,
+            result,
+            events: parse_events(&meta.logs, &signature),
+            logs: meta.logs,
+            compute_units_consumed: meta.compute_units_consumed,
+        })
+    }
+}
+
+/// Parse the drift events from `logs` of the tx `signature`
+fn parse_events(logs: &[String], signature: &Signature) -> Vec<DriftEvent> {
+    logs.iter()
+        .enumerate()
+        .filter_map(|(idx, log)| try_parse_log(log, &signature.to_string(), idx))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use anchor_lang::Event;
+    use base64::Engine;
+    use solana_sdk::signature::Keypair;
+
+    use super::*;
+    use crate::{
+        drift_idl::events::SignedMsgOrderRecord,
+        types::{accounts::PerpMarket, Context},
+    };
+
+    fn harness() -> DriftTestHarness {
+        DriftTestHarness::from_svm(LiteSVM::new())
+    }
+
+    #[test]
+    fn load_and_export_mock_accounts() {
+        let wallet = Wallet::new(Keypair::new());
+        let sub_account = wallet.default_sub_account();
+        let perp_market = Pubkey::new_unique();
+        let mock = MockRpc::new(Context::DevNet);
+        mock.set_perp_market(PerpMarket {
+            pubkey: perp_market,
+            market_index: 0,
+            ..Default::default()
+        });
+        mock.set_user(
+            sub_account,
+            User {
+                authority: *wallet.authority(),
+                ..Default::default()
+            },
+        );
+
+        let mut harness = harness();
+        harness.load(&mock).unwrap();
+        for (pubkey, account) in mock.accounts() {
+            assert_eq!(harness.account(&pubkey).unwrap().data, account.data);
+        }
+        assert_eq!(
+            harness.user(&sub_account).unwrap().authority,
+            *wallet.authority()
+        );
+        assert!(harness.user(&Pubkey::new_unique()).is_err());
+
+        // account changed in the SVM
+        harness
+            .set_account(
+                sub_account,
+                Account {
+                    lamports: 1,
+                    data: vec![1, 2, 3],
+                    owner: PROGRAM_ID,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        let closed = Pubkey::new_unique();
+        mock.set_account(
+            closed,
+            Account {
+                lamports: 1,
+                ..Default::default()
+            },
+        );
+        harness.export(&mock, &[sub_account, closed]);
+        assert_eq!(mock.account(&sub_account).unwrap().data, vec![1, 2, 3]);
+        assert!(mock.account(&closed).is_none());
+        assert!(mock.account(&perp_market).is_some());
+    }
+
+    #[test]
+    fn parses_drift_events_from_logs() {
+        let signature = Signature::new_unique();
+        let user = Pubkey::new_unique();
+        let record = SignedMsgOrderRecord {
+            user,
+            user_order_id: 7,
+            signed_msg_order_max_slot: 100,
+            signed_msg_order_uuid: *b"uuid1234",
+            ..Default::default()
+        };
+        let logs = vec![
+            format!("Program {PROGRAM_ID} invoke [1]"),
+            "Program log: Instruction: PlaceSignedMsgTakerOrder".to_string(),
+            format!(
+                "Program data: {}",
+                base64::engine::general_purpose::STANDARD.encode(record.data())
+            ),
+            format!("Program {PROGRAM_ID} success"),
+        ];
+
+        let events = parse_events(&logs, &signature);
+        assert_eq!(events.len(), 1);
+        match &events[0] {
+            DriftEvent::SwiftOrder {
+                user: event_user,
+                user_order_id,
+                max_slot,
+                uuid,
+                signature: event_signature,
+                tx_idx,
+                ..
+            } => {
+                assert_eq!(*event_user, user);
+                assert_eq!(*user_order_id, 7);
+                assert_eq!(*max_slot, 100);
+                assert_eq!(uuid, b"uuid1234");
+                assert_eq!(*event_signature, signature.to_string());
+                assert_eq!(*tx_idx, 2);
+            }
+            event => panic!("unexpected event: {event:?}"),
+        }
+    }
+}
@@ -0,0 +1,697 @@
+//!
+//! Transaction sender with confirmation tracking
+//!
+//! Rebroadcasts a signed tx until it lands or expires i.e. its blockhash expires or, for durable nonce txs,
+//! the nonce is advanced. Confirmation is tracked via
+//! Ws `signatureSubscribe`, gRPC tx updates (see `TxSender::on_grpc_transaction`) and RPC status polling (fallback)
+//!
+use std::{sync::Arc, time::Duration};
+
+use dashmap::DashMap;
+use futures_util::{future::join_all, StreamExt};
+use log::{debug, warn};
+use solana_rpc_client_api::{
+    config::{RpcSendTransactionConfig, RpcSignatureSubscribeConfig, RpcTransactionConfig},
+    response::{ProcessedSignatureResult, RpcSignatureResult},
+};
+use solana_sdk::{
+    clock::Slot,
+    commitment_config::CommitmentConfig,
+    hash::Hash,
+    pubkey::Pubkey,
+    signature::Signature,
+    transaction::{TransactionError, VersionedTransaction},
+};
+use solana_transaction_status::UiTransactionEncoding;
+use tokio::sync::{oneshot, Notify};
+
+use crate::{
+    durable_nonce,
+    grpc::TransactionUpdate,
+    types::{ProgramError, SdkError, SdkResult},
+    PubsubClient, RpcClient,
+};
+
+const LOG_TARGET: &str = "txsender";
+
+/// Final state of a sent tx
+#[derive(Clone, Debug, PartialEq)]
+pub enum TxOutcome {
+    /// tx landed and executed successfully
+    Landed {
+        signature: Signature,
+        slot: Slot,
+        /// CUs consumed by the tx, if known
+        compute_units_consumed: Option<u64>,
+    },
+    /// tx landed but failed execution
+    Failed {
+        signature: Signature,
+        slot: Slot,
+        error: TransactionError,
+        /// decoded program error, if the tx failed with a custom instruction error
+        program_error: Option<ProgramError>,
+    },
+    /// tx did not land before its blockhash expired or its durable nonce was advanced
+    Expired { signature: Signature },
+}
+
+impl TxOutcome {
+    /// The tx signature
+    pub fn signature(&self) -> Signature {
+        match self {
+            Self::Landed { signature, .. }
+            | Self::Failed { signature, .. }
+            | Self::Expired { signature } => *signature,
+        }
+    }
+    /// True if the tx landed and executed successfully
+    pub fn is_landed(&self) -> bool {
+        matches!(self, Self::Landed { .. })
+    }
+}
+
+/// Config for `TxSender`
+#[derive(Clone, Debug)]
+pub struct TxSenderConfig {
+    /// interval between rebroadcasts and status polls
+    pub rebroadcast_interval: Duration,
+    /// commitment level required to consider a tx landed
+    pub commitment: CommitmentConfig,
+    /// skip RPC preflight simulation on the initial send
+    pub skip_preflight: bool,
+    /// fetch CUs consumed for landed txs (requires an additional RPC request)
+    pub fetch_compute_units: bool,
+}
+
+impl Default for TxSenderConfig {
+    fn default() -> Self {
+        Self {
+            rebroadcast_interval: Duration::from_secs(2),
+            commitment: CommitmentConfig::confirmed(),
+            skip_preflight: true,
+            fetch_compute_units: true,
+        }
+    }
+}
+
+/// Confirmation of a tx from any source
+#[derive(Debug)]
+struct Confirmation {
+    slot: Slot,
+    err: Option<TransactionError>,
+    compute_units_consumed: Option<u64>,
+}
+
+/// A sent tx awaiting confirmation
+struct PendingTx {
+    confirm_tx: oneshot::Sender<Confirmation>,
+    /// wakes the sender to poll the tx status early
+    poll: Arc<Notify>,
+}
+
+/// Sends txs and tracks them until landed, failed, or expired
+///
+/// ```example(no_run)
+/// let tx_sender = TxSender::new(client.rpc(), TxSenderConfig::default())
+///     .with_pubsub(client.ws())
+///     .with_fanout(vec![Arc::new(RpcClient::new("https://another-rpc.com".into()))]);
+///
+/// let tx = client.wallet().sign_tx(message, recent_blockhash)?;
+/// match tx_sender.send(tx).await? {
+///     TxOutcome::Landed { slot, compute_units_consumed, .. } => {}
+///     TxOutcome::Failed { program_error, .. } => {}
+///     TxOutcome::Expired { .. } => {}
+/// }
+/// ```
+#[derive(Clone)]
+pub struct TxSender {
+    /// primary RPC, used for sending and status queries
+    rpc_client: Arc<RpcClient>,
+    /// additional RPCs for sending only
+    fanout: Vec<Arc<RpcClient>>,
+    /// Ws client for signature subscriptions
+    pubsub: Option<Arc<PubsubClient>>,
+    /// pending tx confirmations
+    pending: Arc<DashMap<Signature, PendingTx>>,
+    config: TxSenderConfig,
+}
+
+impl TxSender {
+    /// Create a new `TxSender`
+    ///
+    /// * `rpc_client` - used for sending and polling tx status
+    /// * `config` - tx sender config
+    pub fn new(rpc_client: Arc<RpcClient>, config: TxSenderConfig) -> Self {
+        Self {
+            rpc_client,
+            fanout: Default::default(),
+            pubsub: None,
+            pending: Default::default(),
+            config,
+        }
+    }
+
+    /// Track tx confirmations with Ws `signatureSubscribe`
+    pub fn with_pubsub(mut self, pubsub: Arc<PubsubClient>) -> Self {
+        self.pubsub = Some(pubsub);
+        self
+    }
+
+    /// Additionally (re)broadcast txs to these RPCs
+    pub fn with_fanout(mut self, rpc_clients: Vec<Arc<RpcClient>>) -> Self {
+        self.fanout = rpc_clients;
+        self
+    }
+
+    /// Handle a gRPC tx update, confirming any matching pending tx
+    ///
+    /// gRPC tx updates are processed-level, they confirm a tx only if the configured commitment is
+    /// processed. Otherwise (or if the tx failed) the update triggers an immediate RPC status poll
+    /// which checks the configured commitment.
+    ///
+    /// Register with `GrpcSubscribeOpts::on_transaction` to track confirmations via gRPC
+    /// ```example(no_run)
+    /// let grpc_tx_sender = tx_sender.clone();
+    /// let opts = GrpcSubscribeOpts::default()
+    ///     .transaction_include_accounts(vec![wallet.default_sub_account()])
+    ///     .on_transaction(move |update| grpc_tx_sender.on_grpc_transaction(update));
+    /// ```
+    pub fn on_grpc_transaction(&self, update: &TransactionUpdate) {
+        let Some(signature) = update
+            .transaction
+            .signatures
+            .first()
+            .and_then(|s| Signature::try_from(s.as_slice()).ok())
+        else {
+            return;
+        };
+        // failed txs are resolved by RPC status poll which provides the decoded error
+        if update.meta.err.is_some() || !self.config.commitment.is_processed() {
+            if let Some(pending) = self.pending.get(&signature) {
+                pending.poll.notify_one();
+            }
+            return;
+        }
+        if let Some((_, pending)) = self.pending.remove(&signature) {
+            let _ = pending.confirm_tx.send(Confirmation {
+                slot: update.slot,
+                err: None,
+                compute_units_consumed: update.meta.compute_units_consumed,
+            });
+        }
+    }
+
+    /// Send `tx` and wait until it lands, fails, or expires
+    ///
+    /// Durable nonce txs (see `TransactionBuilder::with_durable_nonce`) expire only once the nonce is advanced
+    ///
+    /// Returns error if the initial send is rejected by all RPCs
+    pub async fn send(&self, tx: VersionedTransaction) -> SdkResult<TxOutcome> {
+        let signature = tx.signatures[0];
+        let recent_blockhash = *tx.message.recent_blockhash();
+        let nonce_account = durable_nonce::durable_nonce_account(&tx.message);
+
+        let (confirm_tx, mut confirm_rx) = oneshot::channel();
+        let poll = Arc::new(Notify::new());
+        self.pending.insert(
+            signature,
+            PendingTx {
+                confirm_tx,
+                poll: Arc::clone(&poll),
+            },
+        );
+        let ws_task = self.pubsub.as_ref().map(|pubsub| {
+            tokio::spawn(Self::signature_subscribe(
+                Arc::clone(pubsub),
+                Arc::clone(&self.pending),
+                signature,
+                self.config.commitment,
+            ))
+        });
+
+        let outcome = self
+            .send_inner(
+                &tx,
+                signature,
+                recent_blockhash,
+                nonce_account,
+                &mut confirm_rx,
+                &poll,
+            )
+            .await;
+
+        self.pending.remove(&signature);
+        if let Some(task) = ws_task {
+            task.abort();
+        }
+
+        outcome
+    }
+
+    async fn send_inner(
+        &self,
+        tx: &VersionedTransaction,
+        signature: Signature,
+        recent_blockhash: Hash,
+        nonce_account: Option<Pubkey>,
+        confirm_rx: &mut oneshot::Receiver<Confirmation>,
+        poll: &Notify,
+    ) -> SdkResult<TxOutcome> {
+        self.broadcast(tx, self.config.skip_preflight).await?;
+
+        let mut rebroadcast = tokio::time::interval(self.config.rebroadcast_interval);
+        // first tick completes immediately
+        rebroadcast.tick().await;
+
+        loop {
+            tokio::select! {
+                biased;
+                Ok(confirmation) = &mut *confirm_rx => {
+                    return Ok(self.resolve_outcome(tx, signature, confirmation).await);
+                }
+                _ = poll.notified() => {
+                    if let Some(confirmation) = self.poll_status(&signature).await {
+                        return Ok(self.resolve_outcome(tx, signature, confirmation).await);
+                    }
+                }
+                _ = rebroadcast.tick() => {
+                    if let Some(confirmation) = self.poll_status(&signature).await {
+                        return Ok(self.resolve_outcome(tx, signature, confirmation).await);
+                    }
+                    match self.is_expired(recent_blockhash, nonce_account).await {
+                        Ok(true) => {
+                            // tx may have landed since last poll
+                            if let Some(confirmation) = self.poll_status(&signature).await {
+                                return Ok(self.resolve_outcome(tx, signature, confirmation).await);
+                            }
+                            debug!(target: LOG_TARGET, "tx expired: {signature:?}");
+                            return Ok(TxOutcome::Expired { signature });
+                        }
+                        Ok(false) => (),
+                        Err(err) => {
+                            warn!(target: LOG_TARGET, "expiry query failed: {err:?}");
+                        }
+                    }
+                    if let Err(err) = self.broadcast(tx, true).await {
+                        debug!(target: LOG_TARGET, "rebroadcast failed: {err:?}");
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns true if a tx signed with `recent_blockhash` can no longer land
+    ///
+    /// For durable nonce txs, `recent_blockhash` is the nonce value which is valid until `nonce_account` advances
+    async fn is_expired(
+        &self,
+        recent_blockhash: Hash,
+        nonce_account: Option<Pubkey>,
+    ) -> SdkResult<bool> {
+        match nonce_account {
+            Some(nonce_account) => {
+                let account = self
+                    .rpc_client
+                    .get_account_with_commitment(&nonce_account, self.config.commitment)
+                    .await?
+                    .value
+                    .ok_or(SdkError::InvalidAccount)?;
+                let nonce = durable_nonce::nonce_from_account(&account)?;
+                Ok(nonce != recent_blockhash)
+            }
+            None => Ok(!self
+                .rpc_client
+                .is_blockhash_valid(&recent_blockhash, self.config.commitment)
+                .await?),
+        }
+    }
+
+    /// Send `tx` to all configured RPCs
+    ///
+    /// Ok if at least one RPC accepted the tx
+    async fn broadcast(&self, tx: &VersionedTransaction, skip_preflight: bool) -> SdkResult<()> {
+        let config = RpcSendTransactionConfig {
+            skip_preflight,
+            max_retries: Some(0),
+            ..Default::default()
+        };
+        let results = join_all(
+            std::iter::once(&self.rpc_client)
+                .chain(self.fanout.iter())
+                .map(|rpc| rpc.send_transaction_with_config(tx, config)),
+        )
+        .await;
+
+        let mut last_err = None;
+        for result in results {
+            match result {
+                Ok(_) => return Ok(()),
+                Err(err) => last_err = Some(err),
+            }
+        }
+
+        let err: SdkError = last_err.expect("at least 1 rpc").into();
+        Err(err.to_out_of_sol_error().unwrap_or(err))
+    }
+
+    /// Query the tx status from RPC, returns `Some` if it satisfies the configured commitment
+    async fn poll_status(&self, signature: &Signature) -> Option<Confirmation> {
+        let response = match self.rpc_client.get_signature_statuses(&[*signature]).await {
+            Ok(response) => response,
+            Err(err) => {
+                warn!(target: LOG_TARGET, "tx status query failed: {err:?}");
+                return None;
+            }
+        };
+
+        response
+            .value
+            .into_iter()
+            .next()
+            .flatten()
+            .filter(|status| status.satisfies_commitment(self.config.commitment))
+            .map(|status| Confirmation {
+                slot: status.slot,
+                err: status.err,
+                compute_units_consumed: None,
+            })
+    }
+
+    async fn resolve_outcome(
+        &self,
+        tx: &VersionedTransaction,
+        signature: Signature,
+        confirmation: Confirmation,
+    ) -> TxOutcome {
+        let Confirmation {
+            slot,
+            err,
+            mut compute_units_consumed,
+        } = confirmation;
+
+        if err.is_none() && compute_units_consumed.is_none() && self.config.fetch_compute_units {
+            compute_units_consumed = self.fetch_compute_units(&signature).await;
+        }
+
+        match err {
+            Some(error) => TxOutcome::Failed {
+                signature,
+                slot,
+                program_error: ProgramError::from_transaction_error(&error, &tx.message),
+                error,
+            },
+            None => TxOutcome::Landed {
+                signature,
+                slot,
+                compute_units_consumed,
+            },
+        }
+    }
+
+    /// Fetch CUs consumed by a landed tx
+    async fn fetch_compute_units(&self, signature: &Signature) -> Option<u64> {
+        let commitment = if self.config.commitment.is_finalized() {
+            self.config.commitment
+        } else {
+            CommitmentConfig::confirmed()
+        };
+        match self
+            .rpc_client
+            .get_transaction_with_config(
+                signature,
+                RpcTransactionConfig {
+                    encoding: Some(UiTransactionEncoding::Base64),
+                    commitment: Some(commitment),
+                    max_supported_transaction_version: Some(0),
+                },
+            )
+            .await
+        {
+            Ok(tx) => tx
+                .transaction
+                .meta
+                .and_then(|meta| meta.compute_units_consumed.into()),
+            Err(err) => {
+                debug!(target: LOG_TARGET, "tx CUs query failed: {err:?}");
+                None
+            }
+        }
+    }
+
+    /// Subscribe to `signature` confirmation, confirming the pending tx on notification
+    async fn signature_subscribe(
+        pubsub: Arc<PubsubClient>,
+        pending: Arc<DashMap<Signature, PendingTx>>,
+        signature: Signature,
+        commitment: CommitmentConfig,
+    ) {
+        let subscribe_result = pubsub
+            .signature_subscribe(
+                &signature,
+                Some(RpcSignatureSubscribeConfig {
+                    commitment: Some(commitment),
+                    enable_received_notification: Some(false),
+                }),
+            )
+            .await;
+        let (mut stream, _unsub) = match subscribe_result {
+            Ok(sub) => sub,
+            Err(err) => {
+                warn!(target: LOG_TARGET, "signature subscribe failed: {err:?}");
+                return;
+            }
+        };
+
+        while let Some(response) = stream.next().await {
+            if let RpcSignatureResult::ProcessedSignature(ProcessedSignatureResult { err }) =
+                response.value
+            {
+                if let Some((_, pending)) = pending.remove(&signature) {
+                    let _ = pending.confirm_tx.send(Confirmation {
+                        slot: response.context.slot,
+                        err,
+                        compute_units_consumed: None,
+                    });
+                }
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::Engine;
+    use serde_json::json;
+    use solana_rpc_client::rpc_client::Mocks;
+    use solana_rpc_client_api::request::RpcRequest;
+    use solana_sdk::{
+        instruction::Instruction,
+        message::{Message, VersionedMessage},
+        nonce::{
+            state::{Data, DurableNonce, Versions},
+            State,
+        },
+        signature::Keypair,
+        signer::Signer,
+        system_program,
+    };
+
+    use super::*;
+    use crate::{constants::PROGRAM_ID, drift_idl::errors::ErrorCode};
+
+    fn signed_tx() -> VersionedTransaction {
+        signed_tx_with_ixs(&[])
+    }
+
+    fn signed_tx_with_ixs(ixs: &[Instruction]) -> VersionedTransaction {
+        let keypair = Keypair::new();
+        let message =
+            Message::new_with_blockhash(ixs, Some(&keypair.pubkey()), &Hash::new_unique());
+        VersionedTransaction::try_new(VersionedMessage::Legacy(message), &[&keypair]).unwrap()
+    }
+
+    /// Durable nonce tx and the account info response of its nonce account,
+    /// the stored nonce differs from the signed one if `advanced`
+    fn signed_nonce_tx(advanced: bool) -> (VersionedTransaction, serde_json::Value) {
+        let keypair = Keypair::new();
+        let nonce_account = Pubkey::new_unique();
+        let nonce = DurableNonce::from_blockhash(&Hash::new_unique());
+        let message = Message::new_with_blockhash(
+            &[durable_nonce::advance_nonce_ix(
+                &nonce_account,
+                &keypair.pubkey(),
+            )],
+            Some(&keypair.pubkey()),
+            nonce.as_hash(),
+        );
+        let tx =
+            VersionedTransaction::try_new(VersionedMessage::Legacy(message), &[&keypair]).unwrap();
+
+        let stored_nonce = if advanced {
+            DurableNonce::from_blockhash(&Hash::new_unique())
+        } else {
+            nonce
+        };
+        let data = Data::new(keypair.pubkey(), stored_nonce, 5_000);
+        let data = bincode::serialize(&Versions::new(State::Initialized(data))).unwrap();
+        let account_info = json!({
+            "context": { "slot": 100 },
+            "value": {
+                "data": [base64::engine::general_purpose::STANDARD.encode(&data), "base64"],
+                "executable": false,
+                "lamports": 1_447_680,
+                "owner": system_program::ID.to_string(),
+                "rentEpoch": 0,
+                "space": data.len(),
+            },
+        });
+
+        (tx, account_info)
+    }
+
+    fn tx_sender(url: &str, mocks: Mocks) -> TxSender {
+        let rpc = RpcClient::new_mock_with_mocks(url.into(), mocks);
+        TxSender::new(
+            Arc::new(rpc),
+            TxSenderConfig {
+                rebroadcast_interval: Duration::from_millis(10),
+                fetch_compute_units: false,
+                ..Default::default()
+            },
+        )
+    }
+
+    fn signature_status(status: serde_json::Value) -> serde_json::Value {
+        json!({
+            "context": { "slot": 100 },
+            "value": [status],
+        })
+    }
+
+    #[tokio::test]
+    async fn tx_sender_landed() {
+        let mut mocks = Mocks::default();
+        mocks.insert(
+            RpcRequest::GetSignatureStatuses,
+            signature_status(json!({
+                "slot": 99,
+                "confirmations": 1,
+                "status": { "Ok": null },
+                "err": null,
+                "confirmationStatus": "confirmed",
+            })),
+        );
+        let tx = signed_tx();
+        let outcome = tx_sender("https://api.mainnet-beta.solana.com", mocks)
+            .send(tx.clone())
+            .await
+            .unwrap();
+        assert_eq!(
+            outcome,
+            TxOutcome::Landed {
+                signature: tx.signatures[0],
+                slot: 99,
+                compute_units_consumed: None,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn tx_sender_failed() {
+        let mut mocks = Mocks::default();
+        let code: u32 = ErrorCode::InsufficientCollateral.into();
+        let err = json!({ "InstructionError": [1, { "Custom": code }] });
+        mocks.insert(
+            RpcRequest::GetSignatureStatuses,
+            signature_status(json!({
+                "slot": 99,
+                "confirmations": 1,
+                "status": { "Err": err },
+                "err": err,
+                "confirmationStatus": "confirmed",
+            })),
+        );
+        let tx = signed_tx_with_ixs(&[
+            Instruction::new_with_bytes(Pubkey::new_unique(), &[], vec![]),
+            Instruction::new_with_bytes(PROGRAM_ID, &[], vec![]),
+        ]);
+        let outcome = tx_sender("https://api.mainnet-beta.solana.com", mocks)
+            .send(tx)
+            .await
+            .unwrap();
+        match outcome {
+            TxOutcome::Failed {
+                slot,
+                program_error,
+                ..
+            } => {
+                assert_eq!(slot, 99);
+                assert_eq!(
+                    program_error,
+                    Some(ProgramError::Drift(ErrorCode::InsufficientCollateral))
+                );
+            }
+            _ => panic!("unexpected outcome: {outcome:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn tx_sender_expired() {
+        let mut mocks = Mocks::default();
+        mocks.insert(
+            RpcRequest::IsBlockhashValid,
+            json!({
+                "context": { "slot": 100 },
+                "value": false,
+            }),
+        );
+        let tx = signed_tx();
+        let outcome = tx_sender("sig_not_found", mocks)
+            .send(tx.clone())
+            .await
+            .unwrap();
+        assert_eq!(
+            outcome,
+            TxOutcome::Expired {
+                signature: tx.signatures[0]
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn tx_sender_durable_nonce_expiry() {
+        let blockhash_expired = json!({
+            "context": { "slot": 100 },
+            "value": false,
+        });
+
+        // nonce not advanced: blockhash expiry is ignored
+        let (tx, account_info) = signed_nonce_tx(false);
+        let mut mocks = Mocks::default();
+        mocks.insert(RpcRequest::IsBlockhashValid, blockhash_expired.clone());
+        mocks.insert(RpcRequest::GetAccountInfo, account_info);
+        let pending = tokio::time::timeout(
+            Duration::from_millis(100),
+            tx_sender("sig_not_found", mocks).send(tx),
+        )
+        .await;
+        assert!(pending.is_err());
+
+        // nonce advanced
+        let (tx, account_info) = signed_nonce_tx(true);
+        let mut mocks = Mocks::default();
+        mocks.insert(RpcRequest::GetAccountInfo, account_info);
+        let outcome = tx_sender("sig_not_found", mocks)
+            .send(tx.clone())
+            .await
+            .unwrap();
+        assert_eq!(
+            outcome,
+            TxOutcome::Expired {
+                signature: tx.signatures[0]
+            }
+        );
+    }
+}
@@ -31,7 +31,9 @@ pub use crate::drift_idl::{
     types::*,
 };
 use crate::{
-    constants::{ids, LUTS_DEVNET, LUTS_MAINNET, TOKEN_2022_PROGRAM_ID, TOKEN_PROGRAM_ID},
+    constants::{
+        ids, LUTS_DEVNET, LUTS_MAINNET, PROGRAM_ID, TOKEN_2022_PROGRAM_ID, TOKEN_PROGRAM_ID,
+    },
     drift_idl::errors::ErrorCode,
     grpc::grpc_subscriber::GrpcError,
     types::accounts::UserStats,
@@ -338,6 +340,8 @@ pub enum SdkError {
     MaxReconnectionAttemptsReached,
     #[error("jit taker order not found")]
     JitOrderNotFound,
+    #[error("invalid bundle size: {0}")]
+    InvalidBundleSize(usize),
     #[error("market data unavailable. subscribe market: {0:?}")]
     NoMarketData(MarketId),
     #[error("account data unavailable. subscribe account: {0:?}")]
@@ -354,6 +358,10 @@ pub enum SdkError {
     WalletSigningDisabled,
     #[error("{0}")]
     Grpc(#[from] Box<GrpcError>),
+    #[error("{0}")]
+    Http(#[from] Box<reqwest::Error>),
+    #[error("swift order rejected: {0}")]
+    SwiftOrderRejected(String),
 }
 
 // Manual From implementations for unboxed error types to avoid breaking changes
@@ -387,8 +395,13 @@ impl From<GrpcError> for SdkError {
         SdkError::Grpc(Box::new(e))
     }
 }
+impl From<reqwest::Error> for SdkError {
+    fn from(e: reqwest::Error) -> Self {
+        SdkError::Http(Box::new(e))
+    }
+}
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 /// Solana program execution error
 pub enum ProgramError {
     /// instruction error from Drift
@@ -408,8 +421,45 @@ impl Display for ProgramError {
     }
 }
 
+impl ProgramError {
+    /// extract the program error from a failed tx, if it exists
+    ///
+    /// custom errors are only decoded as Drift errors if the failing ix is a Drift ix
+    ///
+    /// * `err` - the tx error
+    /// * `message` - message of the failed tx
+    pub fn from_transaction_error(
+        err: &TransactionError,
+        message: &VersionedMessage,
+    ) -> Option<Self> {
+        if let TransactionError::InstructionError(ix_idx, InstructionError::Custom(code)) = err {
+            let is_drift_ix = message
+                .instructions()
+                .get(*ix_idx as usize)
+                .is_some_and(|ix| ix.program_id(message.static_account_keys()) == &PROGRAM_ID);
+            if is_drift_ix {
+                return Some(Self::from_custom_error(*ix_idx, *code));
+            }
+            return Some(ProgramError::Other {
+                ix_idx: *ix_idx,
+                code: *code,
+            });
+        }
+        None
+    }
+    /// decode a custom ix error, assuming it is from Drift
+    fn from_custom_error(ix_idx: u8, code: u32) -> Self {
+        match ErrorCode::try_from(code) {
+            Ok(code) => ProgramError::Drift(code),
+            Err(code) => ProgramError::Other { ix_idx, code },
+        }
+    }
+}
+
 impl SdkError {
     /// extract anchor error code from the SdkError if it exists
+    ///
+    /// assumes custom errors are from Drift, see `ProgramError::from_transaction_error`
     pub fn to_anchor_error_code(&self) -> Option<ProgramError> {
         if let SdkError::Rpc(inner) = self {
             if let Some(TransactionError::InstructionError(
@@ -417,16 +467,7 @@ impl SdkError {
                 InstructionError::Custom(code),
             )) = inner.get_transaction_error()
             {
-                // inverse of anchor's 'From<ErrorCode> for u32'
-                let err = match code.checked_sub(anchor_lang::error::ERROR_CODE_OFFSET) {
-                    Some(code) => {
-                        // this will saturate e.g. if u32 > |ErrorCode\ then it always returns the
-                        // highest idx variant
-                        ProgramError::Drift(unsafe { std::mem::transmute::<u32, ErrorCode>(code) })
-                    }
-                    None => ProgramError::Other { ix_idx, code },
-                };
-                return Some(err);
+                return Some(ProgramError::from_custom_error(ix_idx, code));
             }
         }
         None
@@ -685,11 +726,16 @@ mod tests {
         response::RpcSimulateTransactionResult,
     };
     use solana_sdk::{
-        instruction::InstructionError, pubkey::Pubkey, transaction::TransactionError,
+        instruction::{Instruction, InstructionError},
+        message::{Message, VersionedMessage},
+        pubkey::Pubkey,
+        transaction::TransactionError,
     };
 
     use super::{RemainingAccount, SdkError};
-    use crate::{drift_idl::errors::ErrorCode, types::ProgramError, MarketType};
+    use crate::{
+        constants::PROGRAM_ID, drift_idl::errors::ErrorCode, types::ProgramError, MarketType,
+    };
 
     #[test]
     fn market_type_str() {
@@ -731,6 +777,48 @@ mod tests {
         );
     }
 
+    #[test]
+    fn program_error_from_tx_error() {
+        let message = VersionedMessage::Legacy(Message::new(
+            &[
+                Instruction::new_with_bytes(Pubkey::new_unique(), &[], vec![]),
+                Instruction::new_with_bytes(PROGRAM_ID, &[], vec![]),
+            ],
+            Some(&Pubkey::new_unique()),
+        ));
+        let custom = |ix_idx, code| {
+            ProgramError::from_transaction_error(
+                &TransactionError::InstructionError(ix_idx, InstructionError::Custom(code)),
+                &message,
+            )
+        };
+
+        assert_eq!(
+            custom(1, 6071),
+            Some(ProgramError::Drift(ErrorCode::UserOrderIdAlreadyInUse))
+        );
+        // not a drift ix
+        assert_eq!(
+            custom(0, 6071),
+            Some(ProgramError::Other {
+                ix_idx: 0,
+                code: 6071
+            })
+        );
+        // unknown drift error
+        assert_eq!(
+            custom(1, u32::MAX),
+            Some(ProgramError::Other {
+                ix_idx: 1,
+                code: u32::MAX
+            })
+        );
+        assert_eq!(
+            ProgramError::from_transaction_error(&TransactionError::AccountInUse, &message),
+            None
+        );
+    }
+
     #[test]
     fn account_type_sorting() {
         let mut accounts = vec![
@@ -0,0 +1,625 @@
+//!
+//! User state tracker
+//!
+//! Merges sub-account `User` updates and `DriftEvent`s into a consistent view of positions and orders.
+//!
+//! Account updates are applied in slot order. `DriftEvent`s carry no slot, instead they are ordered
+//! against account snapshots by the program's monotonic `next_order_id` and cumulative fill amounts.
+//! Events not yet reflected by the latest snapshot are applied on top of it until a later snapshot
+//! confirms them.
+//!
+use std::collections::{BTreeMap, BTreeSet};
+
+use ahash::HashSet;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::{
+    event_subscriber::DriftEvent,
+    types::{
+        accounts::User, AccountUpdate, MarketType, Order, OrderStatus, PerpPosition,
+        PositionDirection,
+    },
+};
+
+/// An order of the tracked sub-account
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TrackedOrder {
+    pub order: Order,
+    /// a cancel was sent and is awaiting confirmation
+    pub pending_cancel: bool,
+}
+
+impl TrackedOrder {
+    /// Unfilled order size
+    pub fn remaining(&self) -> u64 {
+        self.order
+            .base_asset_amount
+            .saturating_sub(self.order.base_asset_amount_filled)
+    }
+    /// True if the order has been filled but is not complete
+    pub fn is_partially_filled(&self) -> bool {
+        self.order.base_asset_amount_filled > 0 && self.remaining() > 0
+    }
+}
+
+/// A change of the tracked sub-account's state
+#[derive(Clone, Debug, PartialEq)]
+pub enum StateChange {
+    /// a new open order
+    OrderPlaced(Order),
+    /// an order was (partially) filled, `order` is the updated order
+    OrderFilled { order: Order, fill_amount: u64 },
+    /// an order was canceled or expired before it was completely filled
+    OrderCanceled(Order),
+    /// perp position size changed without changing direction
+    PositionChanged {
+        market_index: u16,
+        old_base_asset_amount: i64,
+        new_base_asset_amount: i64,
+    },
+    /// perp position went from long to short or vice versa
+    PositionFlipped {
+        market_index: u16,
+        old_base_asset_amount: i64,
+        new_base_asset_amount: i64,
+    },
+}
+
+/// Callback for sub-account state changes
+pub type OnStateChangeFn = dyn FnMut(&StateChange) + Send;
+
+/// Sub-account positions and orders at a point in time
+#[derive(Clone, Debug, Default, PartialEq)]
+struct UserView {
+    /// order id → order (open, and recently closed orders)
+    orders: BTreeMap<u32, Order>,
+    /// market index → perp position
+    perp_positions: BTreeMap<u16, PerpPosition>,
+}
+
+impl UserView {
+    fn from_user(user: &User) -> Self {
+        Self {
+            orders: user
+                .orders
+                .iter()
+                .filter(|o| o.status != OrderStatus::Init)
+                .map(|o| (o.order_id, *o))
+                .collect(),
+            perp_positions: user
+                .perp_positions
+                .iter()
+                .filter(|p| !p.is_available())
+                .map(|p| (p.market_index, *p))
+                .collect(),
+        }
+    }
+    fn is_open(&self, order_id: u32) -> bool {
+        self.orders
+            .get(&order_id)
+            .is_some_and(|o| o.status == OrderStatus::Open)
+    }
+    /// Apply an event of `sub_account` to the view
+    fn apply(&mut self, event: &DriftEvent, sub_account: &Pubkey) {
+        match event {
+            DriftEvent::OrderCreate { order, .. } => {
+                self.orders.entry(order.order_id).or_insert(*order);
+            }
+            DriftEvent::OrderCancel { .. } | DriftEvent::OrderExpire { .. } => {
+                if let Some(order_id) = closed_order_id(event, sub_account) {
+                    if let Some(order) = self.orders.get_mut(&order_id) {
+                        if order.status == OrderStatus::Open {
+                            order.status = OrderStatus::Canceled;
+                        }
+                    }
+                }
+            }
+            DriftEvent::OrderFill {
+                base_asset_amount_filled,
+                quote_asset_amount_filled,
+                market_index,
+                market_type,
+                ..
+            } => {
+                let Some(fill) = event.fill_side(sub_account) else {
+                    return;
+                };
+                if let Some(order) = self.orders.get_mut(&fill.order_id) {
+                    let filled = fill
+                        .cumulative_base_filled
+                        .unwrap_or(order.base_asset_amount_filled + *base_asset_amount_filled);
+                    order.base_asset_amount_filled = order.base_asset_amount_filled.max(filled);
+                    if order.base_asset_amount_filled >= order.base_asset_amount {
+                        order.status = OrderStatus::Filled;
+                    }
+                }
+                if *market_type == MarketType::Perp {
+                    let position =
+                        self.perp_positions
+                            .entry(*market_index)
+                            .or_insert(PerpPosition {
+                                market_index: *market_index,
+                                ..Default::default()
+                            });
+                    let (base, quote) = (
+                        *base_asset_amount_filled as i64,
+                        *quote_asset_amount_filled as i64,
+                    );
+                    match fill.direction {
+                        PositionDirection::Long => {
+                            position.base_asset_amount += base;
+                            position.quote_asset_amount -= quote;
+                        }
+                        PositionDirection::Short => {
+                            position.base_asset_amount -= base;
+                            position.quote_asset_amount += quote;
+                        }
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+    /// State changes from `self` to `new`
+    fn diff(&self, new: &Self) -> Vec<StateChange> {
+        let mut changes = Vec::new();
+        for (order_id, order) in &new.orders {
+            match self.orders.get(order_id) {
+                Some(old) if old.status == OrderStatus::Open => {
+                    if order.base_asset_amount_filled > old.base_asset_amount_filled {
+                        changes.push(StateChange::OrderFilled {
+                            order: *order,
+                            fill_amount: order.base_asset_amount_filled
+                                - old.base_asset_amount_filled,
+                        });
+                    }
+                    if order.status == OrderStatus::Canceled {
+                        changes.push(StateChange::OrderCanceled(*order));
+                    }
+                }
+                Some(_) => (),
+                None if order.status == OrderStatus::Open => {
+                    changes.push(StateChange::OrderPlaced(*order));
+                    if order.base_asset_amount_filled > 0 {
+                        changes.push(StateChange::OrderFilled {
+                            order: *order,
+                            fill_amount: order.base_asset_amount_filled,
+                        });
+                    }
+                }
+                None => (),
+            }
+        }
+        // order slot reused before its close was observed
+        for (order_id, order) in &self.orders {
+            if order.status == OrderStatus::Open && !new.orders.contains_key(order_id) {
+                changes.push(StateChange::OrderCanceled(*order));
+            }
+        }
+
+        let market_indexes: BTreeSet<u16> = self
+            .perp_positions
+            .keys()
+            .chain(new.perp_positions.keys())
+            .copied()
+            .collect();
+        for market_index in market_indexes {
+            let base = |view: &Self| {
+                view.perp_positions
+                    .get(&market_index)
+                    .map(|p| p.base_asset_amount)
+                    .unwrap_or_default()
+            };
+            let (old_base_asset_amount, new_base_asset_amount) = (base(self), base(new));
+            if old_base_asset_amount == new_base_asset_amount {
+                continue;
+            }
+            if old_base_asset_amount.signum() * new_base_asset_amount.signum() < 0 {
+                changes.push(StateChange::PositionFlipped {
+                    market_index,
+                    old_base_asset_amount,
+                    new_base_asset_amount,
+                });
+            } else {
+                changes.push(StateChange::PositionChanged {
+                    market_index,
+                    old_base_asset_amount,
+                    new_base_asset_amount,
+                });
+            }
+        }
+
+        changes
+    }
+}
+
+/// Order id of `sub_account` closed by a cancel or expire `event`
+fn closed_order_id(event: &DriftEvent, sub_account: &Pubkey) -> Option<u32> {
+    match event {
+        DriftEvent::OrderCancel {
+            maker,
+            taker,
+            maker_order_id,
+            taker_order_id,
+            ..
+        } => {
+            if maker.as_ref() == Some(sub_account) {
+                Some(*maker_order_id)
+            } else if taker.as_ref() == Some(sub_account) {
+                Some(*taker_order_id)
+            } else {
+                None
+            }
+        }
+        DriftEvent::OrderExpire { order_id, user, .. } if user.as_ref() == Some(sub_account) => {
+            Some(*order_id)
+        }
+        _ => None,
+    }
+}
+
+/// Tracks a sub-account's positions and orders from account updates and events
+///
+/// ```example(no_run)
+///   let mut tracker = UserStateTracker::new(sub_account);
+///   tracker.on_change(Box::new(|change| println!("{change:?}")));
+///
+///   // account updates e.g. from `subscribe_account_with_callback` or gRPC
+///   tracker.on_account_update(&update);
+///   // events from `EventSubscriber`
+///   tracker.on_event(&event);
+///
+///   for order in tracker.open_orders() {
+///       println!("{} remaining: {}", order.order.order_id, order.remaining());
+///   }
+/// ```
+pub struct UserStateTracker {
+    sub_account: Pubkey,
+    /// latest account snapshot and its slot
+    account: Option<(u64, User)>,
+    /// events not yet reflected by `account`
+    pending_events: Vec<DriftEvent>,
+    /// `account` with `pending_events` applied
+    view: UserView,
+    /// order ids with cancels in-flight
+    pending_cancels: HashSet<u32>,
+    on_change: Vec<Box<OnStateChangeFn>>,
+}
+
+impl UserStateTracker {
+    /// Create a new tracker for `sub_account`
+    pub fn new(sub_account: Pubkey) -> Self {
+        Self {
+            sub_account,
+            account: None,
+            pending_events: Default::default(),
+            view: Default::default(),
+            pending_cancels: Default::default(),
+            on_change: Default::default(),
+        }
+    }
+    /// Register a callback for state changes
+    pub fn on_change(&mut self, f: Box<OnStateChangeFn>) {
+        self.on_change.push(f);
+    }
+    /// The tracked sub-account
+    pub fn sub_account(&self) -> Pubkey {
+        self.sub_account
+    }
+    /// Slot of the latest account snapshot
+    pub fn slot(&self) -> Option<u64> {
+        self.account.as_ref().map(|(slot, _)| *slot)
+    }
+    /// Latest account snapshot, excludes unconfirmed events
+    pub fn user(&self) -> Option<&User> {
+        self.account.as_ref().map(|(_, user)| user)
+    }
+    /// Number of events applied on top of the latest account snapshot
+    pub fn unconfirmed_events(&self) -> usize {
+        self.pending_events.len()
+    }
+    /// Open orders of the sub-account
+    pub fn open_orders(&self) -> Vec<TrackedOrder> {
+        self.view
+            .orders
+            .values()
+            .filter(|o| o.status == OrderStatus::Open)
+            .map(|o| self.tracked(o))
+            .collect()
+    }
+    /// Get open order by program assigned `order_id`
+    pub fn order(&self, order_id: u32) -> Option<TrackedOrder> {
+        self.view
+            .orders
+            .get(&order_id)
+            .filter(|o| o.status == OrderStatus::Open)
+            .map(|o| self.tracked(o))
+    }
+    /// Get open order by `user_order_id`
+    pub fn order_by_user_order_id(&self, user_order_id: u8) -> Option<TrackedOrder> {
+        self.view
+            .orders
+            .values()
+            .find(|o| o.status == OrderStatus::Open && o.user_order_id == user_order_id)
+            .map(|o| self.tracked(o))
+    }
+    /// Open orders with a cancel in-flight
+    pub fn pending_cancels(&self) -> Vec<TrackedOrder> {
+        self.open_orders()
+            .into_iter()
+            .filter(|o| o.pending_cancel)
+            .collect()
+    }
+    /// Get the perp position in `market_index`
+    pub fn perp_position(&self, market_index: u16) -> Option<PerpPosition> {
+        self.view.perp_positions.get(&market_index).copied()
+    }
+    /// All perp positions of the sub-account
+    pub fn perp_positions(&self) -> Vec<PerpPosition> {
+        self.view.perp_positions.values().copied().collect()
+    }
+    /// Mark `order_ids` as having a cancel in-flight
+    ///
+    /// Cleared once the orders are closed
+    pub fn mark_cancel_pending(&mut self, order_ids: &[u32]) {
+        self.pending_cancels.extend(order_ids.iter().copied());
+    }
+    /// Apply a raw account update of the sub-account
+    pub fn on_account_update(&mut self, update: &AccountUpdate) {
+        if update.pubkey != self.sub_account || update.data.len() < 8 {
+            return;
+        }
+        match bytemuck::try_pod_read_unaligned::<User>(&update.data[8..]) {
+            Ok(user) => self.on_account(update.slot, &user),
+            Err(err) => log::warn!(target: "userstate", "invalid user account: {err:?}"),
+        }
+    }
+    /// Apply a `user` account snapshot at `slot`, older snapshots are ignored
+    pub fn on_account(&mut self, slot: u64, user: &User) {
+        if self.slot().is_some_and(|latest| slot < latest) {
+            return;
+        }
+        let sub_account = self.sub_account;
+        self.pending_events
+            .retain(|event| !is_reflected(event, user, &sub_account));
+
+        let mut view = UserView::from_user(user);
+        for event in &self.pending_events {
+            view.apply(event, &sub_account);
+        }
+        self.account = Some((slot, *user));
+        self.update_view(view);
+    }
+    /// Apply a `DriftEvent` of the sub-account
+    pub fn on_event(&mut self, event: &DriftEvent) {
+        if !event.pertains_to(self.sub_account) {
+            return;
+        }
+        if self
+            .user()
+            .is_some_and(|user| is_reflected(event, user, &self.sub_account))
+        {
+            return;
+        }
+        let mut view = self.view.clone();
+        view.apply(event, &self.sub_account);
+        self.pending_events.push(event.clone());
+        self.update_view(view);
+    }
+    fn update_view(&mut self, view: UserView) {
+        let changes = self.view.diff(&view);
+        self.view = view;
+        let view = &self.view;
+        self.pending_cancels
+            .retain(|order_id| view.is_open(*order_id));
+        for change in &changes {
+            for f in self.on_change.iter_mut() {
+                f(change);
+            }
+        }
+    }
+    fn tracked(&self, order: &Order) -> TrackedOrder {
+        TrackedOrder {
+            order: *order,
+            pending_cancel: self.pending_cancels.contains(&order.order_id),
+        }
+    }
+}
+
+/// Returns true if `user` already reflects `event`
+fn is_reflected(event: &DriftEvent, user: &User, sub_account: &Pubkey) -> bool {
+    let known_order = |order_id: u32| order_id < user.next_order_id;
+    let open_order = |order_id: u32| {
+        user.orders
+            .iter()
+            .find(|o| o.order_id == order_id && o.status != OrderStatus::Init)
+            .filter(|o| o.status == OrderStatus::Open)
+    };
+    match event {
+        DriftEvent::OrderCreate { order, .. } => known_order(order.order_id),
+        DriftEvent::OrderCancel { .. } | DriftEvent::OrderExpire { .. } => {
+            match closed_order_id(event, sub_account) {
+                Some(order_id) => known_order(order_id) && open_order(order_id).is_none(),
+                None => true,
+            }
+        }
+        DriftEvent::OrderFill { .. } => match event.fill_side(sub_account) {
+            Some(fill) => match (open_order(fill.order_id), fill.cumulative_base_filled) {
+                (Some(order), Some(filled)) => order.base_asset_amount_filled >= filled,
+                (Some(_), None) => true,
+                (None, _) => known_order(fill.order_id),
+            },
+            None => true,
+        },
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::event_subscriber::FillSide;
+
+    const BASE: u64 = 1_000_000_000;
+
+    fn order(order_id: u32, direction: PositionDirection, size: u64) -> Order {
+        Order {
+            order_id,
+            status: OrderStatus::Open,
+            market_type: MarketType::Perp,
+            direction,
+            base_asset_amount: size,
+            price: 100_000_000,
+            ..Default::default()
+        }
+    }
+
+    fn user(next_order_id: u32, orders: &[Order], base_asset_amount: i64) -> User {
+        let mut user = User {
+            next_order_id,
+            ..Default::default()
+        };
+        for (idx, order) in orders.iter().enumerate() {
+            user.orders[idx] = *order;
+        }
+        user.perp_positions[0] = PerpPosition {
+            market_index: 0,
+            base_asset_amount,
+            ..Default::default()
+        };
+        user
+    }
+
+    fn maker_fill(maker: Pubkey, order_id: u32, amount: u64, cumulative: u64) -> DriftEvent {
+        DriftEvent::test_fill(
+            Some(FillSide {
+                user: maker,
+                order_id,
+                direction: PositionDirection::Long,
+                is_maker: true,
+                fee: 0,
+                cumulative_base_filled: Some(cumulative),
+            }),
+            Some(FillSide {
+                user: Pubkey::new_unique(),
+                order_id: 99,
+                direction: PositionDirection::Short,
+                is_maker: false,
+                fee: 0,
+                cumulative_base_filled: None,
+            }),
+            amount,
+            100 * amount / 1_000,
+        )
+    }
+
+    fn tracker(sub_account: Pubkey) -> (UserStateTracker, Arc<Mutex<Vec<StateChange>>>) {
+        let changes = Arc::new(Mutex::new(Vec::new()));
+        let mut tracker = UserStateTracker::new(sub_account);
+        tracker.on_change(Box::new({
+            let changes = Arc::clone(&changes);
+            move |change| changes.lock().unwrap().push(change.clone())
+        }));
+        (tracker, changes)
+    }
+
+    #[test]
+    fn event_before_account_update() {
+        let sub_account = Pubkey::new_unique();
+        let (mut tracker, changes) = tracker(sub_account);
+        let bid = order(1, PositionDirection::Long, 2 * BASE);
+        tracker.on_account(10, &user(2, &[bid], 0));
+        assert_eq!(
+            changes.lock().unwrap().drain(..).collect::<Vec<_>>(),
+            vec![StateChange::OrderPlaced(bid)]
+        );
+
+        // fill seen first via event
+        tracker.on_event(&maker_fill(sub_account, 1, BASE, BASE));
+        let order_state = tracker.order(1).unwrap();
+        assert!(order_state.is_partially_filled());
+        assert_eq!(order_state.remaining(), BASE);
+        assert_eq!(
+            tracker.perp_position(0).unwrap().base_asset_amount,
+            BASE as i64
+        );
+        assert_eq!(changes.lock().unwrap().len(), 2);
+
+        // stale snapshot ignored, event still applied on an older (unconfirmed) snapshot
+        tracker.on_account(9, &user(2, &[bid], 0));
+        tracker.on_account(11, &user(2, &[bid], 0));
+        assert_eq!(tracker.unconfirmed_events(), 1);
+        assert_eq!(
+            tracker.perp_position(0).unwrap().base_asset_amount,
+            BASE as i64
+        );
+
+        // confirmed by account, no double counting or duplicate callbacks
+        let mut filled = bid;
+        filled.base_asset_amount_filled = BASE;
+        tracker.on_account(12, &user(2, &[filled], BASE as i64));
+        assert_eq!(tracker.unconfirmed_events(), 0);
+        assert_eq!(
+            tracker.perp_position(0).unwrap().base_asset_amount,
+            BASE as i64
+        );
+        assert_eq!(changes.lock().unwrap().len(), 2);
+
+        // late duplicate event is ignored
+        tracker.on_event(&maker_fill(sub_account, 1, BASE, BASE));
+        assert_eq!(tracker.unconfirmed_events(), 0);
+    }
+
+    #[test]
+    fn account_update_before_event() {
+        let sub_account = Pubkey::new_unique();
+        let (mut tracker, changes) = tracker(sub_account);
+        let ask = order(5, PositionDirection::Short, BASE);
+        tracker.on_account(10, &user(6, &[ask], BASE as i64));
+        tracker.mark_cancel_pending(&[5]);
+        assert_eq!(tracker.pending_cancels().len(), 1);
+        changes.lock().unwrap().clear();
+
+        let mut canceled = ask;
+        canceled.status = OrderStatus::Canceled;
+        tracker.on_account(11, &user(6, &[canceled], BASE as i64));
+        assert!(tracker.open_orders().is_empty());
+        assert!(tracker.pending_cancels().is_empty());
+
+        tracker.on_event(&DriftEvent::OrderCancel {
+            taker: None,
+            maker: Some(sub_account),
+            taker_order_id: 0,
+            maker_order_id: 5,
+            signature: String::new(),
+            tx_idx: 0,
+            ts: 0,
+        });
+        assert_eq!(tracker.unconfirmed_events(), 0);
+        assert_eq!(
+            changes.lock().unwrap().drain(..).collect::<Vec<_>>(),
+            vec![StateChange::OrderCanceled(canceled)]
+        );
+    }
+
+    #[test]
+    fn position_flip() {
+        let sub_account = Pubkey::new_unique();
+        let (mut tracker, changes) = tracker(sub_account);
+        tracker.on_account(10, &user(1, &[], -(BASE as i64)));
+        changes.lock().unwrap().clear();
+
+        // order placed and filled in the same tx, not yet in the account
+        tracker.on_event(&maker_fill(sub_account, 1, 3 * BASE, 3 * BASE));
+        assert_eq!(
+            changes.lock().unwrap().drain(..).collect::<Vec<_>>(),
+            vec![StateChange::PositionFlipped {
+                market_index: 0,
+                old_base_asset_amount: -(BASE as i64),
+                new_base_asset_amount: 2 * BASE as i64,
+            }]
+        );
+    }
+}
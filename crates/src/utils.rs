@@ -367,6 +367,72 @@ pub mod test_utils {
         bytes
     }
 
+    /// Minimal HTTP/1.1 server stand-in for JSON APIs
+    ///
+    /// `handler` is called with the request line (e.g. `POST /orders HTTP/1.1`) and JSON body,
+    /// returning the response status and body
+    ///
+    /// Returns the server's base URL
+    pub async fn mock_http_server(
+        handler: impl Fn(&str, serde_json::Value) -> (u16, String) + Clone + Send + Sync + 'static,
+    ) -> String {
+        use tokio::{
+            io::{AsyncReadExt, AsyncWriteExt},
+            net::TcpListener,
+        };
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let handler = handler.clone();
+                tokio::spawn(async move {
+                    let mut buf = Vec::with_capacity(4096);
+                    let mut chunk = [0_u8; 4096];
+                    loop {
+                        let n = stream.read(&mut chunk).await.unwrap_or(0);
+                        if n == 0 {
+                            return;
+                        }
+                        buf.extend_from_slice(&chunk[..n]);
+                        let text = String::from_utf8_lossy(&buf).to_string();
+                        let Some(header_end) = text.find("\r\n\r\n") else {
+                            continue;
+                        };
+                        let content_length: usize = text[..header_end]
+                            .lines()
+                            .find_map(|l| {
+                                l.to_lowercase()
+                                    .strip_prefix("content-length:")
+                                    .map(|v| v.trim().parse().unwrap())
+                            })
+                            .unwrap_or(0);
+                        let body_start = header_end + 4;
+                        if buf.len() < body_start + content_length {
+                            continue;
+                        }
+                        let request_line = text.lines().next().unwrap_or_default().to_string();
+                        let request: serde_json::Value =
+                            serde_json::from_slice(&buf[body_start..body_start + content_length])
+                                .unwrap();
+                        buf.drain(..body_start + content_length);
+
+                        let (status, body) = handler(&request_line, request);
+                        let response = format!(
+                            "HTTP/1.1 {status} OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{body}",
+                            body.len()
+                        );
+                        stream.write_all(response.as_bytes()).await.unwrap();
+                    }
+                });
+            }
+        });
+
+        url
+    }
+
     #[macro_export]
     macro_rules! create_account_info {
         ($account:expr, $pubkey:expr, $owner:expr, $name: ident) => {
@@ -5,7 +5,7 @@ use solana_sdk::{
     message::VersionedMessage,
     pubkey::Pubkey,
     signature::{keypair_from_seed, Keypair, Signature},
-    signer::Signer,
+    signer::{Signer, SignerError},
     transaction::VersionedTransaction,
 };
 
@@ -37,11 +37,16 @@ pub enum Mode {
 ///
 /// // place holder wallet for readonly apps
 /// let ro_wallet = Wallet::read_only(drift_authority);
+///
+/// // wallet backed by an externally held key e.g. remote signing service, KMS
+/// let remote_wallet = Wallet::from_signer(Arc::new(my_remote_signer))?;
 /// ```
-#[derive(Clone, Debug)]
+#[derive(Clone)]
 pub struct Wallet {
-    /// The signing keypair, it could be authority or delegate
-    signer: Arc<Keypair>,
+    /// The signer, it could be authority or delegate
+    signer: Arc<dyn Signer + Send + Sync>,
+    /// The signer's address
+    signer_pubkey: Pubkey,
     /// The drift 'authority' account
     /// user (sub)accounts are derived from this
     authority: Pubkey,
@@ -74,8 +79,10 @@ impl Wallet {
     }
     /// Construct a read-only wallet
     pub fn read_only(authority: Pubkey) -> Self {
+        let signer = Keypair::new();
         Self {
-            signer: Arc::new(Keypair::new()),
+            signer_pubkey: signer.pubkey(),
+            signer: Arc::new(signer),
             authority,
             stats: Wallet::derive_stats_account(&authority),
             mode: Mode::ReadOnly,
@@ -100,10 +107,26 @@ impl Wallet {
     ///
     /// * `authority` - keypair for tx signing
     pub fn new(authority: Keypair) -> Self {
+        let authority_pubkey = authority.pubkey();
+        Self::with_signer(Arc::new(authority), authority_pubkey)
+    }
+    /// Init wallet with a custom signer e.g. remote signing service, HSM, KMS
+    ///
+    /// * `authority` - signer for tx and message signing
+    ///
+    /// ! signing happens inline on the calling task, async signers should bridge with e.g. `block_in_place`
+    ///
+    /// Returns error if the signer's pubkey is unavailable e.g. remote signer unreachable
+    pub fn from_signer(authority: Arc<dyn Signer + Send + Sync>) -> SdkResult<Self> {
+        let authority_pubkey = authority.try_pubkey()?;
+        Ok(Self::with_signer(authority, authority_pubkey))
+    }
+    fn with_signer(authority: Arc<dyn Signer + Send + Sync>, authority_pubkey: Pubkey) -> Self {
         Self {
-            stats: Wallet::derive_stats_account(&authority.pubkey()),
-            authority: authority.pubkey(),
-            signer: Arc::new(authority),
+            stats: Wallet::derive_stats_account(&authority_pubkey),
+            authority: authority_pubkey,
+            signer_pubkey: authority_pubkey,
+            signer: authority,
             mode: Mode::Normal,
         }
     }
@@ -121,8 +144,34 @@ impl Wallet {
     /// * `signer` - the delegated keypair for tx signing
     /// * `authority` - drift account to sign for (the delegator)
     pub fn delegated(signer: Keypair, authority: Pubkey) -> Self {
+        let signer_pubkey = signer.pubkey();
+        Self::with_delegated_signer(Arc::new(signer), signer_pubkey, authority)
+    }
+    /// Create a delegated wallet with a custom signer e.g. remote signing service, HSM, KMS
+    ///
+    /// * `signer` - the delegate signer for tx signing
+    /// * `authority` - drift account to sign for (the delegator)
+    ///
+    /// Returns error if the signer's pubkey is unavailable e.g. remote signer unreachable
+    pub fn delegated_from_signer(
+        signer: Arc<dyn Signer + Send + Sync>,
+        authority: Pubkey,
+    ) -> SdkResult<Self> {
+        let signer_pubkey = signer.try_pubkey()?;
+        Ok(Self::with_delegated_signer(
+            signer,
+            signer_pubkey,
+            authority,
+        ))
+    }
+    fn with_delegated_signer(
+        signer: Arc<dyn Signer + Send + Sync>,
+        signer_pubkey: Pubkey,
+        authority: Pubkey,
+    ) -> Self {
         Self {
-            signer: Arc::new(signer),
+            signer_pubkey,
+            signer,
             stats: Wallet::derive_stats_account(&authority),
             authority,
             mode: Mode::Delegated,
@@ -188,12 +237,42 @@ impl Wallet {
         }
     }
 
+    /// Signs a solana message with the wallet's signer, leaving any other required signatures as default
+    ///
+    /// The remaining signers may add their signatures to the returned tx before sending
+    ///
+    /// * `message` - solana VersionedMessage
+    /// * `recent_block_hash` blockhash for  tx longevity
+    pub fn partial_sign_tx(
+        &self,
+        mut message: VersionedMessage,
+        recent_block_hash: Hash,
+    ) -> SdkResult<VersionedTransaction> {
+        if let Mode::ReadOnly = self.mode {
+            return Err(SdkError::WalletSigningDisabled);
+        }
+        message.set_recent_blockhash(recent_block_hash);
+        let num_signers = message.header().num_required_signatures as usize;
+        let signer_idx = message.static_account_keys()[..num_signers]
+            .iter()
+            .position(|k| *k == self.signer_pubkey)
+            .ok_or(SignerError::KeypairPubkeyMismatch)?;
+
+        let mut signatures = vec![Signature::default(); num_signers];
+        signatures[signer_idx] = self.signer.try_sign_message(&message.serialize())?;
+
+        Ok(VersionedTransaction {
+            signatures,
+            message,
+        })
+    }
+
     /// Sign message with the wallet's signer
     pub fn sign_message(&self, message: &[u8]) -> SdkResult<Signature> {
         let signer: &dyn Signer = self.signer.as_ref();
         match self.mode {
             Mode::ReadOnly => Err(SdkError::WalletSigningDisabled),
-            _ => Ok(signer.sign_message(message)),
+            _ => signer.try_sign_message(message).map_err(Into::into),
         }
     }
     /// Return the wallet authority address
@@ -202,7 +281,7 @@ impl Wallet {
     }
     /// Return the wallet signing address
     pub fn signer(&self) -> Pubkey {
-        self.signer.pubkey()
+        self.signer_pubkey
     }
     /// Return the drift user stats address
     pub fn stats(&self) -> &Pubkey {
@@ -224,8 +303,21 @@ impl From<Keypair> for Wallet {
     }
 }
 
+impl std::fmt::Debug for Wallet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Wallet")
+            .field("signer", &self.signer_pubkey)
+            .field("authority", &self.authority)
+            .field("stats", &self.stats)
+            .field("mode", &self.mode)
+            .finish()
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
     use super::*;
 
     #[test]
@@ -238,4 +330,92 @@ mod tests {
         assert_eq!(rw.stats, ro.stats);
         assert_eq!(rw.default_sub_account(), ro.default_sub_account());
     }
+
+    /// Signer held outside the wallet e.g. remote signing service
+    struct RemoteSigner {
+        keypair: Keypair,
+        online: AtomicBool,
+    }
+
+    impl RemoteSigner {
+        fn new(keypair: Keypair) -> Arc<Self> {
+            Arc::new(Self {
+                keypair,
+                online: AtomicBool::new(true),
+            })
+        }
+        fn check_online(&self) -> Result<(), SignerError> {
+            if self.online.load(Ordering::Relaxed) {
+                Ok(())
+            } else {
+                Err(SignerError::Connection("signer offline".into()))
+            }
+        }
+    }
+
+    impl Signer for RemoteSigner {
+        fn try_pubkey(&self) -> Result<Pubkey, SignerError> {
+            self.check_online()?;
+            Ok(self.keypair.pubkey())
+        }
+        fn try_sign_message(&self, message: &[u8]) -> Result<Signature, SignerError> {
+            self.check_online()?;
+            self.keypair.try_sign_message(message)
+        }
+        fn is_interactive(&self) -> bool {
+            false
+        }
+    }
+
+    #[test]
+    fn wallet_custom_signer() {
+        let keypair = Keypair::new();
+        let local = Wallet::new(keypair.insecure_clone());
+        let remote = Wallet::from_signer(RemoteSigner::new(keypair)).unwrap();
+        assert_eq!(remote.authority(), local.authority());
+        assert_eq!(remote.signer(), local.signer());
+        assert_eq!(remote.stats(), local.stats());
+        assert_eq!(remote.sub_account(1), local.sub_account(1));
+        assert_eq!(
+            remote.sign_message(b"nonce").unwrap(),
+            local.sign_message(b"nonce").unwrap()
+        );
+
+        let message = VersionedMessage::Legacy(solana_sdk::message::Message::new(
+            &[],
+            Some(&remote.signer()),
+        ));
+        let tx = remote.sign_tx(message, Hash::new_unique()).unwrap();
+        assert!(tx.verify_with_results().iter().all(|ok| *ok));
+    }
+
+    #[test]
+    fn wallet_delegated_custom_signer() {
+        let authority = Pubkey::new_unique();
+        let delegate = Keypair::new();
+        let delegate_pubkey = delegate.pubkey();
+        let signer = RemoteSigner::new(delegate);
+        let wallet = Wallet::delegated_from_signer(signer.clone(), authority).unwrap();
+        assert!(wallet.is_delegated());
+        assert_eq!(wallet.signer(), delegate_pubkey);
+        assert_eq!(wallet.authority(), &authority);
+        assert_eq!(
+            wallet.default_sub_account(),
+            Wallet::derive_user_account(&authority, 0)
+        );
+
+        signer.online.store(false, Ordering::Relaxed);
+        assert!(matches!(
+            wallet.sign_message(b"nonce"),
+            Err(SdkError::Signing(_))
+        ));
+        assert!(matches!(
+            Wallet::delegated_from_signer(signer.clone(), authority),
+            Err(SdkError::Signing(_))
+        ));
+        assert!(matches!(
+            Wallet::from_signer(signer),
+            Err(SdkError::Signing(_))
+        ));
+    }
 }
@@ -91,7 +91,7 @@ pub async fn buy_jlp_via_jupiter(
         &out_token_account,
         None,
         None,
-    )
+    )?
     .build();
 
     // Send transaction
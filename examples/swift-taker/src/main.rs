@@ -1,7 +1,11 @@
 //! Example place swift taker order
 use argh::FromArgs;
 use base64::Engine;
+
 use drift_rs::{
+    event_subscriber::EventSubscriber,
+    slot_subscriber::SlotSubscriber,
+    swift_client::SwiftClient,
     swift_order_subscriber::{SignedOrderInfo, SignedOrderType},
     types::{MarketType, OrderParams, OrderType, PositionDirection, SignedMsgOrderParamsMessage},
     Context, DriftClient, RpcClient, TransactionBuilder, Wallet,
@@ -90,28 +94,30 @@ async fn main() {
         )
         .await;
     } else {
-        swift_place_order(&drift, swift_order_request).await;
+        swift_place_order(&drift, order_params).await;
     }
 }
 
-async fn swift_place_order(drift: &DriftClient, swift_order_request: serde_json::Value) {
-    println!("sending swift order: {swift_order_request:?}");
-    let swift_url = if drift.context == Context::MainNet {
-        "https://swift.drift.trade/orders"
-    } else {
-        "https://master.swift.drift.trade/orders"
-    };
-    let swift_cli = reqwest::Client::new();
-    let req = swift_cli
-        .post(swift_url)
-        .header(header::CONTENT_TYPE, "application/json")
-        .json(&swift_order_request)
-        .build();
-    dbg!(&req);
-    let res = swift_cli.execute(req.unwrap()).await;
-    dbg!(&res);
-    let response = res.unwrap().text().await.unwrap();
-    dbg!(response);
+async fn swift_place_order(drift: &DriftClient, order_params: OrderParams) {
+    let swift = SwiftClient::new(drift.context, drift.wallet().clone());
+    let mut events = EventSubscriber::subscribe(drift.ws(), drift.wallet().default_sub_account())
+        .await
+        .expect("subscribed events");
+
+    let mut slot_subscriber = SlotSubscriber::new(drift.ws());
+    slot_subscriber.subscribe(|_| {}).expect("subscribed slots");
+
+    let slot = drift.rpc().get_slot().await.expect("get slot");
+    let order = swift
+        .place_order(0, order_params, slot)
+        .await
+        .expect("order accepted");
+    println!("sent swift order: {}", order.order_uuid_str());
+
+    let outcome = swift
+        .wait_for_order(&mut events, &order, || slot_subscriber.current_slot())
+        .await;
+    dbg!(outcome);
 }
 
 async fn swift_deposit_trade(
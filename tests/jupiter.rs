@@ -80,7 +80,7 @@ async fn jupiter_swap_exact_in_udsc_to_sol() {
         &out_token_account,
         None,
         None,
-    )
+    ).unwrap()
     .build();
 
     let result = client.simulate_tx(tx).await;
@@ -149,7 +149,7 @@ async fn jupiter_swap_exact_out_udsc_to_sol() {
         &out_token_account,
         None,
         None,
-    )
+    ).unwrap()
     .build();
 
     let result = client.simulate_tx(tx).await;
@@ -219,7 +219,7 @@ async fn jupiter_swap_exact_out_udsc_jto() {
         &out_token_account,
         None,
         None,
-    )
+    ).unwrap()
     .build();
 
     let result = client.simulate_tx(tx).await;
@@ -289,7 +289,7 @@ async fn jupiter_swap_sol_unwrap() {
         &out_token_account,
         None,
         None,
-    )
+    ).unwrap()
     .build();
 
     let result = client.simulate_tx(tx).await;
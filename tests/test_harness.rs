@@ -0,0 +1,170 @@
+#![cfg(feature = "test-harness")]
+//! Executes built txs against the drift program binary, requires `DRIFT_PROGRAM_SO` is set
+//!
+//! `DRIFT_PROGRAM_SO=path/to/drift.so cargo test --features test-harness --test test_harness -- --ignored`
+use std::sync::Arc;
+
+use drift_rs::{
+    constants::{derive_perp_market_account, derive_spot_market_account, PROGRAM_ID},
+    event_subscriber::DriftEvent,
+    math::constants::{
+        AMM_RESERVE_PRECISION, BASE_PRECISION_U64, PEG_PRECISION, PRICE_PRECISION_I64,
+        PRICE_PRECISION_U64, SPOT_BALANCE_PRECISION, SPOT_CUMULATIVE_INTEREST_PRECISION,
+        SPOT_WEIGHT_PRECISION,
+    },
+    mock::MockRpc,
+    test_harness::{DriftTestHarness, DRIFT_PROGRAM_SO_ENV},
+    types::{
+        accounts::{PerpMarket, PrelaunchOracle, SpotMarket, User, UserStats},
+        Context, MarketStatus, MarketType, OracleSource, OrderParams, OrderStatus, OrderType,
+        PositionDirection, SpotBalanceType, SpotPosition, AMM,
+    },
+    utils::zero_account_to_bytes,
+    DriftClient, Pubkey, Wallet,
+};
+use solana_sdk::{
+    account::Account, native_token::LAMPORTS_PER_SOL, rent::Rent, signature::Keypair,
+};
+
+fn harness() -> DriftTestHarness {
+    DriftTestHarness::from_env()
+        .unwrap_or_else(|err| panic!("{DRIFT_PROGRAM_SO_ENV} must point to the program: {err}"))
+}
+
+/// USDC spot market, SOL-PERP with a prelaunch oracle @ $100, and a user with $1,000 collateral
+fn fixture(wallet: &Wallet) -> Arc<MockRpc> {
+    let mock = Arc::new(MockRpc::new(Context::DevNet));
+
+    mock.set_spot_market(SpotMarket {
+        pubkey: derive_spot_market_account(0),
+        market_index: 0,
+        oracle_source: OracleSource::QuoteAsset,
+        decimals: 6,
+        status: MarketStatus::Active,
+        cumulative_deposit_interest: SPOT_CUMULATIVE_INTEREST_PRECISION.into(),
+        cumulative_borrow_interest: SPOT_CUMULATIVE_INTEREST_PRECISION.into(),
+        deposit_balance: (1_000 * SPOT_BALANCE_PRECISION).into(),
+        initial_asset_weight: SPOT_WEIGHT_PRECISION,
+        maintenance_asset_weight: SPOT_WEIGHT_PRECISION,
+        ..Default::default()
+    });
+
+    let oracle = Pubkey::new_unique();
+    let oracle_data = zero_account_to_bytes(PrelaunchOracle {
+        price: 100 * PRICE_PRECISION_I64,
+        max_price: 1_000 * PRICE_PRECISION_I64,
+        perp_market_index: 0,
+        ..Default::default()
+    });
+    mock.set_account(
+        oracle,
+        Account {
+            lamports: Rent::default().minimum_balance(oracle_data.len()),
+            data: oracle_data,
+            owner: PROGRAM_ID,
+            ..Default::default()
+        },
+    );
+
+    let reserves = 1_000 * AMM_RESERVE_PRECISION;
+    mock.set_perp_market(PerpMarket {
+        pubkey: derive_perp_market_account(0),
+        market_index: 0,
+        status: MarketStatus::Active,
+        margin_ratio_initial: 1_000,
+        margin_ratio_maintenance: 500,
+        amm: AMM {
+            oracle,
+            oracle_source: OracleSource::Prelaunch,
+            base_asset_reserve: reserves.into(),
+            quote_asset_reserve: reserves.into(),
+            sqrt_k: reserves.into(),
+            terminal_quote_asset_reserve: reserves.into(),
+            min_base_asset_reserve: (reserves / 2).into(),
+            max_base_asset_reserve: (reserves * 2).into(),
+            peg_multiplier: (100 * PEG_PRECISION).into(),
+            order_step_size: BASE_PRECISION_U64 / 1_000,
+            order_tick_size: 100,
+            min_order_size: BASE_PRECISION_U64 / 1_000,
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+
+    let mut user = User {
+        authority: *wallet.authority(),
+        ..Default::default()
+    };
+    user.spot_positions[0] = SpotPosition {
+        market_index: 0,
+        scaled_balance: 1_000 * SPOT_BALANCE_PRECISION as u64,
+        balance_type: SpotBalanceType::Deposit,
+        ..Default::default()
+    };
+    mock.set_user(wallet.default_sub_account(), user);
+    mock.set_user_stats(
+        wallet.authority(),
+        UserStats {
+            authority: *wallet.authority(),
+            ..Default::default()
+        },
+    );
+
+    mock
+}
+
+#[tokio::test]
+#[ignore = "requires DRIFT_PROGRAM_SO"]
+async fn place_perp_order_executes() {
+    let mut harness = harness();
+    let wallet = Wallet::new(Keypair::new());
+    let mock = fixture(&wallet);
+
+    harness.load(&mock).unwrap();
+    harness
+        .airdrop(wallet.authority(), LAMPORTS_PER_SOL)
+        .unwrap();
+
+    let client = DriftClient::new_mock(Context::DevNet, Arc::clone(&mock), wallet.clone())
+        .await
+        .unwrap();
+    // perp market, its oracle and the quote spot market are passed as remaining accounts
+    let tx = client
+        .init_tx(&wallet.default_sub_account(), false)
+        .await
+        .unwrap()
+        .place_orders(vec![OrderParams {
+            order_type: OrderType::Limit,
+            market_type: MarketType::Perp,
+            market_index: 0,
+            direction: PositionDirection::Long,
+            base_asset_amount: BASE_PRECISION_U64,
+            price: 99 * PRICE_PRECISION_U64,
+            user_order_id: 1,
+            ..Default::default()
+        }])
+        .build();
+
+    let execution = harness.execute(&wallet, tx).unwrap();
+    assert!(execution.is_ok(), "{:#?}", execution.logs);
+    assert!(execution
+        .events
+        .iter()
+        .any(|e| matches!(e, DriftEvent::OrderCreate { order, .. } if order.user_order_id == 1)));
+
+    let user = harness.user(&wallet.default_sub_account()).unwrap();
+    let order = user
+        .orders
+        .iter()
+        .find(|o| o.user_order_id == 1)
+        .expect("order placed");
+    assert_eq!(order.status, OrderStatus::Open);
+    assert_eq!(order.price, 99 * PRICE_PRECISION_U64);
+
+    harness.export(&mock, &[wallet.default_sub_account()]);
+    let user = client
+        .get_user_account(&wallet.default_sub_account())
+        .await
+        .unwrap();
+    assert!(user.orders.iter().any(|o| o.user_order_id == 1));
+}